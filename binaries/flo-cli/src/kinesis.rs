@@ -1,4 +1,7 @@
-use std::{io::Write, path::PathBuf};
+use std::{
+  io::Write,
+  path::{Path, PathBuf},
+};
 
 use flo_kinesis::{data_stream::DataStream, iterator::ShardIteratorType};
 use flo_net::w3gs::W3GSPacketTypeId;
@@ -58,14 +61,8 @@ impl Command {
         }
       }
       Command::Inspect { ref path } => {
-        use bytes::Buf;
-        let data = std::fs::read(path)?;
-        let mut records = vec![];
-        let mut buf = data.as_slice();
+        let records = read_dump(path)?;
         let mut time: u64 = 0;
-        while buf.remaining() > 0 {
-          records.push(GameRecordData::decode(&mut buf)?)
-        }
         tracing::info!("found {} records", records.len());
         for record in &records {
           if let GameRecordData::W3GS(pkt) = record {
@@ -88,3 +85,17 @@ impl Command {
     Ok(())
   }
 }
+
+/// Reads a game file written by `Dump`.
+/// The file is named by the game id, so the records are written without the game id prefix
+/// of `GameRecord`.
+pub fn read_dump(path: &Path) -> Result<Vec<GameRecordData>> {
+  use bytes::Buf;
+  let data = std::fs::read(path)?;
+  let mut buf = data.as_slice();
+  let mut records = vec![];
+  while buf.remaining() > 0 {
+    records.push(GameRecordData::decode(&mut buf)?)
+  }
+  Ok(records)
+}
@@ -8,6 +8,7 @@ mod lan;
 mod server;
 mod observer;
 mod kinesis;
+mod replay;
 
 pub use anyhow::Result;
 
@@ -33,7 +34,11 @@ enum Opt {
   Kinesis {
     #[structopt(subcommand)]
     cmd: kinesis::Command,
-  }
+  },
+  Replay {
+    #[structopt(subcommand)]
+    cmd: replay::Command,
+  },
 }
 
 #[tokio::main]
@@ -60,6 +65,9 @@ async fn main() -> Result<()> {
     Opt::Kinesis { cmd } => {
       cmd.run().await?;
     }
+    Opt::Replay { cmd } => {
+      cmd.run().await?;
+    }
   }
 
   Ok(())
@@ -1,3 +1,7 @@
+use flo_net::proto::flo_observer::DesyncDiagnostics;
+use flo_observer::record::GameRecordData;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 use crate::{Result, env::ENV};
@@ -6,6 +10,16 @@ use crate::{Result, env::ENV};
 pub enum Command {
   Token { game_id: i32 },
   Watch { game_id: i32, delay_secs: Option<i64> },
+  /// Finds the first diverging tick in the desync diagnostics of a game archive
+  Desync {
+    path: PathBuf,
+    /// Read a game file created by `kinesis dump` instead of an archive
+    #[structopt(long)]
+    raw: bool,
+    /// Number of ticks to print around the first diverging tick
+    #[structopt(long, default_value = "3")]
+    context: u32,
+  },
 }
 
 impl Command {
@@ -25,8 +39,118 @@ impl Command {
         client.watch(token).await?;
         client.serve().await;
       }
+      Command::Desync {
+        ref path,
+        raw,
+        context,
+      } => {
+        let records = if raw {
+          crate::kinesis::read_dump(path)?
+        } else {
+          flo_observer_fs::GameDataArchiveReader::open(path)
+            .await?
+            .records()
+            .collect_vec()
+            .await?
+        };
+
+        let items: Vec<_> = records
+          .into_iter()
+          .filter_map(|record| match record {
+            GameRecordData::Desync(data) => Some(data),
+            _ => None,
+          })
+          .collect();
+        tracing::info!("found {} desync records", items.len());
+
+        for item in &items {
+          print_desync(item, context);
+        }
+      }
     }
 
     Ok(())
   }
 }
+
+fn print_desync(data: &DesyncDiagnostics, context: u32) {
+  println!(
+    "desync detected: tick = {}, time = {}ms, game version = {}",
+    data.tick, data.time, data.game_version
+  );
+  if let Some(map) = data.map.as_ref() {
+    println!(
+      "map: {} (sha1 = {}, checksum = {:08X})",
+      map.path,
+      hex::encode(&map.sha1),
+      map.checksum
+    );
+  }
+  for player in &data.players {
+    println!(
+      "player: id = {}, slot player id = {}, game version = {}, desynced = {}",
+      player.player_id, player.slot_player_id, player.game_version, player.desynced
+    );
+  }
+
+  // tick -> player id -> checksum
+  let mut checksums: BTreeMap<u32, BTreeMap<i32, u32>> = BTreeMap::new();
+  for player in &data.players {
+    for item in &player.checksums {
+      checksums
+        .entry(item.tick)
+        .or_default()
+        .insert(player.player_id, item.checksum);
+    }
+  }
+
+  let first_diverging_tick = checksums
+    .iter()
+    .find(|(_, values)| {
+      let mut iter = values.values();
+      let first = iter.next();
+      iter.any(|v| Some(v) != first)
+    })
+    .map(|(tick, _)| *tick);
+
+  let tick = if let Some(tick) = first_diverging_tick {
+    println!("first diverging tick: {}", tick);
+    tick
+  } else {
+    println!("no diverging tick found in the checksum history, using the reported tick");
+    data.tick
+  };
+
+  let range = tick.saturating_sub(context)..=tick.saturating_add(context);
+
+  println!("checksums:");
+  for (t, values) in checksums.range(range.clone()) {
+    let values: Vec<_> = values
+      .iter()
+      .map(|(player_id, checksum)| format!("{}={:08X}", player_id, checksum))
+      .collect();
+    let mark = if *t == tick { "*" } else { " " };
+    println!("{} {:>8}: {}", mark, t, values.join(" "));
+  }
+
+  println!("actions:");
+  for batch in data
+    .action_batches
+    .iter()
+    .filter(|batch| range.contains(&batch.tick))
+  {
+    println!(
+      "  tick = {}, time increment = {}ms, actions = {}",
+      batch.tick,
+      batch.time_increment_ms,
+      batch.actions.len()
+    );
+    for action in &batch.actions {
+      println!(
+        "    slot player {}: {}",
+        action.slot_player_id,
+        hex::encode(&action.data)
+      );
+    }
+  }
+}
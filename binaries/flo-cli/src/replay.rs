@@ -0,0 +1,58 @@
+use flo_w3replay::transform::{sanitize_replay, SanitizeOptions};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+use crate::Result;
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+  /// Writes a copy of a replay with players masked or renamed
+  Sanitize {
+    input: PathBuf,
+    output: PathBuf,
+    /// Replaces player names with `Player {slot number}`
+    #[structopt(long)]
+    mask_names: bool,
+    /// Renames a player, e.g. `--rename "old=new"`
+    #[structopt(long, parse(try_from_str = parse_rename))]
+    rename: Vec<(String, String)>,
+    /// Removes all chat messages
+    #[structopt(long)]
+    strip_chat: bool,
+    #[structopt(long)]
+    game_name: Option<String>,
+  },
+}
+
+impl Command {
+  pub async fn run(&self) -> Result<()> {
+    match *self {
+      Command::Sanitize {
+        ref input,
+        ref output,
+        mask_names,
+        ref rename,
+        strip_chat,
+        ref game_name,
+      } => {
+        let options = SanitizeOptions {
+          mask_player_names: mask_names,
+          rename: rename.iter().cloned().collect(),
+          strip_chat,
+          game_name: game_name.clone(),
+        };
+        sanitize_replay(input, output, &options)?;
+        tracing::info!("saved to {}", output.display());
+      }
+    }
+    Ok(())
+  }
+}
+
+fn parse_rename(value: &str) -> Result<(String, String)> {
+  let mut parts = value.splitn(2, '=');
+  match (parts.next(), parts.next()) {
+    (Some(from), Some(to)) if !from.is_empty() => Ok((from.to_string(), to.to_string())),
+    _ => Err(anyhow::format_err!("expected `old=new`: {}", value)),
+  }
+}
@@ -1,4 +1,4 @@
-use flo_controller::{serve_grpc, serve_socket, ControllerState};
+use flo_controller::{serve_grpc, serve_http, serve_socket, ControllerState};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -32,7 +32,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
   }
 
-  tokio::try_join!(serve_grpc(state.clone()), serve_socket(state.clone()))?;
+  tokio::try_join!(
+    serve_grpc(state.clone()),
+    serve_socket(state.clone()),
+    serve_http(state.clone())
+  )?;
 
   Ok(())
 }
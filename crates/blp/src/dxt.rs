@@ -0,0 +1,171 @@
+//! S3 texture compression (DXT1/3/5) block decoding
+
+use image::{Rgba, RgbaImage};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum DxtFormat {
+  Dxt1 { alpha: bool },
+  Dxt3,
+  Dxt5,
+}
+
+impl DxtFormat {
+  pub fn block_size(&self) -> usize {
+    match *self {
+      DxtFormat::Dxt1 { .. } => 8,
+      DxtFormat::Dxt3 | DxtFormat::Dxt5 => 16,
+    }
+  }
+
+  pub fn data_size(&self, width: u32, height: u32) -> usize {
+    blocks(width) * blocks(height) * self.block_size()
+  }
+}
+
+fn blocks(v: u32) -> usize {
+  (v as usize).div_ceil(4)
+}
+
+/// Caller checks `data` is at least `format.data_size(width, height)` long
+pub(crate) fn decode(format: DxtFormat, width: u32, height: u32, data: &[u8]) -> RgbaImage {
+  let mut image = RgbaImage::new(width, height);
+  let block_size = format.block_size();
+  let blocks_w = blocks(width);
+  for (i, block) in data
+    .chunks_exact(block_size)
+    .take(blocks_w * blocks(height))
+    .enumerate()
+  {
+    let pixels = match format {
+      DxtFormat::Dxt1 { alpha } => decode_color_block(block, alpha),
+      DxtFormat::Dxt3 => {
+        let mut pixels = decode_color_block(&block[8..], false);
+        let bits = u64::from_le_bytes(read8(block));
+        for (j, pixel) in pixels.iter_mut().enumerate() {
+          pixel[3] = ((bits >> (j * 4)) & 0x0F) as u8 * 17;
+        }
+        pixels
+      }
+      DxtFormat::Dxt5 => {
+        let mut pixels = decode_color_block(&block[8..], false);
+        let alphas = decode_alpha_palette(block[0], block[1]);
+        let mut bits = [0_u8; 8];
+        bits[..6].copy_from_slice(&block[2..8]);
+        let bits = u64::from_le_bytes(bits);
+        for (j, pixel) in pixels.iter_mut().enumerate() {
+          pixel[3] = alphas[((bits >> (j * 3)) & 0x07) as usize];
+        }
+        pixels
+      }
+    };
+
+    let bx = (i % blocks_w) as u32 * 4;
+    let by = (i / blocks_w) as u32 * 4;
+    for (j, pixel) in pixels.iter().enumerate() {
+      let x = bx + (j % 4) as u32;
+      let y = by + (j / 4) as u32;
+      if x < width && y < height {
+        image.put_pixel(x, y, Rgba(*pixel));
+      }
+    }
+  }
+  image
+}
+
+fn read8(block: &[u8]) -> [u8; 8] {
+  let mut bytes = [0_u8; 8];
+  bytes.copy_from_slice(&block[..8]);
+  bytes
+}
+
+fn rgb565(v: u16) -> [u8; 4] {
+  let r = ((v >> 11) & 0x1F) as u32;
+  let g = ((v >> 5) & 0x3F) as u32;
+  let b = (v & 0x1F) as u32;
+  [
+    ((r * 255 + 15) / 31) as u8,
+    ((g * 255 + 31) / 63) as u8,
+    ((b * 255 + 15) / 31) as u8,
+    255,
+  ]
+}
+
+fn mix(a: [u8; 4], b: [u8; 4], wa: u32, wb: u32) -> [u8; 4] {
+  let c = |i: usize| ((a[i] as u32 * wa + b[i] as u32 * wb) / (wa + wb)) as u8;
+  [c(0), c(1), c(2), 255]
+}
+
+/// `punch_through` enables the DXT1 3-color mode with a transparent color
+fn decode_color_block(block: &[u8], punch_through: bool) -> [[u8; 4]; 16] {
+  let c0 = u16::from_le_bytes([block[0], block[1]]);
+  let c1 = u16::from_le_bytes([block[2], block[3]]);
+  let (a, b) = (rgb565(c0), rgb565(c1));
+  let colors = if c0 > c1 || !punch_through {
+    [a, b, mix(a, b, 2, 1), mix(a, b, 1, 2)]
+  } else {
+    [a, b, mix(a, b, 1, 1), [0, 0, 0, 0]]
+  };
+  let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+  let mut pixels = [[0_u8; 4]; 16];
+  for (j, pixel) in pixels.iter_mut().enumerate() {
+    *pixel = colors[((indices >> (j * 2)) & 0x03) as usize];
+  }
+  pixels
+}
+
+fn decode_alpha_palette(a0: u8, a1: u8) -> [u8; 8] {
+  let (a0, a1) = (a0 as u32, a1 as u32);
+  let mut alphas = [a0 as u8, a1 as u8, 0, 0, 0, 0, 0, 255];
+  if a0 > a1 {
+    for (i, alpha) in alphas.iter_mut().enumerate().skip(2) {
+      let i = i as u32;
+      *alpha = ((a0 * (8 - i) + a1 * (i - 1)) / 7) as u8;
+    }
+  } else {
+    for (i, alpha) in alphas.iter_mut().enumerate().skip(2).take(4) {
+      let i = i as u32;
+      *alpha = ((a0 * (6 - i) + a1 * (i - 1)) / 5) as u8;
+    }
+  }
+  alphas
+}
+
+#[test]
+fn test_decode_dxt1() {
+  // red and blue, 4-color mode: index 0, 1, 2, 3 in the first row
+  let block = [0x00, 0xF8, 0x1F, 0x00, 0b1110_0100, 0, 0, 0];
+  let image = decode(DxtFormat::Dxt1 { alpha: false }, 4, 4, &block);
+  assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+  assert_eq!(image.get_pixel(1, 0).0, [0, 0, 255, 255]);
+  assert_eq!(image.get_pixel(2, 0).0, [170, 0, 85, 255]);
+  assert_eq!(image.get_pixel(3, 0).0, [85, 0, 170, 255]);
+  assert_eq!(image.get_pixel(3, 3).0, [255, 0, 0, 255]);
+
+  // 3-color mode with transparency, image smaller than a block
+  let block = [0x1F, 0x00, 0x00, 0xF8, 0b1110_0100, 0, 0, 0];
+  let image = decode(DxtFormat::Dxt1 { alpha: true }, 4, 1, &block);
+  assert_eq!(image.get_pixel(2, 0).0, [127, 0, 127, 255]);
+  assert_eq!(image.get_pixel(3, 0).0, [0, 0, 0, 0]);
+}
+
+#[test]
+fn test_decode_dxt3_dxt5() {
+  let color = [0xE0, 0x07, 0xE0, 0x07, 0, 0, 0, 0];
+
+  let mut block = vec![0x10, 0x32, 0, 0, 0, 0, 0, 0xF0];
+  block.extend_from_slice(&color);
+  let image = decode(DxtFormat::Dxt3, 4, 4, &block);
+  assert_eq!(image.get_pixel(0, 0).0, [0, 255, 0, 0]);
+  assert_eq!(image.get_pixel(1, 0).0, [0, 255, 0, 17]);
+  assert_eq!(image.get_pixel(3, 0).0, [0, 255, 0, 51]);
+  assert_eq!(image.get_pixel(3, 3).0, [0, 255, 0, 255]);
+
+  // index 0, 1, 2 and 7 in the first row
+  let mut block = vec![255, 0, 0b1000_1000, 0b0000_1110, 0, 0, 0, 0];
+  block.extend_from_slice(&color);
+  let image = decode(DxtFormat::Dxt5, 4, 4, &block);
+  assert_eq!(image.get_pixel(0, 0).0[3], 255);
+  assert_eq!(image.get_pixel(1, 0).0[3], 0);
+  assert_eq!(image.get_pixel(2, 0).0[3], 218);
+  assert_eq!(image.get_pixel(3, 0).0[3], 36);
+}
@@ -0,0 +1,305 @@
+//! BLP1/BLP2 encoding, palettized (quantized to 256 colors) or uncompressed BGRA
+
+use flo_util::binary::BufMut;
+use image::imageops::FilterType;
+use image::RgbaImage;
+use std::collections::BTreeSet;
+
+use crate::{
+  BLP2_ENCODING_BGRA, BLP2_ENCODING_PALETTIZED, COMPRESSION_DIRECT, MAX_MIPMAPS, PALETTE_SIZE,
+};
+
+const BLP1_HEADER_SIZE: usize = 4 + 4 * 6 + 4 * 16 * 2;
+const BLP2_HEADER_SIZE: usize = 4 + 4 + 4 + 4 * 2 + 4 * 16 * 2;
+const BLP2_ALPHA_ENCODING_UNCOMPRESSED: u8 = 8;
+/// NeuQuant sampling factor, 1 is the best quality and slowest
+const QUANTIZE_SAMPLE_FACTOR: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BLPFormat {
+  Blp1Palettized,
+  Blp2Palettized,
+  /// Alpha is always stored with 8 bits
+  Blp2Bgra,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BLPAlphaBits {
+  Zero = 0,
+  One = 1,
+  Four = 4,
+  Eight = 8,
+}
+
+#[derive(Debug, Clone)]
+pub struct BLPEncoder {
+  format: BLPFormat,
+  alpha_bits: BLPAlphaBits,
+  mipmaps: bool,
+}
+
+impl BLPEncoder {
+  /// 8-bit alpha with mipmaps
+  pub fn new(format: BLPFormat) -> Self {
+    Self {
+      format,
+      alpha_bits: BLPAlphaBits::Eight,
+      mipmaps: true,
+    }
+  }
+
+  pub fn alpha_bits(mut self, alpha_bits: BLPAlphaBits) -> Self {
+    self.alpha_bits = alpha_bits;
+    self
+  }
+
+  /// Textures need mipmaps, map previews don't
+  pub fn mipmaps(mut self, mipmaps: bool) -> Self {
+    self.mipmaps = mipmaps;
+    self
+  }
+
+  pub fn encode(&self, image: &RgbaImage) -> Vec<u8> {
+    let levels = self.levels(image);
+    let alpha_bits = match self.format {
+      BLPFormat::Blp2Bgra if self.alpha_bits != BLPAlphaBits::Zero => 8,
+      BLPFormat::Blp2Bgra => 0,
+      _ => self.alpha_bits as u8,
+    };
+
+    let quantizer = match self.format {
+      BLPFormat::Blp1Palettized | BLPFormat::Blp2Palettized if !levels.is_empty() => {
+        Some(Quantizer::new(image))
+      }
+      _ => None,
+    };
+    let palette = quantizer
+      .as_ref()
+      .map(Quantizer::palette)
+      .unwrap_or_else(|| vec![0_u8; PALETTE_SIZE]);
+
+    let data: Vec<Vec<u8>> = levels
+      .iter()
+      .map(|level| match quantizer.as_ref() {
+        Some(quantizer) => encode_palettized(level, quantizer, alpha_bits),
+        None => encode_bgra(level, alpha_bits),
+      })
+      .collect();
+
+    let header_size = match self.format {
+      BLPFormat::Blp1Palettized => BLP1_HEADER_SIZE,
+      BLPFormat::Blp2Palettized | BLPFormat::Blp2Bgra => BLP2_HEADER_SIZE,
+    };
+    let mut offsets = [0_u32; MAX_MIPMAPS];
+    let mut lengths = [0_u32; MAX_MIPMAPS];
+    let mut offset = header_size + PALETTE_SIZE;
+    for (i, bytes) in data.iter().enumerate() {
+      offsets[i] = offset as u32;
+      lengths[i] = bytes.len() as u32;
+      offset += bytes.len();
+    }
+
+    let mut buf = Vec::with_capacity(offset);
+    let has_mipmap = self.mipmaps as u8;
+    match self.format {
+      BLPFormat::Blp1Palettized => {
+        buf.put_slice(b"BLP1");
+        buf.put_u32_le(COMPRESSION_DIRECT);
+        buf.put_u32_le(alpha_bits as u32);
+        buf.put_u32_le(image.width());
+        buf.put_u32_le(image.height());
+        // picture type: 4 with an alpha list, 5 without
+        buf.put_u32_le(if alpha_bits > 0 { 4 } else { 5 });
+        buf.put_u32_le(has_mipmap as u32);
+      }
+      BLPFormat::Blp2Palettized | BLPFormat::Blp2Bgra => {
+        buf.put_slice(b"BLP2");
+        buf.put_u32_le(COMPRESSION_DIRECT);
+        buf.put_u8(if self.format == BLPFormat::Blp2Bgra {
+          BLP2_ENCODING_BGRA
+        } else {
+          BLP2_ENCODING_PALETTIZED
+        });
+        buf.put_u8(alpha_bits);
+        buf.put_u8(BLP2_ALPHA_ENCODING_UNCOMPRESSED);
+        buf.put_u8(has_mipmap);
+        buf.put_u32_le(image.width());
+        buf.put_u32_le(image.height());
+      }
+    }
+    for v in offsets.iter().chain(lengths.iter()) {
+      buf.put_u32_le(*v);
+    }
+    buf.put_slice(&palette);
+    for bytes in data {
+      buf.put_slice(&bytes);
+    }
+    buf
+  }
+
+  fn levels(&self, image: &RgbaImage) -> Vec<RgbaImage> {
+    let (mut width, mut height) = image.dimensions();
+    if width == 0 || height == 0 {
+      return vec![];
+    }
+    let mut levels = vec![image.clone()];
+    while self.mipmaps && levels.len() < MAX_MIPMAPS && (width > 1 || height > 1) {
+      width = (width / 2).max(1);
+      height = (height / 2).max(1);
+      let next =
+        image::imageops::resize(levels.last().unwrap(), width, height, FilterType::Triangle);
+      levels.push(next);
+    }
+    levels
+  }
+}
+
+/// Images with up to 256 colors keep their exact colors
+enum Quantizer {
+  Exact(Vec<[u8; 3]>),
+  NeuQuant(color_quant::NeuQuant),
+}
+
+impl Quantizer {
+  fn new(image: &RgbaImage) -> Self {
+    let mut colors = BTreeSet::new();
+    for p in image.pixels() {
+      colors.insert([p[0], p[1], p[2]]);
+      if colors.len() > 256 {
+        let pixels: Vec<u8> = image
+          .pixels()
+          .flat_map(|p| [p[0], p[1], p[2], 255])
+          .collect();
+        return Quantizer::NeuQuant(color_quant::NeuQuant::new(
+          QUANTIZE_SAMPLE_FACTOR,
+          256,
+          &pixels,
+        ));
+      }
+    }
+    Quantizer::Exact(colors.into_iter().collect())
+  }
+
+  /// BGRA, the alpha channel is unused
+  fn palette(&self) -> Vec<u8> {
+    let mut palette = vec![0_u8; PALETTE_SIZE];
+    let colors: Vec<[u8; 3]> = match *self {
+      Quantizer::Exact(ref colors) => colors.clone(),
+      Quantizer::NeuQuant(ref nq) => nq
+        .color_map_rgb()
+        .chunks_exact(3)
+        .map(|c| [c[0], c[1], c[2]])
+        .collect(),
+    };
+    for (entry, [r, g, b]) in palette.chunks_exact_mut(4).zip(colors) {
+      entry.copy_from_slice(&[b, g, r, 0]);
+    }
+    palette
+  }
+
+  fn index_of(&self, rgb: [u8; 3]) -> u8 {
+    match *self {
+      Quantizer::Exact(ref colors) => match colors.binary_search(&rgb) {
+        Ok(index) => index as u8,
+        // colors blended by mipmap scaling
+        Err(_) => {
+          let distance =
+            |c: &[u8; 3]| -> i32 { (0..3).map(|i| (c[i] as i32 - rgb[i] as i32).pow(2)).sum() };
+          (0..colors.len())
+            .min_by_key(|i| distance(&colors[*i]))
+            .unwrap_or(0) as u8
+        }
+      },
+      Quantizer::NeuQuant(ref nq) => nq.index_of(&[rgb[0], rgb[1], rgb[2], 255]) as u8,
+    }
+  }
+}
+
+fn encode_palettized(image: &RgbaImage, quantizer: &Quantizer, alpha_bits: u8) -> Vec<u8> {
+  let len = (image.width() * image.height()) as usize;
+  let alpha_len = (len * alpha_bits as usize).div_ceil(8);
+  let mut bytes = Vec::with_capacity(len + alpha_len);
+  for p in image.pixels() {
+    bytes.push(quantizer.index_of([p[0], p[1], p[2]]));
+  }
+  let mut alpha = vec![0_u8; alpha_len];
+  for (i, p) in image.pixels().enumerate() {
+    match alpha_bits {
+      1 => alpha[i / 8] |= ((p[3] >= 128) as u8) << (i % 8),
+      4 => alpha[i / 2] |= (p[3] >> 4) << ((i % 2) * 4),
+      8 => alpha[i] = p[3],
+      _ => {}
+    }
+  }
+  bytes.extend(alpha);
+  bytes
+}
+
+fn encode_bgra(image: &RgbaImage, alpha_bits: u8) -> Vec<u8> {
+  let mut bytes = Vec::with_capacity((image.width() * image.height() * 4) as usize);
+  for p in image.pixels() {
+    let a = if alpha_bits > 0 { p[3] } else { 255 };
+    bytes.extend_from_slice(&[p[2], p[1], p[0], a]);
+  }
+  bytes
+}
+
+#[cfg(test)]
+fn test_image() -> RgbaImage {
+  let colors = [[200, 30, 30], [30, 200, 30], [30, 30, 200], [220, 220, 40]];
+  RgbaImage::from_fn(8, 4, |x, y| {
+    let [r, g, b] = colors[(x / 2) as usize];
+    image::Rgba([r, g, b, (y * 85) as u8])
+  })
+}
+
+#[test]
+fn test_encode_decode() {
+  use crate::BLPImage;
+  use flo_util::binary::BinDecode;
+
+  let image = test_image();
+  let cases = [
+    (BLPFormat::Blp1Palettized, BLPAlphaBits::Eight),
+    (BLPFormat::Blp1Palettized, BLPAlphaBits::Four),
+    (BLPFormat::Blp1Palettized, BLPAlphaBits::One),
+    (BLPFormat::Blp1Palettized, BLPAlphaBits::Zero),
+    (BLPFormat::Blp2Palettized, BLPAlphaBits::Eight),
+    (BLPFormat::Blp2Bgra, BLPAlphaBits::Eight),
+  ];
+  for (format, alpha_bits) in cases.iter().cloned() {
+    let bytes = BLPEncoder::new(format)
+      .alpha_bits(alpha_bits)
+      .encode(&image);
+    let decoded = BLPImage::decode(&mut bytes.as_slice()).unwrap();
+    assert_eq!(decoded.num_mipmaps(), 4);
+    assert_eq!(decoded.mipmap(3).unwrap().dimensions(), (1, 1));
+
+    for (expected, got) in image.pixels().zip(decoded.pixels()) {
+      for i in 0..3 {
+        assert!(
+          (expected[i] as i32 - got[i] as i32).abs() <= 8,
+          "{:?} {:?}: {:?} != {:?}",
+          format,
+          alpha_bits,
+          expected,
+          got
+        );
+      }
+      let alpha = match alpha_bits {
+        BLPAlphaBits::Zero => 255,
+        BLPAlphaBits::One => (expected[3] >= 128) as u8 * 255,
+        BLPAlphaBits::Four => (expected[3] >> 4) * 17,
+        BLPAlphaBits::Eight => expected[3],
+      };
+      assert_eq!(got[3], alpha);
+    }
+  }
+
+  let bytes = BLPEncoder::new(BLPFormat::Blp2Bgra)
+    .mipmaps(false)
+    .encode(&image);
+  let decoded = BLPImage::decode(&mut bytes.as_slice()).unwrap();
+  assert_eq!(decoded.num_mipmaps(), 1);
+  assert_eq!(decoded.buffer(), &image);
+}
@@ -1,4 +1,6 @@
-//! BLIzzard Picture image format decoder.
+//! BLIzzard Picture image format decoder and encoder.
+//!
+//! JPEG decoding is ported from gowarcraft3:
 //!
 //! Author:  Niels A.D.
 //! Project: gowarcraft3 (https://github.com/nielsAD/gowarcraft3)
@@ -8,31 +10,67 @@
 
 use flo_util::binary::*;
 use flo_util::BinDecode;
-use image::{ImageBuffer, ImageFormat, Rgb, Rgba};
+use image::{ImageBuffer, ImageFormat, Rgb, Rgba, RgbaImage};
+
+mod dxt;
+mod encode;
+
+use self::dxt::DxtFormat;
+pub use self::encode::{BLPAlphaBits, BLPEncoder, BLPFormat};
 
 const COMPRESSION_JPEG: u32 = 0;
+const COMPRESSION_DIRECT: u32 = 1;
+const BLP2_ENCODING_PALETTIZED: u8 = 1;
+const BLP2_ENCODING_DXT: u8 = 2;
+const BLP2_ENCODING_BGRA: u8 = 3;
+const BLP2_ALPHA_ENCODING_DXT1: u8 = 0;
+const BLP2_ALPHA_ENCODING_DXT3: u8 = 1;
+const BLP2_ALPHA_ENCODING_DXT5: u8 = 7;
+const MAX_MIPMAPS: usize = 16;
+const PALETTE_SIZE: usize = 256 * 4;
 const SOI_HEADER: &[u8] = &[
   0xFF, 0xD8, 0xFF, 0xEE, 0x00, 0x0E, //App14Marker
   b'A', b'd', b'o', b'b', b'e', 0, 0, 0, 0, 0, 0, 0,
 ];
 
 pub struct BLPImage {
-  image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+  image: RgbaImage,
+  /// Smaller levels, starting from level 1
+  mipmaps: Vec<RgbaImage>,
 }
 
 impl BLPImage {
   pub fn buffer(&self) -> &ImageBuffer<Rgba<u8>, Vec<u8>> {
     &self.image
   }
+
+  pub fn into_buffer(self) -> RgbaImage {
+    self.image
+  }
+
+  /// Number of decoded levels, including the full size image
+  pub fn num_mipmaps(&self) -> usize {
+    1 + self.mipmaps.len()
+  }
+
+  /// Level 0 is the full size image, each level halves the size
+  pub fn mipmap(&self, level: usize) -> Option<&RgbaImage> {
+    if level == 0 {
+      Some(&self.image)
+    } else {
+      self.mipmaps.get(level - 1)
+    }
+  }
 }
 
 impl std::fmt::Debug for BLPImage {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     write!(
       f,
-      "BLPImage(witdh = {}, height = {})",
+      "BLPImage(witdh = {}, height = {}, mipmaps = {})",
       self.image.width(),
-      self.image.height()
+      self.image.height(),
+      self.num_mipmaps()
     )
   }
 }
@@ -45,74 +83,64 @@ impl std::ops::Deref for BLPImage {
   }
 }
 
+/// Decodes the first level and all smaller levels the file declares
+///
+/// Smaller levels are often broken in files written by third party tools,
+/// decoding stops at the first level that fails.
 impl BinDecode for BLPImage {
-  const MIN_SIZE: usize = BLP1Header::MIN_SIZE;
+  const MIN_SIZE: usize = 4;
   const FIXED_SIZE: bool = false;
   fn decode<T: Buf>(buf: &mut T) -> Result<Self, BinDecodeError> {
-    let size = buf.remaining();
-    let header = BLP1Header::decode(buf)?;
-    match header.alpha_bits {
-      0 | 8 => {}
-      v => return Err(BinDecodeError::failure(format!("invalid alpha bit: {}", v))),
-    }
-
-    if header.compression != COMPRESSION_JPEG {
-      return Err(BinDecodeError::failure(format!(
-        "unsupported compression type: {}",
-        header.compression
-      )));
-    }
+    // mipmap offsets are relative to the start of the file
+    let mut data = vec![0; buf.remaining()];
+    buf.copy_to_slice(&mut data);
 
-    let mipmap_offset_original = header.mipmap_offsets[0] as usize;
-    let mipmap_len = header.mipmap_lengths[0] as usize;
-
-    if mipmap_offset_original == 0 || mipmap_len == 0 {
-      return Err(BinDecodeError::failure("invalid mipmap data"));
-    }
-
-    buf.check_size(4)?;
-    let image_buf_size = u32::decode(buf)? as usize;
-
-    buf.check_size(image_buf_size as usize)?;
-    let mut img_buf = BytesMut::with_capacity((image_buf_size + mipmap_len) as usize);
-    img_buf.resize(img_buf.capacity(), 0);
-    buf.copy_to_slice(&mut img_buf[..image_buf_size]);
-
-    let offset = mipmap_offset_original
-      .checked_sub(size - buf.remaining())
-      .ok_or_else(|| BinDecodeError::failure("invalid mipmap offset"))?;
-
-    buf.check_size(offset + mipmap_len)?;
-    buf.advance(offset);
-    buf.copy_to_slice(&mut img_buf[image_buf_size..]);
-
-    let image = image::load_from_memory_with_format(&img_buf, ImageFormat::Jpeg)
-      .or_else(|e| {
-        if e.to_string().contains("Adobe APP14") {
-          let mut patched = Vec::with_capacity(image_buf_size - 2 + SOI_HEADER.len());
-          patched.extend(SOI_HEADER);
-          patched.extend(&img_buf[2..]);
-          image::load_from_memory_with_format(&patched, ImageFormat::Jpeg)
-        } else {
-          Err(e)
-        }
-      })
-      .map_err(|e| BinDecodeError::failure(format!("decode jpeg: {:?}", e)))?;
-
-    let image = if let Some(rbg_image) = image.as_rgb8() {
-      let (w, h) = rbg_image.dimensions();
-      let mut raw = Vec::with_capacity((w * h * 4) as usize);
-      for Rgb([r, g, b]) in rbg_image.pixels() {
-        raw.extend(&[*b, *g, *r, 255])
+    let mut header_buf = data.as_slice();
+    let (layout, mipmaps) = match header_buf.get(..4) {
+      Some(b"BLP1") => {
+        let header = BLP1Header::decode(&mut header_buf)?;
+        (header.layout(&mut header_buf)?, header.mipmaps())
       }
-      ImageBuffer::from_raw(w, h, raw).unwrap()
-    } else {
-      return Err(BinDecodeError::failure(
-        "decode jpeg: pixel format is not rgb",
-      ));
+      Some(b"BLP2") => {
+        let header = BLP2Header::decode(&mut header_buf)?;
+        (header.layout(&mut header_buf)?, header.mipmaps())
+      }
+      Some(magic) => {
+        return Err(BinDecodeError::failure(format!(
+          "unknown magic: {:?}",
+          magic
+        )))
+      }
+      None => return Err(BinDecodeError::incomplete()),
     };
 
-    Ok(Self { image })
+    let mut levels = mipmaps
+      .offsets
+      .iter()
+      .zip(mipmaps.lengths.iter())
+      .take(mipmaps.count)
+      .enumerate()
+      .map(|(level, (offset, len))| {
+        let width = (mipmaps.width >> level).max(1);
+        let height = (mipmaps.height >> level).max(1);
+        let (offset, len) = (*offset as usize, *len as usize);
+        if offset == 0 || len == 0 {
+          return Err(BinDecodeError::failure("invalid mipmap data"));
+        }
+        let bytes = data
+          .get(offset..(offset + len))
+          .ok_or_else(|| BinDecodeError::failure("invalid mipmap offset"))?;
+        layout
+          .decode(width, height, bytes)
+          .map_err(|e| e.context(format!("mipmap {}", level)))
+      });
+
+    let image = levels
+      .next()
+      .ok_or_else(|| BinDecodeError::failure("invalid mipmap data"))??;
+    let mipmaps = levels.take_while(Result::is_ok).flatten().collect();
+
+    Ok(Self { image, mipmaps })
   }
 }
 
@@ -122,16 +150,270 @@ struct BLP1Header {
   _magic: [u8; 4],
   compression: u32,
   alpha_bits: u32,
-  _width: u32,
-  _height: u32,
+  width: u32,
+  height: u32,
   _flags: u32,
-  _has_mipmap: u32,
+  has_mipmap: u32,
+  mipmap_offsets: [u32; 16],
+  mipmap_lengths: [u32; 16],
+}
+
+impl BLP1Header {
+  fn layout<T: Buf>(&self, buf: &mut T) -> Result<Layout, BinDecodeError> {
+    match self.compression {
+      COMPRESSION_JPEG => {
+        match self.alpha_bits {
+          0 | 8 => {}
+          v => return Err(BinDecodeError::failure(format!("invalid alpha bit: {}", v))),
+        }
+        Layout::jpeg(buf)
+      }
+      COMPRESSION_DIRECT => Layout::palettized(buf, self.alpha_bits),
+      other => Err(BinDecodeError::failure(format!(
+        "unsupported compression type: {}",
+        other
+      ))),
+    }
+  }
+
+  fn mipmaps(&self) -> Mipmaps {
+    Mipmaps {
+      width: self.width,
+      height: self.height,
+      count: if self.has_mipmap != 0 { MAX_MIPMAPS } else { 1 },
+      offsets: self.mipmap_offsets,
+      lengths: self.mipmap_lengths,
+    }
+  }
+}
+
+#[derive(Debug, BinDecode)]
+struct BLP2Header {
+  #[bin(eq = & b"BLP2")]
+  _magic: [u8; 4],
+  compression: u32,
+  encoding: u8,
+  alpha_bits: u8,
+  alpha_encoding: u8,
+  has_mipmap: u8,
+  width: u32,
+  height: u32,
   mipmap_offsets: [u32; 16],
   mipmap_lengths: [u32; 16],
 }
 
+impl BLP2Header {
+  fn layout<T: Buf>(&self, buf: &mut T) -> Result<Layout, BinDecodeError> {
+    if self.compression == COMPRESSION_JPEG {
+      return Layout::jpeg(buf);
+    }
+    if self.compression != COMPRESSION_DIRECT {
+      return Err(BinDecodeError::failure(format!(
+        "unsupported compression type: {}",
+        self.compression
+      )));
+    }
+    match self.encoding {
+      BLP2_ENCODING_PALETTIZED => Layout::palettized(buf, self.alpha_bits as u32),
+      BLP2_ENCODING_DXT => {
+        let format = match self.alpha_encoding {
+          BLP2_ALPHA_ENCODING_DXT1 => DxtFormat::Dxt1 {
+            alpha: self.alpha_bits > 0,
+          },
+          BLP2_ALPHA_ENCODING_DXT3 => DxtFormat::Dxt3,
+          BLP2_ALPHA_ENCODING_DXT5 => DxtFormat::Dxt5,
+          other => {
+            return Err(BinDecodeError::failure(format!(
+              "unsupported alpha encoding: {}",
+              other
+            )))
+          }
+        };
+        Ok(Layout::Dxt(format))
+      }
+      BLP2_ENCODING_BGRA => Ok(Layout::Bgra),
+      other => Err(BinDecodeError::failure(format!(
+        "unsupported encoding: {}",
+        other
+      ))),
+    }
+  }
+
+  fn mipmaps(&self) -> Mipmaps {
+    Mipmaps {
+      width: self.width,
+      height: self.height,
+      count: if self.has_mipmap != 0 { MAX_MIPMAPS } else { 1 },
+      offsets: self.mipmap_offsets,
+      lengths: self.mipmap_lengths,
+    }
+  }
+}
+
+struct Mipmaps {
+  width: u32,
+  height: u32,
+  count: usize,
+  offsets: [u32; 16],
+  lengths: [u32; 16],
+}
+
+/// How mipmap data is stored, shared by BLP1 and BLP2
+enum Layout {
+  /// Shared JPEG header, prepended to each mipmap
+  Jpeg(Vec<u8>),
+  Palettized {
+    palette: Vec<u8>,
+    alpha_bits: u32,
+  },
+  Dxt(DxtFormat),
+  Bgra,
+}
+
+impl Layout {
+  fn jpeg<T: Buf>(buf: &mut T) -> Result<Self, BinDecodeError> {
+    buf.check_size(4)?;
+    let header_size = buf.get_u32_le() as usize;
+    buf.check_size(header_size)?;
+    let mut header = vec![0; header_size];
+    buf.copy_to_slice(&mut header);
+    Ok(Layout::Jpeg(header))
+  }
+
+  fn palettized<T: Buf>(buf: &mut T, alpha_bits: u32) -> Result<Self, BinDecodeError> {
+    match alpha_bits {
+      0 | 1 | 4 | 8 => {}
+      v => return Err(BinDecodeError::failure(format!("invalid alpha bit: {}", v))),
+    }
+    buf.check_size(PALETTE_SIZE)?;
+    let mut palette = vec![0; PALETTE_SIZE];
+    buf.copy_to_slice(&mut palette);
+    Ok(Layout::Palettized {
+      palette,
+      alpha_bits,
+    })
+  }
+
+  fn decode(&self, width: u32, height: u32, bytes: &[u8]) -> Result<RgbaImage, BinDecodeError> {
+    let len = (width as usize) * (height as usize);
+    match *self {
+      Layout::Jpeg(ref header) => decode_jpeg(header, bytes),
+      Layout::Palettized {
+        ref palette,
+        alpha_bits,
+      } => {
+        let alpha_len = (len * alpha_bits as usize).div_ceil(8);
+        if bytes.len() < len + alpha_len {
+          return Err(BinDecodeError::incomplete());
+        }
+        let (indices, alpha) = bytes.split_at(len);
+        let mut raw = Vec::with_capacity(len * 4);
+        for (i, index) in indices.iter().enumerate() {
+          let color = &palette[(*index as usize) * 4..(*index as usize) * 4 + 4];
+          let a = match alpha_bits {
+            1 => ((alpha[i / 8] >> (i % 8)) & 0x01) * 255,
+            4 => ((alpha[i / 2] >> ((i % 2) * 4)) & 0x0F) * 17,
+            8 => alpha[i],
+            _ => 255,
+          };
+          raw.extend_from_slice(&[color[2], color[1], color[0], a]);
+        }
+        Ok(ImageBuffer::from_raw(width, height, raw).unwrap())
+      }
+      Layout::Dxt(format) => {
+        if bytes.len() < format.data_size(width, height) {
+          return Err(BinDecodeError::incomplete());
+        }
+        Ok(dxt::decode(format, width, height, bytes))
+      }
+      Layout::Bgra => {
+        if bytes.len() < len * 4 {
+          return Err(BinDecodeError::incomplete());
+        }
+        let mut raw = Vec::with_capacity(len * 4);
+        for bgra in bytes[..len * 4].chunks_exact(4) {
+          raw.extend_from_slice(&[bgra[2], bgra[1], bgra[0], bgra[3]]);
+        }
+        Ok(ImageBuffer::from_raw(width, height, raw).unwrap())
+      }
+    }
+  }
+}
+
+fn decode_jpeg(header: &[u8], bytes: &[u8]) -> Result<RgbaImage, BinDecodeError> {
+  let mut img_buf = Vec::with_capacity(header.len() + bytes.len());
+  img_buf.extend_from_slice(header);
+  img_buf.extend_from_slice(bytes);
+
+  let image = image::load_from_memory_with_format(&img_buf, ImageFormat::Jpeg)
+    .or_else(|e| {
+      if e.to_string().contains("Adobe APP14") && img_buf.len() >= 2 {
+        let mut patched = Vec::with_capacity(img_buf.len() - 2 + SOI_HEADER.len());
+        patched.extend(SOI_HEADER);
+        patched.extend(&img_buf[2..]);
+        image::load_from_memory_with_format(&patched, ImageFormat::Jpeg)
+      } else {
+        Err(e)
+      }
+    })
+    .map_err(|e| BinDecodeError::failure(format!("decode jpeg: {:?}", e)))?;
+
+  if let Some(rbg_image) = image.as_rgb8() {
+    let (w, h) = rbg_image.dimensions();
+    let mut raw = Vec::with_capacity((w * h * 4) as usize);
+    for Rgb([r, g, b]) in rbg_image.pixels() {
+      raw.extend(&[*b, *g, *r, 255])
+    }
+    Ok(ImageBuffer::from_raw(w, h, raw).unwrap())
+  } else {
+    Err(BinDecodeError::failure(
+      "decode jpeg: pixel format is not rgb",
+    ))
+  }
+}
+
 #[test]
 fn test_blp_to_jpg() {
   let buf = std::fs::read("../../deps/wc3-samples/map/war3mapMap.blp").unwrap();
   dbg!(BLPImage::decode(&mut buf.as_slice()).unwrap());
 }
+
+#[test]
+fn test_decode_blp2_dxt1() {
+  let mut bytes = vec![];
+  bytes.put_slice(b"BLP2");
+  bytes.put_u32_le(COMPRESSION_DIRECT);
+  bytes.put_u8(BLP2_ENCODING_DXT);
+  bytes.put_u8(0);
+  bytes.put_u8(BLP2_ALPHA_ENCODING_DXT1);
+  bytes.put_u8(1);
+  bytes.put_u32_le(8);
+  bytes.put_u32_le(4);
+  let header_size = bytes.len() + 4 * 16 * 2 + PALETTE_SIZE;
+  let mut offsets = [0_u32; 16];
+  let mut lengths = [0_u32; 16];
+  // 8x4: 2 blocks, 4x2 and smaller: 1 block each
+  for level in 0..4 {
+    offsets[level] = (header_size + [0, 16, 24, 32][level]) as u32;
+    lengths[level] = if level == 0 { 16 } else { 8 };
+  }
+  for v in offsets.iter().chain(lengths.iter()) {
+    bytes.put_u32_le(*v);
+  }
+  bytes.put_slice(&[0; PALETTE_SIZE]);
+  // red block, blue block
+  bytes.put_slice(&[0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0]);
+  bytes.put_slice(&[0x1F, 0x00, 0x1F, 0x00, 0, 0, 0, 0]);
+  for _ in 1..4 {
+    bytes.put_slice(&[0xE0, 0x07, 0xE0, 0x07, 0, 0, 0, 0]);
+  }
+
+  let image = BLPImage::decode(&mut bytes.as_slice()).unwrap();
+  assert_eq!(image.dimensions(), (8, 4));
+  assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+  assert_eq!(image.get_pixel(7, 3).0, [0, 0, 255, 255]);
+  assert_eq!(image.num_mipmaps(), 4);
+  assert_eq!(image.mipmap(1).unwrap().dimensions(), (4, 2));
+  assert_eq!(image.mipmap(3).unwrap().get_pixel(0, 0).0, [0, 255, 0, 255]);
+  assert!(image.mipmap(4).is_none());
+}
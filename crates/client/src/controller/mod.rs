@@ -92,6 +92,7 @@ impl ControllerClient {
       node: Arc::new(node_info),
       player_token: event.player_token,
       game: event.game_info,
+      game_setting_flags: event.game_setting_flags,
     };
 
     if let Err(err) = self
@@ -5,7 +5,7 @@ use crate::message::message;
 use crate::message::message::OutgoingMessage;
 use crate::node::{AddNode, GetNodePingMap, NodeRegistry, RemoveNode, UpdateNodes};
 use crate::ping::PingUpdate;
-use crate::platform::{CalcMapChecksum, GetClientPlatformInfo, Platform};
+use crate::platform::{CalcMapChecksum, GetClientPlatformInfo, GetMapDownloadPath, Platform};
 use flo_net::packet::*;
 use flo_net::proto::flo_connect as proto;
 use flo_net::stream::FloStream;
@@ -376,6 +376,7 @@ impl ControllerStream {
                 node_id: p.node_id,
                 game_info: info,
                 player_token: p.player_token,
+                game_setting_flags: p.game_setting_flags,
               }).wrap(id)).await?;
             } else {
               tracing::warn!("received player for game#{} but the active game id is {}", p.game_id, info.game_id);
@@ -546,6 +547,18 @@ where
   }
 }
 
+async fn download_missing_map(
+  platform: Addr<Platform>,
+  domain: &str,
+  path: String,
+  sha1: [u8; 20],
+) -> Result<()> {
+  if let Some(target) = platform.send(GetMapDownloadPath { path }).await?? {
+    crate::map::download_map(domain, sha1, target).await?;
+  }
+  Ok(())
+}
+
 struct SetLocalGameInfo(Option<Arc<LocalGameInfo>>);
 
 impl Message for SetLocalGameInfo {
@@ -556,10 +569,26 @@ impl Message for SetLocalGameInfo {
 impl Handler<SetLocalGameInfo> for ControllerStream {
   async fn handle(
     &mut self,
-    _: &mut Context<Self>,
+    ctx: &mut Context<Self>,
     SetLocalGameInfo(info): SetLocalGameInfo,
   ) -> <SetLocalGameInfo as Message>::Result {
     if let Some(info) = info {
+      let map_changed = self
+        .current_game_info
+        .as_ref()
+        .map(|current| current.map_sha1 != info.map_sha1)
+        .unwrap_or(true);
+      if map_changed {
+        let platform = self.platform.clone();
+        let domain = self.domain.clone();
+        let path = info.map_path.clone();
+        let sha1 = info.map_sha1;
+        ctx.spawn(async move {
+          if let Err(err) = download_missing_map(platform, &domain, path, sha1).await {
+            tracing::error!("download map: {}", err);
+          }
+        });
+      }
       self
         .parent
         .notify(ControllerEventData::SelectNode(info.node_id.clone()).wrap(self.id))
@@ -720,4 +749,6 @@ pub struct GameReceivedEvent {
   pub node_id: i32,
   pub game_info: Arc<LocalGameInfo>,
   pub player_token: Vec<u8>,
+  /// `flo_w3gs` GameSettingFlags from the lobby settings, 0 = default
+  pub game_setting_flags: u32,
 }
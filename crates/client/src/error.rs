@@ -16,6 +16,8 @@ pub enum Error {
   NodeConnectionRejected(flo_net::proto::flo_node::ClientConnectRejectReason, String),
   #[error("Map checksum mismatch")]
   MapChecksumMismatch,
+  #[error("Map download: {0}")]
+  MapDownload(String),
   #[error("Game version mismatch")]
   GameVersionMismatch,
   #[error("FLO observer slot occupied")]
@@ -14,10 +14,11 @@ use crate::lan::game::slot::LanSlotInfo;
 use crate::lan::get_lan_game_name;
 use crate::node::stream::NodeConnectToken;
 use crate::node::NodeInfo;
-use flo_lan::{GameInfo, MdnsPublisher};
+use flo_lan::{GameInfo, LanDiscovery, LanPublisher};
 use flo_state::Addr;
 use flo_task::SpawnScope;
 use flo_types::node::{NodeGameStatus, SlotClientStatus};
+use flo_w3gs::protocol::constants::GameSettingFlags;
 use flo_w3gs::protocol::game::GameSettings;
 use flo_w3map::MapChecksum;
 use proxy::LanProxy;
@@ -44,10 +45,12 @@ pub struct LanGameInfo {
 
 impl LanGame {
   pub async fn create(
+    discovery: LanDiscovery,
     my_player_id: i32,
     node: Arc<NodeInfo>,
     player_token: Vec<u8>,
     game: Arc<LocalGameInfo>,
+    game_setting_flags: u32,
     map_checksum: MapChecksum,
     client: Addr<ControllerClient>,
   ) -> Result<Self> {
@@ -65,6 +68,11 @@ impl LanGame {
       game.map_sha1,
       game.map_checksum,
     )?;
+    // older controllers don't send the lobby settings
+    let flags = GameSettingFlags::from_bits(game_setting_flags).filter(|v| !v.is_empty());
+    if let Some(flags) = flags {
+      game_info.set_game_setting_flags(flags);
+    }
     let token = NodeConnectToken::from_vec(player_token).ok_or_else(|| Error::InvalidNodeToken)?;
 
     let proxy = LanProxy::start(
@@ -93,7 +101,7 @@ impl LanGame {
       {
         let mut scope = scope.handle();
         let mdns_shutdown_notify = mdns_shutdown_notify.clone();
-        let publisher = MdnsPublisher::start(game_info).await?;
+        let publisher = LanPublisher::start(discovery, game_info).await?;
         async move {
           let _publisher = publisher;
           tokio::select! {
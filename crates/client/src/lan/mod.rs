@@ -12,8 +12,9 @@ use crate::error::*;
 use crate::game::LocalGameInfo;
 use crate::node::stream::NodeStreamEvent;
 use crate::node::NodeInfo;
-use crate::platform::{CalcMapChecksum, Platform};
+use crate::platform::{CalcMapChecksum, GetClientPlatformInfo, Platform};
 use crate::StartConfig;
+use flo_lan::LanDiscovery;
 use flo_state::{
   async_trait, Actor, Addr, Context, Deferred, Handler, Message, RegistryRef, Service,
 };
@@ -46,6 +47,7 @@ pub struct ReplaceLanGame {
   pub node: Arc<NodeInfo>,
   pub player_token: Vec<u8>,
   pub game: Arc<LocalGameInfo>,
+  pub game_setting_flags: u32,
 }
 
 impl Message for ReplaceLanGame {
@@ -62,6 +64,7 @@ impl Handler<ReplaceLanGame> for Lan {
       node,
       player_token,
       game,
+      game_setting_flags,
     }: ReplaceLanGame,
   ) -> <ReplaceLanGame as Message>::Result {
     let game_id = game.game_id;
@@ -87,11 +90,20 @@ impl Handler<ReplaceLanGame> for Lan {
         last_game.shutdown();
       }
 
+      let discovery = self
+        .platform
+        .send(GetClientPlatformInfo::default())
+        .await?
+        .map(|info| LanDiscovery::from_game_version(&info.version))
+        .map_err(|_| Error::War3NotLocated)?;
+
       let lan_game = LanGame::create(
+        discovery,
         my_player_id,
         node,
         player_token,
         game,
+        game_setting_flags,
         checksum,
         self.client.resolve().await?,
       )
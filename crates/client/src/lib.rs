@@ -2,6 +2,7 @@ mod controller;
 pub mod error;
 mod game;
 mod lan;
+mod map;
 mod message;
 mod node;
 pub mod observer;
@@ -0,0 +1,48 @@
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::error::*;
+
+const MAX_MAP_FILE_SIZE: u64 = 128 * 1024 * 1024;
+
+/// Downloads a map from the controller map catalogue and saves it to `target`
+pub async fn download_map(domain: &str, sha1: [u8; 20], target: PathBuf) -> Result<()> {
+  let sha1_hex: String = sha1.iter().map(|b| format!("{:02x}", b)).collect();
+  let url = format!(
+    "http://{}:{}/maps/{}",
+    domain,
+    flo_constants::CONTROLLER_HTTP_PORT,
+    sha1_hex
+  );
+  tracing::info!("downloading map: {} -> {}", url, target.display());
+
+  tokio::task::spawn_blocking(move || -> Result<()> {
+    let res = ureq::get(&url)
+      .call()
+      .map_err(|err| Error::MapDownload(err.to_string()))?;
+    let mut bytes = vec![];
+    res
+      .into_reader()
+      .take(MAX_MAP_FILE_SIZE + 1)
+      .read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_MAP_FILE_SIZE {
+      return Err(Error::MapDownload("file too large".to_string()));
+    }
+
+    let (_, checksum) = flo_w3map::W3Map::open_memory_with_checksum(&bytes)?;
+    if checksum.sha1 != sha1 {
+      return Err(Error::MapChecksumMismatch);
+    }
+
+    if let Some(dir) = target.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    let tmp = target.with_extension("download");
+    std::fs::write(&tmp, &bytes)?;
+    std::fs::rename(&tmp, &target)?;
+    Ok(())
+  })
+  .await??;
+
+  Ok(())
+}
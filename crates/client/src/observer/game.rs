@@ -2,7 +2,7 @@ use super::send_queue::SendQueue;
 use crate::error::{Error, Result};
 use crate::lan::game::slot::{LanSlotInfo, SelfPlayer};
 use crate::platform::{GetClientPlatformInfo, OpenMap, Platform};
-use flo_lan::MdnsPublisher;
+use flo_lan::{LanDiscovery, LanPublisher};
 use flo_observer::record::GameRecordData;
 use flo_state::Addr;
 use flo_types::observer::GameInfo;
@@ -119,7 +119,8 @@ where
       game_info
     };
 
-    let _p = MdnsPublisher::start(lan_game_info).await?;
+    let discovery = LanDiscovery::from_game_version(&self.info.game_version);
+    let _p = LanPublisher::start(discovery, lan_game_info).await?;
     let slot_info = crate::lan::game::slot::build_player_slot_info(
       SelfPlayer::StreamObserver,
       self.info.random_seed,
@@ -11,6 +11,7 @@ use futures::future::{abortable, AbortHandle};
 use futures::FutureExt;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub struct Platform {
@@ -152,6 +153,51 @@ impl Handler<CalcMapChecksum> for Platform {
   }
 }
 
+/// Resolves where a map missing from the local storage should be downloaded to,
+/// returns `None` if the map is already available
+pub struct GetMapDownloadPath {
+  pub path: String,
+}
+
+impl Message for GetMapDownloadPath {
+  type Result = Result<Option<PathBuf>>;
+}
+
+#[async_trait]
+impl Handler<GetMapDownloadPath> for Platform {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    GetMapDownloadPath { path }: GetMapDownloadPath,
+  ) -> <GetMapDownloadPath as Message>::Result {
+    let lower = path.to_lowercase();
+    if !lower.starts_with("maps\\")
+      || path
+        .split(|c| c == '\\' || c == '/')
+        .any(|s| s.is_empty() || s == "." || s == ".." || s.contains(':'))
+    {
+      return Err(Error::InvalidMapInfo);
+    }
+
+    let exists = self
+      .with_storage(|storage| Ok(storage.resolve_file(&path)?.is_some()))
+      .await?;
+    if exists {
+      return Ok(None);
+    }
+
+    let user_data_path = match self.info.as_ref() {
+      Ok(info) => info.user_data_path.clone(),
+      Err(_) => return Err(Error::War3NotLocated),
+    };
+    Ok(Some(
+      path
+        .split('\\')
+        .fold(user_data_path, |path, segment| path.join(segment)),
+    ))
+  }
+}
+
 pub struct OpenMap {
   pub path: String,
 }
@@ -5,6 +5,7 @@ pub const CONTROLLER_HOST: &str = "service.w3flo.com";
 pub const STATS_HOST: &str = "stats.w3flo.com";
 pub const CONTROLLER_GRPC_PORT: u16 = 3549;
 pub const CONTROLLER_SOCKET_PORT: u16 = 3550;
+pub const CONTROLLER_HTTP_PORT: u16 = 3559;
 pub const CLIENT_WS_PORT: u16 = 3551;
 pub const CLIENT_ORIGINS: &[&str] = &[
   "http://localhost:3000",
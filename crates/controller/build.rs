@@ -22,5 +22,7 @@ fn main() {
       version_str = pkg_version
     ),
   )
-    .unwrap()
+    .unwrap();
+
+  tonic_build::compile_protos("src/proto/controller_ext.proto").unwrap();
 }
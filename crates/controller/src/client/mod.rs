@@ -13,15 +13,24 @@ use crate::state::{ActorMapExt, ControllerStateRef};
 
 mod handshake;
 mod sender;
-use crate::game::messages::{ResolveGamePlayerPingBroadcastTargets, UpdateSlot};
+use crate::game::db::JoinAccess;
+use crate::game::messages::{
+  AddGamePlayer, BalanceTeams, BalanceTeamsBy, LockSlot, PlayerJoin, ReadyCheckResponse,
+  RequestSlotSwap, ResolveGamePlayerPingBroadcastTargets, RespondSlotSwap, ShuffleTeams,
+  StartReadyCheck, TransferHost, UpdateGameAccess, UpdateGameInvites, UpdateLobbySettings,
+  UpdateNodeSettings, UpdateSlot,
+};
 use crate::game::state::node::SelectNode;
 use crate::game::state::player::GetGamePlayers;
 use crate::game::state::registry::UpdateGameNodeCache;
 use crate::game::state::start::{StartGameCheck, StartGamePlayerAck};
-use crate::game::SlotSettings;
+use crate::game::{GameLagSettings, GameLobbySettings, GameStepSettings, SlotSettings};
+use crate::matchmaking::messages::{JoinQueue, LeaveQueue};
 use crate::node::messages::ListNode;
 use crate::player::state::conn::{Connect, Disconnect};
 use crate::player::state::ping::{GetPlayersPingSnapshot, UpdatePing};
+use crate::schedule::messages::CheckIn;
+use crate::tournament::messages::PickMap;
 use flo_net::ping::{PingMsg, PingStream};
 use flo_types::ping::PingStats;
 use futures::{StreamExt, TryStreamExt};
@@ -70,6 +79,7 @@ pub async fn serve(state: ControllerStateRef) -> Result<()> {
         tracing::debug!("stream error: {}", err);
       }
 
+      state.matchmaking.send(LeaveQueue { player_id }).await?;
       state.players.send(Disconnect { player_id }).await?;
       tracing::debug!("exiting: player_id = {}", player_id);
       Ok::<_, crate::error::Error>(())
@@ -167,6 +177,57 @@ async fn handle_stream(
             packet: proto::flo_connect::PacketPlayerMuteRemoveRequest => {
               handle_player_mute_list_update_request(state.clone(), player_id, packet.into()).await?;
             }
+            packet: proto::flo_connect::PacketMatchmakingJoinRequest => {
+              handle_matchmaking_join_request(state.clone(), player_id, packet).await?;
+            }
+            _packet: proto::flo_connect::PacketMatchmakingLeaveRequest => {
+              handle_matchmaking_leave_request(state.clone(), player_id).await?;
+            }
+            packet: proto::flo_connect::PacketGameSlotShuffleRequest => {
+              handle_game_slot_shuffle_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameSlotBalanceRequest => {
+              handle_game_slot_balance_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameSlotSwapRequest => {
+              handle_game_slot_swap_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameSlotSwapResponse => {
+              handle_game_slot_swap_response(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameSlotLockRequest => {
+              handle_game_slot_lock_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameReadyCheckRequest => {
+              handle_game_ready_check_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameReadyCheckResponse => {
+              handle_game_ready_check_response(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameHostTransferRequest => {
+              handle_game_host_transfer_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameJoinRequest => {
+              handle_game_join_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameAccessUpdateRequest => {
+              handle_game_access_update_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameInviteRequest => {
+              handle_game_invite_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketScheduledGameCheckInRequest => {
+              handle_scheduled_game_check_in_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameLobbySettingsUpdateRequest => {
+              handle_game_lobby_settings_update_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketGameNodeSettingsUpdateRequest => {
+              handle_game_node_settings_update_request(state.clone(), player_id, packet).await?;
+            }
+            packet: proto::flo_connect::PacketTournamentMapPickRequest => {
+              handle_tournament_map_pick_request(state.clone(), player_id, packet).await?;
+            }
           }
         }
       }
@@ -224,7 +285,7 @@ async fn send_initial_state(
   let mut frames = vec![frame_accept];
 
   if let Some(game_id) = game_id {
-    let (mut game, node_player_token) = state
+    let (mut game, node_player_token, lobby_settings) = state
       .db
       .exec(move |conn| crate::game::db::get_full_and_node_token(conn, game_id, player_id))
       .await?;
@@ -250,6 +311,7 @@ async fn send_initial_state(
         game_id,
         player_id,
         player_token: player_token.to_vec(),
+        game_setting_flags: lobby_settings.game_setting_flags().bits(),
       }
       .encode_as_frame()?;
       frames.push(frame);
@@ -461,3 +523,381 @@ async fn handle_player_mute_list_update_request(
     .await?;
   Ok(())
 }
+
+async fn handle_matchmaking_join_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketMatchmakingJoinRequest,
+) -> Result<()> {
+  use proto::flo_connect::{MatchmakingStatus, PacketMatchmakingStatus};
+
+  let queue_id = packet.queue_id;
+  let packet = match state
+    .matchmaking
+    .send(JoinQueue {
+      player_id,
+      queue_id,
+    })
+    .await?
+  {
+    Ok(rating) => PacketMatchmakingStatus {
+      queue_id,
+      status: MatchmakingStatus::Queued.into(),
+      rating,
+      ..Default::default()
+    },
+    Err(err) => PacketMatchmakingStatus {
+      queue_id,
+      status: MatchmakingStatus::Rejected.into(),
+      message: err.to_string(),
+      ..Default::default()
+    },
+  };
+
+  state
+    .player_packet_sender
+    .send(player_id, packet.encode_as_frame()?)
+    .await?;
+  Ok(())
+}
+
+async fn handle_matchmaking_leave_request(state: ControllerStateRef, player_id: i32) -> Result<()> {
+  use proto::flo_connect::{MatchmakingStatus, PacketMatchmakingStatus};
+
+  if let Some(queue_id) = state.matchmaking.send(LeaveQueue { player_id }).await? {
+    state
+      .player_packet_sender
+      .send(
+        player_id,
+        PacketMatchmakingStatus {
+          queue_id,
+          status: MatchmakingStatus::Left.into(),
+          ..Default::default()
+        }
+        .encode_as_frame()?,
+      )
+      .await?;
+  }
+  Ok(())
+}
+
+async fn handle_game_slot_shuffle_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameSlotShuffleRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(packet.game_id, ShuffleTeams { player_id })
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_slot_balance_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameSlotBalanceRequest,
+) -> Result<()> {
+  use proto::flo_connect::GameSlotBalanceMode;
+
+  let by = match packet.mode() {
+    GameSlotBalanceMode::Rating => BalanceTeamsBy::Rating {
+      pool_id: packet.rating_pool_id.clone(),
+    },
+    GameSlotBalanceMode::Ping => BalanceTeamsBy::Ping,
+  };
+  state
+    .games
+    .send_to(packet.game_id, BalanceTeams { player_id, by })
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_slot_swap_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameSlotSwapRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      RequestSlotSwap {
+        player_id,
+        slot_index_a: packet.slot_index_a,
+        slot_index_b: packet.slot_index_b,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_slot_swap_response(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameSlotSwapResponse,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      RespondSlotSwap {
+        player_id,
+        accept: packet.accept,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_slot_lock_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameSlotLockRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      LockSlot {
+        player_id,
+        slot_index: packet.slot_index,
+        locked: packet.locked,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_ready_check_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameReadyCheckRequest,
+) -> Result<()> {
+  let timeout = if packet.timeout_seconds > 0 {
+    Some(Duration::from_secs(packet.timeout_seconds as u64))
+  } else {
+    None
+  };
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      StartReadyCheck {
+        player_id,
+        timeout,
+        kick_unready: packet.kick_unready,
+        registry: state.games.clone(),
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_ready_check_response(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameReadyCheckResponse,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      ReadyCheckResponse {
+        player_id,
+        ready: packet.ready,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_host_transfer_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameHostTransferRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      TransferHost {
+        player_id,
+        to_player_id: packet.player_id,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_join_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameJoinRequest,
+) -> Result<()> {
+  use proto::flo_connect::{GameJoinRejectReason, PacketGameJoinReject};
+
+  let game_id = packet.game_id;
+  let res = state
+    .games
+    .send_to(
+      game_id,
+      PlayerJoin {
+        player_id,
+        access: JoinAccess::Password(Some(packet.password)),
+      },
+    )
+    .await;
+
+  let err = match res {
+    Ok(_) => {
+      state
+        .games
+        .send(AddGamePlayer { game_id, player_id })
+        .await?;
+      return Ok(());
+    }
+    Err(err) => err,
+  };
+
+  let reason = match err {
+    Error::GamePasswordInvalid => GameJoinRejectReason::PasswordInvalid,
+    Error::GameInviteRequired => GameJoinRejectReason::InviteRequired,
+    Error::GameFull => GameJoinRejectReason::GameFull,
+    Error::GameStarted => GameJoinRejectReason::GameStarted,
+    _ => GameJoinRejectReason::Unknown,
+  };
+  let mut reject = PacketGameJoinReject {
+    game_id,
+    message: err.to_string(),
+    ..Default::default()
+  };
+  reject.set_reason(reason);
+  state
+    .player_packet_sender
+    .send(player_id, reject.encode_as_frame()?)
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_access_update_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameAccessUpdateRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      UpdateGameAccess {
+        player_id,
+        password: packet.password,
+        invite_only: packet.invite_only,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_invite_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameInviteRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      UpdateGameInvites {
+        player_id,
+        add_player_ids: packet.add_player_ids,
+        remove_player_ids: packet.remove_player_ids,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_lobby_settings_update_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameLobbySettingsUpdateRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      UpdateLobbySettings {
+        player_id,
+        settings: GameLobbySettings::unpack(packet.settings.extract()?)?,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_node_settings_update_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameNodeSettingsUpdateRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      UpdateNodeSettings {
+        player_id,
+        lag: packet.lag.map(GameLagSettings::unpack).transpose()?,
+        step: packet.step.map(GameStepSettings::unpack).transpose()?,
+        add_referee_player_ids: packet.add_referee_player_ids,
+        remove_referee_player_ids: packet.remove_referee_player_ids,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_scheduled_game_check_in_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketScheduledGameCheckInRequest,
+) -> Result<()> {
+  let scheduled_game_id = packet.scheduled_game_id;
+  let res = state
+    .schedules
+    .send(CheckIn {
+      player_id,
+      scheduled_game_id,
+    })
+    .await?;
+
+  // the updated status is broadcasted by the scheduler on success
+  if let Err(err) = res {
+    let packet = proto::flo_connect::PacketScheduledGameStatus {
+      scheduled_game_id,
+      message: err.to_string(),
+      ..Default::default()
+    };
+    state
+      .player_packet_sender
+      .send(player_id, packet.encode_as_frame()?)
+      .await?;
+  }
+  Ok(())
+}
+
+async fn handle_tournament_map_pick_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketTournamentMapPickRequest,
+) -> Result<()> {
+  state
+    .tournaments
+    .send(PickMap {
+      player_id,
+      match_id: packet.match_id,
+      map_index: packet.map_index as usize,
+    })
+    .await??;
+  Ok(())
+}
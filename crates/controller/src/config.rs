@@ -78,9 +78,34 @@ impl Handler<GetInterceptor> for ConfigStorage {
   }
 }
 
+pub struct GetApiClientIdBySecret {
+  pub secret: Vec<u8>,
+}
+
+impl Message for GetApiClientIdBySecret {
+  type Result = Option<i32>;
+}
+
+#[async_trait]
+impl Handler<GetApiClientIdBySecret> for ConfigStorage {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    GetApiClientIdBySecret { secret }: GetApiClientIdBySecret,
+  ) -> <GetApiClientIdBySecret as Message>::Result {
+    self
+      .api_client_map
+      .load()
+      .get(&secret)
+      .map(|client| client.id)
+  }
+}
+
 pub const REQUEST_META_SECRET: &str = "x-flo-secret";
 pub const REQUEST_META_API_CLIENT_ID: &str = "x-flo-api-client-id-bin";
 pub const REQUEST_META_API_PLAYER_ID: &str = "x-flo-api-player-id-bin";
+/// Rating pool of games created with `CreateGame` or `CreateGameAsBot`
+pub const REQUEST_META_RATING_POOL_ID: &str = "x-flo-rating-pool-id";
 
 #[derive(Clone)]
 pub struct FloGrpcInterceptor {
@@ -58,8 +58,34 @@ pub enum Error {
   GameStarted,
   #[error("Game not in starting state")]
   GameNotStarting,
+  #[error("Slot swap request not found")]
+  GameSlotSwapNotFound,
+  #[error("Ready check not found")]
+  GameReadyCheckNotFound,
+  #[error("Invalid game password")]
+  GamePasswordInvalid,
+  #[error("Game is invite only")]
+  GameInviteRequired,
+  #[error("Invalid lobby settings: {0}")]
+  GameLobbySettingsInvalid(&'static str),
+  #[error("Invalid node settings: {0}")]
+  GameNodeSettingsInvalid(&'static str),
   #[error("This map has no player slot")]
   MapHasNoPlayer,
+  #[error("Map not found")]
+  MapVersionNotFound,
+  #[error("Map pool not found")]
+  MapPoolNotFound,
+  #[error("Invalid map file: {0}")]
+  MapFileInvalid(String),
+  #[error("Map file too large")]
+  MapFileTooLarge,
+  #[error("Map was flagged by the security scan")]
+  MapFlagged,
+  #[error("Invalid map upload: {0}")]
+  MapUploadInvalid(&'static str),
+  #[error("Invalid api client secret")]
+  ApiClientSecretInvalid,
   #[error("Player not in game")]
   PlayerNotInGame,
   #[error("Player already in game")]
@@ -86,6 +112,38 @@ pub enum Error {
   PlayerTeamInvalid,
   #[error("Player not belongs to the current API client")]
   PlayerOwnerCheckFailed,
+  #[error("Matchmaking queue not found")]
+  MatchmakingQueueNotFound,
+  #[error("Invalid matchmaking queue: {0}")]
+  MatchmakingQueueInvalid(&'static str),
+  #[error("Player already in a matchmaking queue")]
+  PlayerAlreadyInQueue,
+  #[error("No map in the map pool fits the matchmaking queue")]
+  MatchmakingMapNotAvailable,
+  #[error("Matchmaking game start rejected: {0}")]
+  MatchmakingGameStartRejected(String),
+  #[error("Rating pool not found")]
+  RatingPoolNotFound,
+  #[error("Rating pool name is empty or already used")]
+  RatingPoolNameInvalid,
+  #[error("Scheduled game not found")]
+  ScheduledGameNotFound,
+  #[error("Invalid schedule: {0}")]
+  ScheduledGameTimeInvalid(&'static str),
+  #[error("Scheduled game check-in is not open")]
+  ScheduledGameCheckInClosed,
+  #[error("Player is not assigned to the scheduled game")]
+  ScheduledGamePlayerNotAssigned,
+  #[error("Scheduled game start rejected: {0}")]
+  ScheduledGameStartRejected(String),
+  #[error("Tournament not found")]
+  TournamentNotFound,
+  #[error("Invalid tournament: {0}")]
+  TournamentInvalid(&'static str),
+  #[error("Tournament match not found")]
+  TournamentMatchNotFound,
+  #[error("Map pick denied")]
+  TournamentMapPickDenied,
   #[error("Operation timeout: {0}")]
   Timeout(anyhow::Error),
   #[error("net: {0}")]
@@ -102,6 +160,10 @@ pub enum Error {
   Proto(#[from] s2_grpc_utils::result::Error),
   #[error("gRPC transport: {0}")]
   GrpcTransport(#[from] tonic::transport::Error),
+  #[error("http: {0}")]
+  Http(#[from] hyper::Error),
+  #[error("io: {0}")]
+  Io(#[from] std::io::Error),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -124,8 +186,26 @@ impl From<Error> for Status {
       e @ Error::GameNotFound
       | e @ Error::PlayerNotFound
       | e @ Error::MapHasNoPlayer
+      | e @ Error::MapVersionNotFound
+      | e @ Error::MapPoolNotFound
+      | e @ Error::MapFileInvalid(_)
+      | e @ Error::MapFileTooLarge
+      | e @ Error::MapFlagged
+      | e @ Error::MapUploadInvalid(_)
       | e @ Error::GameFull
       | e @ Error::GameNotCancellable
+      | e @ Error::MatchmakingQueueNotFound
+      | e @ Error::MatchmakingQueueInvalid(_)
+      | e @ Error::RatingPoolNotFound
+      | e @ Error::RatingPoolNameInvalid
+      | e @ Error::ScheduledGameNotFound
+      | e @ Error::ScheduledGameTimeInvalid(_)
+      | e @ Error::TournamentNotFound
+      | e @ Error::TournamentInvalid(_)
+      | e @ Error::GamePasswordInvalid
+      | e @ Error::GameInviteRequired
+      | e @ Error::GameLobbySettingsInvalid(_)
+      | e @ Error::GameNodeSettingsInvalid(_)
       | e @ Error::JoinTokenExpired => Status::invalid_argument(e.to_string()),
       e @ Error::PlayerTokenExpired => Status::unauthenticated(e.to_string()),
       Error::JsonWebToken(e) => Status::unauthenticated(e.to_string()),
@@ -4,15 +4,16 @@ use diesel::prelude::*;
 use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack, S2ProtoUnpack};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use crate::db::DbConn;
 use crate::error::*;
 use crate::game::slots::{UsedSlot, UsedSlotInfo};
 use crate::game::state::GameStatusUpdate;
 use crate::game::{
-  Computer, CreateGameSlot, Game, GameEntry, GameStatus, Race, Slot, SlotClientStatus,
-  SlotSettings, SlotStatus, Slots,
+  Computer, CreateGameSlot, Game, GameDesync, GameEntry, GameLobbySettings, GameNodeSettings,
+  GameStatus, PlayerGameResult, Race, Slot, SlotClientStatus, SlotSettings, SlotStatus, Slots,
+  UNKNOWN_MAP_FLAGS,
 };
 use crate::map::Map;
 use crate::node::{NodeRef, NodeRefColumns, PlayerToken};
@@ -168,7 +169,14 @@ pub struct CreateGameParams {
 }
 
 /// Creates a game, make the creator as the first player
-pub fn create(conn: &DbConn, params: CreateGameParams) -> Result<Game> {
+///
+/// A rated game must be created by the API client of its creator.
+pub fn create(
+  conn: &DbConn,
+  api_client_id: i32,
+  rating_pool_id: Option<i32>,
+  params: CreateGameParams,
+) -> Result<Game> {
   let max_players = params.map.players.len();
 
   if max_players == 0 {
@@ -176,12 +184,32 @@ pub fn create(conn: &DbConn, params: CreateGameParams) -> Result<Game> {
   }
 
   let player = crate::player::db::get_ref(conn, params.player_id)?;
+  let player_api_client_id: i32 = player::table
+    .find(params.player_id)
+    .select(player::api_client_id)
+    .first(conn)?;
+  if let Some(pool_id) = rating_pool_id {
+    if player_api_client_id != api_client_id {
+      return Err(Error::PlayerOwnerCheckFailed);
+    }
+    crate::rating::db::get_pool(conn, api_client_id, pool_id)?;
+  }
   let mut slots = Slots::new(max_players);
   slots.join(&player);
 
   let meta = Meta {
     map: params.map,
     created_by: player.into(),
+    api_client_id: Some(player_api_client_id),
+    rating_pool_id,
+    node_settings: Default::default(),
+    desyncs: vec![],
+    player_results: BTreeMap::new(),
+    locked_slots: BTreeSet::new(),
+    password_hash: None,
+    invite_only: false,
+    invited_player_ids: BTreeSet::new(),
+    lobby_settings: Default::default(),
   };
 
   let meta_value = serde_json::to_value(&meta)?;
@@ -229,15 +257,19 @@ pub fn create_as_bot(
   conn: &DbConn,
   api_client_id: i32,
   api_player_id: i32,
+  rating_pool_id: Option<i32>,
   params: CreateGameAsBotParams,
 ) -> Result<Game> {
-  use std::collections::{BTreeMap, BTreeSet};
   let max_players = params.map.players.len();
 
   if max_players == 0 {
     return Err(Error::MapHasNoPlayer);
   }
 
+  if let Some(pool_id) = rating_pool_id {
+    crate::rating::db::get_pool(conn, api_client_id, pool_id)?;
+  }
+
   if params.slots.len() > 24 {
     return Err(Error::TooManyPlayers);
   }
@@ -322,6 +354,16 @@ pub fn create_as_bot(
       .remove(&api_player_id)
       .ok_or_else(|| Error::PlayerNotFound)?
       .into(),
+    api_client_id: Some(api_client_id),
+    rating_pool_id,
+    node_settings: Default::default(),
+    desyncs: vec![],
+    player_results: BTreeMap::new(),
+    locked_slots: BTreeSet::new(),
+    password_hash: None,
+    invite_only: false,
+    invited_player_ids: BTreeSet::new(),
+    lobby_settings: Default::default(),
   };
 
   let meta_value = serde_json::to_value(&meta)?;
@@ -384,14 +426,213 @@ pub fn add_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<Vec<Slo
   Ok(slots.into_inner())
 }
 
+/// How a player joins a game
+#[derive(Debug, Clone)]
+pub enum JoinAccess {
+  /// With the lobby password, if the lobby has one
+  Password(Option<String>),
+  /// With a join token created by the host
+  Token,
+}
+
+/// Invited players and token holders bypass the password
+pub fn check_join_access(
+  conn: &DbConn,
+  game_id: i32,
+  player_id: i32,
+  access: &JoinAccess,
+) -> Result<()> {
+  let (host_player_id, meta): (i32, Value) = game::table
+    .find(game_id)
+    .select((game::dsl::created_by, game::dsl::meta))
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  let meta: Meta = serde_json::from_value(meta)?;
+
+  let password = match access {
+    JoinAccess::Token => return Ok(()),
+    JoinAccess::Password(password) => password,
+  };
+
+  if player_id == host_player_id || meta.invited_player_ids.contains(&player_id) {
+    return Ok(());
+  }
+
+  if meta.invite_only {
+    return Err(Error::GameInviteRequired);
+  }
+
+  if let Some(hashed) = meta.password_hash.as_ref() {
+    match password {
+      Some(password) if crate::game::password::verify_password(hashed, password) => {}
+      _ => return Err(Error::GamePasswordInvalid),
+    }
+  }
+
+  Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct UpdateGameAccessParams {
+  /// `None` keeps the current password, an empty string removes it
+  pub password: Option<String>,
+  pub invite_only: Option<bool>,
+}
+
+pub fn update_access(
+  conn: &DbConn,
+  game_id: i32,
+  host_player_id: i32,
+  params: UpdateGameAccessParams,
+) -> Result<()> {
+  let password_hash = params.password.map(|password| {
+    if password.is_empty() {
+      None
+    } else {
+      Some(crate::game::password::hash_password(&password))
+    }
+  });
+
+  conn.transaction(|| {
+    let (created_by, meta): (i32, Value) = game::table
+      .find(game_id)
+      .select((game::dsl::created_by, game::dsl::meta))
+      .for_update()
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::GameNotFound)?;
+    if created_by != host_player_id {
+      return Err(Error::PlayerNotHost);
+    }
+    let mut meta: Meta = serde_json::from_value(meta)?;
+    if let Some(password_hash) = password_hash {
+      meta.password_hash = password_hash;
+    }
+    if let Some(invite_only) = params.invite_only {
+      meta.invite_only = invite_only;
+    }
+    diesel::update(game::table.find(game_id))
+      .set(game::dsl::meta.eq(serde_json::to_value(&meta)?))
+      .execute(conn)?;
+    Ok(())
+  })
+}
+
+pub fn get_lobby_settings(conn: &DbConn, game_id: i32) -> Result<GameLobbySettings> {
+  let meta: Value = game::table
+    .find(game_id)
+    .select(game::dsl::meta)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  let meta: Meta = serde_json::from_value(meta)?;
+  Ok(meta.lobby_settings)
+}
+
+/// Updates the lobby settings and clamps the slot handicaps to the new range
+///
+/// Settings are checked against the flags of the map in the catalogue.
+pub fn update_lobby_settings(
+  conn: &DbConn,
+  game_id: i32,
+  host_player_id: i32,
+  settings: GameLobbySettings,
+) -> Result<UpdateSlotSettings> {
+  conn.transaction(|| {
+    let (created_by, meta): (i32, Value) = game::table
+      .find(game_id)
+      .select((game::dsl::created_by, game::dsl::meta))
+      .for_update()
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::GameNotFound)?;
+    if created_by != host_player_id {
+      return Err(Error::PlayerNotHost);
+    }
+    let mut meta: Meta = serde_json::from_value(meta)?;
+    let map_flags =
+      crate::map::db::find_map_flags(conn, &meta.map.sha1)?.unwrap_or(UNKNOWN_MAP_FLAGS);
+    settings.validate(map_flags)?;
+    meta.lobby_settings = settings.clone();
+    diesel::update(game::table.find(game_id))
+      .set(game::dsl::meta.eq(serde_json::to_value(&meta)?))
+      .execute(conn)?;
+    update_slots(conn, game_id, |slots| {
+      Ok(slots.clamp_handicaps(|handicap| settings.clamp_handicap(handicap)))
+    })
+  })
+}
+
+#[derive(Debug)]
+pub struct UpdatedGameInvites {
+  pub game_name: String,
+  pub host: PlayerRef,
+  /// Players not invited before
+  pub invited_player_ids: Vec<i32>,
+}
+
+pub fn update_invites(
+  conn: &DbConn,
+  game_id: i32,
+  host_player_id: i32,
+  add_player_ids: Vec<i32>,
+  remove_player_ids: Vec<i32>,
+) -> Result<UpdatedGameInvites> {
+  conn.transaction(|| {
+    let (game_name, created_by, meta): (String, i32, Value) = game::table
+      .find(game_id)
+      .select((game::dsl::name, game::dsl::created_by, game::dsl::meta))
+      .for_update()
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::GameNotFound)?;
+    if created_by != host_player_id {
+      return Err(Error::PlayerNotHost);
+    }
+    let mut meta: Meta = serde_json::from_value(meta)?;
+
+    for player_id in &remove_player_ids {
+      meta.invited_player_ids.remove(player_id);
+    }
+
+    let mut invited_player_ids = vec![];
+    for player in crate::player::db::get_refs_by_ids(conn, &add_player_ids)? {
+      if player.id != host_player_id && meta.invited_player_ids.insert(player.id) {
+        invited_player_ids.push(player.id);
+      }
+    }
+
+    diesel::update(game::table.find(game_id))
+      .set(game::dsl::meta.eq(serde_json::to_value(&meta)?))
+      .execute(conn)?;
+
+    Ok(UpdatedGameInvites {
+      game_name,
+      host: crate::player::db::get_ref(conn, host_player_id)?,
+      invited_player_ids,
+    })
+  })
+}
+
 #[derive(Debug)]
 pub struct LeaveGame {
   pub game_ended: bool,
   pub removed_players: Vec<i32>,
   pub slots: Vec<Slot>,
+  /// Set if the host left and the game was handed over
+  pub new_host: Option<PlayerRef>,
 }
 
-pub fn remove_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<LeaveGame> {
+/// Removes a player from a game.
+/// If the host leaves, the game is handed over to `successor`,
+/// or ended if there is no successor in the game
+pub fn remove_player(
+  conn: &DbConn,
+  game_id: i32,
+  player_id: i32,
+  successor: Option<i32>,
+) -> Result<LeaveGame> {
   let InspectId { status, locked } = inspect_id(conn, game_id)?;
 
   if locked {
@@ -407,8 +648,10 @@ pub fn remove_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<Leav
     host_player_id,
   } = get_slots(conn, game_id)?;
 
-  // host left, kick all players
-  if player_id == host_player_id {
+  let successor = successor.filter(|id| *id != player_id && slots.find_player_slot(*id).is_some());
+
+  if player_id == host_player_id && successor.is_none() {
+    // host left, kick all players
     let removed = slots.release_all_player_slots();
     upsert_used_slots(conn, game_id, slots.as_used())?;
     end_game(conn, game_id, GameStatus::Ended)?;
@@ -416,10 +659,12 @@ pub fn remove_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<Leav
       game_ended: true,
       removed_players: removed,
       slots: slots.into_inner(),
+      new_host: None,
     })
   } else {
     let mut ended = false;
     let mut removed_players = Vec::with_capacity(1);
+    let mut new_host = None;
     if slots.release_player_slot(player_id) {
       removed_players.push(player_id);
       upsert_used_slots(conn, game_id, slots.as_used())?;
@@ -428,14 +673,71 @@ pub fn remove_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<Leav
         end_game(conn, game_id, GameStatus::Ended)?;
       }
     }
+    if player_id == host_player_id && !ended {
+      if let Some(successor) = successor {
+        new_host = Some(set_host(conn, game_id, successor)?);
+      }
+    }
     Ok(LeaveGame {
       game_ended: ended,
       removed_players,
       slots: slots.into_inner(),
+      new_host,
     })
   }
 }
 
+/// Hands the ownership of a preparing game over to another player in the game
+pub fn transfer_host(
+  conn: &DbConn,
+  game_id: i32,
+  host_player_id: i32,
+  player_id: i32,
+) -> Result<PlayerRef> {
+  conn.transaction(|| {
+    let InspectId { status, .. } = inspect_id(conn, game_id)?;
+
+    if status != GameStatus::Preparing {
+      return Err(Error::GameStarted);
+    }
+
+    let GetSlots {
+      slots,
+      host_player_id: current_host_player_id,
+    } = get_slots(conn, game_id)?;
+
+    if current_host_player_id != host_player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    if player_id == host_player_id || slots.find_player_slot(player_id).is_none() {
+      return Err(Error::PlayerNotInGame);
+    }
+
+    set_host(conn, game_id, player_id)
+  })
+}
+
+fn set_host(conn: &DbConn, game_id: i32, player_id: i32) -> Result<PlayerRef> {
+  let player = crate::player::db::get_ref(conn, player_id)?;
+  let meta: Value = game::table
+    .find(game_id)
+    .select(game::dsl::meta)
+    .for_update()
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  let mut meta: Meta = serde_json::from_value(meta)?;
+  meta.created_by = Some(player.clone());
+  diesel::update(game::table.find(game_id))
+    .set((
+      game::dsl::created_by.eq(player_id),
+      game::dsl::meta.eq(serde_json::to_value(&meta)?),
+    ))
+    .execute(conn)?;
+  Ok(player)
+}
+
 #[derive(Queryable)]
 struct InspectId {
   status: GameStatus,
@@ -547,6 +849,76 @@ pub fn update_slot_settings(
   })
 }
 
+/// Apply a bulk operation to the slots of a preparing game,
+/// `f` returns the indexes of the updated slots
+pub fn update_slots<F>(conn: &DbConn, game_id: i32, f: F) -> Result<UpdateSlotSettings>
+where
+  F: FnOnce(&mut Slots) -> Result<Vec<i32>>,
+{
+  let InspectId { status, locked } = inspect_id(conn, game_id)?;
+
+  if locked {
+    return Err(Error::GameSlotUpdateDenied);
+  }
+
+  if status != GameStatus::Preparing {
+    return Err(Error::GameStarted);
+  }
+
+  let mut slots = get_slots(conn, game_id)?.slots;
+  let updated_indexes = f(&mut slots)?;
+  for index in &updated_indexes {
+    sync_slot_at(conn, game_id, *index, &slots[*index as usize])?;
+  }
+  Ok(UpdateSlotSettings {
+    slots: slots.into_inner(),
+    updated_indexes,
+  })
+}
+
+pub fn get_locked_slots(conn: &DbConn, game_id: i32) -> Result<BTreeSet<i32>> {
+  let meta: Value = game::table
+    .find(game_id)
+    .select(game::dsl::meta)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  let meta: Meta = serde_json::from_value(meta)?;
+  Ok(meta.locked_slots)
+}
+
+/// Returns `false` if the lock state is unchanged
+pub fn set_slot_locked(conn: &DbConn, game_id: i32, slot_index: i32, locked: bool) -> Result<bool> {
+  if slot_index < 0 || slot_index > 23 {
+    return Err(Error::GameSlotUpdateDenied);
+  }
+
+  conn.transaction(|| {
+    let (status, meta): (GameStatus, Value) = game::table
+      .find(game_id)
+      .select((game::dsl::status, game::dsl::meta))
+      .for_update()
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::GameNotFound)?;
+    if status != GameStatus::Preparing {
+      return Err(Error::GameStarted);
+    }
+    let mut meta: Meta = serde_json::from_value(meta)?;
+    let changed = if locked {
+      meta.locked_slots.insert(slot_index)
+    } else {
+      meta.locked_slots.remove(&slot_index)
+    };
+    if changed {
+      diesel::update(game::table.find(game_id))
+        .set(game::dsl::meta.eq(serde_json::to_value(&meta)?))
+        .execute(conn)?;
+    }
+    Ok(changed)
+  })
+}
+
 fn sync_slot_at(conn: &DbConn, game_id: i32, slot_index: i32, slot: &Slot) -> Result<()> {
   use game_used_slot::dsl;
 
@@ -676,15 +1048,16 @@ struct GetSlots {
 fn get_slots(conn: &DbConn, game_id: i32) -> Result<GetSlots> {
   use game_used_slot::dsl;
 
-  let (host_player_id, max_players): (i32, i32) = {
+  let (host_player_id, max_players, meta): (i32, i32, Value) = {
     use game::dsl;
     game::table
       .find(game_id)
-      .select((dsl::created_by, dsl::max_players))
+      .select((dsl::created_by, dsl::max_players, dsl::meta))
       .first(conn)
       .optional()?
       .ok_or_else(|| Error::GameNotFound)?
   };
+  let meta: Meta = serde_json::from_value(meta)?;
 
   let used_slots: Vec<UsedSlot> = game_used_slot::table
     .left_outer_join(player::table)
@@ -692,7 +1065,8 @@ fn get_slots(conn: &DbConn, game_id: i32) -> Result<GetSlots> {
     .filter(dsl::game_id.eq(game_id))
     .load(conn)?;
 
-  let slots = Slots::from_used(max_players as usize, used_slots);
+  let mut slots = Slots::from_used(max_players as usize, used_slots);
+  slots.set_locked(meta.locked_slots);
   Ok(GetSlots {
     host_player_id,
     slots,
@@ -747,11 +1121,116 @@ pub fn get_full(conn: &DbConn, id: i32) -> Result<Game> {
   Ok(row.into_game(meta, slots)?)
 }
 
+pub fn get_node_settings(conn: &DbConn, game_id: i32) -> Result<GameNodeSettings> {
+  let meta: Value = game::table
+    .find(game_id)
+    .select(game::dsl::meta)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  let meta: Meta = serde_json::from_value(meta)?;
+  Ok(meta.node_settings)
+}
+
+pub fn update_node_settings<F>(conn: &DbConn, game_id: i32, f: F) -> Result<GameNodeSettings>
+where
+  F: FnOnce(&mut GameNodeSettings) -> Result<()>,
+{
+  conn.transaction(|| {
+    let meta: Value = game::table
+      .find(game_id)
+      .select(game::dsl::meta)
+      .for_update()
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::GameNotFound)?;
+    let mut meta: Meta = serde_json::from_value(meta)?;
+    f(&mut meta.node_settings)?;
+    diesel::update(game::table.find(game_id))
+      .set(game::dsl::meta.eq(serde_json::to_value(&meta)?))
+      .execute(conn)?;
+    Ok(meta.node_settings)
+  })
+}
+
+pub fn add_desync(conn: &DbConn, game_id: i32, desync: GameDesync) -> Result<()> {
+  conn.transaction(|| {
+    let meta: Value = game::table
+      .find(game_id)
+      .select(game::dsl::meta)
+      .for_update()
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::GameNotFound)?;
+    let mut meta: Meta = serde_json::from_value(meta)?;
+    meta.desyncs.push(desync);
+    diesel::update(game::table.find(game_id))
+      .set(game::dsl::meta.eq(serde_json::to_value(&meta)?))
+      .execute(conn)?;
+    Ok(())
+  })
+}
+
+pub fn set_player_result(
+  conn: &DbConn,
+  game_id: i32,
+  player_id: i32,
+  result: PlayerGameResult,
+) -> Result<()> {
+  conn.transaction(|| {
+    let meta: Value = game::table
+      .find(game_id)
+      .select(game::dsl::meta)
+      .for_update()
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::GameNotFound)?;
+    let mut meta: Meta = serde_json::from_value(meta)?;
+    meta.player_results.insert(player_id, result);
+    diesel::update(game::table.find(game_id))
+      .set(game::dsl::meta.eq(serde_json::to_value(&meta)?))
+      .execute(conn)?;
+    Ok(())
+  })
+}
+
+/// Returns the API client owning the game and the rating pool the game is rated in
+pub fn get_rating_pool(conn: &DbConn, game_id: i32) -> Result<(i32, Option<i32>)> {
+  let (created_by, meta): (i32, Value) = game::table
+    .find(game_id)
+    .select((game::dsl::created_by, game::dsl::meta))
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  let meta: Meta = serde_json::from_value(meta)?;
+  let api_client_id = match meta.api_client_id {
+    Some(id) => id,
+    // created before the owner was recorded
+    None => player::table
+      .find(created_by)
+      .select(player::api_client_id)
+      .first(conn)?,
+  };
+  Ok((api_client_id, meta.rating_pool_id))
+}
+
+pub fn get_player_results(conn: &DbConn, game_id: i32) -> Result<BTreeMap<i32, PlayerGameResult>> {
+  let meta: Value = game::table
+    .find(game_id)
+    .select(game::dsl::meta)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  let meta: Meta = serde_json::from_value(meta)?;
+  Ok(meta.player_results)
+}
+
+/// Also returns the lobby settings the node token was issued with
 pub fn get_full_and_node_token(
   conn: &DbConn,
   game_id: i32,
   player_id: i32,
-) -> Result<(Game, Option<PlayerToken>)> {
+) -> Result<(Game, Option<PlayerToken>, GameLobbySettings)> {
   use game_used_slot::dsl as gus;
   let row: GameRowWithRelated = game::table
     .find(game_id)
@@ -770,6 +1249,7 @@ pub fn get_full_and_node_token(
     .optional()?
     .ok_or_else(|| Error::PlayerNotInGame)?;
   let max_players = row.max_players;
+  let lobby_settings = meta.lobby_settings.clone();
 
   Ok((
     row.into_game(
@@ -777,6 +1257,7 @@ pub fn get_full_and_node_token(
       Slots::from_used(max_players as usize, used_slots).into_inner(),
     )?,
     player_token.and_then(|bytes| PlayerToken::from_vec(player_id, bytes)),
+    lobby_settings,
   ))
 }
 
@@ -980,6 +1461,27 @@ pub fn get_node_active_game_ids(conn: &DbConn, node_id: i32) -> Result<Vec<i32>>
 pub struct Meta {
   pub map: Map,
   pub created_by: Option<PlayerRef>,
+  /// Owner of the game, `created_by` changes with the host
+  #[serde(default)]
+  pub api_client_id: Option<i32>,
+  #[serde(default)]
+  pub rating_pool_id: Option<i32>,
+  #[serde(default)]
+  pub node_settings: GameNodeSettings,
+  #[serde(default)]
+  pub desyncs: Vec<GameDesync>,
+  #[serde(default)]
+  pub player_results: BTreeMap<i32, PlayerGameResult>,
+  #[serde(default)]
+  pub locked_slots: BTreeSet<i32>,
+  #[serde(default)]
+  pub password_hash: Option<String>,
+  #[serde(default)]
+  pub invite_only: bool,
+  #[serde(default)]
+  pub invited_player_ids: BTreeSet<i32>,
+  #[serde(default)]
+  pub lobby_settings: GameLobbySettings,
 }
 
 #[derive(Debug, Queryable)]
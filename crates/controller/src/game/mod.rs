@@ -1,21 +1,29 @@
 pub mod db;
+pub mod password;
 mod slots;
 pub(crate) mod state;
 pub mod token;
 mod types;
 
 pub mod messages {
+  pub use super::state::access::{UpdateGameAccess, UpdateGameInvites};
   pub use super::state::cancel::CancelGame;
   pub use super::state::create::CreateGame;
+  pub use super::state::host::TransferHost;
   pub use super::state::join::PlayerJoin;
   pub use super::state::leave::PlayerLeave;
   pub use super::state::node::SelectNode;
   pub use super::state::player::GetGamePlayers;
+  pub use super::state::ready::{ReadyCheckResponse, StartReadyCheck};
   pub use super::state::registry::{
-    AddGamePlayer, Register, Remove, RemoveGamePlayer, ResolveGamePlayerPingBroadcastTargets,
+    AddGamePlayer, KickPlayer, Register, Remove, RemoveGamePlayer,
+    ResolveGamePlayerPingBroadcastTargets,
   };
-  pub use super::state::slot::UpdateSlot;
+  pub use super::state::settings::{UpdateLobbySettings, UpdateNodeSettings};
+  pub use super::state::slot::{LockSlot, UpdateSlot};
   pub use super::state::start::{StartGameCheck, StartGamePlayerAck};
+  pub use super::state::swap::{RequestSlotSwap, RespondSlotSwap};
+  pub use super::state::team::{BalanceTeams, BalanceTeamsBy, ShuffleTeams};
 }
 
 pub use slots::Slots;
@@ -0,0 +1,67 @@
+use rand::RngCore;
+use ring::pbkdf2;
+use std::num::NonZeroU32;
+
+const ALGORITHM: pbkdf2::Algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
+const ALGORITHM_NAME: &str = "pbkdf2-sha256";
+const ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+/// Hash a lobby password, the result is `pbkdf2-sha256$<iterations>$<salt>$<hash>`
+pub fn hash_password(password: &str) -> String {
+  let mut salt = [0; SALT_LEN];
+  rand::thread_rng().fill_bytes(&mut salt);
+  let iterations = NonZeroU32::new(ITERATIONS).expect("non-zero");
+  let mut hash = [0; HASH_LEN];
+  pbkdf2::derive(ALGORITHM, iterations, &salt, password.as_bytes(), &mut hash);
+  format!(
+    "{}${}${}${}",
+    ALGORITHM_NAME,
+    ITERATIONS,
+    to_hex(&salt),
+    to_hex(&hash)
+  )
+}
+
+pub fn verify_password(hashed: &str, password: &str) -> bool {
+  let parts: Vec<&str> = hashed.split('$').collect();
+  if parts.len() != 4 || parts[0] != ALGORITHM_NAME {
+    return false;
+  }
+  let iterations = match parts[1].parse().ok().and_then(NonZeroU32::new) {
+    Some(v) => v,
+    None => return false,
+  };
+  match (from_hex(parts[2]), from_hex(parts[3])) {
+    (Some(salt), Some(hash)) => {
+      pbkdf2::verify(ALGORITHM, iterations, &salt, password.as_bytes(), &hash).is_ok()
+    }
+    _ => false,
+  }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(value: &str) -> Option<Vec<u8>> {
+  if value.len() % 2 != 0 {
+    return None;
+  }
+  (0..value.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+    .collect()
+}
+
+#[test]
+fn test_password() {
+  let hashed = hash_password("scrim");
+  assert!(hashed.starts_with("pbkdf2-sha256$100000$"));
+  assert!(verify_password(&hashed, "scrim"));
+  assert!(!verify_password(&hashed, "Scrim"));
+  assert!(!verify_password(&hashed, ""));
+  assert!(!verify_password("invalid", "scrim"));
+  assert_ne!(hash_password("scrim"), hashed);
+}
@@ -1,6 +1,8 @@
 use diesel::helper_types::Nullable;
 use diesel::prelude::*;
-use std::collections::HashMap;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use crate::game::{
   Computer, Slot, SlotClientStatus, SlotSettings, SlotSettingsColumns, SlotStatus,
@@ -12,6 +14,7 @@ use crate::schema::game_used_slot;
 pub struct Slots {
   inner: Vec<Slot>,
   map_players: usize,
+  locked: BTreeSet<i32>,
 }
 
 impl Slots {
@@ -22,7 +25,11 @@ impl Slots {
       .map(|(idx, _)| Self::make_unused_slot(map_players, idx))
       .collect();
 
-    Self { inner, map_players }
+    Self {
+      inner,
+      map_players,
+      locked: BTreeSet::new(),
+    }
   }
 
   pub fn from_used(map_players: usize, slots: Vec<UsedSlot>) -> Self {
@@ -45,7 +52,11 @@ impl Slots {
         }
       })
       .collect();
-    Slots { map_players, inner }
+    Slots {
+      map_players,
+      inner,
+      locked: BTreeSet::new(),
+    }
   }
 
   pub fn as_used(&self) -> Vec<UsedSlot> {
@@ -67,6 +78,15 @@ impl Slots {
     self.inner
   }
 
+  /// Locked slots are skipped when joining and are not moved by bulk operations
+  pub fn set_locked(&mut self, locked: BTreeSet<i32>) {
+    self.locked = locked;
+  }
+
+  pub fn is_locked(&self, slot_index: i32) -> bool {
+    self.locked.contains(&slot_index)
+  }
+
   fn make_unused_slot(map_players: usize, idx: usize) -> Slot {
     Slot {
       settings: SlotSettings {
@@ -140,7 +160,7 @@ impl Slots {
           }
         }
         SlotStatus::Open => {
-          if let None = open_slot_idx {
+          if open_slot_idx.is_none() && !self.locked.contains(&(i as i32)) {
             open_slot_idx = Some(i)
           }
         }
@@ -235,11 +255,13 @@ impl Slots {
           let next_color = color_set.iter().position(|v| !*v).map(|v| v as i32);
 
           // find an open player slot
-          if let Some((index, _player_slot)) = self
-            .inner
-            .iter_mut()
-            .enumerate()
-            .find(|(_index, s)| s.settings.team != 24 && s.settings.status == SlotStatus::Open)
+          let locked = &self.locked;
+          if let Some((index, _player_slot)) =
+            self.inner.iter_mut().enumerate().find(|(index, s)| {
+              s.settings.team != 24
+                && s.settings.status == SlotStatus::Open
+                && !locked.contains(&(*index as i32))
+            })
           {
             target_index = index as i32;
             self.inner[index].player = self.inner[slot_index as usize].player.clone();
@@ -263,11 +285,13 @@ impl Slots {
           // players -> referees:
 
           // find an open referee slot
-          if let Some((index, _player_slot)) = self
-            .inner
-            .iter_mut()
-            .enumerate()
-            .find(|(_index, s)| s.settings.team == 24 && s.settings.status == SlotStatus::Open)
+          let locked = &self.locked;
+          if let Some((index, _player_slot)) =
+            self.inner.iter_mut().enumerate().find(|(index, s)| {
+              s.settings.team == 24
+                && s.settings.status == SlotStatus::Open
+                && !locked.contains(&(*index as i32))
+            })
           {
             target_index = index as i32;
             self.inner[index].player = self.inner[slot_index as usize].player.clone();
@@ -340,6 +364,223 @@ impl Slots {
     Some(updated_slots)
   }
 
+  /// Applies `f` to the handicap of every occupied slot, returns updated slot indexes
+  pub fn clamp_handicaps<F>(&mut self, f: F) -> Vec<i32>
+  where
+    F: Fn(i32) -> i32,
+  {
+    let mut updated = vec![];
+    for (idx, slot) in self.inner.iter_mut().enumerate() {
+      if slot.settings.status != SlotStatus::Occupied {
+        continue;
+      }
+      let handicap = f(slot.settings.handicap);
+      if handicap != slot.settings.handicap {
+        slot.settings.handicap = handicap;
+        updated.push(idx as i32);
+      }
+    }
+    updated
+  }
+
+  /// Randomly reassign the teams of the unlocked player slots,
+  /// team sizes are preserved. Returns updated slot indexes
+  pub fn shuffle_teams<R: Rng>(&mut self, rng: &mut R) -> Vec<i32> {
+    let indexes = self.get_movable_player_slot_indexes();
+    let mut teams: Vec<i32> = indexes
+      .iter()
+      .map(|index| self.inner[*index].settings.team)
+      .collect();
+    teams.shuffle(rng);
+    self.assign_teams(&indexes, &teams)
+  }
+
+  /// Reassign the teams of the unlocked player slots so that the mean value of every team
+  /// is as close as possible, team sizes are preserved.
+  /// `values` maps player ids to the balanced value, e.g. rating or ping,
+  /// players without a value count as the mean of the known values.
+  /// Returns updated slot indexes
+  pub fn balance_teams(&mut self, values: &HashMap<i32, f64>) -> Vec<i32> {
+    let indexes = self.get_movable_player_slot_indexes();
+    if indexes.len() < 2 {
+      return vec![];
+    }
+
+    let known: Vec<f64> = self
+      .inner
+      .iter()
+      .filter_map(|s| s.player.as_ref().and_then(|p| values.get(&p.id).cloned()))
+      .collect();
+    let default_value = if known.is_empty() {
+      0.
+    } else {
+      known.iter().sum::<f64>() / known.len() as f64
+    };
+    let value_of = |slot: &Slot| {
+      slot
+        .player
+        .as_ref()
+        .and_then(|p| values.get(&p.id).cloned())
+        .unwrap_or(default_value)
+    };
+
+    // (sum, size, remaining capacity), locked players stay in their teams
+    let mut teams: BTreeMap<i32, (f64, usize, usize)> = BTreeMap::new();
+    for index in &indexes {
+      teams.entry(self.inner[*index].settings.team).or_default().2 += 1;
+    }
+    for (index, slot) in self.inner.iter().enumerate() {
+      if slot.settings.status != SlotStatus::Occupied
+        || slot.settings.team == 24
+        || slot.player.is_none()
+        || indexes.contains(&index)
+      {
+        continue;
+      }
+      if let Some(team) = teams.get_mut(&slot.settings.team) {
+        team.0 += value_of(slot);
+        team.1 += 1;
+      }
+    }
+
+    if teams.len() < 2 {
+      return vec![];
+    }
+
+    // greedy: strongest first, into the team with the lowest mean
+    let mut order = indexes.clone();
+    order.sort_by(|a, b| {
+      value_of(&self.inner[*b])
+        .partial_cmp(&value_of(&self.inner[*a]))
+        .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let mut assignment: Vec<(usize, i32, f64)> = Vec::with_capacity(order.len());
+    for index in order {
+      let value = value_of(&self.inner[index]);
+      let (team, entry) = teams
+        .iter_mut()
+        .filter(|(_, (_, _, remaining))| *remaining > 0)
+        .min_by(|(_, a), (_, b)| {
+          let mean = |(sum, size, _): &(f64, usize, usize)| {
+            if *size == 0 {
+              std::f64::MIN
+            } else {
+              sum / *size as f64
+            }
+          };
+          mean(*a)
+            .partial_cmp(&mean(*b))
+            .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .expect("team capacity");
+      entry.0 += value;
+      entry.1 += 1;
+      entry.2 -= 1;
+      assignment.push((index, *team, value));
+    }
+
+    // improve by swapping players between teams while the spread decreases
+    let spread = |teams: &BTreeMap<i32, (f64, usize, usize)>| {
+      let means = teams.values().map(|(sum, size, _)| sum / *size as f64);
+      let max = means.clone().fold(std::f64::MIN, f64::max);
+      let min = means.fold(std::f64::MAX, f64::min);
+      max - min
+    };
+    let mut improved = true;
+    while improved {
+      improved = false;
+      let current = spread(&teams);
+      'search: for i in 0..assignment.len() {
+        for j in (i + 1)..assignment.len() {
+          let (_, team_i, value_i) = assignment[i];
+          let (_, team_j, value_j) = assignment[j];
+          if team_i == team_j {
+            continue;
+          }
+          let mut next = teams.clone();
+          next.get_mut(&team_i).expect("team").0 += value_j - value_i;
+          next.get_mut(&team_j).expect("team").0 += value_i - value_j;
+          if spread(&next) + std::f64::EPSILON < current {
+            teams = next;
+            assignment[i].1 = team_j;
+            assignment[j].1 = team_i;
+            improved = true;
+            break 'search;
+          }
+        }
+      }
+    }
+
+    let (indexes, teams): (Vec<usize>, Vec<i32>) = assignment
+      .into_iter()
+      .map(|(index, team, _)| (index, team))
+      .unzip();
+    self.assign_teams(&indexes, &teams)
+  }
+
+  /// Exchange the players of two occupied slots,
+  /// team, color and slot status stay with the slot. Returns updated slots
+  pub fn swap_slots(&mut self, slot_index_a: i32, slot_index_b: i32) -> Option<Vec<(i32, &Slot)>> {
+    if slot_index_a == slot_index_b
+      || slot_index_a < 0
+      || slot_index_a > 23
+      || slot_index_b < 0
+      || slot_index_b > 23
+      || self.is_locked(slot_index_a)
+      || self.is_locked(slot_index_b)
+    {
+      return None;
+    }
+
+    let (a, b) = (slot_index_a as usize, slot_index_b as usize);
+    if self.inner[a].player.is_none() || self.inner[b].player.is_none() {
+      return None;
+    }
+
+    let slot_a = self.inner[a].clone();
+    let slot_b = self.inner[b].clone();
+    for (index, from) in [(a, slot_b), (b, slot_a)].iter().cloned() {
+      let slot = &mut self.inner[index];
+      slot.player = from.player;
+      slot.client_status = from.client_status;
+      slot.settings.race = from.settings.race;
+      slot.settings.handicap = from.settings.handicap;
+    }
+
+    Some(vec![
+      (slot_index_a, &self.inner[a]),
+      (slot_index_b, &self.inner[b]),
+    ])
+  }
+
+  fn get_movable_player_slot_indexes(&self) -> Vec<usize> {
+    self
+      .inner
+      .iter()
+      .enumerate()
+      .filter(|(index, slot)| {
+        slot.settings.status == SlotStatus::Occupied
+          && slot.settings.team != 24
+          && slot.player.is_some()
+          && !self.locked.contains(&(*index as i32))
+      })
+      .map(|(index, _)| index)
+      .collect()
+  }
+
+  fn assign_teams(&mut self, indexes: &[usize], teams: &[i32]) -> Vec<i32> {
+    let mut updated = vec![];
+    for (index, team) in indexes.iter().zip(teams) {
+      let slot = &mut self.inner[*index];
+      if slot.settings.team != *team {
+        slot.settings.team = *team;
+        updated.push(*index as i32);
+      }
+    }
+    updated.sort();
+    updated
+  }
+
   fn get_color_set(&self) -> [bool; 24] {
     let mut set = [false; 24];
     for slot in &self.inner {
@@ -412,3 +653,69 @@ impl UsedSlotInfo {
     )
   }
 }
+
+#[test]
+fn test_slots_team_operations() {
+  use crate::player::PlayerSource;
+
+  let mut slots = Slots::new(4);
+  for id in 1..=4 {
+    let player = PlayerRef {
+      id,
+      name: id.to_string(),
+      source: PlayerSource::Test,
+      realm: None,
+    };
+    slots.join(&player).unwrap();
+  }
+  for (index, team) in [0, 0, 1, 1].iter().enumerate() {
+    slots.inner[index].settings.team = *team;
+  }
+  let team_of = |slots: &Slots, player_id: i32| {
+    slots
+      .find_player_slot(player_id)
+      .map(|s| s.settings.team)
+      .unwrap()
+  };
+
+  // 1 and 2 are the strongest players and must be split
+  let values: HashMap<i32, f64> = vec![(1, 2000.), (2, 1900.), (3, 1000.), (4, 1100.)]
+    .into_iter()
+    .collect();
+  let updated = slots.balance_teams(&values);
+  assert!(!updated.is_empty());
+  assert_ne!(team_of(&slots, 1), team_of(&slots, 2));
+  assert_eq!(slots.balance_teams(&values), vec![]);
+
+  // locked slots keep their team
+  slots.set_locked(vec![0].into_iter().collect());
+  let team = slots[0].settings.team;
+  for _ in 0..10 {
+    slots.shuffle_teams(&mut rand::thread_rng());
+    assert_eq!(slots[0].settings.team, team);
+    let teams: Vec<_> = slots.iter().take(4).map(|s| s.settings.team).collect();
+    assert_eq!(teams.iter().filter(|t| **t == 0).count(), 2);
+  }
+  assert!(slots.swap_slots(0, 1).is_none());
+
+  let (team_2, team_3) = (slots[2].settings.team, slots[3].settings.team);
+  let player_2 = slots[2].player.as_ref().map(|p| p.id);
+  let player_3 = slots[3].player.as_ref().map(|p| p.id);
+  assert_eq!(slots.swap_slots(2, 3).map(|updated| updated.len()), Some(2));
+  assert_eq!(slots[2].player.as_ref().map(|p| p.id), player_3);
+  assert_eq!(slots[3].player.as_ref().map(|p| p.id), player_2);
+  assert_eq!(slots[2].settings.team, team_2);
+  assert_eq!(slots[3].settings.team, team_3);
+
+  // locked open slots are skipped when joining
+  slots.set_locked(vec![4].into_iter().collect());
+  let player = PlayerRef {
+    id: 5,
+    name: "5".to_string(),
+    source: PlayerSource::Test,
+    realm: None,
+  };
+  slots.join(&player).unwrap();
+  assert_eq!(slots.find_player_slot(5).map(|s| s.settings.team), Some(24));
+  assert!(slots[4].player.is_none());
+}
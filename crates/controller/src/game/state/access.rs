@@ -0,0 +1,113 @@
+use crate::error::*;
+use crate::game::db::UpdateGameAccessParams;
+use crate::game::state::GameActor;
+use flo_net::packet::FloPacket;
+use flo_net::proto;
+use flo_state::{async_trait, Context, Handler, Message};
+use s2_grpc_utils::S2ProtoPack;
+
+pub struct UpdateGameAccess {
+  pub player_id: i32,
+  /// `None` keeps the current password, an empty string removes it
+  pub password: Option<String>,
+  pub invite_only: Option<bool>,
+}
+
+impl Message for UpdateGameAccess {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<UpdateGameAccess> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    UpdateGameAccess {
+      player_id,
+      password,
+      invite_only,
+    }: UpdateGameAccess,
+  ) -> Result<()> {
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    if self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    let game_id = self.game_id;
+    self
+      .db
+      .exec(move |conn| {
+        crate::game::db::update_access(
+          conn,
+          game_id,
+          player_id,
+          UpdateGameAccessParams {
+            password,
+            invite_only,
+          },
+        )
+      })
+      .await?;
+
+    Ok(())
+  }
+}
+
+pub struct UpdateGameInvites {
+  pub player_id: i32,
+  pub add_player_ids: Vec<i32>,
+  pub remove_player_ids: Vec<i32>,
+}
+
+impl Message for UpdateGameInvites {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<UpdateGameInvites> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    UpdateGameInvites {
+      player_id,
+      add_player_ids,
+      remove_player_ids,
+    }: UpdateGameInvites,
+  ) -> Result<()> {
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    if self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    let game_id = self.game_id;
+    let updated = self
+      .db
+      .exec(move |conn| {
+        crate::game::db::update_invites(conn, game_id, player_id, add_player_ids, remove_player_ids)
+      })
+      .await?;
+
+    if updated.invited_player_ids.is_empty() {
+      return Ok(());
+    }
+
+    let frame = proto::flo_connect::PacketGameInvite {
+      game_id,
+      game_name: updated.game_name,
+      invited_by: Some(updated.host.pack()?),
+    }
+    .encode_as_frame()?;
+    self
+      .player_reg
+      .broadcast(updated.invited_player_ids, frame)
+      .await?;
+
+    Ok(())
+  }
+}
@@ -24,6 +24,10 @@ impl Handler<CancelGame> for GameActor {
   ) -> Result<()> {
     let game_id = self.game_id;
 
+    if player_id.is_some() && player_id != Some(self.host_player) {
+      return Err(Error::PlayerNotHost);
+    }
+
     self
       .db
       .exec(move |conn| crate::game::db::cancel(conn, game_id, player_id))
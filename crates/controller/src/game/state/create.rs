@@ -6,6 +6,9 @@ use crate::game::{Game, GameStatus};
 use flo_state::{async_trait, Context, Handler, Message};
 
 pub struct CreateGame {
+  pub api_client_id: i32,
+  /// The game is only rated if a pool is set
+  pub rating_pool_id: Option<i32>,
   pub params: CreateGameParams,
 }
 
@@ -18,12 +21,16 @@ impl Handler<CreateGame> for GameRegistry {
   async fn handle(
     &mut self,
     _: &mut Context<Self>,
-    CreateGame { params }: CreateGame,
+    CreateGame {
+      api_client_id,
+      rating_pool_id,
+      params,
+    }: CreateGame,
   ) -> <CreateGame as Message>::Result {
     let player_id = params.player_id;
     let game = self
       .db
-      .exec(move |conn| crate::game::db::create(conn, params))
+      .exec(move |conn| crate::game::db::create(conn, api_client_id, rating_pool_id, params))
       .await?;
 
     self.register(Register {
@@ -46,6 +53,8 @@ impl Handler<CreateGame> for GameRegistry {
 pub struct CreateGameAsBot {
   pub api_client_id: i32,
   pub api_player_id: i32,
+  /// The game is only rated if a pool is set
+  pub rating_pool_id: Option<i32>,
   pub params: CreateGameAsBotParams,
 }
 
@@ -61,13 +70,20 @@ impl Handler<CreateGameAsBot> for GameRegistry {
     CreateGameAsBot {
       api_client_id,
       api_player_id,
+      rating_pool_id,
       params,
     }: CreateGameAsBot,
   ) -> <CreateGameAsBot as Message>::Result {
     let (mut game, player_ids, mute_list_map) = self
       .db
       .exec(move |conn| {
-        let game = crate::game::db::create_as_bot(conn, api_client_id, api_player_id, params)?;
+        let game = crate::game::db::create_as_bot(
+          conn,
+          api_client_id,
+          api_player_id,
+          rating_pool_id,
+          params,
+        )?;
         let player_ids = game.get_player_ids();
         let mute_list_map = crate::player::db::get_mute_list_map(conn, &player_ids)?;
         Ok::<_, Error>((game, player_ids, mute_list_map))
@@ -0,0 +1,30 @@
+use crate::error::*;
+use crate::game::state::GameActor;
+use crate::game::{db, GameDesync};
+use flo_state::{async_trait, Context, Handler, Message};
+
+impl Message for GameDesync {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<GameDesync> for GameActor {
+  async fn handle(&mut self, _ctx: &mut Context<Self>, message: GameDesync) -> Result<()> {
+    let game_id = message.game_id;
+    tracing::warn!(
+      game_id,
+      tick = message.tick,
+      "desync: players = {:?}, dropped = {:?}, resolution = {:?}",
+      message.player_ids,
+      message.dropped_player_ids,
+      message.resolution
+    );
+
+    self
+      .db
+      .exec(move |conn| db::add_desync(conn, game_id, message))
+      .await?;
+
+    Ok(())
+  }
+}
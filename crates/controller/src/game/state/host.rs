@@ -0,0 +1,63 @@
+use crate::error::*;
+use crate::game::state::GameActor;
+use crate::player::PlayerRef;
+use flo_net::packet::FloPacket;
+use flo_net::proto;
+use flo_state::{async_trait, Context, Handler, Message};
+use s2_grpc_utils::S2ProtoPack;
+
+pub struct TransferHost {
+  pub player_id: i32,
+  pub to_player_id: i32,
+}
+
+impl Message for TransferHost {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<TransferHost> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    TransferHost {
+      player_id,
+      to_player_id,
+    }: TransferHost,
+  ) -> Result<()> {
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    if self.start_state.is_some() {
+      return Err(Error::GameStarted);
+    }
+
+    let game_id = self.game_id;
+    let host = self
+      .db
+      .exec(move |conn| crate::game::db::transfer_host(conn, game_id, player_id, to_player_id))
+      .await?;
+
+    self.set_host_player(host).await
+  }
+}
+
+impl GameActor {
+  pub(crate) async fn set_host_player(&mut self, host: PlayerRef) -> Result<()> {
+    self.host_player = host.id;
+    // requested by the previous host
+    self.pending_slot_swap.take();
+
+    let frame = proto::flo_connect::PacketGameHostUpdate {
+      game_id: self.game_id,
+      host: Some(host.pack()?),
+    }
+    .encode_as_frame()?;
+    self
+      .player_reg
+      .broadcast(self.players.clone(), frame)
+      .await?;
+    Ok(())
+  }
+}
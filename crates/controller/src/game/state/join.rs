@@ -1,4 +1,5 @@
 use crate::error::*;
+use crate::game::db::JoinAccess;
 use crate::game::state::GameActor;
 use crate::game::Game;
 use diesel::prelude::*;
@@ -10,6 +11,7 @@ use s2_grpc_utils::S2ProtoPack;
 
 pub struct PlayerJoin {
   pub player_id: i32,
+  pub access: JoinAccess,
 }
 
 impl Message for PlayerJoin {
@@ -21,18 +23,24 @@ impl Handler<PlayerJoin> for GameActor {
   async fn handle(
     &mut self,
     _: &mut Context<Self>,
-    PlayerJoin { player_id }: PlayerJoin,
+    PlayerJoin { player_id, access }: PlayerJoin,
   ) -> Result<Game> {
     let game_id = self.game_id;
-    let (game, mute_list) = self
+    let (game, mute_list, locked_slots) = self
       .db
       .exec(move |conn| {
         conn.transaction(|| {
+          crate::game::db::check_join_access(conn, game_id, player_id, &access)?;
           crate::game::db::add_player(conn, game_id, player_id)?;
           let game = crate::game::db::get_full(conn, game_id)?;
           let mut mute_list_map =
             crate::player::db::get_mute_list_map(conn, &game.get_player_ids())?;
-          Ok::<_, Error>((game, mute_list_map.remove(&player_id).unwrap_or_default()))
+          let locked_slots = crate::game::db::get_locked_slots(conn, game_id)?;
+          Ok::<_, Error>((
+            game,
+            mute_list_map.remove(&player_id).unwrap_or_default(),
+            locked_slots,
+          ))
         })
       })
       .await?;
@@ -45,6 +53,21 @@ impl Handler<PlayerJoin> for GameActor {
       .player_replace_game(player_id, game.clone(), mute_list)
       .await?;
 
+    if !locked_slots.is_empty() {
+      let frames = locked_slots
+        .into_iter()
+        .map(|slot_index| {
+          proto::flo_connect::PacketGameSlotLockUpdate {
+            game_id,
+            slot_index,
+            locked: true,
+          }
+          .encode_as_frame()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+      self.player_reg.send(player_id, frames).await?;
+    }
+
     {
       let slot_info = game
         .get_player_slot_info(player_id)
@@ -71,6 +94,8 @@ impl Handler<PlayerJoin> for GameActor {
       self.player_reg.broadcast(players, frame).await?;
     }
 
+    self.add_ready_check_player(player_id).await?;
+
     Ok(game)
   }
 }
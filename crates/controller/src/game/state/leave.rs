@@ -71,11 +71,19 @@ async fn leave_game_lobby(
   game_id: i32,
   player_id: i32,
 ) -> Result<PlayerLeaveResult> {
+  // the longest present player takes over if the host leaves
+  let successor = state.players.iter().cloned().find(|id| *id != player_id);
   let leave = state
     .db
-    .exec(move |conn| crate::game::db::remove_player(conn, game_id, player_id))
+    .exec(move |conn| {
+      conn.transaction(|| crate::game::db::remove_player(conn, game_id, player_id, successor))
+    })
     .await?;
 
+  state
+    .players
+    .retain(|id| !leave.removed_players.contains(id));
+
   let recipient_player_ids: Vec<i32> = leave
     .slots
     .iter()
@@ -92,6 +100,14 @@ async fn leave_game_lobby(
   )
   .await?;
 
+  if !leave.game_ended {
+    state.remove_ready_check_player(player_id).await?;
+    if let Some(host) = leave.new_host {
+      tracing::info!(game_id, "host migrated: {} -> {}", player_id, host.id);
+      state.set_host_player(host).await?;
+    }
+  }
+
   Ok(PlayerLeaveResult {
     game_ended: leave.game_ended,
   })
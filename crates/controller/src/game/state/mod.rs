@@ -1,13 +1,20 @@
+pub mod access;
 pub mod cancel;
 pub mod create;
+pub mod desync;
+pub mod host;
 pub mod join;
 pub mod leave;
 pub mod node;
 pub mod player;
+pub mod ready;
 pub mod registry;
+pub mod settings;
 pub mod slot;
 pub mod start;
 pub mod status;
+pub mod swap;
+pub mod team;
 
 pub use status::{GameSlotClientStatusUpdate, GameStatusUpdate};
 
@@ -87,6 +94,8 @@ impl GameRegistry {
           start_state: None,
           player_tokens,
           player_client_status_map: Default::default(),
+          pending_slot_swap: None,
+          ready_check: None,
         }),
       );
     }
@@ -198,6 +207,8 @@ pub struct GameActor {
   pub start_state: Option<Owner<StartGameState>>,
   pub player_tokens: HashMap<i32, [u8; 16]>,
   pub player_client_status_map: HashMap<i32, SlotClientStatus>,
+  pub pending_slot_swap: Option<swap::PendingSlotSwap>,
+  pub ready_check: Option<ready::ReadyCheck>,
 }
 
 impl Actor for GameActor {}
@@ -23,6 +23,10 @@ impl Handler<SelectNode> for GameActor {
   ) -> Result<()> {
     let game_id = self.game_id;
 
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
     if self.started() {
       return Err(Error::GameStarted);
     }
@@ -0,0 +1,419 @@
+use crate::error::*;
+use crate::game::state::registry::KickPlayer;
+use crate::game::state::{GameActor, GameRegistry};
+use crate::game::GameStatus;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::{
+  GameReadyCheckState, GameReadyStatus, PacketGameReadyCheck, PacketGameReadyStatusUpdate,
+};
+use flo_state::{async_trait, Addr, Context, Handler, Message};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const MIN_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub struct ReadyCheck {
+  deadline: Instant,
+  kick_unready: bool,
+  registry: Addr<GameRegistry>,
+  players: ReadyCheckPlayers,
+}
+
+/// Ready status of every player in a ready check
+#[derive(Debug)]
+struct ReadyCheckPlayers {
+  status_map: BTreeMap<i32, GameReadyStatus>,
+}
+
+impl ReadyCheckPlayers {
+  /// The host is ready by requesting
+  fn new(player_ids: &[i32], host_player_id: i32) -> Self {
+    let status_map = player_ids
+      .iter()
+      .map(|id| {
+        let status = if *id == host_player_id {
+          GameReadyStatus::Ready
+        } else {
+          GameReadyStatus::Pending
+        };
+        (*id, status)
+      })
+      .collect();
+    Self { status_map }
+  }
+
+  /// Returns the new status, or `None` if it did not change
+  fn respond(&mut self, player_id: i32, ready: bool) -> Result<Option<GameReadyStatus>> {
+    let status = self
+      .status_map
+      .get_mut(&player_id)
+      .ok_or_else(|| Error::PlayerNotInGame)?;
+    let next = if ready {
+      GameReadyStatus::Ready
+    } else {
+      GameReadyStatus::NotReady
+    };
+    if *status == next {
+      return Ok(None);
+    }
+    *status = next;
+    Ok(Some(next))
+  }
+
+  fn add(&mut self, player_id: i32) {
+    self.status_map.insert(player_id, GameReadyStatus::Pending);
+  }
+
+  fn remove(&mut self, player_id: i32) -> bool {
+    self.status_map.remove(&player_id).is_some()
+  }
+
+  fn pending_player_ids(&self) -> Vec<i32> {
+    self
+      .status_map
+      .iter()
+      .filter(|(_, status)| **status == GameReadyStatus::Pending)
+      .map(|(id, _)| *id)
+      .collect()
+  }
+
+  fn unready_player_ids(&self) -> Vec<i32> {
+    self
+      .status_map
+      .iter()
+      .filter(|(_, status)| **status != GameReadyStatus::Ready)
+      .map(|(id, _)| *id)
+      .collect()
+  }
+
+  fn all_responded(&self) -> bool {
+    !self
+      .status_map
+      .values()
+      .any(|status| *status == GameReadyStatus::Pending)
+  }
+
+  fn outcome(&self) -> GameReadyCheckState {
+    if self.unready_player_ids().is_empty() {
+      GameReadyCheckState::Passed
+    } else {
+      GameReadyCheckState::Failed
+    }
+  }
+}
+
+pub struct StartReadyCheck {
+  pub player_id: i32,
+  pub timeout: Option<Duration>,
+  pub kick_unready: bool,
+  /// Used to remove unready players from the game
+  pub registry: Addr<GameRegistry>,
+}
+
+impl Message for StartReadyCheck {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<StartReadyCheck> for GameActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    StartReadyCheck {
+      player_id,
+      timeout,
+      kick_unready,
+      registry,
+    }: StartReadyCheck,
+  ) -> Result<()> {
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    if self.status != GameStatus::Preparing || self.start_state.is_some() {
+      return Err(Error::GameStarted);
+    }
+
+    let timeout = timeout
+      .unwrap_or(DEFAULT_TIMEOUT)
+      .max(MIN_TIMEOUT)
+      .min(MAX_TIMEOUT);
+    let deadline = Instant::now() + timeout;
+
+    // replaces the running ready check
+    self.ready_check = Some(ReadyCheck {
+      deadline,
+      kick_unready,
+      registry,
+      players: ReadyCheckPlayers::new(&self.players, player_id),
+    });
+
+    self
+      .broadcast_ready_check_state(GameReadyCheckState::Started, vec![])
+      .await?;
+    self
+      .broadcast_ready_status(player_id, GameReadyStatus::Ready)
+      .await?;
+
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      sleep(timeout).await;
+      addr.notify(ReadyCheckTimeout { deadline }).await.ok();
+    });
+
+    self.check_ready_check_done().await
+  }
+}
+
+pub struct ReadyCheckResponse {
+  pub player_id: i32,
+  pub ready: bool,
+}
+
+impl Message for ReadyCheckResponse {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<ReadyCheckResponse> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    ReadyCheckResponse { player_id, ready }: ReadyCheckResponse,
+  ) -> Result<()> {
+    let check = self
+      .ready_check
+      .as_mut()
+      .ok_or_else(|| Error::GameReadyCheckNotFound)?;
+    let next = if let Some(next) = check.players.respond(player_id, ready)? {
+      next
+    } else {
+      return Ok(());
+    };
+
+    self.broadcast_ready_status(player_id, next).await?;
+    self.check_ready_check_done().await
+  }
+}
+
+struct ReadyCheckTimeout {
+  deadline: Instant,
+}
+
+impl Message for ReadyCheckTimeout {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<ReadyCheckTimeout> for GameActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    ReadyCheckTimeout { deadline }: ReadyCheckTimeout,
+  ) {
+    if let Err(err) = self.handle_ready_check_timeout(ctx, deadline).await {
+      tracing::error!(game_id = self.game_id, "ready check timeout: {}", err);
+    }
+  }
+}
+
+impl GameActor {
+  async fn handle_ready_check_timeout(
+    &mut self,
+    ctx: &mut Context<Self>,
+    deadline: Instant,
+  ) -> Result<()> {
+    // a newer ready check replaced the timed out one
+    match self.ready_check.as_ref() {
+      Some(check) if check.deadline == deadline => {}
+      _ => return Ok(()),
+    }
+
+    let check = self.ready_check.take().expect("ready check");
+    let timeout_player_ids = check.players.pending_player_ids();
+
+    for player_id in &timeout_player_ids {
+      self
+        .broadcast_ready_status(*player_id, GameReadyStatus::Timeout)
+        .await?;
+    }
+    self
+      .broadcast_ready_check_state(
+        GameReadyCheckState::Failed,
+        check.players.unready_player_ids(),
+      )
+      .await?;
+
+    if check.kick_unready && !timeout_player_ids.is_empty() {
+      let game_id = self.game_id;
+      let registry = check.registry;
+      tracing::info!(
+        game_id,
+        "ready check: kick players: {:?}",
+        timeout_player_ids
+      );
+      ctx.spawn(async move {
+        for player_id in timeout_player_ids {
+          if let Err(err) = registry.send(KickPlayer { game_id, player_id }).await {
+            tracing::error!(game_id, player_id, "ready check: kick player: {}", err);
+          }
+        }
+      });
+    }
+
+    Ok(())
+  }
+
+  /// Finish the ready check once every player responded
+  async fn check_ready_check_done(&mut self) -> Result<()> {
+    let done = self
+      .ready_check
+      .as_ref()
+      .map(|check| check.players.all_responded())
+      .unwrap_or_default();
+    if !done {
+      return Ok(());
+    }
+
+    let check = self.ready_check.take().expect("ready check");
+    self
+      .broadcast_ready_check_state(check.players.outcome(), check.players.unready_player_ids())
+      .await
+  }
+
+  /// Cancel the running ready check, e.g. when the game is starting
+  pub(crate) async fn cancel_ready_check(&mut self) -> Result<()> {
+    if self.ready_check.is_some() {
+      self
+        .broadcast_ready_check_state(GameReadyCheckState::Cancelled, vec![])
+        .await?;
+      self.ready_check.take();
+    }
+    Ok(())
+  }
+
+  /// Include a joined player in the running ready check
+  pub(crate) async fn add_ready_check_player(&mut self, player_id: i32) -> Result<()> {
+    let timeout_seconds = if let Some(check) = self.ready_check.as_mut() {
+      check.players.add(player_id);
+      check
+        .deadline
+        .saturating_duration_since(Instant::now())
+        .as_secs() as i32
+    } else {
+      return Ok(());
+    };
+
+    let mut pkt = PacketGameReadyCheck {
+      game_id: self.game_id,
+      timeout_seconds,
+      ..Default::default()
+    };
+    pkt.set_state(GameReadyCheckState::Started);
+    self
+      .player_reg
+      .send(player_id, pkt.encode_as_frame()?)
+      .await?;
+    self
+      .broadcast_ready_status(player_id, GameReadyStatus::Pending)
+      .await
+  }
+
+  /// Exclude a left player from the running ready check
+  pub(crate) async fn remove_ready_check_player(&mut self, player_id: i32) -> Result<()> {
+    if let Some(check) = self.ready_check.as_mut() {
+      if check.players.remove(player_id) {
+        return self.check_ready_check_done().await;
+      }
+    }
+    Ok(())
+  }
+
+  async fn broadcast_ready_check_state(
+    &self,
+    state: GameReadyCheckState,
+    unready_player_ids: Vec<i32>,
+  ) -> Result<()> {
+    let timeout_seconds = self
+      .ready_check
+      .as_ref()
+      .map(|check| {
+        check
+          .deadline
+          .saturating_duration_since(Instant::now())
+          .as_secs() as i32
+      })
+      .unwrap_or_default();
+    let mut pkt = PacketGameReadyCheck {
+      game_id: self.game_id,
+      timeout_seconds,
+      unready_player_ids,
+      ..Default::default()
+    };
+    pkt.set_state(state);
+    self
+      .player_reg
+      .broadcast(self.players.clone(), pkt.encode_as_frame()?)
+      .await?;
+    Ok(())
+  }
+
+  async fn broadcast_ready_status(&self, player_id: i32, status: GameReadyStatus) -> Result<()> {
+    let mut pkt = PacketGameReadyStatusUpdate {
+      game_id: self.game_id,
+      player_id,
+      ..Default::default()
+    };
+    pkt.set_status(status);
+    self
+      .player_reg
+      .broadcast(self.players.clone(), pkt.encode_as_frame()?)
+      .await?;
+    Ok(())
+  }
+}
+
+#[test]
+fn test_ready_check_players() {
+  // 1 is the host, 4 joins during the check
+  let mut players = ReadyCheckPlayers::new(&[1, 2, 3], 1);
+  assert!(!players.all_responded());
+  assert_eq!(players.pending_player_ids(), vec![2, 3]);
+
+  assert_eq!(
+    players.respond(2, true).unwrap(),
+    Some(GameReadyStatus::Ready)
+  );
+  assert_eq!(players.respond(2, true).unwrap(), None);
+  assert!(players.respond(5, true).is_err());
+
+  players.add(4);
+  assert_eq!(players.pending_player_ids(), vec![3, 4]);
+
+  // a leaving player no longer blocks the check
+  assert!(players.remove(3));
+  assert!(!players.remove(3));
+  assert_eq!(
+    players.respond(4, false).unwrap(),
+    Some(GameReadyStatus::NotReady)
+  );
+  assert!(players.all_responded());
+  assert_eq!(players.outcome(), GameReadyCheckState::Failed);
+  assert_eq!(players.unready_player_ids(), vec![4]);
+
+  players.respond(4, true).unwrap();
+  assert_eq!(players.outcome(), GameReadyCheckState::Passed);
+}
+
+#[test]
+fn test_ready_check_players_timeout() {
+  let mut players = ReadyCheckPlayers::new(&[1, 2, 3], 1);
+  players.respond(2, false).unwrap();
+  // timed out players are the pending ones, the failed check reports all unready players
+  assert_eq!(players.pending_player_ids(), vec![3]);
+  assert_eq!(players.unready_player_ids(), vec![2, 3]);
+  assert_eq!(players.outcome(), GameReadyCheckState::Failed);
+}
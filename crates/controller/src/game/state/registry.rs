@@ -1,4 +1,5 @@
 use crate::error::*;
+use crate::game::state::leave::PlayerLeave;
 use crate::game::state::{GameActor, GameRegistry};
 use crate::game::GameStatus;
 use flo_state::{async_trait, Context, Handler, Message, Owner};
@@ -52,6 +53,8 @@ impl GameRegistry {
         start_state: None,
         player_tokens: Default::default(),
         player_client_status_map: Default::default(),
+        pending_slot_swap: None,
+        ready_check: None,
       }),
     );
   }
@@ -141,6 +144,38 @@ impl Handler<RemoveGamePlayer> for GameRegistry {
   }
 }
 
+/// Remove a player from a game on behalf of the controller
+pub struct KickPlayer {
+  pub game_id: i32,
+  pub player_id: i32,
+}
+
+impl Message for KickPlayer {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<KickPlayer> for GameRegistry {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    KickPlayer { game_id, player_id }: KickPlayer,
+  ) -> Result<()> {
+    let addr = self
+      .map
+      .get(&game_id)
+      .map(|v| v.addr())
+      .ok_or_else(|| Error::GameNotFound)?;
+    let res = addr.send(PlayerLeave { player_id }).await??;
+    if res.game_ended {
+      self.handle(ctx, Remove { game_id }).await;
+    } else {
+      self.remove_game_player(game_id, player_id);
+    }
+    Ok(())
+  }
+}
+
 pub struct UpdateGameNodeCache {
   pub game_id: i32,
   pub node_id: Option<i32>,
@@ -0,0 +1,133 @@
+use crate::error::*;
+use crate::game::state::GameActor;
+use crate::game::{GameLagSettings, GameLobbySettings, GameStepSettings};
+use flo_net::packet::FloPacket;
+use flo_net::proto;
+use flo_state::{async_trait, Context, Handler, Message};
+use s2_grpc_utils::S2ProtoPack;
+
+pub struct UpdateLobbySettings {
+  pub player_id: i32,
+  pub settings: GameLobbySettings,
+}
+
+impl Message for UpdateLobbySettings {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<UpdateLobbySettings> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    UpdateLobbySettings {
+      player_id,
+      settings,
+    }: UpdateLobbySettings,
+  ) -> Result<()> {
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    if self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    let game_id = self.game_id;
+    let updated = self
+      .db
+      .exec({
+        let settings = settings.clone();
+        move |conn| crate::game::db::update_lobby_settings(conn, game_id, player_id, settings)
+      })
+      .await?;
+
+    if !updated.updated_indexes.is_empty() {
+      self
+        .broadcast_slot_updates(&updated.slots, updated.updated_indexes)
+        .await?;
+    }
+
+    let frame = proto::flo_connect::PacketGameLobbySettingsUpdate {
+      game_id,
+      settings: Some(settings.pack()?),
+    }
+    .encode_as_frame()?;
+    self
+      .player_reg
+      .broadcast(self.players.clone(), frame)
+      .await?;
+
+    Ok(())
+  }
+}
+
+pub struct UpdateNodeSettings {
+  pub player_id: i32,
+  pub lag: Option<GameLagSettings>,
+  pub step: Option<GameStepSettings>,
+  pub add_referee_player_ids: Vec<i32>,
+  pub remove_referee_player_ids: Vec<i32>,
+}
+
+impl Message for UpdateNodeSettings {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<UpdateNodeSettings> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    UpdateNodeSettings {
+      player_id,
+      lag,
+      step,
+      add_referee_player_ids,
+      remove_referee_player_ids,
+    }: UpdateNodeSettings,
+  ) -> Result<()> {
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    if self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    if let Some(lag) = lag.as_ref() {
+      lag.validate()?;
+    }
+
+    if let Some(step) = step.as_ref() {
+      step.validate()?;
+    }
+
+    // referees must be in the lobby when they are assigned
+    if add_referee_player_ids
+      .iter()
+      .any(|id| !self.players.contains(id))
+    {
+      return Err(Error::PlayerNotInGame);
+    }
+
+    let game_id = self.game_id;
+    self
+      .db
+      .exec(move |conn| {
+        crate::game::db::update_node_settings(conn, game_id, move |settings| {
+          if let Some(lag) = lag {
+            settings.lag.replace(lag);
+          }
+          if let Some(step) = step {
+            settings.step.replace(step);
+          }
+          settings.update_referees(&add_referee_player_ids, &remove_referee_player_ids);
+          Ok(())
+        })
+      })
+      .await?;
+
+    Ok(())
+  }
+}
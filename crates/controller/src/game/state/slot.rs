@@ -42,11 +42,83 @@ impl Handler<UpdateSlot> for GameActor {
           if !info.is_slot_owner(player_id) {
             return Err(Error::GameSlotUpdateDenied);
           }
+          // only the host can update a locked slot
+          if info.host_player_id != player_id
+            && crate::game::db::get_locked_slots(conn, game_id)?.contains(&slot_index)
+          {
+            return Err(Error::GameSlotUpdateDenied);
+          }
+          let lobby_settings = crate::game::db::get_lobby_settings(conn, game_id)?;
+          let settings = SlotSettings {
+            handicap: lobby_settings.clamp_handicap(settings.handicap),
+            ..settings
+          };
           crate::game::db::update_slot_settings(conn, game_id, slot_index, settings)
         })
       })
       .await?;
 
+    self.broadcast_slot_updates(&slots, updated_indexes).await?;
+
+    Ok(slots)
+  }
+}
+
+pub struct LockSlot {
+  pub player_id: i32,
+  pub slot_index: i32,
+  pub locked: bool,
+}
+
+impl Message for LockSlot {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<LockSlot> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    LockSlot {
+      player_id,
+      slot_index,
+      locked,
+    }: LockSlot,
+  ) -> Result<()> {
+    if player_id != self.host_player {
+      return Err(Error::GameSlotUpdateDenied);
+    }
+
+    let game_id = self.game_id;
+    let changed = self
+      .db
+      .exec(move |conn| crate::game::db::set_slot_locked(conn, game_id, slot_index, locked))
+      .await?;
+
+    if changed {
+      let frame = proto::flo_connect::PacketGameSlotLockUpdate {
+        game_id,
+        slot_index,
+        locked,
+      }
+      .encode_as_frame()?;
+      self
+        .player_reg
+        .broadcast(self.players.clone(), frame)
+        .await?;
+    }
+
+    Ok(())
+  }
+}
+
+impl GameActor {
+  pub(crate) async fn broadcast_slot_updates(
+    &self,
+    slots: &[Slot],
+    updated_indexes: Vec<i32>,
+  ) -> Result<()> {
+    let game_id = self.game_id;
     let mut frames_slot_update = Vec::with_capacity(updated_indexes.len());
 
     for index in updated_indexes {
@@ -71,6 +143,6 @@ impl Handler<UpdateSlot> for GameActor {
       .broadcast(players, frames_slot_update)
       .await?;
 
-    Ok(slots)
+    Ok(())
   }
 }
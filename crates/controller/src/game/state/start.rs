@@ -45,6 +45,8 @@ impl Handler<StartGameCheck> for GameActor {
       return Err(Error::GameStarted);
     }
 
+    self.cancel_ready_check().await?;
+
     self.start_state = StartGameState::new(game_id, ctx.addr(), players, None)
       .start()
       .into();
@@ -111,15 +113,27 @@ impl GameActor {
       return Ok(Err(pkt));
     }
 
-    let (game, ban_list_map) = self
+    let game_version = agreed_version.clone();
+    let (game, settings, lobby_settings, ban_list_map) = self
       .db
       .exec(move |conn| {
-        let game = crate::game::db::get_full(conn, game_id)?;
+        let mut game = crate::game::db::get_full(conn, game_id)?;
+        game.game_version = game_version;
+        let settings = crate::game::db::get_node_settings(conn, game_id)?;
+        let lobby_settings = crate::game::db::get_lobby_settings(conn, game_id)?;
         let players = game.get_player_ids();
-        Ok::<_, Error>((game, crate::player::db::get_ban_list_map(conn, &players)?))
+        Ok::<_, Error>((
+          game,
+          settings,
+          lobby_settings,
+          crate::player::db::get_ban_list_map(conn, &players)?,
+        ))
       })
       .await?;
 
+    // applied to the LAN game by the clients
+    let game_setting_flags = lobby_settings.game_setting_flags().bits();
+
     let node_id = if let Some(id) = game.node.as_ref().map(|node| node.id) {
       id
     } else {
@@ -128,7 +142,18 @@ impl GameActor {
 
     let created = self
       .nodes
-      .send_to(node_id, NodeCreateGame { game, ban_list_map })
+      .send_to(
+        node_id,
+        NodeCreateGame {
+          game,
+          settings,
+          ban_list_map,
+          game_version_map: map
+            .iter()
+            .map(|(player_id, req)| (*player_id, req.war3_version.clone()))
+            .collect(),
+        },
+      )
       .await?
       .await
       .or_cancelled();
@@ -200,6 +225,7 @@ impl GameActor {
             game_id,
             player_id: *player_id,
             player_token: token.to_vec(),
+            game_setting_flags,
           })
         } else {
           tracing::error!(game_id, player_id, "player token was not found");
@@ -573,6 +599,8 @@ impl Handler<StartGameCheckAsBot> for GameActor {
       return Err(Error::GameStarted);
     }
 
+    self.cancel_ready_check().await?;
+
     self.start_state = StartGameState::new(game_id, ctx.addr(), players, Some(tx))
       .start()
       .into();
@@ -0,0 +1,199 @@
+use crate::error::*;
+use crate::game::db::UpdateSlotSettings;
+use crate::game::state::GameActor;
+use crate::game::{GameStatus, Slot};
+use flo_net::packet::FloPacket;
+use flo_net::proto;
+use flo_state::{async_trait, Context, Handler, Message};
+use std::collections::BTreeSet;
+
+/// A host requested slot swap waiting for the consent of both players
+#[derive(Debug)]
+pub struct PendingSlotSwap {
+  pub slot_index_a: i32,
+  pub slot_index_b: i32,
+  pub player_ids: [i32; 2],
+  pub accepted: BTreeSet<i32>,
+}
+
+impl PendingSlotSwap {
+  fn is_accepted(&self) -> bool {
+    self.player_ids.iter().all(|id| self.accepted.contains(id))
+  }
+}
+
+pub struct RequestSlotSwap {
+  pub player_id: i32,
+  pub slot_index_a: i32,
+  pub slot_index_b: i32,
+}
+
+impl Message for RequestSlotSwap {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<RequestSlotSwap> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    RequestSlotSwap {
+      player_id,
+      slot_index_a,
+      slot_index_b,
+    }: RequestSlotSwap,
+  ) -> Result<()> {
+    if player_id != self.host_player {
+      return Err(Error::GameSlotUpdateDenied);
+    }
+
+    if self.status != GameStatus::Preparing {
+      return Err(Error::GameStarted);
+    }
+
+    let game_id = self.game_id;
+    let game = self
+      .db
+      .exec(move |conn| crate::game::db::get_full(conn, game_id))
+      .await?;
+
+    let get_player_id = |index: i32| -> Option<i32> {
+      if index < 0 || index > 23 {
+        return None;
+      }
+      game.slots[index as usize].player.as_ref().map(|p| p.id)
+    };
+    let player_ids = match (get_player_id(slot_index_a), get_player_id(slot_index_b)) {
+      (Some(a), Some(b)) if slot_index_a != slot_index_b => [a, b],
+      _ => return Err(Error::GameSlotUpdateDenied),
+    };
+
+    // the host consents by requesting
+    let mut accepted = BTreeSet::new();
+    if player_ids.contains(&player_id) {
+      accepted.insert(player_id);
+    }
+
+    // replaces the previous request
+    self.pending_slot_swap = Some(PendingSlotSwap {
+      slot_index_a,
+      slot_index_b,
+      player_ids,
+      accepted,
+    });
+    self
+      .broadcast_slot_swap_status(proto::flo_connect::GameSlotSwapStatus::Pending)
+      .await?;
+
+    Ok(())
+  }
+}
+
+pub struct RespondSlotSwap {
+  pub player_id: i32,
+  pub accept: bool,
+}
+
+impl Message for RespondSlotSwap {
+  type Result = Result<Option<Vec<Slot>>>;
+}
+
+#[async_trait]
+impl Handler<RespondSlotSwap> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    RespondSlotSwap { player_id, accept }: RespondSlotSwap,
+  ) -> Result<Option<Vec<Slot>>> {
+    let pending = match self.pending_slot_swap.as_mut() {
+      Some(pending) if pending.player_ids.contains(&player_id) => pending,
+      _ => return Err(Error::GameSlotSwapNotFound),
+    };
+
+    if !accept {
+      self
+        .broadcast_slot_swap_status(proto::flo_connect::GameSlotSwapStatus::Declined)
+        .await?;
+      self.pending_slot_swap.take();
+      return Ok(None);
+    }
+
+    pending.accepted.insert(player_id);
+    if !pending.is_accepted() {
+      self
+        .broadcast_slot_swap_status(proto::flo_connect::GameSlotSwapStatus::Pending)
+        .await?;
+      return Ok(None);
+    }
+
+    let game_id = self.game_id;
+    let (slot_index_a, slot_index_b, player_ids) = (
+      pending.slot_index_a,
+      pending.slot_index_b,
+      pending.player_ids,
+    );
+    let res = self
+      .db
+      .exec(move |conn| {
+        crate::game::db::update_slots(conn, game_id, |slots| {
+          // players might have moved since the request
+          let current = |index: i32| slots[index as usize].player.as_ref().map(|p| p.id);
+          if current(slot_index_a) != Some(player_ids[0])
+            || current(slot_index_b) != Some(player_ids[1])
+          {
+            return Err(Error::GameSlotSwapNotFound);
+          }
+          slots
+            .swap_slots(slot_index_a, slot_index_b)
+            .map(|updated| updated.into_iter().map(|(index, _)| index).collect())
+            .ok_or_else(|| Error::GameSlotUpdateDenied)
+        })
+      })
+      .await;
+
+    let UpdateSlotSettings {
+      slots,
+      updated_indexes,
+    } = match res {
+      Ok(res) => res,
+      Err(err) => {
+        self
+          .broadcast_slot_swap_status(proto::flo_connect::GameSlotSwapStatus::Declined)
+          .await?;
+        self.pending_slot_swap.take();
+        return Err(err);
+      }
+    };
+
+    self
+      .broadcast_slot_swap_status(proto::flo_connect::GameSlotSwapStatus::Accepted)
+      .await?;
+    self.pending_slot_swap.take();
+    self.broadcast_slot_updates(&slots, updated_indexes).await?;
+
+    Ok(Some(slots))
+  }
+}
+
+impl GameActor {
+  async fn broadcast_slot_swap_status(
+    &self,
+    status: proto::flo_connect::GameSlotSwapStatus,
+  ) -> Result<()> {
+    if let Some(pending) = self.pending_slot_swap.as_ref() {
+      let mut packet = proto::flo_connect::PacketGameSlotSwapStatus {
+        game_id: self.game_id,
+        slot_index_a: pending.slot_index_a,
+        slot_index_b: pending.slot_index_b,
+        accepted_player_ids: pending.accepted.iter().cloned().collect(),
+        ..Default::default()
+      };
+      packet.set_status(status);
+      self
+        .player_reg
+        .broadcast(self.players.clone(), packet.encode_as_frame()?)
+        .await?;
+    }
+    Ok(())
+  }
+}
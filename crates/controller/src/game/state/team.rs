@@ -0,0 +1,119 @@
+use crate::error::*;
+use crate::game::db::UpdateSlotSettings;
+use crate::game::state::GameActor;
+use crate::game::Slot;
+use flo_state::{async_trait, Context, Handler, Message};
+use std::collections::HashMap;
+
+pub struct ShuffleTeams {
+  pub player_id: i32,
+}
+
+impl Message for ShuffleTeams {
+  type Result = Result<Vec<Slot>>;
+}
+
+#[async_trait]
+impl Handler<ShuffleTeams> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    ShuffleTeams { player_id }: ShuffleTeams,
+  ) -> Result<Vec<Slot>> {
+    if player_id != self.host_player {
+      return Err(Error::GameSlotUpdateDenied);
+    }
+
+    let game_id = self.game_id;
+    let UpdateSlotSettings {
+      slots,
+      updated_indexes,
+    } = self
+      .db
+      .exec(move |conn| {
+        crate::game::db::update_slots(conn, game_id, |slots| {
+          Ok(slots.shuffle_teams(&mut rand::thread_rng()))
+        })
+      })
+      .await?;
+
+    self.broadcast_slot_updates(&slots, updated_indexes).await?;
+
+    Ok(slots)
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BalanceTeamsBy {
+  /// Rating in a rating pool of the game creator's API client,
+  /// defaults to the first pool
+  Rating { pool_id: Option<i32> },
+  /// Ping to the selected node
+  Ping,
+}
+
+pub struct BalanceTeams {
+  pub player_id: i32,
+  pub by: BalanceTeamsBy,
+}
+
+impl Message for BalanceTeams {
+  type Result = Result<Vec<Slot>>;
+}
+
+#[async_trait]
+impl Handler<BalanceTeams> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    BalanceTeams { player_id, by }: BalanceTeams,
+  ) -> Result<Vec<Slot>> {
+    if player_id != self.host_player {
+      return Err(Error::GameSlotUpdateDenied);
+    }
+
+    let game_id = self.game_id;
+    let values: HashMap<i32, f64> = match by {
+      BalanceTeamsBy::Rating { pool_id } => {
+        self
+          .db
+          .exec(move |conn| crate::rating::db::get_game_rating_values(conn, game_id, pool_id))
+          .await?
+      }
+      BalanceTeamsBy::Ping => {
+        let node_id = self
+          .selected_node_id
+          .ok_or_else(|| Error::GameNodeNotSelected)?;
+        let snapshot = self
+          .player_reg
+          .get_ping_snapshot(self.players.clone())
+          .await?;
+        snapshot
+          .map
+          .into_iter()
+          .filter_map(|(player_id, node_map)| {
+            let stats = node_map.get(&node_id)?;
+            stats
+              .avg
+              .or(stats.current)
+              .map(|ping| (player_id, ping as f64))
+          })
+          .collect()
+      }
+    };
+
+    let UpdateSlotSettings {
+      slots,
+      updated_indexes,
+    } = self
+      .db
+      .exec(move |conn| {
+        crate::game::db::update_slots(conn, game_id, |slots| Ok(slots.balance_teams(&values)))
+      })
+      .await?;
+
+    self.broadcast_slot_updates(&slots, updated_indexes).await?;
+
+    Ok(slots)
+  }
+}
@@ -1,3 +1,4 @@
+use crate::error::Error;
 use crate::map::Map;
 use crate::node::{NodeRef, NodeRefColumns};
 use crate::player::{PlayerRef, PlayerRefColumns};
@@ -5,6 +6,7 @@ use crate::schema::{game, game_used_slot};
 use bs_diesel_utils::BSDieselEnum;
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use flo_w3gs::constants::GameSettingFlags;
 use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack, S2ProtoUnpack};
 use serde::{Deserialize, Serialize};
 
@@ -88,6 +90,288 @@ impl Game {
   }
 }
 
+/// Settings forwarded to the node when the game is created
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GameNodeSettings {
+  #[serde(default)]
+  pub step: Option<GameStepSettings>,
+  #[serde(default)]
+  pub lag: Option<GameLagSettings>,
+  /// Players allowed to use the referee in-game commands
+  #[serde(default)]
+  pub referee_player_ids: Vec<i32>,
+}
+
+impl GameNodeSettings {
+  pub fn update_referees(&mut self, add_player_ids: &[i32], remove_player_ids: &[i32]) {
+    self
+      .referee_player_ids
+      .retain(|id| !remove_player_ids.contains(id));
+    for id in add_player_ids {
+      if !self.referee_player_ids.contains(id) {
+        self.referee_player_ids.push(*id);
+      }
+    }
+  }
+}
+
+/// Game step (action batching interval), zero values use the node defaults
+#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Clone, Default)]
+#[s2_grpc(message_type(flo_net::proto::flo_node::GameStepSettings))]
+pub struct GameStepSettings {
+  pub initial_ms: u32,
+  pub adaptive: bool,
+  pub min_ms: u32,
+  pub max_ms: u32,
+}
+
+/// Step range accepted by the node
+const STEP_MIN_MS: u32 = 15;
+const STEP_MAX_MS: u32 = 250;
+
+impl GameStepSettings {
+  pub fn validate(&self) -> Result<(), Error> {
+    let in_range = |v: u32| v == 0 || (STEP_MIN_MS..=STEP_MAX_MS).contains(&v);
+    if !in_range(self.initial_ms) || !in_range(self.min_ms) || !in_range(self.max_ms) {
+      return Err(Error::GameNodeSettingsInvalid("step is out of range"));
+    }
+    if self.min_ms != 0 && self.max_ms != 0 && self.min_ms > self.max_ms {
+      return Err(Error::GameNodeSettingsInvalid(
+        "minimum step exceeds the maximum step",
+      ));
+    }
+    Ok(())
+  }
+}
+
+/// Lag screen and drop vote policy, zero values use the node defaults
+#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Clone, Default)]
+#[s2_grpc(message_type(flo_net::proto::flo_node::GameLagSettings))]
+pub struct GameLagSettings {
+  pub lagging_threshold_ms: u32,
+  pub drop_min_wait_ms: u32,
+  pub player_lag_budget_ms: u32,
+  pub auto_drop_ms: u32,
+  pub exclude_teammate_votes: bool,
+}
+
+const LAG_MAX_LAGGING_THRESHOLD_MS: u32 = 10_000;
+/// The node stops waiting for lagging players after this long
+const LAG_MAX_AUTO_DROP_MS: u32 = 57_000;
+
+impl GameLagSettings {
+  pub fn validate(&self) -> Result<(), Error> {
+    if self.lagging_threshold_ms > LAG_MAX_LAGGING_THRESHOLD_MS {
+      return Err(Error::GameNodeSettingsInvalid(
+        "lagging threshold is too long",
+      ));
+    }
+    if self.auto_drop_ms > LAG_MAX_AUTO_DROP_MS {
+      return Err(Error::GameNodeSettingsInvalid("auto drop time is too long"));
+    }
+    let auto_drop_ms = if self.auto_drop_ms == 0 {
+      LAG_MAX_AUTO_DROP_MS
+    } else {
+      self.auto_drop_ms
+    };
+    if self.drop_min_wait_ms > auto_drop_ms {
+      return Err(Error::GameNodeSettingsInvalid(
+        "drop vote wait time exceeds the auto drop time",
+      ));
+    }
+    Ok(())
+  }
+}
+
+/// Bits of `flo_w3map::MapFlags` the lobby settings are checked against
+const MAP_FLAG_MELEE: u32 = 0x0004;
+const MAP_FLAG_FIXED_PLAYER_SETTINGS: u32 = 0x0020;
+/// Maps outside the catalogue are checked as custom maps with fixed player settings
+pub const UNKNOWN_MAP_FLAGS: u32 = MAP_FLAG_FIXED_PLAYER_SETTINGS;
+
+/// Game options chosen by the host in the lobby
+#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Clone, PartialEq)]
+#[s2_grpc(message_type(flo_net::proto::flo_connect::GameLobbySettings))]
+#[serde(default)]
+pub struct GameLobbySettings {
+  #[s2_grpc(proto_enum)]
+  pub speed: GameSpeed,
+  #[s2_grpc(proto_enum)]
+  pub visibility: GameTerrainVisibility,
+  #[s2_grpc(proto_enum)]
+  pub observers: GameObserverMode,
+  pub teams_together: bool,
+  pub fixed_teams: bool,
+  pub random_races: bool,
+  pub random_heroes: bool,
+  pub min_handicap: i32,
+  pub max_handicap: i32,
+}
+
+impl Default for GameLobbySettings {
+  fn default() -> Self {
+    GameLobbySettings {
+      speed: GameSpeed::Fast,
+      visibility: GameTerrainVisibility::Default,
+      observers: GameObserverMode::Full,
+      teams_together: true,
+      fixed_teams: true,
+      random_races: false,
+      random_heroes: false,
+      min_handicap: 50,
+      max_handicap: 100,
+    }
+  }
+}
+
+impl GameLobbySettings {
+  pub fn validate(&self, map_flags: u32) -> Result<(), Error> {
+    if self.min_handicap < 50
+      || self.max_handicap > 100
+      || self.min_handicap > self.max_handicap
+      || self.min_handicap % 10 != 0
+      || self.max_handicap % 10 != 0
+    {
+      return Err(Error::GameLobbySettingsInvalid("invalid handicap range"));
+    }
+    if map_flags & MAP_FLAG_FIXED_PLAYER_SETTINGS != 0 {
+      if self.random_races {
+        return Err(Error::GameLobbySettingsInvalid(
+          "the map does not allow random races",
+        ));
+      }
+      if !self.fixed_teams {
+        return Err(Error::GameLobbySettingsInvalid(
+          "the map requires fixed teams",
+        ));
+      }
+    }
+    if map_flags & MAP_FLAG_MELEE == 0 && self.random_heroes {
+      return Err(Error::GameLobbySettingsInvalid(
+        "random heroes require a melee map",
+      ));
+    }
+    Ok(())
+  }
+
+  /// Clamps a slot handicap to the allowed range
+  pub fn clamp_handicap(&self, handicap: i32) -> i32 {
+    std::cmp::min(
+      self.max_handicap,
+      std::cmp::max(self.min_handicap, handicap - (handicap % 10)),
+    )
+  }
+
+  pub fn game_setting_flags(&self) -> GameSettingFlags {
+    let mut flags = match self.speed {
+      GameSpeed::Slow => GameSettingFlags::SPEED_SLOW,
+      GameSpeed::Normal => GameSettingFlags::SPEED_NORMAL,
+      GameSpeed::Fast => GameSettingFlags::SPEED_FAST,
+    };
+    flags |= match self.visibility {
+      GameTerrainVisibility::Default => GameSettingFlags::TERRAIN_DEFAULT,
+      GameTerrainVisibility::Hidden => GameSettingFlags::TERRAIN_HIDDEN,
+      GameTerrainVisibility::Explored => GameSettingFlags::TERRAIN_EXPLORED,
+      GameTerrainVisibility::AlwaysVisible => GameSettingFlags::TERRAIN_VISIBLE,
+    };
+    flags |= match self.observers {
+      GameObserverMode::None => GameSettingFlags::OBS_NONE,
+      GameObserverMode::OnDefeat => GameSettingFlags::OBS_ON_DEFEAT,
+      GameObserverMode::Full => GameSettingFlags::OBS_FULL,
+      GameObserverMode::Referees => GameSettingFlags::OBS_REFEREES,
+    };
+    if self.teams_together {
+      flags |= GameSettingFlags::TEAMS_TOGETHER;
+    }
+    if self.fixed_teams {
+      flags |= GameSettingFlags::TEAMS_FIXED;
+    }
+    if self.random_races {
+      flags |= GameSettingFlags::RANDOM_RACE;
+    }
+    if self.random_heroes {
+      flags |= GameSettingFlags::RANDOM_HERO;
+    }
+    flags
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_net::proto::flo_connect::GameSpeed))]
+pub enum GameSpeed {
+  Fast = 0,
+  Normal = 1,
+  Slow = 2,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_net::proto::flo_connect::GameTerrainVisibility))]
+pub enum GameTerrainVisibility {
+  Default = 0,
+  Hidden = 1,
+  Explored = 2,
+  AlwaysVisible = 3,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_net::proto::flo_connect::GameObserverMode))]
+pub enum GameObserverMode {
+  Full = 0,
+  None = 1,
+  OnDefeat = 2,
+  Referees = 3,
+}
+
+/// Desync reported by the node after it has been resolved
+#[derive(Debug, Serialize, Deserialize, S2ProtoUnpack, Clone)]
+#[s2_grpc(message_type(flo_net::proto::flo_node::PacketNodeGameDesync))]
+pub struct GameDesync {
+  pub game_id: i32,
+  pub tick: u32,
+  pub player_ids: Vec<i32>,
+  pub dropped_player_ids: Vec<i32>,
+  #[s2_grpc(proto_enum)]
+  pub resolution: GameDesyncResolution,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_net::proto::flo_node::NodeGameDesyncResolution))]
+pub enum GameDesyncResolution {
+  /// Players with the minority checksum were dropped
+  MinorityDropped = 0,
+  /// No majority, the players agreed to continue
+  Continued = 1,
+  /// No majority, the game was ended
+  Aborted = 2,
+}
+
+/// Reported by the node when a player leaves a running game
+#[derive(Debug, S2ProtoUnpack, Clone)]
+#[s2_grpc(message_type(flo_net::proto::flo_node::PacketNodeGamePlayerResult))]
+pub struct GamePlayerResult {
+  pub game_id: i32,
+  pub player_id: i32,
+  #[s2_grpc(proto_enum)]
+  pub result: PlayerGameResult,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_net::proto::flo_node::NodeGamePlayerResult))]
+pub enum PlayerGameResult {
+  Unknown = 0,
+  Won = 1,
+  Lost = 2,
+  Draw = 3,
+  Disconnected = 4,
+  /// Ended by a remake vote
+  Aborted = 5,
+}
+
 #[derive(Debug)]
 pub struct PlayerSlotInfo<'a> {
   pub slot_index: usize,
@@ -334,3 +618,105 @@ impl SlotClientStatus {
     }
   }
 }
+
+#[test]
+fn test_game_lobby_settings() {
+  let settings = GameLobbySettings::default();
+  assert_eq!(settings.game_setting_flags(), GameSettingFlags::default());
+  assert_eq!(settings.clamp_handicap(75), 70);
+  assert_eq!(settings.clamp_handicap(0), 50);
+  assert!(settings.validate(0).is_ok());
+  assert!(settings.validate(UNKNOWN_MAP_FLAGS).is_ok());
+
+  let settings = GameLobbySettings {
+    observers: GameObserverMode::Referees,
+    random_races: true,
+    random_heroes: true,
+    min_handicap: 80,
+    ..Default::default()
+  };
+  assert!(settings
+    .game_setting_flags()
+    .contains(GameSettingFlags::OBS_REFEREES | GameSettingFlags::RANDOM_RACE));
+  assert_eq!(settings.clamp_handicap(50), 80);
+  assert!(settings.validate(MAP_FLAG_MELEE).is_ok());
+  assert!(settings.validate(0).is_err());
+  assert!(settings
+    .validate(MAP_FLAG_MELEE | MAP_FLAG_FIXED_PLAYER_SETTINGS)
+    .is_err());
+  assert!(settings.validate(UNKNOWN_MAP_FLAGS).is_err());
+}
+
+#[test]
+fn test_game_lag_settings() {
+  assert!(GameLagSettings::default().validate().is_ok());
+
+  let settings = GameLagSettings {
+    drop_min_wait_ms: 10_000,
+    player_lag_budget_ms: 120_000,
+    auto_drop_ms: 30_000,
+    ..Default::default()
+  };
+  assert!(settings.validate().is_ok());
+  assert!(GameLagSettings {
+    auto_drop_ms: 5_000,
+    ..settings.clone()
+  }
+  .validate()
+  .is_err());
+  assert!(GameLagSettings {
+    auto_drop_ms: 0,
+    drop_min_wait_ms: 60_000,
+    ..settings.clone()
+  }
+  .validate()
+  .is_err());
+  assert!(GameLagSettings {
+    lagging_threshold_ms: 60_000,
+    ..settings
+  }
+  .validate()
+  .is_err());
+}
+
+#[test]
+fn test_game_step_settings() {
+  assert!(GameStepSettings::default().validate().is_ok());
+
+  let settings = GameStepSettings {
+    initial_ms: 30,
+    adaptive: true,
+    min_ms: 20,
+    max_ms: 60,
+  };
+  assert!(settings.validate().is_ok());
+  assert!(GameStepSettings {
+    initial_ms: 5,
+    ..settings.clone()
+  }
+  .validate()
+  .is_err());
+  assert!(GameStepSettings {
+    max_ms: 500,
+    ..settings.clone()
+  }
+  .validate()
+  .is_err());
+  assert!(GameStepSettings {
+    min_ms: 100,
+    ..settings
+  }
+  .validate()
+  .is_err());
+}
+
+#[test]
+fn test_game_node_settings_update_referees() {
+  let mut settings = GameNodeSettings::default();
+  settings.update_referees(&[1, 2, 2], &[]);
+  assert_eq!(settings.referee_player_ids, vec![1, 2]);
+  settings.update_referees(&[3], &[1]);
+  assert_eq!(settings.referee_player_ids, vec![2, 3]);
+  settings.update_referees(&[], &[2, 3, 4]);
+  assert!(settings.referee_player_ids.is_empty());
+}
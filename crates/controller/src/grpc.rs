@@ -1,32 +1,58 @@
-use crate::config::{ApiRequestExt, GetInterceptor};
+use crate::config::{ApiRequestExt, GetInterceptor, REQUEST_META_RATING_POOL_ID};
+use crate::db::DbConn;
 use crate::error::{Error, Result};
-use crate::game::db::{CreateGameAsBotParams, CreateGameParams};
-use crate::game::messages::{CreateGame, PlayerJoin, PlayerLeave};
+use crate::game::db::{CreateGameAsBotParams, CreateGameParams, JoinAccess};
+use crate::game::messages::{
+  BalanceTeams, BalanceTeamsBy, CreateGame, LockSlot, PlayerJoin, PlayerLeave, RequestSlotSwap,
+  RespondSlotSwap, ShuffleTeams,
+};
 use crate::game::state::cancel::CancelGame;
 use crate::game::state::create::CreateGameAsBot;
 use crate::game::state::node::SelectNode;
 use crate::game::state::registry::{AddGamePlayer, Remove, RemoveGamePlayer, UpdateGameNodeCache};
 use crate::game::state::start::{StartGameCheckAsBot, StartGameCheckAsBotResult};
+use crate::game::{CreateGameSlot, SlotSettings};
+use crate::matchmaking::{MatchmakingQueue, MatchmakingQueueParams};
 use crate::node::messages::ListNode;
 use crate::player::state::ping::GetPlayersPingSnapshot;
 use crate::player::{PlayerBanType, PlayerSource, SourceState};
-use crate::state::{ActorMapExt, ControllerStateRef};
+use crate::rating::{PlayerRating, PlayerRatingHistory, RatingAlgorithm, RatingPool, Score};
+use crate::schedule::messages::{CancelScheduledGame, CreateScheduledGame};
+use crate::schedule::{CreateScheduledGameParams, ScheduledGame, ScheduledGameStatus};
+use crate::state::{ActorMapExt, ControllerStateRef, Reload};
+use crate::tournament::messages::{CancelTournament, CreateTournament, GetTournament};
+use crate::tournament::{
+  BracketSide, CreateTournamentParams, MatchSlotRef, Tournament, TournamentFormat, TournamentMatch,
+  TournamentMatchStatus, TournamentStatus,
+};
 use bs_diesel_utils::executor::ExecutorError;
 use chrono::{DateTime, Utc};
+use ext::flo_controller_ext_server::{FloControllerExt, FloControllerExtServer};
 use flo_grpc::controller::flo_controller_server::*;
 use flo_grpc::controller::*;
 use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack, S2ProtoUnpack};
+use std::convert::TryFrom;
 use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::SystemTime;
 use tonic::transport::Server;
 use tonic::{Request, Response, Status};
 
+/// Controller RPCs defined in this crate, see `src/proto/controller_ext.proto`
+pub mod ext {
+  tonic::include_proto!("flo_controller_ext");
+}
+
 pub async fn serve(state: ControllerStateRef) -> Result<()> {
   let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, flo_constants::CONTROLLER_GRPC_PORT);
   let server_impl = FloControllerService::new(state.clone());
+  let ext_server_impl = FloControllerExtService::new(state.clone());
 
   let interceptor = state.config.send(GetInterceptor).await?;
-  let server = FloControllerServer::with_interceptor(server_impl, interceptor);
-  let server = Server::builder().add_service(server);
+  let server = FloControllerServer::with_interceptor(server_impl, interceptor.clone());
+  let ext_server = FloControllerExtServer::with_interceptor(ext_server_impl, interceptor);
+  let server = Server::builder()
+    .add_service(server)
+    .add_service(ext_server);
   server.serve(addr.into()).await?;
   Ok(())
 }
@@ -162,6 +188,8 @@ impl FloController for FloControllerService {
       .state
       .games
       .send(CreateGame {
+        api_client_id: request.get_api_client_id(),
+        rating_pool_id: get_rating_pool_id(&request)?,
         params: CreateGameParams::unpack(request.into_inner()).map_err(Error::from)?,
       })
       .await
@@ -185,6 +213,7 @@ impl FloController for FloControllerService {
         params.game_id,
         PlayerJoin {
           player_id: params.player_id,
+          access: JoinAccess::Password(None),
         },
       )
       .await?;
@@ -241,6 +270,7 @@ impl FloController for FloControllerService {
         join_token.game_id,
         PlayerJoin {
           player_id: params.player_id,
+          access: JoinAccess::Token,
         },
       )
       .await?;
@@ -455,6 +485,7 @@ impl FloController for FloControllerService {
       .send(CreateGameAsBot {
         api_client_id: request.get_api_client_id(),
         api_player_id: request.get_api_player_id(),
+        rating_pool_id: get_rating_pool_id(&request)?,
         params: CreateGameAsBotParams::unpack(request.into_inner()).map_err(Error::from)?,
       })
       .await
@@ -600,3 +631,714 @@ impl FloController for FloControllerService {
     Ok(Response::new(()))
   }
 }
+
+/// Reads the rating pool from the `x-flo-rating-pool-id` metadata, the flo-grpc create requests
+/// have no field for it
+fn get_rating_pool_id<T>(request: &Request<T>) -> Result<Option<i32>, Status> {
+  request
+    .metadata()
+    .get(REQUEST_META_RATING_POOL_ID)
+    .map(|value| {
+      value
+        .to_str()
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| {
+          Status::invalid_argument(format!(
+            "invalid `{}` metadata",
+            REQUEST_META_RATING_POOL_ID
+          ))
+        })
+    })
+    .transpose()
+}
+
+/// Leaderboards and histories are paged by this many items at most
+const MAX_PAGE_SIZE: i64 = 100;
+
+pub struct FloControllerExtService {
+  state: ControllerStateRef,
+}
+
+impl FloControllerExtService {
+  pub fn new(state: ControllerStateRef) -> Self {
+    FloControllerExtService { state }
+  }
+}
+
+#[tonic::async_trait]
+impl FloControllerExt for FloControllerExtService {
+  async fn list_rating_pools(
+    &self,
+    request: Request<()>,
+  ) -> Result<Response<ext::ListRatingPoolsReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let pools = self
+      .state
+      .db
+      .exec(move |conn| crate::rating::db::get_pools(conn, api_client_id))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ext::ListRatingPoolsReply {
+      pools: pools.into_iter().map(pack_rating_pool).collect(),
+    }))
+  }
+
+  async fn create_rating_pool(
+    &self,
+    request: Request<ext::CreateRatingPoolRequest>,
+  ) -> Result<Response<ext::CreateRatingPoolReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let params = request.into_inner();
+    let algorithm = match params.algorithm() {
+      ext::RatingAlgorithm::Elo => RatingAlgorithm::Elo,
+      ext::RatingAlgorithm::Glicko2 => RatingAlgorithm::Glicko2,
+      ext::RatingAlgorithm::TrueSkill => RatingAlgorithm::TrueSkill,
+    };
+    let pool = self
+      .state
+      .db
+      .exec(move |conn| {
+        crate::rating::db::create_pool(conn, api_client_id, &params.name, algorithm)
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ext::CreateRatingPoolReply {
+      pool: Some(pack_rating_pool(pool)),
+    }))
+  }
+
+  async fn get_leaderboard(
+    &self,
+    request: Request<ext::GetLeaderboardRequest>,
+  ) -> Result<Response<ext::GetLeaderboardReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let params = request.into_inner();
+    let offset = params.offset.max(0);
+    let limit = get_page_size(params.limit);
+    let ratings = self
+      .state
+      .db
+      .exec(move |conn| {
+        crate::rating::db::get_leaderboard(conn, api_client_id, params.pool_id, offset, limit)
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ext::GetLeaderboardReply {
+      ratings: ratings.into_iter().map(pack_player_rating).collect(),
+    }))
+  }
+
+  async fn get_player_ratings(
+    &self,
+    request: Request<ext::GetPlayerRatingsRequest>,
+  ) -> Result<Response<ext::GetPlayerRatingsReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let player_id = request.into_inner().player_id;
+    let ratings = self
+      .state
+      .db
+      .exec(move |conn| crate::rating::db::get_player_ratings(conn, api_client_id, player_id))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ext::GetPlayerRatingsReply {
+      ratings: ratings.into_iter().map(pack_player_rating).collect(),
+    }))
+  }
+
+  async fn get_player_rating_history(
+    &self,
+    request: Request<ext::GetPlayerRatingHistoryRequest>,
+  ) -> Result<Response<ext::GetPlayerRatingHistoryReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let params = request.into_inner();
+    let limit = get_page_size(params.limit);
+    let items = self
+      .state
+      .db
+      .exec(move |conn| {
+        crate::rating::db::get_player_rating_history(
+          conn,
+          api_client_id,
+          params.pool_id,
+          params.player_id,
+          limit,
+        )
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ext::GetPlayerRatingHistoryReply {
+      items: items.into_iter().map(pack_player_rating_history).collect(),
+    }))
+  }
+
+  async fn list_matchmaking_queues(
+    &self,
+    request: Request<()>,
+  ) -> Result<Response<ext::ListMatchmakingQueuesReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let queues = self
+      .state
+      .db
+      .exec(move |conn| crate::matchmaking::db::list_queues(conn, api_client_id))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ext::ListMatchmakingQueuesReply {
+      queues: queues.into_iter().map(pack_matchmaking_queue).collect(),
+    }))
+  }
+
+  async fn create_matchmaking_queue(
+    &self,
+    request: Request<ext::CreateMatchmakingQueueRequest>,
+  ) -> Result<Response<ext::CreateMatchmakingQueueReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let api_player_id = request.get_api_player_id();
+    let config = request
+      .into_inner()
+      .config
+      .ok_or_else(|| Status::invalid_argument("config required"))?;
+    let queue = self
+      .state
+      .db
+      .exec(move |conn| {
+        let params = unpack_matchmaking_queue_config(conn, config)?;
+        crate::matchmaking::db::create_queue(conn, api_client_id, api_player_id, params)
+      })
+      .await
+      .map_err(Error::from)?;
+    self
+      .state
+      .matchmaking
+      .send(Reload)
+      .await
+      .map_err(Error::from)??;
+    Ok(Response::new(ext::CreateMatchmakingQueueReply {
+      queue: Some(pack_matchmaking_queue(queue)),
+    }))
+  }
+
+  async fn update_matchmaking_queue(
+    &self,
+    request: Request<ext::UpdateMatchmakingQueueRequest>,
+  ) -> Result<Response<ext::UpdateMatchmakingQueueReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let params = request.into_inner();
+    let id = params.id;
+    let config = params
+      .config
+      .ok_or_else(|| Status::invalid_argument("config required"))?;
+    let queue = self
+      .state
+      .db
+      .exec(move |conn| {
+        let params = unpack_matchmaking_queue_config(conn, config)?;
+        crate::matchmaking::db::update_queue(conn, api_client_id, id, params)
+      })
+      .await
+      .map_err(Error::from)?;
+    // Players of a disabled queue are removed and notified by the reload
+    self
+      .state
+      .matchmaking
+      .send(Reload)
+      .await
+      .map_err(Error::from)??;
+    Ok(Response::new(ext::UpdateMatchmakingQueueReply {
+      queue: Some(pack_matchmaking_queue(queue)),
+    }))
+  }
+
+  async fn shuffle_game_teams(
+    &self,
+    request: Request<ext::ShuffleGameTeamsRequest>,
+  ) -> Result<Response<()>, Status> {
+    let ext::ShuffleGameTeamsRequest { game_id, player_id } = request.into_inner();
+    self
+      .state
+      .games
+      .send_to(game_id, ShuffleTeams { player_id })
+      .await?;
+    Ok(Response::new(()))
+  }
+
+  async fn balance_game_teams(
+    &self,
+    request: Request<ext::BalanceGameTeamsRequest>,
+  ) -> Result<Response<()>, Status> {
+    let params = request.into_inner();
+    let by = match params.mode() {
+      ext::GameTeamBalanceMode::Rating => BalanceTeamsBy::Rating {
+        pool_id: params.rating_pool_id,
+      },
+      ext::GameTeamBalanceMode::Ping => BalanceTeamsBy::Ping,
+    };
+    self
+      .state
+      .games
+      .send_to(
+        params.game_id,
+        BalanceTeams {
+          player_id: params.player_id,
+          by,
+        },
+      )
+      .await?;
+    Ok(Response::new(()))
+  }
+
+  async fn request_game_slot_swap(
+    &self,
+    request: Request<ext::RequestGameSlotSwapRequest>,
+  ) -> Result<Response<()>, Status> {
+    let params = request.into_inner();
+    self
+      .state
+      .games
+      .send_to(
+        params.game_id,
+        RequestSlotSwap {
+          player_id: params.player_id,
+          slot_index_a: params.slot_index_a,
+          slot_index_b: params.slot_index_b,
+        },
+      )
+      .await?;
+    Ok(Response::new(()))
+  }
+
+  async fn respond_game_slot_swap(
+    &self,
+    request: Request<ext::RespondGameSlotSwapRequest>,
+  ) -> Result<Response<()>, Status> {
+    let params = request.into_inner();
+    self
+      .state
+      .games
+      .send_to(
+        params.game_id,
+        RespondSlotSwap {
+          player_id: params.player_id,
+          accept: params.accept,
+        },
+      )
+      .await?;
+    Ok(Response::new(()))
+  }
+
+  async fn lock_game_slot(
+    &self,
+    request: Request<ext::LockGameSlotRequest>,
+  ) -> Result<Response<()>, Status> {
+    let params = request.into_inner();
+    self
+      .state
+      .games
+      .send_to(
+        params.game_id,
+        LockSlot {
+          player_id: params.player_id,
+          slot_index: params.slot_index,
+          locked: params.locked,
+        },
+      )
+      .await?;
+    Ok(Response::new(()))
+  }
+
+  async fn create_scheduled_game(
+    &self,
+    request: Request<ext::CreateScheduledGameRequest>,
+  ) -> Result<Response<ext::CreateScheduledGameReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let api_player_id = request.get_api_player_id();
+    let params = request.into_inner();
+    let slots = params
+      .slots
+      .into_iter()
+      .map(unpack_game_slot)
+      .collect::<Result<Vec<_>>>()?;
+    let check_in_at = unpack_timestamp(params.check_in_at)?;
+    let start_at = unpack_timestamp(params.start_at)?;
+    let end_at = unpack_timestamp(params.end_at)?;
+    let map_sha1 = params.map_sha1;
+    let map = self
+      .state
+      .db
+      .exec(move |conn| crate::map::db::get_map(conn, &map_sha1))
+      .await
+      .map_err(Error::from)?;
+    let schedule = self
+      .state
+      .schedules
+      .send(CreateScheduledGame {
+        api_client_id,
+        api_player_id,
+        params: CreateScheduledGameParams {
+          name: params.name,
+          map,
+          node_id: params.node_id,
+          slots,
+          check_in_at,
+          start_at,
+          end_at,
+        },
+      })
+      .await
+      .map_err(Error::from)??;
+    Ok(Response::new(ext::CreateScheduledGameReply {
+      scheduled_game: Some(pack_scheduled_game(schedule)),
+    }))
+  }
+
+  async fn get_scheduled_game(
+    &self,
+    request: Request<ext::GetScheduledGameRequest>,
+  ) -> Result<Response<ext::GetScheduledGameReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let id = request.into_inner().id;
+    let schedule = self
+      .state
+      .db
+      .exec(move |conn| crate::schedule::db::get(conn, id))
+      .await
+      .map_err(Error::from)?;
+    if schedule.api_client_id != api_client_id {
+      return Err(Error::ScheduledGameNotFound.into());
+    }
+    Ok(Response::new(ext::GetScheduledGameReply {
+      scheduled_game: Some(pack_scheduled_game(schedule)),
+    }))
+  }
+
+  async fn cancel_scheduled_game(
+    &self,
+    request: Request<ext::CancelScheduledGameRequest>,
+  ) -> Result<Response<()>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let id = request.into_inner().id;
+    self
+      .state
+      .schedules
+      .send(CancelScheduledGame { api_client_id, id })
+      .await
+      .map_err(Error::from)??;
+    Ok(Response::new(()))
+  }
+
+  async fn create_tournament(
+    &self,
+    request: Request<ext::CreateTournamentRequest>,
+  ) -> Result<Response<ext::CreateTournamentReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let api_player_id = request.get_api_player_id();
+    let params = request.into_inner();
+    let format = match params.format() {
+      ext::TournamentFormat::SingleElimination => TournamentFormat::SingleElimination,
+      ext::TournamentFormat::DoubleElimination => TournamentFormat::DoubleElimination,
+      ext::TournamentFormat::RoundRobin => TournamentFormat::RoundRobin,
+      ext::TournamentFormat::Swiss => TournamentFormat::Swiss,
+    };
+    let map_sha1s = params.map_sha1s;
+    let map_pool = self
+      .state
+      .db
+      .exec(move |conn| crate::map::db::get_maps(conn, &map_sha1s))
+      .await
+      .map_err(Error::from)?;
+    let detail = self
+      .state
+      .tournaments
+      .send(CreateTournament {
+        api_client_id,
+        api_player_id,
+        params: CreateTournamentParams {
+          name: params.name,
+          format,
+          best_of: params.best_of,
+          map_pool,
+          node_id: params.node_id,
+          check_in_minutes: params.check_in_minutes,
+          player_ids: params.player_ids,
+        },
+      })
+      .await
+      .map_err(Error::from)??;
+    Ok(Response::new(ext::CreateTournamentReply {
+      tournament: Some(pack_tournament(detail.tournament)),
+      matches: detail
+        .matches
+        .into_iter()
+        .map(pack_tournament_match)
+        .collect(),
+    }))
+  }
+
+  async fn get_tournament(
+    &self,
+    request: Request<ext::GetTournamentRequest>,
+  ) -> Result<Response<ext::GetTournamentReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let id = request.into_inner().id;
+    let detail = self
+      .state
+      .tournaments
+      .send(GetTournament { id })
+      .await
+      .map_err(Error::from)??;
+    if detail.tournament.api_client_id != api_client_id {
+      return Err(Error::TournamentNotFound.into());
+    }
+    Ok(Response::new(ext::GetTournamentReply {
+      tournament: Some(pack_tournament(detail.tournament)),
+      matches: detail
+        .matches
+        .into_iter()
+        .map(pack_tournament_match)
+        .collect(),
+    }))
+  }
+
+  async fn cancel_tournament(
+    &self,
+    request: Request<ext::CancelTournamentRequest>,
+  ) -> Result<Response<()>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let id = request.into_inner().id;
+    self
+      .state
+      .tournaments
+      .send(CancelTournament { api_client_id, id })
+      .await
+      .map_err(Error::from)??;
+    Ok(Response::new(()))
+  }
+}
+
+fn get_page_size(limit: Option<i64>) -> i64 {
+  limit.unwrap_or(MAX_PAGE_SIZE).max(0).min(MAX_PAGE_SIZE)
+}
+
+fn pack_rating_pool(pool: RatingPool) -> ext::RatingPool {
+  ext::RatingPool {
+    id: pool.id,
+    name: pool.name,
+    algorithm: match pool.algorithm {
+      RatingAlgorithm::Elo => ext::RatingAlgorithm::Elo,
+      RatingAlgorithm::Glicko2 => ext::RatingAlgorithm::Glicko2,
+      RatingAlgorithm::TrueSkill => ext::RatingAlgorithm::TrueSkill,
+    }
+    .into(),
+    created_at: Some(SystemTime::from(pool.created_at).into()),
+  }
+}
+
+fn pack_player_rating(rating: PlayerRating) -> ext::PlayerRating {
+  ext::PlayerRating {
+    pool_id: rating.pool_id,
+    player_id: rating.player_id,
+    rating: rating.rating,
+    deviation: rating.deviation,
+    volatility: rating.volatility,
+    score: rating.score,
+    games: rating.games,
+    wins: rating.wins,
+    losses: rating.losses,
+    draws: rating.draws,
+    updated_at: Some(SystemTime::from(rating.updated_at).into()),
+  }
+}
+
+fn pack_player_rating_history(item: PlayerRatingHistory) -> ext::PlayerRatingHistory {
+  ext::PlayerRatingHistory {
+    pool_id: item.pool_id,
+    player_id: item.player_id,
+    game_id: item.game_id,
+    rating_before: item.rating_before,
+    rating_after: item.rating_after,
+    deviation_before: item.deviation_before,
+    deviation_after: item.deviation_after,
+    result: match item.result {
+      Score::Loss => ext::RatingResult::Loss,
+      Score::Draw => ext::RatingResult::Draw,
+      Score::Win => ext::RatingResult::Win,
+    }
+    .into(),
+    created_at: Some(SystemTime::from(item.created_at).into()),
+  }
+}
+
+fn pack_matchmaking_queue(queue: MatchmakingQueue) -> ext::MatchmakingQueue {
+  ext::MatchmakingQueue {
+    id: queue.id,
+    config: Some(ext::MatchmakingQueueConfig {
+      name: queue.name,
+      team_size: queue.team_size,
+      region: queue.region,
+      map_sha1s: queue
+        .map_pool
+        .iter()
+        .map(|map| map.sha1.to_hex_string())
+        .collect(),
+      rating_pool_id: queue.rating_pool_id,
+      rating_spread: queue.rating_spread,
+      rating_spread_per_minute: queue.rating_spread_per_minute,
+      max_ping: queue.max_ping,
+      enabled: queue.enabled,
+    }),
+  }
+}
+
+fn unpack_matchmaking_queue_config(
+  conn: &DbConn,
+  config: ext::MatchmakingQueueConfig,
+) -> Result<MatchmakingQueueParams> {
+  Ok(MatchmakingQueueParams {
+    map_pool: crate::map::db::get_maps(conn, &config.map_sha1s)?,
+    name: config.name,
+    team_size: config.team_size,
+    region: config.region,
+    rating_pool_id: config.rating_pool_id,
+    rating_spread: config.rating_spread,
+    rating_spread_per_minute: config.rating_spread_per_minute,
+    max_ping: config.max_ping,
+    enabled: config.enabled,
+  })
+}
+
+fn unpack_timestamp(value: Option<prost_types::Timestamp>) -> Result<DateTime<Utc>, Status> {
+  value
+    .and_then(|value| SystemTime::try_from(value).ok())
+    .map(DateTime::from)
+    .ok_or_else(|| Status::invalid_argument("invalid timestamp"))
+}
+
+fn unpack_game_slot(slot: ext::GameSlot) -> Result<CreateGameSlot> {
+  let settings = slot.settings.unwrap_or_default();
+  Ok(CreateGameSlot {
+    player_id: slot.player_id,
+    settings: SlotSettings::unpack(flo_grpc::game::SlotSettings {
+      team: settings.team,
+      color: settings.color,
+      computer: settings.computer,
+      handicap: settings.handicap,
+      status: settings.status,
+      race: settings.race,
+    })?,
+  })
+}
+
+fn pack_game_slot(slot: CreateGameSlot) -> ext::GameSlot {
+  ext::GameSlot {
+    player_id: slot.player_id,
+    settings: Some(ext::GameSlotSettings {
+      team: slot.settings.team,
+      color: slot.settings.color,
+      computer: slot.settings.computer as i32,
+      handicap: slot.settings.handicap,
+      status: slot.settings.status as i32,
+      race: slot.settings.race as i32,
+    }),
+  }
+}
+
+fn pack_scheduled_game(schedule: ScheduledGame) -> ext::ScheduledGame {
+  ext::ScheduledGame {
+    id: schedule.id,
+    name: schedule.name,
+    map_sha1: schedule.map.sha1.to_hex_string(),
+    node_id: schedule.node_id,
+    slots: schedule.slots.into_iter().map(pack_game_slot).collect(),
+    check_in_at: Some(SystemTime::from(schedule.check_in_at).into()),
+    start_at: Some(SystemTime::from(schedule.start_at).into()),
+    end_at: Some(SystemTime::from(schedule.end_at).into()),
+    status: match schedule.status {
+      ScheduledGameStatus::Scheduled => ext::ScheduledGameStatus::Scheduled,
+      ScheduledGameStatus::Started => ext::ScheduledGameStatus::Started,
+      ScheduledGameStatus::Forfeited => ext::ScheduledGameStatus::Forfeited,
+      ScheduledGameStatus::Cancelled => ext::ScheduledGameStatus::Cancelled,
+      ScheduledGameStatus::Failed => ext::ScheduledGameStatus::Failed,
+    }
+    .into(),
+    checked_in_player_ids: schedule.checked_in_player_ids,
+    no_show_player_ids: schedule.no_show_player_ids,
+    game_id: schedule.game_id,
+  }
+}
+
+fn pack_tournament(tournament: Tournament) -> ext::Tournament {
+  ext::Tournament {
+    id: tournament.id,
+    name: tournament.name,
+    format: match tournament.format {
+      TournamentFormat::SingleElimination => ext::TournamentFormat::SingleElimination,
+      TournamentFormat::DoubleElimination => ext::TournamentFormat::DoubleElimination,
+      TournamentFormat::RoundRobin => ext::TournamentFormat::RoundRobin,
+      TournamentFormat::Swiss => ext::TournamentFormat::Swiss,
+    }
+    .into(),
+    best_of: tournament.best_of,
+    map_sha1s: tournament
+      .map_pool
+      .iter()
+      .map(|map| map.sha1.to_hex_string())
+      .collect(),
+    node_id: tournament.node_id,
+    check_in_minutes: tournament.check_in_minutes,
+    player_ids: tournament.player_ids,
+    status: match tournament.status {
+      TournamentStatus::Running => ext::TournamentStatus::Running,
+      TournamentStatus::Finished => ext::TournamentStatus::Finished,
+      TournamentStatus::Cancelled => ext::TournamentStatus::Cancelled,
+    }
+    .into(),
+    winner_id: tournament.winner_id,
+  }
+}
+
+fn pack_tournament_match(m: TournamentMatch) -> ext::TournamentMatch {
+  let pack_slot_ref = |slot_ref: MatchSlotRef| ext::TournamentMatchSlotRef {
+    match_id: slot_ref.match_id,
+    slot: slot_ref.slot as i32,
+  };
+  ext::TournamentMatch {
+    id: m.id,
+    round: m.round,
+    position: m.position,
+    bracket: match m.bracket {
+      BracketSide::Winners => ext::TournamentBracketSide::Winners,
+      BracketSide::Losers => ext::TournamentBracketSide::Losers,
+      BracketSide::GrandFinal => ext::TournamentBracketSide::GrandFinal,
+    }
+    .into(),
+    slots: m
+      .player_ids
+      .iter()
+      .enumerate()
+      .map(|(slot, player_id)| ext::TournamentMatchSlot {
+        player_id: *player_id,
+        wins: m.wins(slot),
+      })
+      .collect(),
+    winner_to: m.winner_to.map(pack_slot_ref),
+    loser_to: m.loser_to.map(pack_slot_ref),
+    status: match m.status {
+      TournamentMatchStatus::Pending => ext::TournamentMatchStatus::Pending,
+      TournamentMatchStatus::Running => ext::TournamentMatchStatus::Running,
+      TournamentMatchStatus::Finished => ext::TournamentMatchStatus::Finished,
+    }
+    .into(),
+    winner_id: m.winner_id,
+    games: m
+      .games
+      .iter()
+      .map(|game| ext::TournamentSeriesGame {
+        scheduled_game_id: game.scheduled_game_id,
+        map_index: game.map_index as i32,
+        winner_slot: game.winner_slot.map(|slot| slot as i32),
+      })
+      .collect(),
+    map_pick: m.map_pick.map(|index| index as i32),
+    next_game_at: m.next_game_at.map(|at| SystemTime::from(at).into()),
+  }
+}
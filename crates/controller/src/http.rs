@@ -0,0 +1,317 @@
+use hyper::body::HttpBody;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+use crate::config::{GetApiClientIdBySecret, REQUEST_META_SECRET};
+use crate::error::*;
+use crate::map::catalogue::{normalize_map_path, parse_map, parse_sha1_hex};
+use crate::map::db::ListVersionsParams;
+use crate::map::storage::MapStorage;
+use crate::state::ControllerStateRef;
+
+/// Reforged raised the map size limit to 128MB
+const MAX_MAP_FILE_SIZE: usize = 128 * 1024 * 1024;
+/// Uploads are buffered in memory, this bounds the memory used by uploads in progress
+const MAX_CONCURRENT_UPLOADS: usize = 4;
+const MAX_JSON_BODY_SIZE: usize = 64 * 1024;
+
+/// Controller HTTP API: map catalogue
+///
+/// - `GET /maps/{sha1}`: download a map file
+/// - `GET /maps/{sha1}/preview.png`: map preview
+/// - `GET /maps/{sha1}/info`: map metadata
+///
+/// Requests below require the `x-flo-secret` header:
+///
+/// - `GET /maps?name=&tag=`: search maps
+/// - `POST /maps?path=&tags=`: upload a map file, `path` is where clients save the file
+/// - `PUT /maps/{sha1}/tags`: replace tags, body is a JSON array of strings
+/// - `GET /pools`: list map pools
+/// - `GET /pools/{name}`: get a map pool
+/// - `PUT /pools/{name}`: create or replace a map pool, body is a JSON array of map sha1
+pub async fn serve(state: ControllerStateRef) -> Result<()> {
+  let storage = MapStorage::from_env();
+  let uploads = Arc::new(Semaphore::new(MAX_CONCURRENT_UPLOADS));
+  let addr = SocketAddr::from(SocketAddrV4::new(
+    Ipv4Addr::UNSPECIFIED,
+    flo_constants::CONTROLLER_HTTP_PORT,
+  ));
+
+  let server = Server::bind(&addr).serve(make_service_fn(move |_| {
+    let state = state.clone();
+    let storage = storage.clone();
+    let uploads = uploads.clone();
+    async move {
+      Ok::<_, hyper::Error>(service_fn(move |req| {
+        let state = state.clone();
+        let storage = storage.clone();
+        let uploads = uploads.clone();
+        async move {
+          let res = match handle_request(state, storage, uploads, req).await {
+            Ok(res) => res,
+            Err(err) => error_response(err),
+          };
+          Ok::<_, hyper::Error>(res)
+        }
+      }))
+    }
+  }));
+  tracing::info!("http listening on port {}", addr.port());
+  server.await?;
+
+  Ok(())
+}
+
+async fn handle_request(
+  state: ControllerStateRef,
+  storage: MapStorage,
+  uploads: Arc<Semaphore>,
+  req: Request<Body>,
+) -> Result<Response<Body>> {
+  let path = req.uri().path().to_string();
+  let segments: Vec<String> = path
+    .trim_matches('/')
+    .split('/')
+    .map(percent_decode)
+    .collect();
+  let segments: Vec<&str> = segments.iter().map(AsRef::as_ref).collect();
+  let method = req.method().clone();
+
+  match (&method, segments.as_slice()) {
+    (&Method::GET, ["maps", sha1]) => {
+      let sha1 = validate_sha1(sha1)?;
+      let bytes = storage.read_map(&sha1).await?;
+      Ok(bytes_response("application/octet-stream", bytes))
+    }
+    (&Method::GET, ["maps", sha1, "preview.png"]) => {
+      let sha1 = validate_sha1(sha1)?;
+      let bytes = storage.read_preview(&sha1).await?;
+      Ok(bytes_response("image/png", bytes))
+    }
+    (&Method::GET, ["maps", sha1, "info"]) => {
+      let sha1 = validate_sha1(sha1)?;
+      let version = state
+        .db
+        .exec(move |conn| crate::map::db::get_version(conn, &sha1))
+        .await?;
+      json_response(&version)
+    }
+    (&Method::GET, ["maps"]) => {
+      authorize(&state, &req).await?;
+      let mut query = parse_query(req.uri().query());
+      let params = ListVersionsParams {
+        name: query.remove("name"),
+        tag: query.remove("tag"),
+      };
+      let versions = state
+        .db
+        .exec(move |conn| crate::map::db::list_versions(conn, params))
+        .await?;
+      json_response(&versions)
+    }
+    (&Method::POST, ["maps"]) => {
+      let api_client_id = authorize(&state, &req).await?;
+      let mut query = parse_query(req.uri().query());
+      let path = normalize_map_path(
+        &query
+          .remove("path")
+          .ok_or_else(|| Error::MapUploadInvalid("path"))?,
+      )?;
+      let tags = parse_tags(query.remove("tags"));
+      let _permit = uploads.acquire().await.map_err(|_| Error::TaskCancelled)?;
+      let bytes = read_body(req.into_body(), MAX_MAP_FILE_SIZE).await?;
+      let file_size = bytes.len() as i64;
+      let (parsed, bytes) = tokio::task::spawn_blocking(move || -> Result<_> {
+        let parsed = parse_map(&bytes)?;
+        Ok((parsed, bytes))
+      })
+      .await
+      .map_err(|_| Error::TaskCancelled)??;
+      storage
+        .save(&parsed.sha1, &bytes, &parsed.preview_png)
+        .await?;
+      let version = state
+        .db
+        .exec(move |conn| {
+          crate::map::db::register_version(conn, api_client_id, &path, &tags, &parsed, file_size)
+        })
+        .await?;
+      json_response(&version)
+    }
+    (&Method::PUT, ["maps", sha1, "tags"]) => {
+      let api_client_id = authorize(&state, &req).await?;
+      let sha1 = validate_sha1(sha1)?;
+      let tags: Vec<String> = read_json(req.into_body()).await?;
+      let tags = parse_tags(Some(tags.join(",")));
+      let version = state
+        .db
+        .exec(move |conn| crate::map::db::update_tags(conn, api_client_id, &sha1, &tags))
+        .await?;
+      json_response(&version)
+    }
+    (&Method::GET, ["pools"]) => {
+      let api_client_id = authorize(&state, &req).await?;
+      let pools = state
+        .db
+        .exec(move |conn| crate::map::db::list_pools(conn, api_client_id))
+        .await?;
+      json_response(&pools)
+    }
+    (&Method::GET, ["pools", name]) => {
+      let api_client_id = authorize(&state, &req).await?;
+      let name = name.to_string();
+      let pool = state
+        .db
+        .exec(move |conn| crate::map::db::get_pool(conn, api_client_id, &name))
+        .await?;
+      json_response(&pool)
+    }
+    (&Method::PUT, ["pools", name]) => {
+      let api_client_id = authorize(&state, &req).await?;
+      let name = name.trim().to_string();
+      if name.is_empty() {
+        return Err(Error::MapUploadInvalid("pool name"));
+      }
+      let sha1_list: Vec<String> = read_json(req.into_body()).await?;
+      let sha1_list = sha1_list
+        .iter()
+        .map(|sha1| validate_sha1(sha1))
+        .collect::<Result<Vec<_>>>()?;
+      let pool = state
+        .db
+        .exec(move |conn| crate::map::db::upsert_pool(conn, api_client_id, &name, &sha1_list))
+        .await?;
+      json_response(&pool)
+    }
+    _ => Ok(
+      Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::empty())
+        .unwrap(),
+    ),
+  }
+}
+
+async fn authorize(state: &ControllerStateRef, req: &Request<Body>) -> Result<i32> {
+  let secret = req
+    .headers()
+    .get(REQUEST_META_SECRET)
+    .ok_or_else(|| Error::ApiClientSecretInvalid)?
+    .as_bytes()
+    .to_vec();
+  state
+    .config
+    .send(GetApiClientIdBySecret { secret })
+    .await?
+    .ok_or_else(|| Error::ApiClientSecretInvalid)
+}
+
+fn validate_sha1(value: &str) -> Result<String> {
+  let value = value.to_lowercase();
+  parse_sha1_hex(&value)?;
+  Ok(value)
+}
+
+fn parse_tags(value: Option<String>) -> Vec<String> {
+  let mut tags: Vec<String> = value
+    .iter()
+    .flat_map(|v| v.split(','))
+    .map(|tag| tag.trim().to_lowercase())
+    .filter(|tag| !tag.is_empty())
+    .collect();
+  tags.sort();
+  tags.dedup();
+  tags
+}
+
+async fn read_body(mut body: Body, limit: usize) -> Result<Vec<u8>> {
+  let mut buf = vec![];
+  while let Some(chunk) = body.data().await {
+    let chunk = chunk?;
+    if buf.len() + chunk.len() > limit {
+      return Err(Error::MapFileTooLarge);
+    }
+    buf.extend_from_slice(&chunk);
+  }
+  Ok(buf)
+}
+
+async fn read_json<T: serde::de::DeserializeOwned>(body: Body) -> Result<T> {
+  let bytes = read_body(body, MAX_JSON_BODY_SIZE).await?;
+  Ok(serde_json::from_slice(&bytes)?)
+}
+
+fn bytes_response(content_type: &str, bytes: Vec<u8>) -> Response<Body> {
+  Response::builder()
+    .header(CONTENT_TYPE, content_type)
+    .header(CONTENT_LENGTH, bytes.len())
+    .body(Body::from(bytes))
+    .unwrap()
+}
+
+fn json_response<T: Serialize>(value: &T) -> Result<Response<Body>> {
+  Ok(
+    Response::builder()
+      .header(CONTENT_TYPE, "application/json")
+      .body(Body::from(serde_json::to_vec(value)?))
+      .unwrap(),
+  )
+}
+
+fn error_response(err: Error) -> Response<Body> {
+  let status = match err {
+    Error::MapVersionNotFound | Error::MapPoolNotFound => StatusCode::NOT_FOUND,
+    Error::ApiClientSecretInvalid => StatusCode::UNAUTHORIZED,
+    Error::MapFlagged => StatusCode::FORBIDDEN,
+    Error::MapFileTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+    Error::MapFileInvalid(_)
+    | Error::MapUploadInvalid(_)
+    | Error::MapHasNoPlayer
+    | Error::Json(_) => StatusCode::BAD_REQUEST,
+    ref err => {
+      tracing::error!("http: {}", err);
+      StatusCode::INTERNAL_SERVER_ERROR
+    }
+  };
+  Response::builder()
+    .status(status)
+    .header(CONTENT_TYPE, "application/json")
+    .body(Body::from(
+      serde_json::json!({ "message": err.to_string() }).to_string(),
+    ))
+    .unwrap()
+}
+
+fn parse_query(query: Option<&str>) -> HashMap<String, String> {
+  form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+    .into_owned()
+    .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+  percent_encoding::percent_decode_str(value)
+    .decode_utf8_lossy()
+    .into_owned()
+}
+
+#[test]
+fn test_parse_query() {
+  let query = parse_query(Some(
+    "name=Echo+Isles&tag=ladder%2C1v1&path=maps%5Cflo%5C%282%29a.w3x",
+  ));
+  assert_eq!(query.get("name").unwrap(), "Echo Isles");
+  assert_eq!(query.get("tag").unwrap(), "ladder,1v1");
+  assert_eq!(query.get("path").unwrap(), "maps\\flo\\(2)a.w3x");
+  assert_eq!(percent_decode("100%"), "100%");
+  assert_eq!(percent_decode("%E4%B8%AD"), "中");
+  assert_eq!(
+    parse_tags(Some(" Ladder,1v1,,ladder".to_string())),
+    vec!["1v1", "ladder"]
+  );
+}
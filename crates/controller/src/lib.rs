@@ -20,11 +20,17 @@ pub mod error;
 pub mod game;
 mod grpc;
 pub mod host;
+mod http;
 pub mod map;
+pub mod matchmaking;
 pub mod node;
 pub mod player;
+pub mod rating;
+pub mod schedule;
 mod state;
+pub mod tournament;
 
 pub use client::serve as serve_socket;
-pub use grpc::serve as serve_grpc;
+pub use grpc::{ext as grpc_ext, serve as serve_grpc};
+pub use http::serve as serve_http;
 pub use state::{ControllerState, ControllerStateRef};
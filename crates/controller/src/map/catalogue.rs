@@ -0,0 +1,220 @@
+use chrono::{DateTime, Utc};
+use flo_w3map::{ScanLimits, ScanReport, ScanVerdict, W3Map};
+use serde::{Deserialize, Serialize};
+
+use crate::error::*;
+use crate::map::{Map, MapForce, MapPlayer, MapSha1};
+
+/// Metadata extracted from the map file on upload
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MapVersionMeta {
+  pub description: String,
+  pub author: String,
+  pub suggested_players: String,
+  pub width: u32,
+  pub height: u32,
+  pub flags: u32,
+  pub players: Vec<MapPlayer>,
+  pub forces: Vec<MapForce>,
+  pub has_preview: bool,
+  /// Missing for maps uploaded before uploads were scanned
+  #[serde(default)]
+  pub scan: Option<MapScan>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MapScanVerdict {
+  Clean,
+  Suspicious,
+  /// Rejected on upload before the archive is opened, never stored in the catalogue
+  Flagged,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MapScan {
+  pub verdict: MapScanVerdict,
+  pub findings: Vec<String>,
+}
+
+impl From<ScanReport> for MapScan {
+  fn from(report: ScanReport) -> Self {
+    MapScan {
+      verdict: match report.verdict {
+        ScanVerdict::Clean => MapScanVerdict::Clean,
+        ScanVerdict::Suspicious => MapScanVerdict::Suspicious,
+        ScanVerdict::Flagged => MapScanVerdict::Flagged,
+      },
+      findings: report.findings.into_iter().map(|f| f.message).collect(),
+    }
+  }
+}
+
+/// A map file registered in the catalogue, identified by its sha1
+#[derive(Debug, Serialize, Clone)]
+pub struct MapVersion {
+  pub id: i32,
+  pub api_client_id: i32,
+  pub sha1: String,
+  pub checksum: u32,
+  pub name: String,
+  pub path: String,
+  pub file_size: i64,
+  pub meta: MapVersionMeta,
+  pub tags: Vec<String>,
+  pub created_at: DateTime<Utc>,
+  pub updated_at: DateTime<Utc>,
+}
+
+impl MapVersion {
+  pub fn to_map(&self) -> Result<Map> {
+    Ok(Map {
+      sha1: parse_sha1_hex(&self.sha1)?,
+      checksum: self.checksum,
+      name: self.name.clone(),
+      description: self.meta.description.clone(),
+      author: self.meta.author.clone(),
+      path: self.path.clone(),
+      width: self.meta.width,
+      height: self.meta.height,
+      players: self.meta.players.clone(),
+      forces: self.meta.forces.clone(),
+    })
+  }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MapPool {
+  pub id: i32,
+  pub name: String,
+  pub maps: Vec<MapVersion>,
+  pub created_at: DateTime<Utc>,
+  pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct ParsedMap {
+  pub sha1: String,
+  pub checksum: u32,
+  pub name: String,
+  pub meta: MapVersionMeta,
+  pub preview_png: Vec<u8>,
+}
+
+const PREVIEW_SIZE: u32 = 256;
+
+pub fn parse_map(bytes: &[u8]) -> Result<ParsedMap> {
+  // before StormLib opens the archive, flagged maps are never parsed, stored or served
+  let report = ScanReport::scan(bytes, &ScanLimits::default());
+  if report.is_flagged() {
+    tracing::warn!("map upload flagged: {:?}", report.findings);
+    return Err(Error::MapFlagged);
+  }
+  let scan = MapScan::from(report);
+  let (map, checksum) = W3Map::open_memory_with_checksum(bytes)
+    .map_err(|err| Error::MapFileInvalid(err.to_string()))?;
+  let players: Vec<_> = map
+    .get_players()
+    .into_iter()
+    .map(|p| MapPlayer {
+      name: p.name.to_string(),
+      r#type: p.r#type,
+      race: p.race,
+      flags: p.flags,
+    })
+    .collect();
+  if players.is_empty() {
+    return Err(Error::MapHasNoPlayer);
+  }
+  let (width, height) = map.dimension();
+  let mut preview_png = map.render_preview_png();
+  if preview_png.is_empty() {
+    // maps without an embedded preview get one rendered from terrain data
+    if let Ok((_, data)) = W3Map::open_memory_with_data(bytes) {
+      preview_png = data.render_minimap_png(PREVIEW_SIZE, PREVIEW_SIZE);
+    }
+  }
+  Ok(ParsedMap {
+    sha1: checksum.get_sha1_hex_string(),
+    checksum: checksum.xoro,
+    name: map.name().to_string(),
+    meta: MapVersionMeta {
+      description: map.description().to_string(),
+      author: map.author().to_string(),
+      suggested_players: map.suggested_players().to_string(),
+      width,
+      height,
+      flags: map.flags().bits(),
+      players,
+      forces: map
+        .get_forces()
+        .into_iter()
+        .map(|f| MapForce {
+          name: f.name.to_string(),
+          flags: f.flags,
+          player_set: f.player_set,
+        })
+        .collect(),
+      has_preview: !preview_png.is_empty(),
+      scan: Some(scan),
+    },
+    preview_png,
+  })
+}
+
+/// Maps are stored under their lowercase hex sha1
+pub fn parse_sha1_hex(value: &str) -> Result<MapSha1> {
+  if value.len() != 40 || !value.is_ascii() {
+    return Err(Error::MapVersionNotFound);
+  }
+  let mut bytes = [0_u8; 20];
+  for (i, b) in bytes.iter_mut().enumerate() {
+    *b = u8::from_str_radix(&value[(i * 2)..(i * 2 + 2)], 16)
+      .map_err(|_| Error::MapVersionNotFound)?;
+  }
+  Ok(MapSha1(bytes))
+}
+
+/// Normalizes the map path clients will save the file to, e.g. `maps\flo\(2)EchoIsles.w3x`
+pub fn normalize_map_path(path: &str) -> Result<String> {
+  let path = path.replace('/', "\\");
+  let lower = path.to_lowercase();
+  if !lower.starts_with("maps\\")
+    || !(lower.ends_with(".w3x") || lower.ends_with(".w3m"))
+    || path
+      .split('\\')
+      .any(|s| s.is_empty() || s == "." || s == "..")
+  {
+    return Err(Error::MapUploadInvalid("path"));
+  }
+  Ok(path)
+}
+
+#[test]
+fn test_parse_sha1_hex() {
+  let sha1 = parse_sha1_hex("c9e46ed656ff8e8d8c608d39036e3f1bfa0b1cc2").unwrap();
+  assert_eq!(sha1.0[0], 0xc9);
+  assert_eq!(sha1.0[19], 0xc2);
+  assert!(parse_sha1_hex("c9e46ed656ff8e8d8c608d39036e3f1bfa0b1cc").is_err());
+  assert!(parse_sha1_hex("z9e46ed656ff8e8d8c608d39036e3f1bfa0b1cc2").is_err());
+}
+
+#[test]
+fn test_normalize_map_path() {
+  assert_eq!(
+    normalize_map_path("maps/flo/(2)EchoIsles.w3x").unwrap(),
+    "maps\\flo\\(2)EchoIsles.w3x"
+  );
+  assert!(normalize_map_path("maps\\..\\war3.exe").is_err());
+  assert!(normalize_map_path("maps\\..\\a.w3x").is_err());
+  assert!(normalize_map_path("c:\\a.w3x").is_err());
+}
+
+#[test]
+fn test_parse_map_flagged() {
+  // rejected by the scan, StormLib never sees the bytes
+  assert!(matches!(
+    parse_map(b"not a map archive"),
+    Err(Error::MapFlagged)
+  ));
+}
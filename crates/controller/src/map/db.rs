@@ -1,10 +1,16 @@
+use chrono::{DateTime, Utc};
+use diesel::dsl::sql;
 use diesel::prelude::*;
+use diesel::sql_types::{Bool, Jsonb};
 use s2_grpc_utils::S2ProtoUnpack;
 use serde::Deserialize;
+use serde_json::Value;
 
 use crate::db::DbConn;
 use crate::error::*;
-use crate::schema::map_checksum;
+use crate::map::catalogue::{MapPool, MapVersion, ParsedMap};
+use crate::map::{Map, MapSha1};
+use crate::schema::{map_checksum, map_pool, map_version};
 
 pub fn search_checksum(conn: &DbConn, sha1: String) -> Result<Option<u32>> {
   use map_checksum::dsl;
@@ -62,3 +68,317 @@ struct Insert<'a> {
   sha1: &'a str,
   checksum: Vec<u8>,
 }
+
+/// Registers an uploaded map, returns the existing version if the file was uploaded before
+pub fn register_version(
+  conn: &DbConn,
+  api_client_id: i32,
+  path: &str,
+  tags: &[String],
+  parsed: &ParsedMap,
+  file_size: i64,
+) -> Result<MapVersion> {
+  #[derive(Insertable)]
+  #[table_name = "map_version"]
+  struct VersionInsert<'a> {
+    api_client_id: i32,
+    sha1: &'a str,
+    checksum: i64,
+    name: &'a str,
+    path: &'a str,
+    file_size: i64,
+    meta: Value,
+    tags: Value,
+  }
+
+  conn.transaction(|| {
+    if let Some(version) = find_version(conn, &parsed.sha1)? {
+      return Ok(version);
+    }
+
+    let row: VersionRow = diesel::insert_into(map_version::table)
+      .values(&VersionInsert {
+        api_client_id,
+        sha1: &parsed.sha1,
+        checksum: parsed.checksum as i64,
+        name: &parsed.name,
+        path,
+        file_size,
+        meta: serde_json::to_value(&parsed.meta)?,
+        tags: serde_json::to_value(tags)?,
+      })
+      .returning(VersionRow::COLUMNS)
+      .get_result(conn)?;
+
+    import(
+      conn,
+      vec![ImportItem {
+        sha1: parsed.sha1.clone(),
+        checksum: parsed.checksum,
+      }],
+    )?;
+
+    row.into_map_version()
+  })
+}
+
+pub fn get_version(conn: &DbConn, sha1: &str) -> Result<MapVersion> {
+  find_version(conn, sha1)?.ok_or_else(|| Error::MapVersionNotFound)
+}
+
+fn find_version(conn: &DbConn, sha1: &str) -> Result<Option<MapVersion>> {
+  map_version::table
+    .filter(map_version::sha1.eq(sha1))
+    .select(VersionRow::COLUMNS)
+    .first::<VersionRow>(conn)
+    .optional()?
+    .map(VersionRow::into_map_version)
+    .transpose()
+}
+
+/// `flo_w3map::MapFlags` of a catalogue map, `None` if the map is not in the catalogue
+pub fn find_map_flags(conn: &DbConn, sha1: &MapSha1) -> Result<Option<u32>> {
+  Ok(find_version(conn, &sha1.to_hex_string())?.map(|version| version.meta.flags))
+}
+
+pub fn get_map(conn: &DbConn, sha1: &str) -> Result<Map> {
+  get_version(conn, &sha1.to_lowercase())?.to_map()
+}
+
+/// Catalogue maps in the given order
+pub fn get_maps(conn: &DbConn, sha1s: &[String]) -> Result<Vec<Map>> {
+  sha1s.iter().map(|sha1| get_map(conn, sha1)).collect()
+}
+
+#[derive(Debug, Default)]
+pub struct ListVersionsParams {
+  pub name: Option<String>,
+  pub tag: Option<String>,
+}
+
+pub fn list_versions(conn: &DbConn, params: ListVersionsParams) -> Result<Vec<MapVersion>> {
+  let mut q = map_version::table
+    .select(VersionRow::COLUMNS)
+    .order(map_version::id.desc())
+    .limit(100)
+    .into_boxed();
+  if let Some(name) = params.name {
+    q = q.filter(map_version::name.ilike(format!("%{}%", escape_like(&name))));
+  }
+  if let Some(tag) = params.tag {
+    q = q.filter(sql::<Bool>("tags @> ").bind::<Jsonb, _>(serde_json::to_value(vec![tag])?));
+  }
+  let rows: Vec<VersionRow> = q.load(conn)?;
+  rows.into_iter().map(VersionRow::into_map_version).collect()
+}
+
+/// Only the api client that uploaded the map can change its tags
+pub fn update_tags(
+  conn: &DbConn,
+  api_client_id: i32,
+  sha1: &str,
+  tags: &[String],
+) -> Result<MapVersion> {
+  let row: VersionRow = diesel::update(
+    map_version::table.filter(
+      map_version::sha1
+        .eq(sha1)
+        .and(map_version::api_client_id.eq(api_client_id)),
+    ),
+  )
+  .set((
+    map_version::tags.eq(serde_json::to_value(tags)?),
+    map_version::updated_at.eq(diesel::dsl::now),
+  ))
+  .returning(VersionRow::COLUMNS)
+  .get_result(conn)
+  .optional()?
+  .ok_or_else(|| Error::MapVersionNotFound)?;
+  row.into_map_version()
+}
+
+pub fn upsert_pool(
+  conn: &DbConn,
+  api_client_id: i32,
+  name: &str,
+  sha1_list: &[String],
+) -> Result<MapPool> {
+  use diesel::pg::upsert::excluded;
+
+  #[derive(Insertable)]
+  #[table_name = "map_pool"]
+  struct PoolInsert<'a> {
+    api_client_id: i32,
+    name: &'a str,
+    map_version_ids: Value,
+  }
+
+  conn.transaction(|| {
+    let mut ids = vec![];
+    for sha1 in sha1_list {
+      let id = map_version::table
+        .filter(map_version::sha1.eq(sha1))
+        .select(map_version::id)
+        .first::<i32>(conn)
+        .optional()?
+        .ok_or_else(|| Error::MapVersionNotFound)?;
+      if !ids.contains(&id) {
+        ids.push(id);
+      }
+    }
+
+    diesel::insert_into(map_pool::table)
+      .values(&PoolInsert {
+        api_client_id,
+        name,
+        map_version_ids: serde_json::to_value(&ids)?,
+      })
+      .on_conflict((map_pool::api_client_id, map_pool::name))
+      .do_update()
+      .set((
+        map_pool::map_version_ids.eq(excluded(map_pool::map_version_ids)),
+        map_pool::updated_at.eq(diesel::dsl::now),
+      ))
+      .execute(conn)?;
+
+    get_pool(conn, api_client_id, name)
+  })
+}
+
+pub fn get_pool(conn: &DbConn, api_client_id: i32, name: &str) -> Result<MapPool> {
+  let row: PoolRow = map_pool::table
+    .filter(
+      map_pool::api_client_id
+        .eq(api_client_id)
+        .and(map_pool::name.eq(name)),
+    )
+    .select(PoolRow::COLUMNS)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::MapPoolNotFound)?;
+  load_pool(conn, row)
+}
+
+pub fn list_pools(conn: &DbConn, api_client_id: i32) -> Result<Vec<MapPool>> {
+  let rows: Vec<PoolRow> = map_pool::table
+    .filter(map_pool::api_client_id.eq(api_client_id))
+    .select(PoolRow::COLUMNS)
+    .order(map_pool::name)
+    .load(conn)?;
+  rows.into_iter().map(|row| load_pool(conn, row)).collect()
+}
+
+fn load_pool(conn: &DbConn, row: PoolRow) -> Result<MapPool> {
+  let ids: Vec<i32> = serde_json::from_value(row.map_version_ids)?;
+  let rows: Vec<VersionRow> = map_version::table
+    .filter(map_version::id.eq_any(&ids))
+    .select(VersionRow::COLUMNS)
+    .load(conn)?;
+  let mut versions = rows
+    .into_iter()
+    .map(VersionRow::into_map_version)
+    .collect::<Result<Vec<_>>>()?;
+  versions.sort_by_key(|v| ids.iter().position(|id| *id == v.id));
+  Ok(MapPool {
+    id: row.id,
+    name: row.name,
+    maps: versions,
+    created_at: row.created_at,
+    updated_at: row.updated_at,
+  })
+}
+
+fn escape_like(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('%', "\\%")
+    .replace('_', "\\_")
+}
+
+#[derive(Debug, Queryable)]
+struct VersionRow {
+  id: i32,
+  api_client_id: i32,
+  sha1: String,
+  checksum: i64,
+  name: String,
+  path: String,
+  file_size: i64,
+  meta: Value,
+  tags: Value,
+  created_at: DateTime<Utc>,
+  updated_at: DateTime<Utc>,
+}
+
+type VersionRowColumns = (
+  map_version::dsl::id,
+  map_version::dsl::api_client_id,
+  map_version::dsl::sha1,
+  map_version::dsl::checksum,
+  map_version::dsl::name,
+  map_version::dsl::path,
+  map_version::dsl::file_size,
+  map_version::dsl::meta,
+  map_version::dsl::tags,
+  map_version::dsl::created_at,
+  map_version::dsl::updated_at,
+);
+
+impl VersionRow {
+  const COLUMNS: VersionRowColumns = (
+    map_version::dsl::id,
+    map_version::dsl::api_client_id,
+    map_version::dsl::sha1,
+    map_version::dsl::checksum,
+    map_version::dsl::name,
+    map_version::dsl::path,
+    map_version::dsl::file_size,
+    map_version::dsl::meta,
+    map_version::dsl::tags,
+    map_version::dsl::created_at,
+    map_version::dsl::updated_at,
+  );
+
+  fn into_map_version(self) -> Result<MapVersion> {
+    Ok(MapVersion {
+      id: self.id,
+      api_client_id: self.api_client_id,
+      sha1: self.sha1,
+      checksum: self.checksum as u32,
+      name: self.name,
+      path: self.path,
+      file_size: self.file_size,
+      meta: serde_json::from_value(self.meta)?,
+      tags: serde_json::from_value(self.tags)?,
+      created_at: self.created_at,
+      updated_at: self.updated_at,
+    })
+  }
+}
+
+#[derive(Debug, Queryable)]
+struct PoolRow {
+  id: i32,
+  name: String,
+  map_version_ids: Value,
+  created_at: DateTime<Utc>,
+  updated_at: DateTime<Utc>,
+}
+
+type PoolRowColumns = (
+  map_pool::dsl::id,
+  map_pool::dsl::name,
+  map_pool::dsl::map_version_ids,
+  map_pool::dsl::created_at,
+  map_pool::dsl::updated_at,
+);
+
+impl PoolRow {
+  const COLUMNS: PoolRowColumns = (
+    map_pool::dsl::id,
+    map_pool::dsl::name,
+    map_pool::dsl::map_version_ids,
+    map_pool::dsl::created_at,
+    map_pool::dsl::updated_at,
+  );
+}
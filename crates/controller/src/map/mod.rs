@@ -1,4 +1,6 @@
+pub mod catalogue;
 pub mod db;
+pub mod storage;
 
 use s2_grpc_utils::result::Error as ProtoError;
 use s2_grpc_utils::{S2ProtoPack, S2ProtoUnpack};
@@ -27,6 +29,11 @@ impl MapSha1 {
   pub fn to_vec(&self) -> Vec<u8> {
     self.0.to_vec()
   }
+
+  /// Lowercase, as stored in the map catalogue
+  pub fn to_hex_string(&self) -> String {
+    self.0.iter().map(|b| format!("{:02x}", b)).collect()
+  }
 }
 
 impl S2ProtoUnpack<Vec<u8>> for MapSha1 {
@@ -0,0 +1,58 @@
+use once_cell::sync::Lazy;
+use std::env;
+use std::path::PathBuf;
+
+use crate::error::*;
+
+pub static MAP_STORAGE_DIR: Lazy<PathBuf> = Lazy::new(|| {
+  env::var("FLO_MAP_STORAGE_DIR")
+    .map(PathBuf::from)
+    .unwrap_or_else(|_| PathBuf::from("maps"))
+});
+
+/// Uploaded maps are saved as `<sha1>` and `<sha1>.png` files
+#[derive(Debug, Clone)]
+pub struct MapStorage {
+  dir: PathBuf,
+}
+
+impl MapStorage {
+  pub fn from_env() -> Self {
+    Self {
+      dir: MAP_STORAGE_DIR.clone(),
+    }
+  }
+
+  pub async fn save(&self, sha1: &str, bytes: &[u8], preview_png: &[u8]) -> Result<()> {
+    tokio::fs::create_dir_all(&self.dir).await?;
+    tokio::fs::write(self.map_path(sha1), bytes).await?;
+    if !preview_png.is_empty() {
+      tokio::fs::write(self.preview_path(sha1), preview_png).await?;
+    }
+    Ok(())
+  }
+
+  pub async fn read_map(&self, sha1: &str) -> Result<Vec<u8>> {
+    Self::read(self.map_path(sha1)).await
+  }
+
+  pub async fn read_preview(&self, sha1: &str) -> Result<Vec<u8>> {
+    Self::read(self.preview_path(sha1)).await
+  }
+
+  async fn read(path: PathBuf) -> Result<Vec<u8>> {
+    match tokio::fs::read(path).await {
+      Ok(bytes) => Ok(bytes),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(Error::MapVersionNotFound),
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  fn map_path(&self, sha1: &str) -> PathBuf {
+    self.dir.join(sha1)
+  }
+
+  fn preview_path(&self, sha1: &str) -> PathBuf {
+    self.dir.join(format!("{}.png", sha1))
+  }
+}
@@ -0,0 +1,216 @@
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::matchmaking::types::{MatchmakingQueue, MatchmakingQueueParams, DEFAULT_RATING};
+use crate::schema::{matchmaking_queue, player};
+
+pub fn list_queues(conn: &DbConn, api_client_id: i32) -> Result<Vec<MatchmakingQueue>> {
+  use matchmaking_queue::dsl;
+  let rows: Vec<QueueRow> = matchmaking_queue::table
+    .filter(dsl::api_client_id.eq(api_client_id))
+    .select(QueueRow::COLUMNS)
+    .order(dsl::id)
+    .load(conn)?;
+  rows.into_iter().map(QueueRow::into_queue).collect()
+}
+
+pub fn get_queue(conn: &DbConn, api_client_id: i32, id: i32) -> Result<MatchmakingQueue> {
+  use matchmaking_queue::dsl;
+  let row: QueueRow = matchmaking_queue::table
+    .filter(dsl::id.eq(id).and(dsl::api_client_id.eq(api_client_id)))
+    .select(QueueRow::COLUMNS)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::MatchmakingQueueNotFound)?;
+  row.into_queue()
+}
+
+/// Games of the queue are created by `api_player_id`
+pub fn create_queue(
+  conn: &DbConn,
+  api_client_id: i32,
+  api_player_id: i32,
+  params: MatchmakingQueueParams,
+) -> Result<MatchmakingQueue> {
+  use matchmaking_queue::dsl;
+  check_queue_params(conn, api_client_id, &params)?;
+  let id: i32 = diesel::insert_into(matchmaking_queue::table)
+    .values((
+      dsl::api_client_id.eq(api_client_id),
+      dsl::api_player_id.eq(api_player_id),
+      QueueChangeset::new(&params)?,
+    ))
+    .returning(dsl::id)
+    .get_result(conn)?;
+  get_queue(conn, api_client_id, id)
+}
+
+pub fn update_queue(
+  conn: &DbConn,
+  api_client_id: i32,
+  id: i32,
+  params: MatchmakingQueueParams,
+) -> Result<MatchmakingQueue> {
+  use matchmaking_queue::dsl;
+  check_queue_params(conn, api_client_id, &params)?;
+  let updated = diesel::update(
+    matchmaking_queue::table.filter(dsl::id.eq(id).and(dsl::api_client_id.eq(api_client_id))),
+  )
+  .set((
+    QueueChangeset::new(&params)?,
+    dsl::updated_at.eq(diesel::dsl::now),
+  ))
+  .execute(conn)?;
+  if updated == 0 {
+    return Err(Error::MatchmakingQueueNotFound);
+  }
+  get_queue(conn, api_client_id, id)
+}
+
+fn check_queue_params(
+  conn: &DbConn,
+  api_client_id: i32,
+  params: &MatchmakingQueueParams,
+) -> Result<()> {
+  params.validate().map_err(Error::MatchmakingQueueInvalid)?;
+  if let Some(pool_id) = params.rating_pool_id {
+    crate::rating::db::get_pool(conn, api_client_id, pool_id)?;
+  }
+  Ok(())
+}
+
+#[derive(AsChangeset, Insertable)]
+#[table_name = "matchmaking_queue"]
+#[changeset_options(treat_none_as_null = "true")]
+struct QueueChangeset<'a> {
+  name: &'a str,
+  team_size: i32,
+  region: Option<&'a str>,
+  map_pool: Value,
+  rating_pool_id: Option<i32>,
+  rating_spread: i32,
+  rating_spread_per_minute: i32,
+  max_ping: i32,
+  enabled: bool,
+}
+
+impl<'a> QueueChangeset<'a> {
+  fn new(params: &'a MatchmakingQueueParams) -> Result<Self> {
+    Ok(QueueChangeset {
+      name: params.name.trim(),
+      team_size: params.team_size,
+      region: params.region.as_deref(),
+      map_pool: serde_json::to_value(&params.map_pool)?,
+      rating_pool_id: params.rating_pool_id,
+      rating_spread: params.rating_spread,
+      rating_spread_per_minute: params.rating_spread_per_minute,
+      max_ping: params.max_ping,
+      enabled: params.enabled,
+    })
+  }
+}
+
+pub fn get_enabled_queues(conn: &DbConn) -> Result<Vec<MatchmakingQueue>> {
+  use matchmaking_queue::dsl;
+  let rows: Vec<QueueRow> = matchmaking_queue::table
+    .filter(dsl::enabled.eq(true))
+    .select(QueueRow::COLUMNS)
+    .order(dsl::id)
+    .load(conn)?;
+  rows.into_iter().map(QueueRow::into_queue).collect()
+}
+
+/// Returns the player's rating in the queue's rating pool, rounded for the matcher.
+/// Unrated players get the pool's initial rating, queues without a pool use the default rating.
+pub fn get_player_rating(
+  conn: &DbConn,
+  api_client_id: i32,
+  rating_pool_id: Option<i32>,
+  player_id: i32,
+) -> Result<i32> {
+  let player_api_client_id: i32 = player::table
+    .find(player_id)
+    .select(player::api_client_id)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::PlayerNotFound)?;
+  if player_api_client_id != api_client_id {
+    return Err(Error::PlayerOwnerCheckFailed);
+  }
+
+  let pool_id = if let Some(id) = rating_pool_id {
+    id
+  } else {
+    return Ok(DEFAULT_RATING);
+  };
+
+  let rating = crate::rating::db::get_player_rating_value(conn, pool_id, player_id)?;
+  Ok(rating.round() as i32)
+}
+
+#[derive(Debug, Queryable)]
+struct QueueRow {
+  id: i32,
+  name: String,
+  api_client_id: i32,
+  api_player_id: i32,
+  team_size: i32,
+  region: Option<String>,
+  map_pool: Value,
+  rating_spread: i32,
+  rating_spread_per_minute: i32,
+  max_ping: i32,
+  rating_pool_id: Option<i32>,
+  enabled: bool,
+}
+
+type QueueRowColumns = (
+  matchmaking_queue::dsl::id,
+  matchmaking_queue::dsl::name,
+  matchmaking_queue::dsl::api_client_id,
+  matchmaking_queue::dsl::api_player_id,
+  matchmaking_queue::dsl::team_size,
+  matchmaking_queue::dsl::region,
+  matchmaking_queue::dsl::map_pool,
+  matchmaking_queue::dsl::rating_spread,
+  matchmaking_queue::dsl::rating_spread_per_minute,
+  matchmaking_queue::dsl::max_ping,
+  matchmaking_queue::dsl::rating_pool_id,
+  matchmaking_queue::dsl::enabled,
+);
+
+impl QueueRow {
+  const COLUMNS: QueueRowColumns = (
+    matchmaking_queue::dsl::id,
+    matchmaking_queue::dsl::name,
+    matchmaking_queue::dsl::api_client_id,
+    matchmaking_queue::dsl::api_player_id,
+    matchmaking_queue::dsl::team_size,
+    matchmaking_queue::dsl::region,
+    matchmaking_queue::dsl::map_pool,
+    matchmaking_queue::dsl::rating_spread,
+    matchmaking_queue::dsl::rating_spread_per_minute,
+    matchmaking_queue::dsl::max_ping,
+    matchmaking_queue::dsl::rating_pool_id,
+    matchmaking_queue::dsl::enabled,
+  );
+
+  fn into_queue(self) -> Result<MatchmakingQueue> {
+    Ok(MatchmakingQueue {
+      id: self.id,
+      name: self.name,
+      api_client_id: self.api_client_id,
+      api_player_id: self.api_player_id,
+      team_size: self.team_size,
+      region: self.region,
+      map_pool: serde_json::from_value(self.map_pool)?,
+      rating_spread: self.rating_spread,
+      rating_spread_per_minute: self.rating_spread_per_minute,
+      max_ping: self.max_ping,
+      rating_pool_id: self.rating_pool_id,
+      enabled: self.enabled,
+    })
+  }
+}
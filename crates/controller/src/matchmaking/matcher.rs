@@ -0,0 +1,208 @@
+use flo_types::ping::PingStats;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+#[derive(Debug, Clone)]
+pub struct QueueEntry {
+  pub player_id: i32,
+  pub rating: i32,
+  pub joined_at: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatcherConfig {
+  pub team_size: usize,
+  pub rating_spread: i32,
+  pub rating_spread_per_minute: i32,
+  pub max_ping: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+  pub node_id: i32,
+  pub teams: Vec<Vec<i32>>,
+}
+
+impl Match {
+  pub fn player_ids(&self) -> Vec<i32> {
+    self.teams.iter().flatten().cloned().collect()
+  }
+}
+
+/// Groups queued players into 2-team matches.
+///
+/// Players are sorted by rating and every window of `team_size * 2` consecutive players
+/// is accepted if its rating spread is within the allowed spread, which widens with the
+/// longest waiting time in the window, and there is a node every player can reach
+/// with a ping of at most `max_ping`.
+pub fn find_matches(
+  config: &MatcherConfig,
+  entries: &[QueueEntry],
+  ping_map: &BTreeMap<i32, BTreeMap<i32, PingStats>>,
+  node_ids: &[i32],
+  now: Instant,
+) -> Vec<Match> {
+  let size = config.team_size * 2;
+  if size == 0 || entries.len() < size {
+    return vec![];
+  }
+
+  let mut sorted: Vec<&QueueEntry> = entries.iter().collect();
+  sorted.sort_by_key(|e| (e.rating, e.joined_at));
+
+  let mut matches = vec![];
+  let mut i = 0;
+  while i + size <= sorted.len() {
+    let window = &sorted[i..(i + size)];
+    if let Some(m) = try_match(config, window, ping_map, node_ids, now) {
+      matches.push(m);
+      i += size;
+    } else {
+      i += 1;
+    }
+  }
+  matches
+}
+
+fn try_match(
+  config: &MatcherConfig,
+  window: &[&QueueEntry],
+  ping_map: &BTreeMap<i32, BTreeMap<i32, PingStats>>,
+  node_ids: &[i32],
+  now: Instant,
+) -> Option<Match> {
+  let spread = window.last()?.rating - window.first()?.rating;
+  let max_wait_secs = window
+    .iter()
+    .map(|e| now.saturating_duration_since(e.joined_at).as_secs())
+    .max()
+    .unwrap_or_default();
+  let allowed = config.rating_spread as i64
+    + config.rating_spread_per_minute as i64 * max_wait_secs as i64 / 60;
+  if spread as i64 > allowed {
+    return None;
+  }
+
+  let node_id = select_node(config, window, ping_map, node_ids)?;
+
+  Some(Match {
+    node_id,
+    teams: split_teams(window),
+  })
+}
+
+/// Picks the node with the lowest worst-case ping among the players.
+fn select_node(
+  config: &MatcherConfig,
+  window: &[&QueueEntry],
+  ping_map: &BTreeMap<i32, BTreeMap<i32, PingStats>>,
+  node_ids: &[i32],
+) -> Option<i32> {
+  node_ids
+    .iter()
+    .filter_map(|node_id| {
+      let mut worst = 0;
+      for entry in window {
+        let ping = ping_map
+          .get(&entry.player_id)
+          .and_then(|map| map.get(node_id))
+          .and_then(|stats| stats.avg.or(stats.current))?;
+        worst = std::cmp::max(worst, ping);
+      }
+      if worst <= config.max_ping {
+        Some((worst, *node_id))
+      } else {
+        None
+      }
+    })
+    .min()
+    .map(|(_, node_id)| node_id)
+}
+
+/// Snake draft by rating: 1st -> A, 2nd -> B, 3rd -> B, 4th -> A, ...
+fn split_teams(window: &[&QueueEntry]) -> Vec<Vec<i32>> {
+  let mut sorted = window.to_vec();
+  sorted.sort_by_key(|e| std::cmp::Reverse(e.rating));
+  let mut teams = vec![vec![], vec![]];
+  for (idx, entry) in sorted.into_iter().enumerate() {
+    let team = match idx % 4 {
+      0 | 3 => 0,
+      _ => 1,
+    };
+    teams[team].push(entry.player_id);
+  }
+  teams
+}
+
+#[test]
+fn test_find_matches() {
+  use std::time::Duration;
+
+  let now = Instant::now() + Duration::from_secs(3600);
+  let config = MatcherConfig {
+    team_size: 2,
+    rating_spread: 100,
+    rating_spread_per_minute: 60,
+    max_ping: 150,
+  };
+  let ping = |avg: u32| PingStats {
+    min: None,
+    max: None,
+    avg: Some(avg),
+    current: None,
+    loss_rate: 0.,
+  };
+  let entry = |player_id: i32, rating: i32, wait_secs: u64| QueueEntry {
+    player_id,
+    rating,
+    joined_at: now - Duration::from_secs(wait_secs),
+  };
+
+  let mut ping_map = BTreeMap::new();
+  for player_id in 1..=6 {
+    let mut map = BTreeMap::new();
+    map.insert(1, ping(if player_id == 1 { 200 } else { 100 }));
+    map.insert(2, ping(80));
+    ping_map.insert(player_id, map);
+  }
+
+  let entries = vec![
+    entry(1, 1500, 0),
+    entry(2, 1550, 0),
+    entry(3, 1450, 0),
+    entry(4, 1520, 0),
+    entry(5, 2000, 0),
+  ];
+
+  // player 1 can only play on node 2
+  let matches = find_matches(&config, &entries, &ping_map, &[1, 2], now);
+  assert_eq!(
+    matches,
+    vec![Match {
+      node_id: 2,
+      teams: vec![vec![2, 3], vec![4, 1]],
+    }]
+  );
+
+  // no common node
+  let matches = find_matches(&config, &entries, &ping_map, &[1], now);
+  assert!(matches.is_empty());
+
+  // spread too large until player 5 has waited long enough
+  let entries = vec![
+    entry(2, 1550, 0),
+    entry(3, 1450, 0),
+    entry(4, 1520, 0),
+    entry(5, 2000, 0),
+  ];
+  assert!(find_matches(&config, &entries, &ping_map, &[1], now).is_empty());
+  let entries = vec![
+    entry(2, 1550, 0),
+    entry(3, 1450, 0),
+    entry(4, 1520, 0),
+    entry(5, 2000, 450),
+  ];
+  let matches = find_matches(&config, &entries, &ping_map, &[1], now);
+  assert_eq!(matches.len(), 1);
+  assert_eq!(matches[0].teams, vec![vec![5, 3], vec![2, 4]]);
+}
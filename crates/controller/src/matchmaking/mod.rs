@@ -0,0 +1,10 @@
+pub mod db;
+mod matcher;
+pub(crate) mod state;
+mod types;
+
+pub mod messages {
+  pub use super::state::{JoinQueue, LeaveQueue};
+}
+
+pub use types::*;
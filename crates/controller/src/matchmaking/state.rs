@@ -0,0 +1,602 @@
+use crate::error::*;
+use crate::game::db::CreateGameAsBotParams;
+use crate::game::state::cancel::CancelGame;
+use crate::game::state::create::CreateGameAsBot;
+use crate::game::state::registry::Remove;
+use crate::game::state::start::{StartGameCheckAsBot, StartGameCheckAsBotResult};
+use crate::game::state::GameRegistry;
+use crate::game::{CreateGameSlot, Race, SlotSettings, SlotStatus};
+use crate::matchmaking::matcher::{find_matches, Match, QueueEntry};
+use crate::matchmaking::MatchmakingQueue;
+use crate::node::messages::ListNode;
+use crate::node::NodeRegistry;
+use crate::player::message::GetPlayersPingSnapshot;
+use crate::player::state::sender::PlayerRegistryHandle;
+use crate::player::state::PlayerRegistry;
+use crate::state::{ActorMapExt, Data, Reload};
+use bs_diesel_utils::ExecutorRef;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::{
+  MatchmakingStatus, PacketGameStartReject, PacketMatchmakingStatus,
+};
+use flo_state::*;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tokio::time::sleep;
+
+const MATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Matchmaking queues, players wait here until the matcher finds a game for them.
+pub struct MatchmakingRegistry {
+  db: ExecutorRef,
+  games: Addr<GameRegistry>,
+  players: Addr<PlayerRegistry>,
+  nodes: Addr<NodeRegistry>,
+  player_packet_sender: PlayerRegistryHandle,
+  queues: BTreeMap<i32, QueueState>,
+  player_queue_map: BTreeMap<i32, i32>,
+}
+
+struct QueueState {
+  config: MatchmakingQueue,
+  entries: Vec<QueueEntry>,
+}
+
+impl MatchmakingRegistry {
+  /// Returns `(queue_id, player_id)` of the players removed from the closed queues
+  async fn load_queues(&mut self) -> Result<Vec<(i32, i32)>> {
+    let queues = self
+      .db
+      .exec(|conn| crate::matchmaking::db::get_enabled_queues(conn))
+      .await?;
+
+    let mut queues: BTreeMap<_, _> = queues.into_iter().map(|q| (q.id, q)).collect();
+    let mut removed = vec![];
+    for (id, state) in std::mem::take(&mut self.queues) {
+      if let Some(config) = queues.remove(&id) {
+        self.queues.insert(
+          id,
+          QueueState {
+            config,
+            entries: state.entries,
+          },
+        );
+      } else {
+        tracing::info!(queue_id = id, "queue removed");
+        for entry in state.entries {
+          self.player_queue_map.remove(&entry.player_id);
+          removed.push((id, entry.player_id));
+        }
+      }
+    }
+    for (id, config) in queues {
+      self.queues.insert(
+        id,
+        QueueState {
+          config,
+          entries: vec![],
+        },
+      );
+    }
+    Ok(removed)
+  }
+
+  async fn run_matcher(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+    let players: Vec<i32> = self.player_queue_map.keys().cloned().collect();
+    if players.is_empty() {
+      return Ok(());
+    }
+
+    let snapshot = self
+      .players
+      .send(GetPlayersPingSnapshot { players })
+      .await?;
+    let nodes = self.nodes.send(ListNode).await?;
+    let now = Instant::now();
+
+    let mut matches = vec![];
+    for state in self.queues.values_mut() {
+      let config = &state.config;
+      let node_ids: Vec<i32> = nodes
+        .iter()
+        .filter(|node| config.accepts_node(&node.location, &node.country_id))
+        .map(|node| node.id)
+        .collect();
+      for m in find_matches(
+        &config.matcher_config(),
+        &state.entries,
+        &snapshot.map,
+        &node_ids,
+        now,
+      ) {
+        let player_ids = m.player_ids();
+        let (entries, rest) = std::mem::take(&mut state.entries)
+          .into_iter()
+          .partition(|e| player_ids.contains(&e.player_id));
+        state.entries = rest;
+        matches.push((config.clone(), m, entries));
+      }
+    }
+
+    let addr = ctx.addr();
+    for (queue, m, entries) in matches {
+      for player_id in m.player_ids() {
+        self.player_queue_map.remove(&player_id);
+      }
+      ctx.spawn(start_match(
+        addr.clone(),
+        self.games.clone(),
+        self.player_packet_sender.clone(),
+        queue,
+        m,
+        entries,
+      ));
+    }
+
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl Actor for MatchmakingRegistry {
+  async fn started(&mut self, ctx: &mut Context<Self>) {
+    if let Err(err) = self.load_queues().await {
+      tracing::error!("load queues: {}", err);
+    }
+    self.handle(ctx, RunMatcher).await;
+  }
+}
+
+#[async_trait]
+impl Service<Data> for MatchmakingRegistry {
+  type Error = Error;
+
+  async fn create(registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
+    let games = registry.resolve::<GameRegistry>().await?;
+    let players = registry.resolve::<PlayerRegistry>().await?;
+    let nodes = registry.resolve::<NodeRegistry>().await?;
+    Ok(Self {
+      db: registry.data().db.clone(),
+      games,
+      players: players.clone(),
+      nodes,
+      player_packet_sender: players.into(),
+      queues: BTreeMap::new(),
+      player_queue_map: BTreeMap::new(),
+    })
+  }
+}
+
+#[async_trait]
+impl Handler<Reload> for MatchmakingRegistry {
+  async fn handle(&mut self, _: &mut Context<Self>, _: Reload) -> Result<()> {
+    let removed = self.load_queues().await?;
+    for (queue_id, player_id) in removed {
+      let frame = PacketMatchmakingStatus {
+        queue_id,
+        status: MatchmakingStatus::Left.into(),
+        message: "The queue has been closed.".to_string(),
+        ..Default::default()
+      }
+      .encode_as_frame()?;
+      self.player_packet_sender.send(player_id, frame).await.ok();
+    }
+    Ok(())
+  }
+}
+
+/// Adds a player to a queue, returns the player's rating in the queue
+pub struct JoinQueue {
+  pub player_id: i32,
+  pub queue_id: i32,
+}
+
+impl Message for JoinQueue {
+  type Result = Result<i32>;
+}
+
+#[async_trait]
+impl Handler<JoinQueue> for MatchmakingRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    JoinQueue {
+      player_id,
+      queue_id,
+    }: JoinQueue,
+  ) -> <JoinQueue as Message>::Result {
+    if let Some(current) = self.player_queue_map.get(&player_id) {
+      if *current == queue_id {
+        let rating = self
+          .queues
+          .get(&queue_id)
+          .and_then(|q| q.entries.iter().find(|e| e.player_id == player_id))
+          .map(|e| e.rating)
+          .ok_or_else(|| Error::MatchmakingQueueNotFound)?;
+        return Ok(rating);
+      }
+      return Err(Error::PlayerAlreadyInQueue);
+    }
+
+    let (api_client_id, rating_pool_id) = self
+      .queues
+      .get(&queue_id)
+      .map(|q| (q.config.api_client_id, q.config.rating_pool_id))
+      .ok_or_else(|| Error::MatchmakingQueueNotFound)?;
+
+    let rating = self
+      .db
+      .exec(move |conn| {
+        if !crate::game::db::get_player_active_slots(conn, player_id)?.is_empty() {
+          return Err(Error::PlayerAlreadyInGame);
+        }
+        crate::matchmaking::db::get_player_rating(conn, api_client_id, rating_pool_id, player_id)
+      })
+      .await?;
+
+    let queue = self
+      .queues
+      .get_mut(&queue_id)
+      .ok_or_else(|| Error::MatchmakingQueueNotFound)?;
+    queue.entries.push(QueueEntry {
+      player_id,
+      rating,
+      joined_at: Instant::now(),
+    });
+    self.player_queue_map.insert(player_id, queue_id);
+
+    Ok(rating)
+  }
+}
+
+/// Removes a player from the queue, returns the id of the queue the player left
+pub struct LeaveQueue {
+  pub player_id: i32,
+}
+
+impl Message for LeaveQueue {
+  type Result = Option<i32>;
+}
+
+#[async_trait]
+impl Handler<LeaveQueue> for MatchmakingRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    LeaveQueue { player_id }: LeaveQueue,
+  ) -> <LeaveQueue as Message>::Result {
+    let queue_id = self.player_queue_map.remove(&player_id)?;
+    if let Some(queue) = self.queues.get_mut(&queue_id) {
+      queue.entries.retain(|e| e.player_id != player_id);
+    }
+    Some(queue_id)
+  }
+}
+
+/// Puts players back into the queue after a failed match, keeping their waiting time.
+/// Returns the entries put back.
+struct RequeuePlayers {
+  queue_id: i32,
+  entries: Vec<QueueEntry>,
+}
+
+impl Message for RequeuePlayers {
+  type Result = Vec<QueueEntry>;
+}
+
+#[async_trait]
+impl Handler<RequeuePlayers> for MatchmakingRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    RequeuePlayers { queue_id, entries }: RequeuePlayers,
+  ) -> <RequeuePlayers as Message>::Result {
+    let queue = if let Some(queue) = self.queues.get_mut(&queue_id) {
+      queue
+    } else {
+      return vec![];
+    };
+    let mut requeued = vec![];
+    for entry in entries {
+      // joined a queue again while the match was starting
+      if self.player_queue_map.contains_key(&entry.player_id) {
+        continue;
+      }
+      self.player_queue_map.insert(entry.player_id, queue_id);
+      queue.entries.push(entry.clone());
+      requeued.push(entry);
+    }
+    requeued
+  }
+}
+
+struct RunMatcher;
+
+impl Message for RunMatcher {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<RunMatcher> for MatchmakingRegistry {
+  async fn handle(&mut self, ctx: &mut Context<Self>, _: RunMatcher) {
+    if let Err(err) = self.run_matcher(ctx).await {
+      tracing::error!("run matcher: {}", err);
+    }
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      sleep(MATCH_INTERVAL).await;
+      addr.notify(RunMatcher).await.ok();
+    });
+  }
+}
+
+async fn start_match(
+  addr: Addr<MatchmakingRegistry>,
+  games: Addr<GameRegistry>,
+  player_packet_sender: PlayerRegistryHandle,
+  queue: MatchmakingQueue,
+  m: Match,
+  entries: Vec<QueueEntry>,
+) {
+  let queue_id = queue.id;
+  let player_ids = m.player_ids();
+
+  let (packet, requeued) = match create_and_start_game(&games, queue, m).await {
+    Ok(Ok(game_id)) => {
+      tracing::info!(queue_id, game_id, "match started");
+      (
+        PacketMatchmakingStatus {
+          queue_id,
+          status: MatchmakingStatus::Matched.into(),
+          game_id: Some(game_id),
+          ..Default::default()
+        },
+        vec![],
+      )
+    }
+    Ok(Err(reject)) => {
+      let rejected = get_rejected_player_ids(&player_ids, &reject);
+      tracing::error!(
+        queue_id,
+        "match rejected: {}, players: {:?}",
+        reject.message,
+        rejected
+      );
+      let entries = entries
+        .into_iter()
+        .filter(|e| !rejected.contains(&e.player_id))
+        .collect();
+      let requeued = addr
+        .send(RequeuePlayers { queue_id, entries })
+        .await
+        .unwrap_or_default();
+      (
+        PacketMatchmakingStatus {
+          queue_id,
+          status: MatchmakingStatus::Rejected.into(),
+          message: Error::MatchmakingGameStartRejected(reject.message).to_string(),
+          ..Default::default()
+        },
+        requeued,
+      )
+    }
+    Err(err) => {
+      tracing::error!(queue_id, "start match: {}", err);
+      (
+        PacketMatchmakingStatus {
+          queue_id,
+          status: MatchmakingStatus::Rejected.into(),
+          message: err.to_string(),
+          ..Default::default()
+        },
+        vec![],
+      )
+    }
+  };
+
+  let mut frames = Vec::with_capacity(requeued.len() + 1);
+  for entry in &requeued {
+    let packet = PacketMatchmakingStatus {
+      queue_id,
+      status: MatchmakingStatus::Queued.into(),
+      rating: entry.rating,
+      message: "Match could not be started, you have been returned to the queue.".to_string(),
+      ..Default::default()
+    };
+    frames.push((vec![entry.player_id], packet));
+  }
+  let player_ids: Vec<i32> = player_ids
+    .into_iter()
+    .filter(|id| !requeued.iter().any(|e| e.player_id == *id))
+    .collect();
+  frames.push((player_ids, packet));
+
+  for (player_ids, packet) in frames {
+    let frame = match packet.encode_as_frame() {
+      Ok(frame) => frame,
+      Err(err) => {
+        tracing::error!(queue_id, "encode matchmaking status: {}", err);
+        continue;
+      }
+    };
+    if let Err(err) = player_packet_sender.broadcast(player_ids, frame).await {
+      tracing::error!(queue_id, "broadcast matchmaking status: {}", err);
+    }
+  }
+}
+
+/// Returns the players who caused a start check rejection:
+/// players who did not report their client info, and players whose game or map version
+/// differs from the most common one. Nobody is at fault if no client info was reported,
+/// the node rejected the game in that case.
+///
+/// Without a clear majority, e.g. a 1v1 with different versions, all players are rejected,
+/// otherwise the matcher would pair them again right away.
+fn get_rejected_player_ids(player_ids: &[i32], reject: &PacketGameStartReject) -> Vec<i32> {
+  let map = &reject.player_client_info_map;
+  if map.is_empty() {
+    return vec![];
+  }
+
+  let mut counts: BTreeMap<(&str, &[u8]), usize> = BTreeMap::new();
+  for info in map.values() {
+    *counts
+      .entry((info.war3_version.as_str(), info.map_sha1.as_slice()))
+      .or_default() += 1;
+  }
+  let max_count = counts.values().cloned().max().unwrap_or_default();
+  let mut majority = counts.into_iter().filter(|(_, count)| *count == max_count);
+  let majority = match (majority.next(), majority.next()) {
+    (Some((key, _)), None) => Some(key),
+    _ => None,
+  };
+
+  player_ids
+    .iter()
+    .filter(|id| match (map.get(id), majority) {
+      (Some(info), Some(key)) => key != (info.war3_version.as_str(), info.map_sha1.as_slice()),
+      _ => true,
+    })
+    .cloned()
+    .collect()
+}
+
+/// Returns the start check rejection if the players failed to start the game
+async fn create_and_start_game(
+  games: &Addr<GameRegistry>,
+  queue: MatchmakingQueue,
+  m: Match,
+) -> Result<Result<i32, PacketGameStartReject>> {
+  let map = queue
+    .pick_map()
+    .ok_or_else(|| Error::MatchmakingMapNotAvailable)?;
+
+  let mut slots = vec![];
+  for (team, player_ids) in m.teams.iter().enumerate() {
+    for player_id in player_ids {
+      slots.push(CreateGameSlot {
+        player_id: Some(*player_id),
+        settings: SlotSettings {
+          team: team as i32,
+          color: slots.len() as i32,
+          status: SlotStatus::Occupied,
+          race: Race::Random,
+          ..Default::default()
+        },
+      });
+    }
+  }
+
+  let game = games
+    .send(CreateGameAsBot {
+      api_client_id: queue.api_client_id,
+      api_player_id: queue.api_player_id,
+      rating_pool_id: queue.rating_pool_id,
+      params: CreateGameAsBotParams {
+        name: queue.name.clone(),
+        map,
+        is_private: true,
+        is_live: false,
+        node_id: m.node_id,
+        slots,
+        mask_player_names: None,
+      },
+    })
+    .await??;
+  let game_id = game.id;
+
+  let (tx, rx) = oneshot::channel();
+  let res = match games.send_to(game_id, StartGameCheckAsBot { tx }).await {
+    Ok(_) => match rx.await {
+      Ok(StartGameCheckAsBotResult::Started(_)) => Ok(Ok(game_id)),
+      Ok(StartGameCheckAsBotResult::Rejected(pkt)) => Ok(Err(pkt)),
+      Err(_) => Err(Error::TaskCancelled),
+    },
+    Err(err) => Err(err),
+  };
+
+  if !matches!(res, Ok(Ok(_))) {
+    games
+      .send_to(
+        game_id,
+        CancelGame {
+          player_id: Some(queue.api_player_id),
+        },
+      )
+      .await
+      .ok();
+    games.send(Remove { game_id }).await.ok();
+  }
+
+  res
+}
+
+#[test]
+fn test_get_rejected_player_ids() {
+  use flo_net::proto::flo_connect::PacketGameStartPlayerClientInfoRequest;
+
+  let info = |war3_version: &str, map_sha1: &[u8]| PacketGameStartPlayerClientInfoRequest {
+    game_id: 1,
+    war3_version: war3_version.to_string(),
+    map_sha1: map_sha1.to_vec(),
+  };
+  let reject = |infos: Vec<(i32, PacketGameStartPlayerClientInfoRequest)>| PacketGameStartReject {
+    game_id: 1,
+    message: "rejected".to_string(),
+    player_client_info_map: infos.into_iter().collect(),
+  };
+  let player_ids = [1, 2, 3, 4];
+
+  // node error
+  assert_eq!(
+    get_rejected_player_ids(&player_ids, &reject(vec![])),
+    Vec::<i32>::new()
+  );
+
+  // timeout
+  assert_eq!(
+    get_rejected_player_ids(
+      &player_ids,
+      &reject(vec![(1, info("1.32", &[1])), (3, info("1.32", &[1]))])
+    ),
+    vec![2, 4]
+  );
+
+  // version mismatch
+  assert_eq!(
+    get_rejected_player_ids(
+      &player_ids,
+      &reject(vec![
+        (1, info("1.32", &[1])),
+        (2, info("1.31", &[1])),
+        (3, info("1.32", &[1])),
+        (4, info("1.32", &[2])),
+      ])
+    ),
+    vec![2, 4]
+  );
+
+  // no majority, 1v1
+  assert_eq!(
+    get_rejected_player_ids(
+      &[1, 2],
+      &reject(vec![(1, info("1.32", &[1])), (2, info("1.31", &[1]))])
+    ),
+    vec![1, 2]
+  );
+
+  // no majority, 2v2
+  assert_eq!(
+    get_rejected_player_ids(
+      &player_ids,
+      &reject(vec![
+        (1, info("1.32", &[1])),
+        (2, info("1.31", &[1])),
+        (3, info("1.32", &[1])),
+        (4, info("1.31", &[1])),
+      ])
+    ),
+    vec![1, 2, 3, 4]
+  );
+}
@@ -0,0 +1,137 @@
+use crate::map::Map;
+use rand::seq::SliceRandom;
+
+use super::matcher::MatcherConfig;
+
+pub const DEFAULT_RATING: i32 = 1500;
+/// 2 teams in a 24 player game
+const MAX_TEAM_SIZE: i32 = 12;
+
+#[derive(Debug, Clone)]
+pub struct MatchmakingQueue {
+  pub id: i32,
+  pub name: String,
+  pub api_client_id: i32,
+  pub api_player_id: i32,
+  pub team_size: i32,
+  /// Node location or country id, matches all nodes if not set
+  pub region: Option<String>,
+  pub map_pool: Vec<Map>,
+  pub rating_spread: i32,
+  pub rating_spread_per_minute: i32,
+  pub max_ping: i32,
+  /// Matched games are rated in this pool
+  pub rating_pool_id: Option<i32>,
+  pub enabled: bool,
+}
+
+impl MatchmakingQueue {
+  pub(crate) fn matcher_config(&self) -> MatcherConfig {
+    MatcherConfig {
+      team_size: self.team_size as usize,
+      rating_spread: self.rating_spread,
+      rating_spread_per_minute: self.rating_spread_per_minute,
+      max_ping: self.max_ping as u32,
+    }
+  }
+
+  pub fn accepts_node(&self, location: &str, country_id: &str) -> bool {
+    self
+      .region
+      .as_ref()
+      .map(|region| region == location || region == country_id)
+      .unwrap_or(true)
+  }
+
+  /// Randomly picks a map that has enough player slots
+  pub fn pick_map(&self) -> Option<Map> {
+    let players = (self.team_size * 2) as usize;
+    let maps: Vec<_> = self
+      .map_pool
+      .iter()
+      .filter(|map| map.players.len() >= players)
+      .collect();
+    maps
+      .choose(&mut rand::thread_rng())
+      .map(|map| (*map).clone())
+  }
+}
+
+/// Queue configuration set through the API
+#[derive(Debug)]
+pub struct MatchmakingQueueParams {
+  pub name: String,
+  pub team_size: i32,
+  pub region: Option<String>,
+  pub map_pool: Vec<Map>,
+  pub rating_pool_id: Option<i32>,
+  pub rating_spread: i32,
+  pub rating_spread_per_minute: i32,
+  pub max_ping: i32,
+  pub enabled: bool,
+}
+
+impl MatchmakingQueueParams {
+  pub fn validate(&self) -> Result<(), &'static str> {
+    if self.name.trim().is_empty() {
+      return Err("empty name");
+    }
+    if self.team_size < 1 || self.team_size > MAX_TEAM_SIZE {
+      return Err("team size out of range");
+    }
+    if self.rating_spread < 0 || self.rating_spread_per_minute < 0 {
+      return Err("negative rating spread");
+    }
+    if self.max_ping <= 0 {
+      return Err("max ping must be positive");
+    }
+    let players = (self.team_size * 2) as usize;
+    if !self.map_pool.iter().any(|map| map.players.len() >= players) {
+      return Err("no map in the pool has enough player slots");
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn test_matchmaking_queue_params_validate() {
+  use crate::map::{MapPlayer, MapSha1};
+
+  let map = |players: usize| Map {
+    sha1: MapSha1([0; 20]),
+    checksum: 0,
+    name: "map".to_string(),
+    description: String::new(),
+    author: String::new(),
+    path: String::new(),
+    width: 0,
+    height: 0,
+    players: (0..players)
+      .map(|_| MapPlayer {
+        name: String::new(),
+        r#type: 1,
+        race: 0,
+        flags: 0,
+      })
+      .collect(),
+    forces: vec![],
+  };
+  let params = |team_size: i32, map_pool: Vec<Map>| MatchmakingQueueParams {
+    name: "1v1".to_string(),
+    team_size,
+    region: None,
+    map_pool,
+    rating_pool_id: None,
+    rating_spread: 100,
+    rating_spread_per_minute: 50,
+    max_ping: 150,
+    enabled: true,
+  };
+
+  assert_eq!(params(1, vec![map(2)]).validate(), Ok(()));
+  assert_eq!(params(2, vec![map(2), map(4)]).validate(), Ok(()));
+  assert!(params(2, vec![map(2)]).validate().is_err());
+  assert!(params(1, vec![]).validate().is_err());
+  assert!(params(0, vec![map(2)]).validate().is_err());
+  assert!(params(13, vec![map(24)]).validate().is_err());
+}
@@ -1,7 +1,8 @@
+use crate::db::ExecutorRef;
 use crate::error::*;
 use crate::game::state::GameRegistry;
 use crate::game::state::{GameSlotClientStatusUpdate, GameStatusUpdate};
-use crate::game::{Game, GameStatus};
+use crate::game::{Game, GameDesync, GameNodeSettings, GamePlayerResult, GameStatus};
 use crate::node::state::request::{CreatedGameInfo, NodeRequestActor, NodeRequestExt};
 use crate::node::{NodeConnConfig, PlayerLeaveResponse};
 use crate::state::ActorMapExt;
@@ -33,16 +34,18 @@ pub struct NodeConnActor {
   status: NodeConnStatus,
   request_actor: Option<Owner<NodeRequestActor>>,
   game_reg_addr: Addr<GameRegistry>,
+  db: ExecutorRef,
 }
 
 impl NodeConnActor {
-  pub fn new(config: NodeConnConfig, game_reg_addr: Addr<GameRegistry>) -> Self {
+  pub fn new(config: NodeConnConfig, game_reg_addr: Addr<GameRegistry>, db: ExecutorRef) -> Self {
     Self {
       config,
       status: NodeConnStatus::Connecting,
       reconnect_backoff: None,
       request_actor: None,
       game_reg_addr,
+      db,
     }
   }
 
@@ -274,6 +277,8 @@ impl Handler<IncomingFrame> for NodeConnActor {
       Response(RequestDone),
       GameSlotClientStatusUpdate(GameSlotClientStatusUpdate),
       GameStatusUpdate(Vec<GameStatusUpdate>),
+      GameDesync(GameDesync),
+      GamePlayerResult(GamePlayerResult),
     }
 
     let parsed = flo_net::try_flo_packet! {
@@ -331,6 +336,12 @@ impl Handler<IncomingFrame> for NodeConnActor {
         packet: PacketNodeGameStatusUpdateBulk => {
           Parsed::GameStatusUpdate(packet.games.into_iter().map(Into::into).collect())
         }
+        packet: PacketNodeGameDesync => {
+          Parsed::GameDesync(S2ProtoUnpack::unpack(packet)?)
+        }
+        packet: PacketNodeGamePlayerResult => {
+          Parsed::GamePlayerResult(S2ProtoUnpack::unpack(packet)?)
+        }
       }
     };
 
@@ -352,6 +363,7 @@ impl Handler<IncomingFrame> for NodeConnActor {
       }
       Parsed::GameStatusUpdate(messages) => {
         let addr = self.game_reg_addr.clone();
+        let db = self.db.clone();
         ctx.spawn(async move {
           for message in messages {
             let game_id = message.game_id;
@@ -371,19 +383,65 @@ impl Handler<IncomingFrame> for NodeConnActor {
                   tracing::warn!(game_id, "remove game: {:?}", err);
                 }
               }
+              if GameStatus::from(status) == GameStatus::Ended {
+                rate_game(&db, game_id).await;
+              }
             }
           }
         });
       }
+      Parsed::GameDesync(message) => {
+        let addr = self.game_reg_addr.clone();
+        ctx.spawn(async move {
+          let game_id = message.game_id;
+          if let Err(err) = addr.send_to(game_id, message).await {
+            tracing::warn!(game_id, "GameDesync: {}", err);
+          }
+        });
+      }
+      Parsed::GamePlayerResult(message) => {
+        // the last results arrive after the game has ended and its actor is removed
+        let db = self.db.clone();
+        ctx.spawn(async move {
+          let GamePlayerResult {
+            game_id,
+            player_id,
+            result,
+          } = message;
+          tracing::debug!(game_id, player_id, "player result: {:?}", result);
+          if let Err(err) = db
+            .exec(move |conn| crate::game::db::set_player_result(conn, game_id, player_id, result))
+            .await
+          {
+            tracing::warn!(game_id, "GamePlayerResult: {}", Error::from(err));
+            return;
+          }
+          rate_game(&db, game_id).await;
+        });
+      }
     }
 
     Ok(())
   }
 }
 
+/// Called when the game ends and after each player result,
+/// the game is rated once the outcome can be determined
+async fn rate_game(db: &ExecutorRef, game_id: i32) {
+  if let Err(err) = db
+    .exec(move |conn| crate::rating::db::rate_game(conn, game_id))
+    .await
+  {
+    tracing::error!(game_id, "rate game: {}", Error::from(err));
+  }
+}
+
 pub struct NodeCreateGame {
   pub game: Game,
+  pub settings: GameNodeSettings,
   pub ban_list_map: BTreeMap<i32, Vec<PlayerBanType>>,
+  /// Game versions reported by the players in the start check
+  pub game_version_map: BTreeMap<i32, String>,
 }
 
 impl Message for NodeCreateGame {
@@ -395,7 +453,12 @@ impl Handler<NodeCreateGame> for NodeConnActor {
   async fn handle(
     &mut self,
     ctx: &mut Context<Self>,
-    NodeCreateGame { game, ban_list_map }: NodeCreateGame,
+    NodeCreateGame {
+      game,
+      settings,
+      ban_list_map,
+      game_version_map,
+    }: NodeCreateGame,
   ) -> Result<FutureReply<Result<CreatedGameInfo>>> {
     let addr = self
       .request_actor
@@ -404,7 +467,12 @@ impl Handler<NodeCreateGame> for NodeConnActor {
       .ok_or_else(|| Error::NodeNotReady)?;
     let (tx, rx) = FutureReply::channel();
     ctx.spawn(async move {
-      tx.send(addr.create_game(game, ban_list_map).await).ok();
+      tx.send(
+        addr
+          .create_game(game, settings, ban_list_map, game_version_map)
+          .await,
+      )
+      .ok();
     });
     Ok(rx)
   }
@@ -58,7 +58,7 @@ impl NodeRegistry {
       tracing::debug!(node_id = node.id, "added");
       self.map.insert(
         node.id,
-        NodeConnActor::new(node.into(), game_reg_addr.clone()).start(),
+        NodeConnActor::new(node.into(), game_reg_addr.clone(), self.db.clone()).start(),
       );
     }
 
@@ -114,7 +114,7 @@ impl Handler<Reload> for NodeRegistry {
         tracing::info!(id = config.id, "node added: {}", config.addr);
         self.map.insert(
           config.id,
-          NodeConnActor::new(config, self.game_reg_addr.resolve().await?).start(),
+          NodeConnActor::new(config, self.game_reg_addr.resolve().await?, self.db.clone()).start(),
         );
         broadcast_frames.push(
           PacketAddNode {
@@ -1,5 +1,5 @@
 use crate::error::*;
-use crate::game::{Game, SlotClientStatus, SlotStatus};
+use crate::game::{Game, GameNodeSettings, SlotClientStatus, SlotStatus};
 use crate::node::PlayerToken;
 use crate::player::PlayerBanType;
 use flo_net::packet::*;
@@ -230,7 +230,9 @@ pub trait NodeRequestExt {
   async fn create_game(
     &self,
     game: Game,
+    settings: GameNodeSettings,
     ban_list_map: BTreeMap<i32, Vec<PlayerBanType>>,
+    game_version_map: BTreeMap<i32, String>,
   ) -> Result<CreatedGameInfo>;
   async fn player_force_leave(&self, game_id: i32, player_id: i32) -> Result<PlayerLeaveResponse>;
 }
@@ -240,7 +242,9 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
   async fn create_game(
     &self,
     game: Game,
+    settings: GameNodeSettings,
     mut ban_list_map: BTreeMap<i32, Vec<PlayerBanType>>,
+    mut game_version_map: BTreeMap<i32, String>,
   ) -> Result<CreatedGameInfo> {
     let game_id = game.id;
 
@@ -262,6 +266,15 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
               .remove(&player.id)
               .map(|items| items.into_iter().map(|v| v as i32).collect())
               .unwrap_or_default(),
+            role: if settings.referee_player_ids.contains(&player.id) {
+              GamePlayerRole::Referee
+            } else if player.id == game.created_by.id {
+              GamePlayerRole::Host
+            } else {
+              GamePlayerRole::Player
+            }
+            .into(),
+            game_version: game_version_map.remove(&player.id).unwrap_or_default(),
           }),
           settings: Some(slot.settings.clone().pack()?),
           client_status: Default::default(),
@@ -276,6 +289,9 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
           map_path: game.map.path.clone(),
           map_sha1: game.map.sha1.to_vec(),
           map_checksum: game.map.checksum,
+          step: settings.step.pack()?,
+          lag: settings.lag.pack()?,
+          game_version: game.game_version.clone().unwrap_or_default(),
         }),
         slots,
         status: Default::default(),
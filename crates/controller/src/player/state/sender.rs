@@ -1,3 +1,4 @@
+use super::ping::{GetPlayersPingSnapshot, NodePlayersPingSnapshot};
 use super::{PlayerRegistry, PlayerState};
 use crate::error::*;
 use crate::game::Game;
@@ -430,6 +431,11 @@ impl PlayerRegistryHandle {
       .await??;
     Ok(())
   }
+
+  pub async fn get_ping_snapshot(&self, players: Vec<i32>) -> Result<NodePlayersPingSnapshot> {
+    let snapshot = self.0.send(GetPlayersPingSnapshot { players }).await?;
+    Ok(snapshot)
+  }
 }
 
 impl From<Addr<PlayerRegistry>> for PlayerRegistryHandle {
@@ -0,0 +1,412 @@
+use diesel::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::game::GameStatus;
+use crate::rating::types::*;
+use crate::schema::{game, game_used_slot, player_rating, player_rating_history, rating_pool};
+
+pub fn get_pools(conn: &DbConn, api_client_id: i32) -> Result<Vec<RatingPool>> {
+  rating_pool::table
+    .filter(rating_pool::api_client_id.eq(api_client_id))
+    .order(rating_pool::id)
+    .load(conn)
+    .map_err(Into::into)
+}
+
+/// Returns a pool of the API client
+pub fn get_pool(conn: &DbConn, api_client_id: i32, pool_id: i32) -> Result<RatingPool> {
+  rating_pool::table
+    .filter(
+      rating_pool::id
+        .eq(pool_id)
+        .and(rating_pool::api_client_id.eq(api_client_id)),
+    )
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::RatingPoolNotFound)
+}
+
+pub fn create_pool(
+  conn: &DbConn,
+  api_client_id: i32,
+  name: &str,
+  algorithm: RatingAlgorithm,
+) -> Result<RatingPool> {
+  #[derive(Insertable)]
+  #[table_name = "rating_pool"]
+  struct Insert<'a> {
+    api_client_id: i32,
+    name: &'a str,
+    algorithm: RatingAlgorithm,
+  }
+
+  let name = name.trim();
+  if name.is_empty() {
+    return Err(Error::RatingPoolNameInvalid);
+  }
+
+  conn.transaction(|| {
+    let exists: i64 = rating_pool::table
+      .filter(
+        rating_pool::api_client_id
+          .eq(api_client_id)
+          .and(rating_pool::name.eq(name)),
+      )
+      .count()
+      .get_result(conn)?;
+    if exists > 0 {
+      return Err(Error::RatingPoolNameInvalid);
+    }
+
+    diesel::insert_into(rating_pool::table)
+      .values(&Insert {
+        api_client_id,
+        name,
+        algorithm,
+      })
+      .get_result(conn)
+      .map_err(Into::into)
+  })
+}
+
+pub fn get_leaderboard(
+  conn: &DbConn,
+  api_client_id: i32,
+  pool_id: i32,
+  offset: i64,
+  limit: i64,
+) -> Result<Vec<PlayerRating>> {
+  get_pool(conn, api_client_id, pool_id)?;
+  player_rating::table
+    .filter(player_rating::pool_id.eq(pool_id))
+    .select(PlayerRating::COLUMNS)
+    .order((player_rating::score.desc(), player_rating::id))
+    .offset(offset)
+    .limit(limit)
+    .load(conn)
+    .map_err(Into::into)
+}
+
+/// Returns the player's ratings in the pools of the API client
+pub fn get_player_ratings(
+  conn: &DbConn,
+  api_client_id: i32,
+  player_id: i32,
+) -> Result<Vec<PlayerRating>> {
+  crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)?;
+  let pool_ids = rating_pool::table
+    .filter(rating_pool::api_client_id.eq(api_client_id))
+    .select(rating_pool::id);
+  player_rating::table
+    .filter(
+      player_rating::player_id
+        .eq(player_id)
+        .and(player_rating::pool_id.eq_any(pool_ids)),
+    )
+    .select(PlayerRating::COLUMNS)
+    .order(player_rating::pool_id)
+    .load(conn)
+    .map_err(Into::into)
+}
+
+pub fn get_player_rating_history(
+  conn: &DbConn,
+  api_client_id: i32,
+  pool_id: i32,
+  player_id: i32,
+  limit: i64,
+) -> Result<Vec<PlayerRatingHistory>> {
+  get_pool(conn, api_client_id, pool_id)?;
+  crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)?;
+  player_rating_history::table
+    .filter(
+      player_rating_history::pool_id
+        .eq(pool_id)
+        .and(player_rating_history::player_id.eq(player_id)),
+    )
+    .select(PlayerRatingHistory::COLUMNS)
+    .order(player_rating_history::id.desc())
+    .limit(limit)
+    .load(conn)
+    .map_err(Into::into)
+}
+
+/// Returns the player's rating in a pool, unrated players get the initial rating
+pub fn get_player_rating_value(conn: &DbConn, pool_id: i32, player_id: i32) -> Result<f64> {
+  let pool: RatingPool = rating_pool::table
+    .find(pool_id)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::RatingPoolNotFound)?;
+  let rating: Option<f64> = player_rating::table
+    .filter(
+      player_rating::pool_id
+        .eq(pool_id)
+        .and(player_rating::player_id.eq(player_id)),
+    )
+    .select(player_rating::rating)
+    .first(conn)
+    .optional()?;
+  Ok(rating.unwrap_or(pool.algorithm.initial_rating().value))
+}
+
+/// Returns the ratings of a game's players in a pool of the game's API client,
+/// defaults to the pool the game is rated in. Unrated players get the initial rating.
+pub fn get_game_rating_values(
+  conn: &DbConn,
+  game_id: i32,
+  pool_id: Option<i32>,
+) -> Result<HashMap<i32, f64>> {
+  let (api_client_id, game_pool_id) = crate::game::db::get_rating_pool(conn, game_id)?;
+
+  let mut q = rating_pool::table
+    .filter(rating_pool::api_client_id.eq(api_client_id))
+    .order(rating_pool::id)
+    .into_boxed();
+  if let Some(pool_id) = pool_id.or(game_pool_id) {
+    q = q.filter(rating_pool::id.eq(pool_id));
+  }
+  let pool: RatingPool = q
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::RatingPoolNotFound)?;
+
+  let player_ids: Vec<i32> = game_used_slot::table
+    .filter(
+      game_used_slot::game_id
+        .eq(game_id)
+        .and(game_used_slot::player_id.is_not_null()),
+    )
+    .select(game_used_slot::player_id)
+    .load::<Option<i32>>(conn)?
+    .into_iter()
+    .filter_map(|id| id)
+    .collect();
+
+  let ratings: HashMap<i32, f64> = player_rating::table
+    .filter(
+      player_rating::pool_id
+        .eq(pool.id)
+        .and(player_rating::player_id.eq_any(&player_ids)),
+    )
+    .select((player_rating::player_id, player_rating::rating))
+    .load::<(i32, f64)>(conn)?
+    .into_iter()
+    .collect();
+
+  let initial = pool.algorithm.initial_rating().value;
+  Ok(
+    player_ids
+      .into_iter()
+      .map(|id| (id, ratings.get(&id).cloned().unwrap_or(initial)))
+      .collect(),
+  )
+}
+
+/// Updates the ratings of an ended game in the pool it was created for.
+/// Does nothing if the game has no pool, has not ended, has been rated, or the outcome
+/// can not be determined from the results reported so far.
+pub fn rate_game(conn: &DbConn, game_id: i32) -> Result<()> {
+  conn.transaction(|| {
+    // serializes with `set_player_result` and concurrent calls
+    game::table
+      .find(game_id)
+      .select(game::id)
+      .for_update()
+      .first::<i32>(conn)
+      .optional()?
+      .ok_or_else(|| Error::GameNotFound)?;
+
+    let (api_client_id, pool_id) = crate::game::db::get_rating_pool(conn, game_id)?;
+    let pool_id = if let Some(id) = pool_id {
+      id
+    } else {
+      return Ok(());
+    };
+
+    let game = crate::game::db::get_full(conn, game_id)?;
+    if game.status != GameStatus::Ended {
+      return Ok(());
+    }
+
+    let results = crate::game::db::get_player_results(conn, game_id)?;
+    let outcome = if let Some(outcome) = GameOutcome::from_results(&game.slots, &results) {
+      outcome
+    } else {
+      tracing::debug!(game_id, "game outcome undetermined: {:?}", results);
+      return Ok(());
+    };
+
+    let pool: RatingPool = rating_pool::table
+      .filter(
+        rating_pool::id
+          .eq(pool_id)
+          .and(rating_pool::api_client_id.eq(api_client_id)),
+      )
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::RatingPoolNotFound)?;
+
+    update_pool_ratings(conn, &pool, game_id, &outcome)
+  })
+}
+
+fn update_pool_ratings(
+  conn: &DbConn,
+  pool: &RatingPool,
+  game_id: i32,
+  outcome: &GameOutcome,
+) -> Result<()> {
+  #[derive(Insertable)]
+  #[table_name = "player_rating"]
+  struct InsertRating {
+    pool_id: i32,
+    player_id: i32,
+    rating: f64,
+    deviation: f64,
+    volatility: f64,
+    score: f64,
+    games: i32,
+    wins: i32,
+    losses: i32,
+    draws: i32,
+  }
+
+  #[derive(Insertable)]
+  #[table_name = "player_rating_history"]
+  struct InsertHistory {
+    pool_id: i32,
+    player_id: i32,
+    game_id: i32,
+    rating_before: f64,
+    rating_after: f64,
+    deviation_before: f64,
+    deviation_after: f64,
+    result: Score,
+  }
+
+  let pool_id = pool.id;
+  let player_ids: Vec<i32> = outcome.teams.iter().flatten().cloned().collect();
+
+  conn.transaction(|| {
+    let rated: i64 = player_rating_history::table
+      .filter(
+        player_rating_history::pool_id
+          .eq(pool_id)
+          .and(player_rating_history::game_id.eq(game_id)),
+      )
+      .count()
+      .get_result(conn)?;
+    if rated > 0 {
+      return Ok(());
+    }
+
+    let mut current: BTreeMap<i32, PlayerRating> = player_rating::table
+      .filter(
+        player_rating::pool_id
+          .eq(pool_id)
+          .and(player_rating::player_id.eq_any(&player_ids)),
+      )
+      .select(PlayerRating::COLUMNS)
+      .for_update()
+      .load::<PlayerRating>(conn)?
+      .into_iter()
+      .map(|r| (r.player_id, r))
+      .collect();
+
+    let teams: Vec<Vec<Rating>> = outcome
+      .teams
+      .iter()
+      .map(|team| {
+        team
+          .iter()
+          .map(|id| {
+            current
+              .get(id)
+              .map(|r| r.to_rating())
+              .unwrap_or_else(|| pool.algorithm.initial_rating())
+          })
+          .collect()
+      })
+      .collect();
+    let rated_teams = pool.algorithm.rate(&teams, &outcome.ranks);
+
+    let mut history = vec![];
+    for (team_idx, player_ids) in outcome.teams.iter().enumerate() {
+      let result = if outcome.ranks.iter().all(|r| *r == outcome.ranks[team_idx]) {
+        Score::Draw
+      } else if outcome.ranks[team_idx] == 0 {
+        Score::Win
+      } else {
+        Score::Loss
+      };
+
+      for (idx, player_id) in player_ids.iter().enumerate() {
+        let before = teams[team_idx][idx];
+        let after = rated_teams[team_idx][idx];
+        let score = pool.algorithm.score(&after);
+        let (wins, losses, draws) = match result {
+          Score::Win => (1, 0, 0),
+          Score::Loss => (0, 1, 0),
+          Score::Draw => (0, 0, 1),
+        };
+
+        if let Some(row) = current.remove(player_id) {
+          diesel::update(
+            player_rating::table.filter(
+              player_rating::pool_id
+                .eq(pool_id)
+                .and(player_rating::player_id.eq(player_id)),
+            ),
+          )
+          .set((
+            player_rating::rating.eq(after.value),
+            player_rating::deviation.eq(after.deviation),
+            player_rating::volatility.eq(after.volatility),
+            player_rating::score.eq(score),
+            player_rating::games.eq(row.games + 1),
+            player_rating::wins.eq(row.wins + wins),
+            player_rating::losses.eq(row.losses + losses),
+            player_rating::draws.eq(row.draws + draws),
+            player_rating::updated_at.eq(diesel::dsl::now),
+          ))
+          .execute(conn)?;
+        } else {
+          diesel::insert_into(player_rating::table)
+            .values(&InsertRating {
+              pool_id,
+              player_id: *player_id,
+              rating: after.value,
+              deviation: after.deviation,
+              volatility: after.volatility,
+              score,
+              games: 1,
+              wins,
+              losses,
+              draws,
+            })
+            .execute(conn)?;
+        }
+
+        history.push(InsertHistory {
+          pool_id,
+          player_id: *player_id,
+          game_id,
+          rating_before: before.value,
+          rating_after: after.value,
+          deviation_before: before.deviation,
+          deviation_after: after.deviation,
+          result,
+        });
+      }
+    }
+
+    diesel::insert_into(player_rating_history::table)
+      .values(&history)
+      .execute(conn)?;
+
+    Ok(())
+  })
+}
@@ -0,0 +1,56 @@
+use super::{Rating, Score};
+
+pub const INITIAL_RATING: f64 = 1500.;
+const K: f64 = 32.;
+
+/// Team ratings are the mean of the players' ratings,
+/// every player of a team gets the same delta.
+pub fn rate(teams: &[Vec<Rating>], ranks: &[u32]) -> Vec<Vec<Rating>> {
+  let means: Vec<f64> = teams
+    .iter()
+    .map(|team| team.iter().map(|r| r.value).sum::<f64>() / team.len() as f64)
+    .collect();
+
+  teams
+    .iter()
+    .enumerate()
+    .map(|(i, team)| {
+      let mut delta = 0.;
+      for j in 0..teams.len() {
+        if i == j {
+          continue;
+        }
+        let expected = 1. / (1. + 10f64.powf((means[j] - means[i]) / 400.));
+        delta += K * (Score::from_ranks(ranks[i], ranks[j]).value() - expected);
+      }
+      let delta = delta / (teams.len() - 1) as f64;
+      team
+        .iter()
+        .map(|r| Rating {
+          value: r.value + delta,
+          ..*r
+        })
+        .collect()
+    })
+    .collect()
+}
+
+#[test]
+fn test_elo() {
+  let r = |value| Rating {
+    value,
+    deviation: 0.,
+    volatility: 0.,
+  };
+
+  let rated = rate(&[vec![r(1500.)], vec![r(1500.)]], &[0, 1]);
+  assert_eq!(rated[0][0].value, 1516.);
+  assert_eq!(rated[1][0].value, 1484.);
+
+  let rated = rate(&[vec![r(1600.), r(1400.)], vec![r(1500.)]], &[0, 0]);
+  assert_eq!(rated[0][0].value, 1600.);
+  assert_eq!(rated[1][0].value, 1500.);
+
+  let rated = rate(&[vec![r(1800.)], vec![r(1400.)]], &[1, 0]);
+  assert!((rated[1][0].value - 1429.09).abs() < 0.01);
+}
@@ -0,0 +1,144 @@
+use super::{Rating, Score};
+use std::f64::consts::PI;
+
+pub const INITIAL_RATING: f64 = 1500.;
+pub const INITIAL_DEVIATION: f64 = 350.;
+pub const INITIAL_VOLATILITY: f64 = 0.06;
+
+const SCALE: f64 = 173.7178;
+const TAU: f64 = 0.5;
+const CONVERGENCE_TOLERANCE: f64 = 0.000001;
+
+/// Every game is a rating period.
+/// Each player is rated against the other teams,
+/// a team is treated as a single opponent with the mean rating and deviation of its players.
+pub fn rate(teams: &[Vec<Rating>], ranks: &[u32]) -> Vec<Vec<Rating>> {
+  let opponents: Vec<(f64, f64)> = teams
+    .iter()
+    .map(|team| {
+      let n = team.len() as f64;
+      let mu = team.iter().map(|r| to_mu(r.value)).sum::<f64>() / n;
+      let phi = (team
+        .iter()
+        .map(|r| (r.deviation / SCALE).powi(2))
+        .sum::<f64>()
+        / n)
+        .sqrt();
+      (mu, phi)
+    })
+    .collect();
+
+  teams
+    .iter()
+    .enumerate()
+    .map(|(i, team)| {
+      let games: Vec<_> = opponents
+        .iter()
+        .enumerate()
+        .filter(|(j, _)| *j != i)
+        .map(|(j, (mu, phi))| (*mu, *phi, Score::from_ranks(ranks[i], ranks[j]).value()))
+        .collect();
+      team.iter().map(|r| rate_player(r, &games)).collect()
+    })
+    .collect()
+}
+
+fn rate_player(rating: &Rating, games: &[(f64, f64, f64)]) -> Rating {
+  let mu = to_mu(rating.value);
+  let phi = rating.deviation / SCALE;
+  let sigma = rating.volatility;
+
+  let mut v_inv = 0.;
+  let mut delta_sum = 0.;
+  for (mu_j, phi_j, score) in games {
+    let g = g(*phi_j);
+    let e = e(mu, *mu_j, g);
+    v_inv += g * g * e * (1. - e);
+    delta_sum += g * (score - e);
+  }
+  let v = 1. / v_inv;
+  let delta = v * delta_sum;
+
+  let sigma = volatility(phi, sigma, v, delta);
+  let phi_star = (phi * phi + sigma * sigma).sqrt();
+  let phi = 1. / (1. / (phi_star * phi_star) + 1. / v).sqrt();
+  let mu = mu + phi * phi * delta_sum;
+
+  Rating {
+    value: mu * SCALE + INITIAL_RATING,
+    deviation: phi * SCALE,
+    volatility: sigma,
+  }
+}
+
+fn volatility(phi: f64, sigma: f64, v: f64, delta: f64) -> f64 {
+  let a = (sigma * sigma).ln();
+  let f = |x: f64| {
+    let ex = x.exp();
+    ex * (delta * delta - phi * phi - v - ex) / (2. * (phi * phi + v + ex).powi(2))
+      - (x - a) / (TAU * TAU)
+  };
+
+  let mut big_a = a;
+  let mut big_b = if delta * delta > phi * phi + v {
+    (delta * delta - phi * phi - v).ln()
+  } else {
+    let mut k = 1.;
+    while f(a - k * TAU) < 0. {
+      k += 1.;
+    }
+    a - k * TAU
+  };
+
+  let mut f_a = f(big_a);
+  let mut f_b = f(big_b);
+  while (big_b - big_a).abs() > CONVERGENCE_TOLERANCE {
+    let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+    let f_c = f(big_c);
+    if f_c * f_b <= 0. {
+      big_a = big_b;
+      f_a = f_b;
+    } else {
+      f_a /= 2.;
+    }
+    big_b = big_c;
+    f_b = f_c;
+  }
+
+  (big_a / 2.).exp()
+}
+
+fn to_mu(value: f64) -> f64 {
+  (value - INITIAL_RATING) / SCALE
+}
+
+fn g(phi: f64) -> f64 {
+  1. / (1. + 3. * phi * phi / (PI * PI)).sqrt()
+}
+
+fn e(mu: f64, mu_j: f64, g: f64) -> f64 {
+  1. / (1. + (-g * (mu - mu_j)).exp())
+}
+
+#[test]
+fn test_glicko2() {
+  // Example from http://www.glicko.net/glicko/glicko2.pdf
+  let r = |value, deviation| Rating {
+    value,
+    deviation,
+    volatility: INITIAL_VOLATILITY,
+  };
+  let rated = rate(
+    &[
+      vec![r(1500., 200.)],
+      vec![r(1400., 30.)],
+      vec![r(1550., 100.)],
+      vec![r(1700., 300.)],
+    ],
+    &[1, 2, 0, 0],
+  );
+  let player = rated[0][0];
+  assert!((player.value - 1464.05).abs() < 0.01);
+  assert!((player.deviation - 151.52).abs() < 0.01);
+  assert!((player.volatility - 0.05999).abs() < 0.00001);
+}
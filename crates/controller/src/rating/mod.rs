@@ -0,0 +1,7 @@
+pub mod db;
+mod elo;
+mod glicko2;
+mod trueskill;
+mod types;
+
+pub use types::*;
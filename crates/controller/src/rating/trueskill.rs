@@ -0,0 +1,202 @@
+use super::{Rating, Score};
+
+pub const INITIAL_MU: f64 = 25.;
+pub const INITIAL_SIGMA: f64 = INITIAL_MU / 3.;
+
+const BETA: f64 = INITIAL_SIGMA / 2.;
+const TAU: f64 = INITIAL_SIGMA / 100.;
+const DRAW_PROBABILITY: f64 = 0.1;
+
+/// Two-team games use the exact TrueSkill update.
+/// Games with more teams are approximated by updating
+/// each pair of adjacent teams in rank order.
+pub fn rate(teams: &[Vec<Rating>], ranks: &[u32]) -> Vec<Vec<Rating>> {
+  let mut teams: Vec<Vec<Rating>> = teams
+    .iter()
+    .map(|team| {
+      team
+        .iter()
+        .map(|r| Rating {
+          deviation: (r.deviation * r.deviation + TAU * TAU).sqrt(),
+          ..*r
+        })
+        .collect()
+    })
+    .collect();
+
+  let mut order: Vec<usize> = (0..teams.len()).collect();
+  order.sort_by_key(|i| ranks[*i]);
+
+  for pair in order.windows(2) {
+    let (a, b) = (pair[0], pair[1]);
+    let score = Score::from_ranks(ranks[a], ranks[b]);
+    let (rated_a, rated_b) = rate_pair(&teams[a], &teams[b], score);
+    teams[a] = rated_a;
+    teams[b] = rated_b;
+  }
+
+  teams
+}
+
+/// `a` is ranked higher than or equal to `b`
+fn rate_pair(a: &[Rating], b: &[Rating], score: Score) -> (Vec<Rating>, Vec<Rating>) {
+  let size = (a.len() + b.len()) as f64;
+  let c = (a
+    .iter()
+    .chain(b.iter())
+    .map(|r| r.deviation * r.deviation)
+    .sum::<f64>()
+    + size * BETA * BETA)
+    .sqrt();
+  let mu_a: f64 = a.iter().map(|r| r.value).sum();
+  let mu_b: f64 = b.iter().map(|r| r.value).sum();
+  let draw_margin = ppf((DRAW_PROBABILITY + 1.) / 2.) * size.sqrt() * BETA;
+
+  let t = (mu_a - mu_b) / c;
+  let e = draw_margin / c;
+  let (v, w) = match score {
+    Score::Draw => (v_draw(t, e), w_draw(t, e)),
+    _ => (v_win(t, e), w_win(t, e)),
+  };
+
+  let update = |team: &[Rating], sign: f64| -> Vec<Rating> {
+    team
+      .iter()
+      .map(|r| {
+        let variance = r.deviation * r.deviation;
+        Rating {
+          value: r.value + sign * variance / c * v,
+          deviation: (variance * (1. - variance / (c * c) * w).max(f64::EPSILON)).sqrt(),
+          ..*r
+        }
+      })
+      .collect()
+  };
+
+  (update(a, 1.), update(b, -1.))
+}
+
+fn v_win(t: f64, e: f64) -> f64 {
+  let x = t - e;
+  let denom = cdf(x);
+  if denom > 0. {
+    pdf(x) / denom
+  } else {
+    -x
+  }
+}
+
+fn w_win(t: f64, e: f64) -> f64 {
+  let v = v_win(t, e);
+  v * (v + t - e)
+}
+
+fn v_draw(t: f64, e: f64) -> f64 {
+  let abs_t = t.abs();
+  let (a, b) = (e - abs_t, -e - abs_t);
+  let denom = cdf(a) - cdf(b);
+  let v = if denom > 0. {
+    (pdf(b) - pdf(a)) / denom
+  } else {
+    a
+  };
+  if t < 0. {
+    -v
+  } else {
+    v
+  }
+}
+
+fn w_draw(t: f64, e: f64) -> f64 {
+  let abs_t = t.abs();
+  let (a, b) = (e - abs_t, -e - abs_t);
+  let denom = cdf(a) - cdf(b);
+  let v = v_draw(abs_t, e);
+  v * v + (a * pdf(a) - b * pdf(b)) / denom
+}
+
+fn pdf(x: f64) -> f64 {
+  (-x * x / 2.).exp() / (2. * std::f64::consts::PI).sqrt()
+}
+
+fn cdf(x: f64) -> f64 {
+  0.5 * erfc(-x / std::f64::consts::SQRT_2)
+}
+
+fn ppf(x: f64) -> f64 {
+  -std::f64::consts::SQRT_2 * erfcinv(2. * x)
+}
+
+/// Complementary error function, Numerical Recipes `erfcc`
+fn erfc(x: f64) -> f64 {
+  let z = x.abs();
+  let t = 1. / (1. + z / 2.);
+  let r = t
+    * (-z * z - 1.26551223
+      + t
+        * (1.00002368
+          + t
+            * (0.37409196
+              + t
+                * (0.09678418
+                  + t
+                    * (-0.18628806
+                      + t
+                        * (0.27886807
+                          + t
+                            * (-1.13520398
+                              + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+      .exp();
+  if x < 0. {
+    2. - r
+  } else {
+    r
+  }
+}
+
+fn erfcinv(y: f64) -> f64 {
+  if y >= 2. {
+    return -100.;
+  }
+  if y <= 0. {
+    return 100.;
+  }
+  let zero_point = y < 1.;
+  let y = if zero_point { y } else { 2. - y };
+  let t = (-2. * (y / 2.).ln()).sqrt();
+  let mut x = -0.70711 * ((2.30753 + t * 0.27061) / (1. + t * (0.99229 + t * 0.04481)) - t);
+  for _ in 0..2 {
+    let err = erfc(x) - y;
+    x += err / (1.128_379_167_095_512_6 * (-x * x).exp() - x * err);
+  }
+  if zero_point {
+    x
+  } else {
+    -x
+  }
+}
+
+#[test]
+fn test_trueskill() {
+  let r = || Rating {
+    value: INITIAL_MU,
+    deviation: INITIAL_SIGMA,
+    volatility: 0.,
+  };
+  let assert_rating = |rating: &Rating, mu: f64, sigma: f64| {
+    assert!((rating.value - mu).abs() < 0.001, "{:?}", rating);
+    assert!((rating.deviation - sigma).abs() < 0.001, "{:?}", rating);
+  };
+
+  let rated = rate(&[vec![r()], vec![r()]], &[0, 1]);
+  assert_rating(&rated[0][0], 29.396, 7.171);
+  assert_rating(&rated[1][0], 20.604, 7.171);
+
+  let rated = rate(&[vec![r()], vec![r()]], &[1, 0]);
+  assert_rating(&rated[0][0], 20.604, 7.171);
+  assert_rating(&rated[1][0], 29.396, 7.171);
+
+  let rated = rate(&[vec![r()], vec![r()]], &[0, 0]);
+  assert_rating(&rated[0][0], 25.000, 6.458);
+  assert_rating(&rated[1][0], 25.000, 6.458);
+}
@@ -0,0 +1,348 @@
+use bs_diesel_utils::BSDieselEnum;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::{elo, glicko2, trueskill};
+use crate::game::{PlayerGameResult, Slot, SlotStatus};
+use crate::schema::{player_rating, player_rating_history};
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum)]
+#[repr(i32)]
+pub enum RatingAlgorithm {
+  Elo = 0,
+  Glicko2 = 1,
+  /// Designed for team games
+  TrueSkill = 2,
+}
+
+impl RatingAlgorithm {
+  pub fn initial_rating(self) -> Rating {
+    match self {
+      RatingAlgorithm::Elo => Rating {
+        value: elo::INITIAL_RATING,
+        deviation: 0.,
+        volatility: 0.,
+      },
+      RatingAlgorithm::Glicko2 => Rating {
+        value: glicko2::INITIAL_RATING,
+        deviation: glicko2::INITIAL_DEVIATION,
+        volatility: glicko2::INITIAL_VOLATILITY,
+      },
+      RatingAlgorithm::TrueSkill => Rating {
+        value: trueskill::INITIAL_MU,
+        deviation: trueskill::INITIAL_SIGMA,
+        volatility: 0.,
+      },
+    }
+  }
+
+  /// Returns the new ratings of every team's players.
+  /// Teams are ranked by `ranks`, lower is better, equal ranks are draws.
+  pub fn rate(self, teams: &[Vec<Rating>], ranks: &[u32]) -> Vec<Vec<Rating>> {
+    match self {
+      RatingAlgorithm::Elo => elo::rate(teams, ranks),
+      RatingAlgorithm::Glicko2 => glicko2::rate(teams, ranks),
+      RatingAlgorithm::TrueSkill => trueskill::rate(teams, ranks),
+    }
+  }
+
+  /// Conservative skill estimate used to sort leaderboards
+  pub fn score(self, rating: &Rating) -> f64 {
+    match self {
+      RatingAlgorithm::Elo => rating.value,
+      RatingAlgorithm::Glicko2 => rating.value - 2. * rating.deviation,
+      RatingAlgorithm::TrueSkill => rating.value - 3. * rating.deviation,
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rating {
+  pub value: f64,
+  pub deviation: f64,
+  pub volatility: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum)]
+#[repr(i32)]
+pub enum Score {
+  Loss = 0,
+  Draw = 1,
+  Win = 2,
+}
+
+impl Score {
+  pub fn from_ranks(rank: u32, opponent_rank: u32) -> Self {
+    use std::cmp::Ordering;
+    match rank.cmp(&opponent_rank) {
+      Ordering::Less => Score::Win,
+      Ordering::Equal => Score::Draw,
+      Ordering::Greater => Score::Loss,
+    }
+  }
+
+  pub fn value(self) -> f64 {
+    match self {
+      Score::Loss => 0.,
+      Score::Draw => 0.5,
+      Score::Win => 1.,
+    }
+  }
+}
+
+#[derive(Debug, Queryable)]
+pub struct RatingPool {
+  pub id: i32,
+  pub api_client_id: i32,
+  pub name: String,
+  pub algorithm: RatingAlgorithm,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Queryable)]
+pub struct PlayerRating {
+  pub pool_id: i32,
+  pub player_id: i32,
+  pub rating: f64,
+  pub deviation: f64,
+  pub volatility: f64,
+  pub score: f64,
+  pub games: i32,
+  pub wins: i32,
+  pub losses: i32,
+  pub draws: i32,
+  pub updated_at: DateTime<Utc>,
+}
+
+pub(crate) type PlayerRatingColumns = (
+  player_rating::pool_id,
+  player_rating::player_id,
+  player_rating::rating,
+  player_rating::deviation,
+  player_rating::volatility,
+  player_rating::score,
+  player_rating::games,
+  player_rating::wins,
+  player_rating::losses,
+  player_rating::draws,
+  player_rating::updated_at,
+);
+
+impl PlayerRating {
+  pub(crate) const COLUMNS: PlayerRatingColumns = (
+    player_rating::pool_id,
+    player_rating::player_id,
+    player_rating::rating,
+    player_rating::deviation,
+    player_rating::volatility,
+    player_rating::score,
+    player_rating::games,
+    player_rating::wins,
+    player_rating::losses,
+    player_rating::draws,
+    player_rating::updated_at,
+  );
+
+  pub fn to_rating(&self) -> Rating {
+    Rating {
+      value: self.rating,
+      deviation: self.deviation,
+      volatility: self.volatility,
+    }
+  }
+}
+
+#[derive(Debug, Queryable)]
+pub struct PlayerRatingHistory {
+  pub pool_id: i32,
+  pub player_id: i32,
+  pub game_id: i32,
+  pub rating_before: f64,
+  pub rating_after: f64,
+  pub deviation_before: f64,
+  pub deviation_after: f64,
+  pub result: Score,
+  pub created_at: DateTime<Utc>,
+}
+
+pub(crate) type PlayerRatingHistoryColumns = (
+  player_rating_history::pool_id,
+  player_rating_history::player_id,
+  player_rating_history::game_id,
+  player_rating_history::rating_before,
+  player_rating_history::rating_after,
+  player_rating_history::deviation_before,
+  player_rating_history::deviation_after,
+  player_rating_history::result,
+  player_rating_history::created_at,
+);
+
+impl PlayerRatingHistory {
+  pub(crate) const COLUMNS: PlayerRatingHistoryColumns = (
+    player_rating_history::pool_id,
+    player_rating_history::player_id,
+    player_rating_history::game_id,
+    player_rating_history::rating_before,
+    player_rating_history::rating_after,
+    player_rating_history::deviation_before,
+    player_rating_history::deviation_after,
+    player_rating_history::result,
+    player_rating_history::created_at,
+  );
+}
+
+/// Teams of an ended game and their ranks, lower is better
+#[derive(Debug, PartialEq)]
+pub struct GameOutcome {
+  pub teams: Vec<Vec<i32>>,
+  pub ranks: Vec<u32>,
+}
+
+impl GameOutcome {
+  /// Returns `None` if the outcome can not be determined from the results reported by the node
+  pub fn from_results(slots: &[Slot], results: &BTreeMap<i32, PlayerGameResult>) -> Option<Self> {
+    // remade games have no outcome
+    if results.values().any(|r| *r == PlayerGameResult::Aborted) {
+      return None;
+    }
+
+    let mut teams: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+    for slot in slots {
+      // observers and referees
+      if slot.settings.status != SlotStatus::Occupied || slot.settings.team == 24 {
+        continue;
+      }
+      if let Some(player) = slot.player.as_ref() {
+        teams.entry(slot.settings.team).or_default().push(player.id);
+      }
+    }
+
+    if teams.len() < 2 {
+      return None;
+    }
+
+    let team_results: Vec<PlayerGameResult> = teams
+      .values()
+      .map(|player_ids| {
+        let team: Vec<_> = player_ids
+          .iter()
+          .filter_map(|id| results.get(id).cloned())
+          .collect();
+        if team.contains(&PlayerGameResult::Won) {
+          PlayerGameResult::Won
+        } else if team.contains(&PlayerGameResult::Draw) {
+          PlayerGameResult::Draw
+        } else if team.len() == player_ids.len()
+          && team
+            .iter()
+            .all(|r| *r == PlayerGameResult::Lost || *r == PlayerGameResult::Disconnected)
+        {
+          PlayerGameResult::Lost
+        } else {
+          PlayerGameResult::Unknown
+        }
+      })
+      .collect();
+
+    let count = |result: PlayerGameResult| team_results.iter().filter(|r| **r == result).count();
+    let ranks: Vec<u32> = if count(PlayerGameResult::Won) == 1 {
+      team_results
+        .iter()
+        .map(|r| if *r == PlayerGameResult::Won { 0 } else { 1 })
+        .collect()
+    } else if count(PlayerGameResult::Draw) == team_results.len() {
+      vec![0; team_results.len()]
+    } else if count(PlayerGameResult::Won) == 0
+      && count(PlayerGameResult::Lost) == team_results.len() - 1
+    {
+      // the last team standing left before reporting its result
+      team_results
+        .iter()
+        .map(|r| if *r == PlayerGameResult::Lost { 1 } else { 0 })
+        .collect()
+    } else {
+      return None;
+    };
+
+    Some(GameOutcome {
+      teams: teams
+        .into_iter()
+        .map(|(_, player_ids)| player_ids)
+        .collect(),
+      ranks,
+    })
+  }
+}
+
+#[test]
+fn test_game_outcome() {
+  use crate::game::{SlotClientStatus, SlotSettings};
+  use crate::player::{PlayerRef, PlayerSource};
+
+  let slot = |id: i32, team: i32| Slot {
+    player: Some(PlayerRef {
+      id,
+      name: id.to_string(),
+      source: PlayerSource::Test,
+      realm: None,
+    }),
+    settings: SlotSettings {
+      team,
+      status: SlotStatus::Occupied,
+      ..Default::default()
+    },
+    client_status: SlotClientStatus::Left,
+  };
+  let slots = vec![slot(1, 0), slot(2, 0), slot(3, 1), slot(4, 1), slot(5, 24)];
+  let results = |items: &[(i32, PlayerGameResult)]| items.iter().cloned().collect();
+
+  let outcome = GameOutcome::from_results(
+    &slots,
+    &results(&[
+      (1, PlayerGameResult::Lost),
+      (3, PlayerGameResult::Won),
+      (5, PlayerGameResult::Lost),
+    ]),
+  )
+  .unwrap();
+  assert_eq!(outcome.teams, vec![vec![1, 2], vec![3, 4]]);
+  assert_eq!(outcome.ranks, vec![1, 0]);
+
+  let outcome = GameOutcome::from_results(
+    &slots,
+    &results(&[
+      (1, PlayerGameResult::Lost),
+      (2, PlayerGameResult::Disconnected),
+    ]),
+  )
+  .unwrap();
+  assert_eq!(outcome.ranks, vec![1, 0]);
+
+  let outcome = GameOutcome::from_results(
+    &slots,
+    &results(&[(1, PlayerGameResult::Draw), (4, PlayerGameResult::Draw)]),
+  )
+  .unwrap();
+  assert_eq!(outcome.ranks, vec![0, 0]);
+
+  assert_eq!(
+    GameOutcome::from_results(&slots, &results(&[(1, PlayerGameResult::Lost)])),
+    None
+  );
+  assert_eq!(
+    GameOutcome::from_results(&slots[0..2], &results(&[(1, PlayerGameResult::Won)])),
+    None
+  );
+  assert_eq!(
+    GameOutcome::from_results(
+      &slots,
+      &results(&[
+        (1, PlayerGameResult::Lost),
+        (2, PlayerGameResult::Aborted),
+        (3, PlayerGameResult::Aborted),
+      ])
+    ),
+    None
+  );
+}
@@ -0,0 +1,250 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::schedule::types::*;
+use crate::schema::scheduled_game;
+
+pub fn create(
+  conn: &DbConn,
+  api_client_id: i32,
+  api_player_id: i32,
+  params: CreateScheduledGameParams,
+) -> Result<ScheduledGame> {
+  #[derive(Insertable)]
+  #[table_name = "scheduled_game"]
+  struct Insert<'a> {
+    api_client_id: i32,
+    api_player_id: i32,
+    name: &'a str,
+    map: Value,
+    node_id: i32,
+    slots: Value,
+    check_in_at: DateTime<Utc>,
+    start_at: DateTime<Utc>,
+    end_at: DateTime<Utc>,
+  }
+
+  params
+    .validate()
+    .map_err(|msg| Error::ScheduledGameTimeInvalid(msg))?;
+
+  if params.map.players.is_empty() {
+    return Err(Error::MapHasNoPlayer);
+  }
+
+  let mut player_ids: Vec<i32> = params.slots.iter().filter_map(|s| s.player_id).collect();
+  player_ids.sort();
+  player_ids.dedup();
+  let players = crate::player::db::get_client_refs_by_ids(conn, api_client_id, &player_ids)?;
+  if players.len() != player_ids.len() {
+    return Err(Error::PlayerNotFound);
+  }
+
+  crate::node::db::get_node(conn, params.node_id)?;
+
+  let row: Row = diesel::insert_into(scheduled_game::table)
+    .values(&Insert {
+      api_client_id,
+      api_player_id,
+      name: &params.name,
+      map: serde_json::to_value(&params.map)?,
+      node_id: params.node_id,
+      slots: serde_json::to_value(&params.slots)?,
+      check_in_at: params.check_in_at,
+      start_at: params.start_at,
+      end_at: params.end_at,
+    })
+    .returning(Row::COLUMNS)
+    .get_result(conn)?;
+  row.into_scheduled_game()
+}
+
+pub fn get(conn: &DbConn, id: i32) -> Result<ScheduledGame> {
+  let row: Row = scheduled_game::table
+    .find(id)
+    .select(Row::COLUMNS)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::ScheduledGameNotFound)?;
+  row.into_scheduled_game()
+}
+
+/// Games waiting for check-in or start, loaded by the scheduler on start
+pub fn get_pending(conn: &DbConn) -> Result<Vec<ScheduledGame>> {
+  let rows: Vec<Row> = scheduled_game::table
+    .filter(scheduled_game::status.eq(ScheduledGameStatus::Scheduled))
+    .select(Row::COLUMNS)
+    .order(scheduled_game::start_at)
+    .load(conn)?;
+  rows.into_iter().map(Row::into_scheduled_game).collect()
+}
+
+pub fn check_in(conn: &DbConn, id: i32, player_id: i32) -> Result<ScheduledGame> {
+  conn.transaction(|| {
+    let row: Row = scheduled_game::table
+      .find(id)
+      .select(Row::COLUMNS)
+      .for_update()
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::ScheduledGameNotFound)?;
+    let mut game = row.into_scheduled_game()?;
+
+    if !game.player_ids().contains(&player_id) {
+      return Err(Error::ScheduledGamePlayerNotAssigned);
+    }
+
+    if !game.is_check_in_open(Utc::now()) {
+      return Err(Error::ScheduledGameCheckInClosed);
+    }
+
+    if game.checked_in_player_ids.contains(&player_id) {
+      return Ok(game);
+    }
+
+    game.checked_in_player_ids.push(player_id);
+    diesel::update(scheduled_game::table.find(id))
+      .set((
+        scheduled_game::checked_in_player_ids
+          .eq(serde_json::to_value(&game.checked_in_player_ids)?),
+        scheduled_game::updated_at.eq(diesel::dsl::now),
+      ))
+      .execute(conn)?;
+    Ok(game)
+  })
+}
+
+pub fn cancel(conn: &DbConn, id: i32, api_client_id: i32) -> Result<ScheduledGame> {
+  conn.transaction(|| {
+    let mut game = get(conn, id)?;
+    if game.api_client_id != api_client_id {
+      return Err(Error::ScheduledGameNotFound);
+    }
+    if game.status != ScheduledGameStatus::Scheduled {
+      return Err(Error::GameNotCancellable);
+    }
+    game.status = ScheduledGameStatus::Cancelled;
+    update_status(conn, id, game.status)?;
+    Ok(game)
+  })
+}
+
+pub fn set_started(conn: &DbConn, id: i32, game_id: i32) -> Result<()> {
+  diesel::update(
+    scheduled_game::table
+      .find(id)
+      .filter(scheduled_game::status.eq(ScheduledGameStatus::Scheduled)),
+  )
+  .set((
+    scheduled_game::status.eq(ScheduledGameStatus::Started),
+    scheduled_game::game_id.eq(game_id),
+    scheduled_game::updated_at.eq(diesel::dsl::now),
+  ))
+  .execute(conn)?;
+  Ok(())
+}
+
+pub fn set_forfeited(conn: &DbConn, id: i32, no_show_player_ids: &[i32]) -> Result<()> {
+  diesel::update(
+    scheduled_game::table
+      .find(id)
+      .filter(scheduled_game::status.eq(ScheduledGameStatus::Scheduled)),
+  )
+  .set((
+    scheduled_game::status.eq(ScheduledGameStatus::Forfeited),
+    scheduled_game::no_show_player_ids.eq(serde_json::to_value(no_show_player_ids)?),
+    scheduled_game::updated_at.eq(diesel::dsl::now),
+  ))
+  .execute(conn)?;
+  Ok(())
+}
+
+pub fn set_failed(conn: &DbConn, id: i32) -> Result<()> {
+  update_status(conn, id, ScheduledGameStatus::Failed)
+}
+
+fn update_status(conn: &DbConn, id: i32, status: ScheduledGameStatus) -> Result<()> {
+  diesel::update(scheduled_game::table.find(id))
+    .set((
+      scheduled_game::status.eq(status),
+      scheduled_game::updated_at.eq(diesel::dsl::now),
+    ))
+    .execute(conn)?;
+  Ok(())
+}
+
+#[derive(Debug, Queryable)]
+struct Row {
+  id: i32,
+  api_client_id: i32,
+  api_player_id: i32,
+  name: String,
+  map: Value,
+  node_id: i32,
+  slots: Value,
+  check_in_at: DateTime<Utc>,
+  start_at: DateTime<Utc>,
+  end_at: DateTime<Utc>,
+  status: ScheduledGameStatus,
+  checked_in_player_ids: Value,
+  no_show_player_ids: Value,
+  game_id: Option<i32>,
+}
+
+type RowColumns = (
+  scheduled_game::dsl::id,
+  scheduled_game::dsl::api_client_id,
+  scheduled_game::dsl::api_player_id,
+  scheduled_game::dsl::name,
+  scheduled_game::dsl::map,
+  scheduled_game::dsl::node_id,
+  scheduled_game::dsl::slots,
+  scheduled_game::dsl::check_in_at,
+  scheduled_game::dsl::start_at,
+  scheduled_game::dsl::end_at,
+  scheduled_game::dsl::status,
+  scheduled_game::dsl::checked_in_player_ids,
+  scheduled_game::dsl::no_show_player_ids,
+  scheduled_game::dsl::game_id,
+);
+
+impl Row {
+  const COLUMNS: RowColumns = (
+    scheduled_game::dsl::id,
+    scheduled_game::dsl::api_client_id,
+    scheduled_game::dsl::api_player_id,
+    scheduled_game::dsl::name,
+    scheduled_game::dsl::map,
+    scheduled_game::dsl::node_id,
+    scheduled_game::dsl::slots,
+    scheduled_game::dsl::check_in_at,
+    scheduled_game::dsl::start_at,
+    scheduled_game::dsl::end_at,
+    scheduled_game::dsl::status,
+    scheduled_game::dsl::checked_in_player_ids,
+    scheduled_game::dsl::no_show_player_ids,
+    scheduled_game::dsl::game_id,
+  );
+
+  fn into_scheduled_game(self) -> Result<ScheduledGame> {
+    Ok(ScheduledGame {
+      id: self.id,
+      api_client_id: self.api_client_id,
+      api_player_id: self.api_player_id,
+      name: self.name,
+      map: serde_json::from_value(self.map)?,
+      node_id: self.node_id,
+      slots: serde_json::from_value(self.slots)?,
+      check_in_at: self.check_in_at,
+      start_at: self.start_at,
+      end_at: self.end_at,
+      status: self.status,
+      checked_in_player_ids: serde_json::from_value(self.checked_in_player_ids)?,
+      no_show_player_ids: serde_json::from_value(self.no_show_player_ids)?,
+      game_id: self.game_id,
+    })
+  }
+}
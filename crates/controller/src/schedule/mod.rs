@@ -0,0 +1,9 @@
+pub mod db;
+pub(crate) mod state;
+mod types;
+
+pub mod messages {
+  pub use super::state::{CancelScheduledGame, CheckIn, CreateScheduledGame};
+}
+
+pub use types::*;
@@ -0,0 +1,392 @@
+use crate::error::*;
+use crate::game::db::CreateGameAsBotParams;
+use crate::game::state::cancel::CancelGame;
+use crate::game::state::create::CreateGameAsBot;
+use crate::game::state::registry::Remove;
+use crate::game::state::start::{StartGameCheckAsBot, StartGameCheckAsBotResult};
+use crate::game::state::GameRegistry;
+use crate::player::state::sender::PlayerRegistryHandle;
+use crate::player::state::PlayerRegistry;
+use crate::schedule::types::*;
+use crate::state::{ActorMapExt, Data};
+use bs_diesel_utils::ExecutorRef;
+use chrono::Utc;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::PacketScheduledGameStatus;
+use flo_state::*;
+use s2_grpc_utils::S2ProtoEnum;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tokio::time::sleep;
+
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(5);
+const START_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Scheduled games waiting for check-in, starts or forfeits them when their time comes.
+pub struct ScheduleRegistry {
+  db: ExecutorRef,
+  games: Addr<GameRegistry>,
+  player_packet_sender: PlayerRegistryHandle,
+  schedules: BTreeMap<i32, ScheduledGame>,
+  starting: BTreeSet<i32>,
+  retry_at: BTreeMap<i32, Instant>,
+}
+
+impl ScheduleRegistry {
+  async fn load_schedules(&mut self) -> Result<()> {
+    let schedules = self
+      .db
+      .exec(|conn| crate::schedule::db::get_pending(conn))
+      .await?;
+    self.schedules = schedules.into_iter().map(|s| (s.id, s)).collect();
+    Ok(())
+  }
+
+  async fn run_schedules(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+    let now = Utc::now();
+    let mut actions = vec![];
+    for (id, schedule) in &self.schedules {
+      if self.starting.contains(id) {
+        continue;
+      }
+      match schedule.next_action(now) {
+        ScheduledGameAction::Wait => {}
+        ScheduledGameAction::Start => {
+          if self
+            .retry_at
+            .get(id)
+            .map(|t| *t > Instant::now())
+            .unwrap_or(false)
+          {
+            continue;
+          }
+          actions.push((*id, ScheduledGameAction::Start))
+        }
+        action => actions.push((*id, action)),
+      }
+    }
+
+    for (id, action) in actions {
+      match action {
+        ScheduledGameAction::Start => {
+          if let Some(schedule) = self.schedules.get(&id).cloned() {
+            self.starting.insert(id);
+            let addr = ctx.addr();
+            let games = self.games.clone();
+            ctx.spawn(async move {
+              let result = create_and_start_game(&games, &schedule).await;
+              addr.notify(StartDone { id, result }).await.ok();
+            });
+          }
+        }
+        ScheduledGameAction::Forfeit => {
+          if let Some(mut schedule) = self.schedules.remove(&id) {
+            self.retry_at.remove(&id);
+            let no_show_player_ids = schedule.get_no_show_player_ids();
+            tracing::info!(
+              scheduled_game_id = id,
+              "forfeited, no-shows: {:?}",
+              no_show_player_ids
+            );
+            self
+              .db
+              .exec({
+                let no_show_player_ids = no_show_player_ids.clone();
+                move |conn| crate::schedule::db::set_forfeited(conn, id, &no_show_player_ids)
+              })
+              .await?;
+            schedule.status = ScheduledGameStatus::Forfeited;
+            schedule.no_show_player_ids = no_show_player_ids;
+            self.broadcast_status(&schedule, None).await;
+          }
+        }
+        ScheduledGameAction::Fail => {
+          if let Some(mut schedule) = self.schedules.remove(&id) {
+            self.retry_at.remove(&id);
+            tracing::warn!(
+              scheduled_game_id = id,
+              "failed to start before the window closed"
+            );
+            self
+              .db
+              .exec(move |conn| crate::schedule::db::set_failed(conn, id))
+              .await?;
+            schedule.status = ScheduledGameStatus::Failed;
+            self.broadcast_status(&schedule, None).await;
+          }
+        }
+        ScheduledGameAction::Wait => {}
+      }
+    }
+
+    Ok(())
+  }
+
+  async fn broadcast_status(&self, schedule: &ScheduledGame, message: Option<String>) {
+    let mut pkt = PacketScheduledGameStatus {
+      scheduled_game_id: schedule.id,
+      name: schedule.name.clone(),
+      check_in_at: schedule.check_in_at.timestamp(),
+      start_at: schedule.start_at.timestamp(),
+      end_at: schedule.end_at.timestamp(),
+      checked_in_player_ids: schedule.checked_in_player_ids.clone(),
+      no_show_player_ids: schedule.no_show_player_ids.clone(),
+      game_id: schedule.game_id,
+      message: message.unwrap_or_default(),
+      ..Default::default()
+    };
+    pkt.set_status(schedule.status.into_proto_enum());
+    let frame = match pkt.encode_as_frame() {
+      Ok(frame) => frame,
+      Err(err) => {
+        tracing::error!(scheduled_game_id = schedule.id, "encode status: {}", err);
+        return;
+      }
+    };
+    if let Err(err) = self
+      .player_packet_sender
+      .broadcast(schedule.player_ids(), frame)
+      .await
+    {
+      tracing::error!(scheduled_game_id = schedule.id, "broadcast status: {}", err);
+    }
+  }
+}
+
+#[async_trait]
+impl Actor for ScheduleRegistry {
+  async fn started(&mut self, ctx: &mut Context<Self>) {
+    if let Err(err) = self.load_schedules().await {
+      tracing::error!("load scheduled games: {}", err);
+    }
+    self.handle(ctx, RunSchedules).await;
+  }
+}
+
+#[async_trait]
+impl Service<Data> for ScheduleRegistry {
+  type Error = Error;
+
+  async fn create(registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
+    let games = registry.resolve::<GameRegistry>().await?;
+    let players = registry.resolve::<PlayerRegistry>().await?;
+    Ok(Self {
+      db: registry.data().db.clone(),
+      games,
+      player_packet_sender: players.into(),
+      schedules: BTreeMap::new(),
+      starting: BTreeSet::new(),
+      retry_at: BTreeMap::new(),
+    })
+  }
+}
+
+pub struct CreateScheduledGame {
+  pub api_client_id: i32,
+  pub api_player_id: i32,
+  pub params: CreateScheduledGameParams,
+}
+
+impl Message for CreateScheduledGame {
+  type Result = Result<ScheduledGame>;
+}
+
+#[async_trait]
+impl Handler<CreateScheduledGame> for ScheduleRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    CreateScheduledGame {
+      api_client_id,
+      api_player_id,
+      params,
+    }: CreateScheduledGame,
+  ) -> <CreateScheduledGame as Message>::Result {
+    let schedule = self
+      .db
+      .exec(move |conn| crate::schedule::db::create(conn, api_client_id, api_player_id, params))
+      .await?;
+    self.schedules.insert(schedule.id, schedule.clone());
+    self.broadcast_status(&schedule, None).await;
+    Ok(schedule)
+  }
+}
+
+pub struct CancelScheduledGame {
+  pub api_client_id: i32,
+  pub id: i32,
+}
+
+impl Message for CancelScheduledGame {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<CancelScheduledGame> for ScheduleRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    CancelScheduledGame { api_client_id, id }: CancelScheduledGame,
+  ) -> <CancelScheduledGame as Message>::Result {
+    if self.starting.contains(&id) {
+      return Err(Error::GameNotCancellable);
+    }
+    let schedule = self
+      .db
+      .exec(move |conn| crate::schedule::db::cancel(conn, id, api_client_id))
+      .await?;
+    self.schedules.remove(&id);
+    self.retry_at.remove(&id);
+    self.broadcast_status(&schedule, None).await;
+    Ok(())
+  }
+}
+
+pub struct CheckIn {
+  pub player_id: i32,
+  pub scheduled_game_id: i32,
+}
+
+impl Message for CheckIn {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<CheckIn> for ScheduleRegistry {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    CheckIn {
+      player_id,
+      scheduled_game_id,
+    }: CheckIn,
+  ) -> <CheckIn as Message>::Result {
+    let schedule = self
+      .db
+      .exec(move |conn| crate::schedule::db::check_in(conn, scheduled_game_id, player_id))
+      .await?;
+    self.schedules.insert(schedule.id, schedule.clone());
+    self.broadcast_status(&schedule, None).await;
+
+    // start right away if this was the last player
+    if schedule.next_action(Utc::now()) == ScheduledGameAction::Start {
+      self.run_schedules(ctx).await?;
+    }
+
+    Ok(())
+  }
+}
+
+struct RunSchedules;
+
+impl Message for RunSchedules {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<RunSchedules> for ScheduleRegistry {
+  async fn handle(&mut self, ctx: &mut Context<Self>, _: RunSchedules) {
+    if let Err(err) = self.run_schedules(ctx).await {
+      tracing::error!("run schedules: {}", err);
+    }
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      sleep(SCHEDULE_INTERVAL).await;
+      addr.notify(RunSchedules).await.ok();
+    });
+  }
+}
+
+struct StartDone {
+  id: i32,
+  result: Result<i32>,
+}
+
+impl Message for StartDone {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<StartDone> for ScheduleRegistry {
+  async fn handle(&mut self, _: &mut Context<Self>, StartDone { id, result }: StartDone) {
+    self.starting.remove(&id);
+    match result {
+      Ok(game_id) => {
+        tracing::info!(scheduled_game_id = id, game_id, "scheduled game started");
+        self.retry_at.remove(&id);
+        if let Err(err) = self
+          .db
+          .exec(move |conn| crate::schedule::db::set_started(conn, id, game_id))
+          .await
+        {
+          tracing::error!(scheduled_game_id = id, "set started: {}", err);
+        }
+        if let Some(mut schedule) = self.schedules.remove(&id) {
+          schedule.status = ScheduledGameStatus::Started;
+          schedule.game_id = Some(game_id);
+          self.broadcast_status(&schedule, None).await;
+        }
+      }
+      Err(err) => {
+        tracing::error!(scheduled_game_id = id, "start scheduled game: {}", err);
+        self
+          .retry_at
+          .insert(id, Instant::now() + START_RETRY_INTERVAL);
+        if let Some(schedule) = self.schedules.get(&id) {
+          self.broadcast_status(schedule, Some(err.to_string())).await;
+        }
+      }
+    }
+  }
+}
+
+async fn create_and_start_game(
+  games: &Addr<GameRegistry>,
+  schedule: &ScheduledGame,
+) -> Result<i32> {
+  let game = games
+    .send(CreateGameAsBot {
+      api_client_id: schedule.api_client_id,
+      api_player_id: schedule.api_player_id,
+      rating_pool_id: None,
+      params: CreateGameAsBotParams {
+        name: schedule.name.clone(),
+        map: schedule.map.clone(),
+        is_private: true,
+        is_live: false,
+        node_id: schedule.node_id,
+        slots: schedule.slots.clone(),
+        mask_player_names: None,
+      },
+    })
+    .await??;
+  let game_id = game.id;
+
+  let (tx, rx) = oneshot::channel();
+  let res = match games.send_to(game_id, StartGameCheckAsBot { tx }).await {
+    Ok(_) => match rx.await {
+      Ok(StartGameCheckAsBotResult::Started(_)) => Ok(game_id),
+      Ok(StartGameCheckAsBotResult::Rejected(pkt)) => {
+        Err(Error::ScheduledGameStartRejected(pkt.message))
+      }
+      Err(_) => Err(Error::TaskCancelled),
+    },
+    Err(err) => Err(err),
+  };
+
+  if res.is_err() {
+    games
+      .send_to(
+        game_id,
+        CancelGame {
+          player_id: Some(schedule.api_player_id),
+        },
+      )
+      .await
+      .ok();
+    games.send(Remove { game_id }).await.ok();
+  }
+
+  res
+}
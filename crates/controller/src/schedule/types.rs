@@ -0,0 +1,189 @@
+use bs_diesel_utils::BSDieselEnum;
+use chrono::{DateTime, Utc};
+use s2_grpc_utils::S2ProtoEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::game::CreateGameSlot;
+use crate::map::Map;
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_net::proto::flo_connect::ScheduledGameStatus))]
+pub enum ScheduledGameStatus {
+  Scheduled = 0,
+  Started = 1,
+  /// Not every player checked in before the window closed
+  Forfeited = 2,
+  Cancelled = 3,
+  /// Every player checked in but the game could not be started before the window closed
+  Failed = 4,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ScheduledGame {
+  pub id: i32,
+  pub api_client_id: i32,
+  pub api_player_id: i32,
+  pub name: String,
+  pub map: Map,
+  pub node_id: i32,
+  pub slots: Vec<CreateGameSlot>,
+  /// Players can check in from this time
+  pub check_in_at: DateTime<Utc>,
+  /// The game starts as soon as every player has checked in after this time
+  pub start_at: DateTime<Utc>,
+  /// Players not checked in by this time forfeit
+  pub end_at: DateTime<Utc>,
+  pub status: ScheduledGameStatus,
+  pub checked_in_player_ids: Vec<i32>,
+  pub no_show_player_ids: Vec<i32>,
+  pub game_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScheduledGameAction {
+  Wait,
+  Start,
+  Forfeit,
+  Fail,
+}
+
+impl ScheduledGame {
+  pub fn player_ids(&self) -> Vec<i32> {
+    self
+      .slots
+      .iter()
+      .filter_map(|slot| slot.player_id)
+      .collect()
+  }
+
+  pub fn is_check_in_open(&self, now: DateTime<Utc>) -> bool {
+    self.status == ScheduledGameStatus::Scheduled && now >= self.check_in_at && now < self.end_at
+  }
+
+  pub fn get_no_show_player_ids(&self) -> Vec<i32> {
+    self
+      .player_ids()
+      .into_iter()
+      .filter(|id| !self.checked_in_player_ids.contains(id))
+      .collect()
+  }
+
+  /// What the scheduler should do with this game at `now`
+  pub fn next_action(&self, now: DateTime<Utc>) -> ScheduledGameAction {
+    if self.status != ScheduledGameStatus::Scheduled {
+      return ScheduledGameAction::Wait;
+    }
+    let all_checked_in = self.get_no_show_player_ids().is_empty();
+    if now >= self.end_at {
+      if all_checked_in {
+        ScheduledGameAction::Fail
+      } else {
+        ScheduledGameAction::Forfeit
+      }
+    } else if now >= self.start_at && all_checked_in {
+      ScheduledGameAction::Start
+    } else {
+      ScheduledGameAction::Wait
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateScheduledGameParams {
+  pub name: String,
+  pub map: Map,
+  pub node_id: i32,
+  pub slots: Vec<CreateGameSlot>,
+  pub check_in_at: DateTime<Utc>,
+  pub start_at: DateTime<Utc>,
+  pub end_at: DateTime<Utc>,
+}
+
+impl CreateScheduledGameParams {
+  pub fn validate(&self) -> Result<(), &'static str> {
+    if self.check_in_at > self.start_at {
+      return Err("check-in must open before the start time");
+    }
+    if self.start_at >= self.end_at {
+      return Err("the start time must be before the end of the window");
+    }
+    if !self.slots.iter().any(|slot| slot.player_id.is_some()) {
+      return Err("no player assigned");
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn test_scheduled_game_next_action() {
+  use crate::game::SlotSettings;
+  use crate::map::MapSha1;
+  use chrono::Duration;
+
+  let now = Utc::now();
+  let slot = |player_id| CreateGameSlot {
+    player_id: Some(player_id),
+    settings: SlotSettings::default(),
+  };
+  let mut game = ScheduledGame {
+    id: 1,
+    api_client_id: 1,
+    api_player_id: 1,
+    name: "match".to_string(),
+    map: Map {
+      sha1: MapSha1([0; 20]),
+      checksum: 0,
+      name: String::new(),
+      description: String::new(),
+      author: String::new(),
+      path: String::new(),
+      width: 0,
+      height: 0,
+      players: vec![],
+      forces: vec![],
+    },
+    node_id: 1,
+    slots: vec![slot(1), slot(2)],
+    check_in_at: now - Duration::minutes(10),
+    start_at: now + Duration::minutes(5),
+    end_at: now + Duration::minutes(15),
+    status: ScheduledGameStatus::Scheduled,
+    checked_in_player_ids: vec![1],
+    no_show_player_ids: vec![],
+    game_id: None,
+  };
+
+  assert!(game.is_check_in_open(now));
+  assert!(!game.is_check_in_open(now - Duration::minutes(11)));
+  assert!(!game.is_check_in_open(now + Duration::minutes(15)));
+  assert_eq!(game.get_no_show_player_ids(), vec![2]);
+
+  assert_eq!(game.next_action(now), ScheduledGameAction::Wait);
+  assert_eq!(
+    game.next_action(now + Duration::minutes(6)),
+    ScheduledGameAction::Wait
+  );
+  assert_eq!(
+    game.next_action(now + Duration::minutes(15)),
+    ScheduledGameAction::Forfeit
+  );
+
+  game.checked_in_player_ids.push(2);
+  assert_eq!(game.next_action(now), ScheduledGameAction::Wait);
+  assert_eq!(
+    game.next_action(now + Duration::minutes(6)),
+    ScheduledGameAction::Start
+  );
+  assert_eq!(
+    game.next_action(now + Duration::minutes(15)),
+    ScheduledGameAction::Fail
+  );
+
+  game.status = ScheduledGameStatus::Cancelled;
+  assert!(!game.is_check_in_open(now));
+  assert_eq!(
+    game.next_action(now + Duration::minutes(6)),
+    ScheduledGameAction::Wait
+  );
+}
@@ -59,6 +59,52 @@ table! {
     }
 }
 
+table! {
+    map_pool (id) {
+        id -> Int4,
+        api_client_id -> Int4,
+        name -> Text,
+        map_version_ids -> Jsonb,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    map_version (id) {
+        id -> Int4,
+        api_client_id -> Int4,
+        sha1 -> Text,
+        checksum -> Int8,
+        name -> Text,
+        path -> Text,
+        file_size -> Int8,
+        meta -> Jsonb,
+        tags -> Jsonb,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    matchmaking_queue (id) {
+        id -> Int4,
+        name -> Text,
+        api_client_id -> Int4,
+        api_player_id -> Int4,
+        team_size -> Int4,
+        region -> Nullable<Text>,
+        map_pool -> Jsonb,
+        rating_pool_id -> Nullable<Int4>,
+        rating_spread -> Int4,
+        rating_spread_per_minute -> Int4,
+        max_ping -> Int4,
+        enabled -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 table! {
     node (id) {
         id -> Int4,
@@ -106,20 +152,151 @@ table! {
     }
 }
 
+table! {
+    player_rating (id) {
+        id -> Int4,
+        pool_id -> Int4,
+        player_id -> Int4,
+        rating -> Float8,
+        deviation -> Float8,
+        volatility -> Float8,
+        score -> Float8,
+        games -> Int4,
+        wins -> Int4,
+        losses -> Int4,
+        draws -> Int4,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    player_rating_history (id) {
+        id -> Int4,
+        pool_id -> Int4,
+        player_id -> Int4,
+        game_id -> Int4,
+        rating_before -> Float8,
+        rating_after -> Float8,
+        deviation_before -> Float8,
+        deviation_after -> Float8,
+        result -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    rating_pool (id) {
+        id -> Int4,
+        api_client_id -> Int4,
+        name -> Text,
+        algorithm -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    scheduled_game (id) {
+        id -> Int4,
+        api_client_id -> Int4,
+        api_player_id -> Int4,
+        name -> Text,
+        map -> Jsonb,
+        node_id -> Int4,
+        slots -> Jsonb,
+        check_in_at -> Timestamptz,
+        start_at -> Timestamptz,
+        end_at -> Timestamptz,
+        status -> Int4,
+        checked_in_player_ids -> Jsonb,
+        no_show_player_ids -> Jsonb,
+        game_id -> Nullable<Int4>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    tournament (id) {
+        id -> Int4,
+        api_client_id -> Int4,
+        api_player_id -> Int4,
+        name -> Text,
+        format -> Int4,
+        best_of -> Int4,
+        map_pool -> Jsonb,
+        node_id -> Int4,
+        check_in_minutes -> Int4,
+        player_ids -> Jsonb,
+        status -> Int4,
+        winner_id -> Nullable<Int4>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    tournament_match (id) {
+        id -> Int4,
+        tournament_id -> Int4,
+        round -> Int4,
+        position -> Int4,
+        bracket -> Int4,
+        player_a -> Nullable<Int4>,
+        player_b -> Nullable<Int4>,
+        winner_to -> Nullable<Jsonb>,
+        loser_to -> Nullable<Jsonb>,
+        status -> Int4,
+        winner_id -> Nullable<Int4>,
+        games -> Jsonb,
+        map_pick -> Nullable<Int4>,
+        next_game_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 joinable!(game -> node (node_id));
 joinable!(game -> player (created_by));
 joinable!(game_used_slot -> game (game_id));
 joinable!(game_used_slot -> player (player_id));
+joinable!(map_pool -> api_client (api_client_id));
+joinable!(map_version -> api_client (api_client_id));
+joinable!(matchmaking_queue -> api_client (api_client_id));
+joinable!(matchmaking_queue -> player (api_player_id));
+joinable!(matchmaking_queue -> rating_pool (rating_pool_id));
 joinable!(player -> api_client (api_client_id));
 joinable!(player_ban -> player (player_id));
+joinable!(player_rating -> player (player_id));
+joinable!(player_rating -> rating_pool (pool_id));
+joinable!(player_rating_history -> game (game_id));
+joinable!(player_rating_history -> player (player_id));
+joinable!(player_rating_history -> rating_pool (pool_id));
+joinable!(rating_pool -> api_client (api_client_id));
+joinable!(scheduled_game -> api_client (api_client_id));
+joinable!(scheduled_game -> game (game_id));
+joinable!(scheduled_game -> node (node_id));
+joinable!(scheduled_game -> player (api_player_id));
+joinable!(tournament -> api_client (api_client_id));
+joinable!(tournament -> node (node_id));
+joinable!(tournament_match -> tournament (tournament_id));
 
 allow_tables_to_appear_in_same_query!(
     api_client,
     game,
     game_used_slot,
     map_checksum,
+    map_pool,
+    map_version,
+    matchmaking_queue,
     node,
     player,
     player_ban,
     player_mute,
+    player_rating,
+    player_rating_history,
+    rating_pool,
+    scheduled_game,
+    tournament,
+    tournament_match,
 );
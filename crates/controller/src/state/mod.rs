@@ -7,9 +7,12 @@ use std::sync::Arc;
 
 use crate::error::*;
 use crate::game::state::GameRegistry;
+use crate::matchmaking::state::MatchmakingRegistry;
 
 use crate::node::NodeRegistry;
 use crate::player::state::PlayerRegistry;
+use crate::schedule::state::ScheduleRegistry;
+use crate::tournament::state::TournamentRegistry;
 
 use crate::config::ConfigStorage;
 use crate::player::state::sender::PlayerRegistryHandle;
@@ -26,6 +29,9 @@ pub struct ControllerState {
   pub nodes: Addr<NodeRegistry>,
   pub games: Addr<GameRegistry>,
   pub players: Addr<PlayerRegistry>,
+  pub matchmaking: Addr<MatchmakingRegistry>,
+  pub schedules: Addr<ScheduleRegistry>,
+  pub tournaments: Addr<TournamentRegistry>,
   pub player_packet_sender: PlayerRegistryHandle,
   pub config: Addr<ConfigStorage>,
 }
@@ -47,6 +53,9 @@ impl ControllerState {
     let games = registry.resolve().await?;
     let players = registry.resolve().await?;
     let config = registry.resolve().await?;
+    let matchmaking = registry.resolve().await?;
+    let schedules = registry.resolve().await?;
+    let tournaments = registry.resolve().await?;
 
     Ok(ControllerState {
       db,
@@ -54,6 +63,9 @@ impl ControllerState {
       nodes,
       games,
       players: players.clone(),
+      matchmaking,
+      schedules,
+      tournaments,
       player_packet_sender: PlayerRegistryHandle::from(players),
       config,
     })
@@ -62,6 +74,7 @@ impl ControllerState {
   pub async fn reload(&self) -> Result<()> {
     self.config.send(Reload).await??;
     self.nodes.send(Reload).await??;
+    self.matchmaking.send(Reload).await??;
     Ok(())
   }
 
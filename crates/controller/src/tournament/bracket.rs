@@ -0,0 +1,599 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::types::*;
+
+/// A match of a newly generated bracket, `winner_to` and `loser_to` refer to indexes in the generated list
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchSeed {
+  pub round: i32,
+  pub position: i32,
+  pub bracket: BracketSide,
+  pub player_ids: [Option<i32>; 2],
+  pub winner_to: Option<(usize, usize)>,
+  pub loser_to: Option<(usize, usize)>,
+}
+
+impl MatchSeed {
+  fn new(round: i32, position: i32, bracket: BracketSide) -> Self {
+    MatchSeed {
+      round,
+      position,
+      bracket,
+      player_ids: [None, None],
+      winner_to: None,
+      loser_to: None,
+    }
+  }
+}
+
+/// Matches known when the tournament is created, Swiss rounds are generated one at a time
+pub fn generate(format: TournamentFormat, player_ids: &[i32]) -> Vec<MatchSeed> {
+  match format {
+    TournamentFormat::SingleElimination => elimination(player_ids, false),
+    TournamentFormat::DoubleElimination => elimination(player_ids, true),
+    TournamentFormat::RoundRobin => round_robin(player_ids),
+    TournamentFormat::Swiss => swiss_round(1, player_ids, &BTreeSet::new(), &BTreeSet::new()),
+  }
+}
+
+/// Seed numbers of the first round, in bracket order, so that the top seeds meet last
+fn seed_order(size: usize) -> Vec<usize> {
+  let mut order = vec![1];
+  while order.len() < size {
+    let len = order.len() * 2;
+    order = order
+      .into_iter()
+      .flat_map(|seed| vec![seed, len + 1 - seed])
+      .collect();
+  }
+  order
+}
+
+fn elimination(player_ids: &[i32], double: bool) -> Vec<MatchSeed> {
+  let size = player_ids.len().max(2).next_power_of_two();
+  let rounds = size.trailing_zeros() as i32;
+  let mut matches = vec![];
+
+  // winners bracket, `winners[r][i]` is the index of the i-th match of round r + 1
+  let mut winners: Vec<Vec<usize>> = vec![];
+  let order = seed_order(size);
+  for round in 1..=rounds {
+    let count = size >> round;
+    let mut indexes = vec![];
+    for position in 0..count {
+      let mut m = MatchSeed::new(round, position as i32, BracketSide::Winners);
+      if round == 1 {
+        for slot in 0..2 {
+          m.player_ids[slot] = player_ids.get(order[position * 2 + slot] - 1).cloned();
+        }
+      }
+      indexes.push(matches.len());
+      matches.push(m);
+    }
+    if let Some(prev) = winners.last() {
+      for (i, idx) in prev.iter().enumerate() {
+        matches[*idx].winner_to = Some((indexes[i / 2], i % 2));
+      }
+    }
+    winners.push(indexes);
+  }
+
+  if !double {
+    return matches;
+  }
+
+  let grand_final = matches.len();
+  let wb_final = winners[rounds as usize - 1][0];
+  matches.push(MatchSeed::new(rounds + 1, 0, BracketSide::GrandFinal));
+  matches[wb_final].winner_to = Some((grand_final, 0));
+
+  if rounds == 1 {
+    matches[wb_final].loser_to = Some((grand_final, 1));
+    return matches;
+  }
+
+  let mut prev: Vec<usize> = vec![];
+  for round in 1..=(2 * (rounds - 1)) {
+    let indexes: Vec<usize>;
+    if round == 1 {
+      let count = size / 4;
+      indexes = (0..count).map(|i| matches.len() + i).collect();
+      for position in 0..count {
+        matches.push(MatchSeed::new(round, position as i32, BracketSide::Losers));
+      }
+      for (i, idx) in winners[0].iter().enumerate() {
+        matches[*idx].loser_to = Some((indexes[i / 2], i % 2));
+      }
+    } else if round % 2 == 0 {
+      // survivors meet the losers dropping from the winners bracket, in reverse order to avoid rematches
+      let dropping = &winners[(round / 2) as usize];
+      let count = dropping.len();
+      indexes = (0..count).map(|i| matches.len() + i).collect();
+      for position in 0..count {
+        matches.push(MatchSeed::new(round, position as i32, BracketSide::Losers));
+      }
+      for (i, idx) in prev.iter().enumerate() {
+        matches[*idx].winner_to = Some((indexes[i], 0));
+      }
+      for (i, idx) in dropping.iter().enumerate() {
+        matches[*idx].loser_to = Some((indexes[count - 1 - i], 1));
+      }
+    } else {
+      let count = prev.len() / 2;
+      indexes = (0..count).map(|i| matches.len() + i).collect();
+      for position in 0..count {
+        matches.push(MatchSeed::new(round, position as i32, BracketSide::Losers));
+      }
+      for (i, idx) in prev.iter().enumerate() {
+        matches[*idx].winner_to = Some((indexes[i / 2], i % 2));
+      }
+    }
+    prev = indexes;
+  }
+  matches[prev[0]].winner_to = Some((grand_final, 1));
+
+  matches
+}
+
+/// Circle method, players without an opponent in a round sit out
+fn round_robin(player_ids: &[i32]) -> Vec<MatchSeed> {
+  let mut list: Vec<Option<i32>> = player_ids.iter().cloned().map(Some).collect();
+  if list.len() % 2 == 1 {
+    list.push(None);
+  }
+  let n = list.len();
+  let mut matches = vec![];
+  for round in 1..n {
+    let mut position = 0;
+    for i in 0..n / 2 {
+      if let (Some(a), Some(b)) = (list[i], list[n - 1 - i]) {
+        let mut m = MatchSeed::new(round as i32, position, BracketSide::Winners);
+        m.player_ids = [Some(a), Some(b)];
+        matches.push(m);
+        position += 1;
+      }
+    }
+    list[1..].rotate_right(1);
+  }
+  matches
+}
+
+/// Pairs players in standing order with the closest opponent they have not met yet.
+/// With an odd number of players the lowest ranked player without a bye gets one.
+pub fn swiss_round(
+  round: i32,
+  standings: &[i32],
+  played: &BTreeSet<(i32, i32)>,
+  had_bye: &BTreeSet<i32>,
+) -> Vec<MatchSeed> {
+  let mut unpaired: Vec<i32> = standings.to_vec();
+  let mut bye = None;
+  if unpaired.len() % 2 == 1 {
+    let idx = unpaired
+      .iter()
+      .rposition(|id| !had_bye.contains(id))
+      .unwrap_or(unpaired.len() - 1);
+    bye = Some(unpaired.remove(idx));
+  }
+
+  let mut budget = SWISS_PAIRING_BUDGET;
+  let pairs = pair_without_rematch(&unpaired, played, &mut budget).unwrap_or_else(|| {
+    // every pairing has a rematch, pair by standings
+    unpaired.chunks(2).map(|c| (c[0], c[1])).collect()
+  });
+
+  let mut matches = vec![];
+  for (a, b) in pairs {
+    let mut m = MatchSeed::new(round, matches.len() as i32, BracketSide::Winners);
+    m.player_ids = [Some(a), Some(b)];
+    matches.push(m);
+  }
+
+  if let Some(id) = bye {
+    let mut m = MatchSeed::new(round, matches.len() as i32, BracketSide::Winners);
+    m.player_ids = [Some(id), None];
+    matches.push(m);
+  }
+
+  matches
+}
+
+const SWISS_PAIRING_BUDGET: usize = 100_000;
+
+fn pair_without_rematch(
+  unpaired: &[i32],
+  played: &BTreeSet<(i32, i32)>,
+  budget: &mut usize,
+) -> Option<Vec<(i32, i32)>> {
+  let (a, rest) = match unpaired.split_first() {
+    Some(v) => v,
+    None => return Some(vec![]),
+  };
+  for (i, b) in rest.iter().enumerate() {
+    if *budget == 0 {
+      return None;
+    }
+    *budget -= 1;
+    if played.contains(&((*a).min(*b), (*a).max(*b))) {
+      continue;
+    }
+    let mut remaining = rest.to_vec();
+    remaining.remove(i);
+    if let Some(mut pairs) = pair_without_rematch(&remaining, played, budget) {
+      pairs.insert(0, (*a, *b));
+      return Some(pairs);
+    }
+  }
+  None
+}
+
+/// Tournament state machine over the stored matches
+pub struct Bracket<'a> {
+  pub tournament: &'a Tournament,
+  pub matches: &'a mut Vec<TournamentMatch>,
+}
+
+impl<'a> Bracket<'a> {
+  /// A slot is pending while an unfinished match sends its winner or loser there
+  pub fn is_slot_pending(&self, match_id: i32, slot: usize) -> bool {
+    let target = MatchSlotRef { match_id, slot };
+    self.matches.iter().any(|m| {
+      m.status != TournamentMatchStatus::Finished
+        && (m.winner_to == Some(target) || m.loser_to == Some(target))
+    })
+  }
+
+  /// Unfinished matches with both players known
+  pub fn get_playable_match_ids(&self) -> Vec<i32> {
+    self
+      .matches
+      .iter()
+      .filter(|m| {
+        m.status != TournamentMatchStatus::Finished
+          && m.player_ids.iter().all(|id| id.is_some())
+          && !self.is_slot_pending(m.id, 0)
+          && !self.is_slot_pending(m.id, 1)
+      })
+      .map(|m| m.id)
+      .collect()
+  }
+
+  /// Finishes the match and moves its players forward, returns the ids of the updated matches
+  pub fn set_winner(&mut self, match_id: i32, winner_id: Option<i32>) -> Vec<i32> {
+    let (winner_to, loser_to, loser_id) = match self.matches.iter_mut().find(|m| m.id == match_id) {
+      Some(m) => {
+        m.status = TournamentMatchStatus::Finished;
+        m.winner_id = winner_id;
+        m.map_pick = None;
+        m.next_game_at = None;
+        let loser_id = m
+          .player_ids
+          .iter()
+          .cloned()
+          .find(|id| id.is_some() && *id != winner_id)
+          .flatten();
+        (m.winner_to, m.loser_to, loser_id)
+      }
+      None => return vec![],
+    };
+    let mut updated = vec![match_id];
+    for (to, player_id) in [(winner_to, winner_id), (loser_to, loser_id)].iter() {
+      if let Some(to) = to {
+        if let Some(m) = self.matches.iter_mut().find(|m| m.id == to.match_id) {
+          m.player_ids[to.slot] = *player_id;
+          updated.push(m.id);
+        }
+      }
+    }
+    updated
+  }
+
+  /// Finishes matches that can't be played because a slot stays empty, returns the updated match ids
+  pub fn resolve_byes(&mut self) -> Vec<i32> {
+    let mut updated = BTreeSet::new();
+    loop {
+      let next = self
+        .matches
+        .iter()
+        .find(|m| {
+          m.status == TournamentMatchStatus::Pending
+            && m.player_ids.iter().any(|id| id.is_none())
+            && !self.is_slot_pending(m.id, 0)
+            && !self.is_slot_pending(m.id, 1)
+        })
+        .map(|m| (m.id, m.player_ids[0].or(m.player_ids[1])));
+      match next {
+        Some((match_id, winner_id)) => updated.extend(self.set_winner(match_id, winner_id)),
+        None => break,
+      }
+    }
+    updated.into_iter().collect()
+  }
+
+  /// Round robin and Swiss standings, by match wins then seed
+  pub fn get_standings(&self) -> Vec<i32> {
+    let mut wins: BTreeMap<i32, i32> = BTreeMap::new();
+    for m in self.matches.iter() {
+      if let Some(id) = m.winner_id {
+        *wins.entry(id).or_default() += 1;
+      }
+    }
+    let mut standings: Vec<(usize, i32)> = self
+      .tournament
+      .player_ids
+      .iter()
+      .cloned()
+      .enumerate()
+      .collect();
+    standings.sort_by_key(|(seed, id)| (-wins.get(id).cloned().unwrap_or(0), *seed));
+    standings.into_iter().map(|(_, id)| id).collect()
+  }
+
+  /// The next Swiss round once every match of the current round is finished
+  pub fn next_swiss_round(&self) -> Option<Vec<MatchSeed>> {
+    if self.tournament.format != TournamentFormat::Swiss {
+      return None;
+    }
+    if self
+      .matches
+      .iter()
+      .any(|m| m.status != TournamentMatchStatus::Finished)
+    {
+      return None;
+    }
+    let round = self.matches.iter().map(|m| m.round).max().unwrap_or(0);
+    if round >= self.tournament.swiss_rounds() {
+      return None;
+    }
+    let mut played = BTreeSet::new();
+    let mut had_bye = BTreeSet::new();
+    for m in self.matches.iter() {
+      match m.player_ids {
+        [Some(a), Some(b)] => {
+          played.insert((a.min(b), a.max(b)));
+        }
+        [Some(id), None] | [None, Some(id)] => {
+          had_bye.insert(id);
+        }
+        _ => {}
+      }
+    }
+    Some(swiss_round(
+      round + 1,
+      &self.get_standings(),
+      &played,
+      &had_bye,
+    ))
+  }
+
+  /// `Some(winner_id)` once every match is finished
+  pub fn get_result(&self) -> Option<Option<i32>> {
+    if self
+      .matches
+      .iter()
+      .any(|m| m.status != TournamentMatchStatus::Finished)
+    {
+      return None;
+    }
+    match self.tournament.format {
+      TournamentFormat::SingleElimination | TournamentFormat::DoubleElimination => Some(
+        self
+          .matches
+          .iter()
+          .find(|m| m.winner_to.is_none() && m.bracket != BracketSide::Losers)
+          .and_then(|m| m.winner_id),
+      ),
+      TournamentFormat::RoundRobin => Some(self.get_standings().first().cloned()),
+      TournamentFormat::Swiss => {
+        let round = self.matches.iter().map(|m| m.round).max().unwrap_or(0);
+        if round < self.tournament.swiss_rounds() {
+          None
+        } else {
+          Some(self.get_standings().first().cloned())
+        }
+      }
+    }
+  }
+}
+
+/// Converts generated seeds to matches, match ids are `first_id + index`
+pub fn seeds_to_matches(
+  tournament_id: i32,
+  first_id: i32,
+  seeds: Vec<MatchSeed>,
+) -> Vec<TournamentMatch> {
+  let slot_ref = |to: Option<(usize, usize)>| {
+    to.map(|(idx, slot)| MatchSlotRef {
+      match_id: first_id + idx as i32,
+      slot,
+    })
+  };
+  seeds
+    .into_iter()
+    .enumerate()
+    .map(|(idx, seed)| TournamentMatch {
+      id: first_id + idx as i32,
+      tournament_id,
+      round: seed.round,
+      position: seed.position,
+      bracket: seed.bracket,
+      player_ids: seed.player_ids,
+      winner_to: slot_ref(seed.winner_to),
+      loser_to: slot_ref(seed.loser_to),
+      status: TournamentMatchStatus::Pending,
+      winner_id: None,
+      games: vec![],
+      map_pick: None,
+      next_game_at: None,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+fn test_tournament(format: TournamentFormat, player_ids: Vec<i32>) -> Tournament {
+  Tournament {
+    id: 1,
+    api_client_id: 1,
+    api_player_id: 1,
+    name: "test".to_string(),
+    format,
+    best_of: 1,
+    map_pool: vec![],
+    node_id: 1,
+    check_in_minutes: 10,
+    player_ids,
+    status: TournamentStatus::Running,
+    winner_id: None,
+  }
+}
+
+/// Plays every playable match, the lower player id wins
+#[cfg(test)]
+fn play_all(bracket: &mut Bracket) -> usize {
+  let mut played = 0;
+  bracket.resolve_byes();
+  loop {
+    let ids = bracket.get_playable_match_ids();
+    if ids.is_empty() {
+      break;
+    }
+    for id in ids {
+      let m = bracket.matches.iter().find(|m| m.id == id).unwrap();
+      let winner = m.player_ids.iter().flatten().cloned().min();
+      bracket.set_winner(id, winner);
+      played += 1;
+    }
+    bracket.resolve_byes();
+  }
+  played
+}
+
+#[test]
+fn test_seed_order() {
+  assert_eq!(seed_order(2), vec![1, 2]);
+  assert_eq!(seed_order(4), vec![1, 4, 2, 3]);
+  assert_eq!(seed_order(8), vec![1, 8, 4, 5, 2, 7, 3, 6]);
+}
+
+#[test]
+fn test_single_elimination() {
+  let tournament = test_tournament(TournamentFormat::SingleElimination, vec![1, 2, 3, 4, 5]);
+  let seeds = generate(tournament.format, &tournament.player_ids);
+  assert_eq!(seeds.len(), 7);
+  assert_eq!(seeds[0].player_ids, [Some(1), None]);
+  assert_eq!(seeds[1].player_ids, [Some(4), Some(5)]);
+
+  let mut matches = seeds_to_matches(1, 1, seeds);
+  let mut bracket = Bracket {
+    tournament: &tournament,
+    matches: &mut matches,
+  };
+  assert_eq!(bracket.get_result(), None);
+  // 3 byes
+  assert_eq!(play_all(&mut bracket), 4);
+  assert_eq!(bracket.get_result(), Some(Some(1)));
+}
+
+#[test]
+fn test_double_elimination() {
+  let tournament = test_tournament(TournamentFormat::DoubleElimination, vec![1, 2, 3, 4]);
+  let seeds = generate(tournament.format, &tournament.player_ids);
+  // 3 winners, 2 losers, 1 grand final
+  assert_eq!(seeds.len(), 6);
+  assert_eq!(
+    seeds
+      .iter()
+      .filter(|m| m.bracket == BracketSide::Losers)
+      .count(),
+    2
+  );
+
+  let mut matches = seeds_to_matches(1, 1, seeds);
+  let mut bracket = Bracket {
+    tournament: &tournament,
+    matches: &mut matches,
+  };
+  assert_eq!(play_all(&mut bracket), 6);
+  assert_eq!(bracket.get_result(), Some(Some(1)));
+  let grand_final = bracket
+    .matches
+    .iter()
+    .find(|m| m.bracket == BracketSide::GrandFinal)
+    .unwrap();
+  assert_eq!(grand_final.player_ids, [Some(1), Some(2)]);
+
+  for count in 2..=9 {
+    let player_ids: Vec<i32> = (1..=count).collect();
+    let tournament = test_tournament(TournamentFormat::DoubleElimination, player_ids);
+    let mut matches = seeds_to_matches(1, 1, generate(tournament.format, &tournament.player_ids));
+    let mut bracket = Bracket {
+      tournament: &tournament,
+      matches: &mut matches,
+    };
+    play_all(&mut bracket);
+    assert_eq!(bracket.get_result(), Some(Some(1)), "{} players", count);
+  }
+}
+
+#[test]
+fn test_round_robin() {
+  let tournament = test_tournament(TournamentFormat::RoundRobin, vec![1, 2, 3, 4, 5]);
+  let seeds = generate(tournament.format, &tournament.player_ids);
+  assert_eq!(seeds.len(), 10);
+  let mut pairs = BTreeSet::new();
+  for m in &seeds {
+    let (a, b) = (m.player_ids[0].unwrap(), m.player_ids[1].unwrap());
+    assert!(pairs.insert((a.min(b), a.max(b))));
+  }
+
+  let mut matches = seeds_to_matches(1, 1, seeds);
+  let mut bracket = Bracket {
+    tournament: &tournament,
+    matches: &mut matches,
+  };
+  assert_eq!(play_all(&mut bracket), 10);
+  assert_eq!(bracket.get_standings(), vec![1, 2, 3, 4, 5]);
+  assert_eq!(bracket.get_result(), Some(Some(1)));
+}
+
+#[test]
+fn test_swiss() {
+  let tournament = test_tournament(TournamentFormat::Swiss, vec![1, 2, 3, 4, 5]);
+  assert_eq!(tournament.swiss_rounds(), 3);
+  let mut matches = seeds_to_matches(1, 1, generate(tournament.format, &tournament.player_ids));
+  assert_eq!(matches.len(), 3);
+  assert_eq!(matches[2].player_ids, [Some(5), None]);
+
+  let mut pairs = BTreeSet::new();
+  let mut byes = BTreeSet::new();
+  loop {
+    let mut bracket = Bracket {
+      tournament: &tournament,
+      matches: &mut matches,
+    };
+    play_all(&mut bracket);
+    match bracket.next_swiss_round() {
+      Some(seeds) => {
+        let first_id = matches.len() as i32 + 1;
+        matches.extend(seeds_to_matches(1, first_id, seeds));
+      }
+      None => break,
+    }
+  }
+
+  for m in &matches {
+    match m.player_ids {
+      [Some(a), Some(b)] => assert!(pairs.insert((a.min(b), a.max(b)))),
+      [Some(a), None] => assert!(byes.insert(a)),
+      _ => unreachable!(),
+    }
+  }
+  assert_eq!(matches.iter().map(|m| m.round).max(), Some(3));
+  assert_eq!(byes.len(), 3);
+
+  let bracket = Bracket {
+    tournament: &tournament,
+    matches: &mut matches,
+  };
+  assert_eq!(bracket.get_result(), Some(Some(1)));
+}
@@ -0,0 +1,479 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::game::{GameStatus, PlayerGameResult};
+use crate::rating::GameOutcome;
+use crate::schedule::ScheduledGameStatus;
+use crate::schema::{tournament, tournament_match};
+use crate::tournament::bracket::{self, MatchSeed};
+use crate::tournament::types::*;
+
+/// How long to wait for the player results of an ended game before replaying it
+const RESULT_WAIT_SECS: i64 = 300;
+
+pub fn create(
+  conn: &DbConn,
+  api_client_id: i32,
+  api_player_id: i32,
+  params: CreateTournamentParams,
+) -> Result<TournamentDetail> {
+  #[derive(Insertable)]
+  #[table_name = "tournament"]
+  struct Insert<'a> {
+    api_client_id: i32,
+    api_player_id: i32,
+    name: &'a str,
+    format: TournamentFormat,
+    best_of: i32,
+    map_pool: Value,
+    node_id: i32,
+    check_in_minutes: i32,
+    player_ids: Value,
+  }
+
+  if params.best_of < 1 || params.best_of % 2 == 0 {
+    return Err(Error::TournamentInvalid("best of must be an odd number"));
+  }
+  if params.check_in_minutes < 1 {
+    return Err(Error::TournamentInvalid("check-in period too short"));
+  }
+  if params.map_pool.is_empty() {
+    return Err(Error::TournamentInvalid("empty map pool"));
+  }
+  if params.map_pool.iter().any(|map| map.players.len() < 2) {
+    return Err(Error::TournamentInvalid(
+      "map pool contains maps for less than 2 players",
+    ));
+  }
+
+  let mut player_ids = params.player_ids.clone();
+  player_ids.sort();
+  player_ids.dedup();
+  if player_ids.len() != params.player_ids.len() {
+    return Err(Error::TournamentInvalid("duplicate player"));
+  }
+  if player_ids.len() < 2 {
+    return Err(Error::TournamentInvalid("not enough players"));
+  }
+  let players = crate::player::db::get_client_refs_by_ids(conn, api_client_id, &player_ids)?;
+  if players.len() != player_ids.len() {
+    return Err(Error::PlayerNotFound);
+  }
+
+  crate::node::db::get_node(conn, params.node_id)?;
+
+  conn.transaction(|| {
+    let row: Row = diesel::insert_into(tournament::table)
+      .values(&Insert {
+        api_client_id,
+        api_player_id,
+        name: &params.name,
+        format: params.format,
+        best_of: params.best_of,
+        map_pool: serde_json::to_value(&params.map_pool)?,
+        node_id: params.node_id,
+        check_in_minutes: params.check_in_minutes,
+        player_ids: serde_json::to_value(&params.player_ids)?,
+      })
+      .returning(Row::COLUMNS)
+      .get_result(conn)?;
+    let tournament = row.into_tournament()?;
+    let matches = insert_matches(
+      conn,
+      tournament.id,
+      bracket::generate(tournament.format, &tournament.player_ids),
+    )?;
+    Ok(TournamentDetail {
+      tournament,
+      matches,
+    })
+  })
+}
+
+/// Inserts generated matches and links them with their ids
+pub fn insert_matches(
+  conn: &DbConn,
+  tournament_id: i32,
+  seeds: Vec<MatchSeed>,
+) -> Result<Vec<TournamentMatch>> {
+  #[derive(Insertable)]
+  #[table_name = "tournament_match"]
+  struct Insert {
+    tournament_id: i32,
+    round: i32,
+    position: i32,
+    bracket: BracketSide,
+    player_a: Option<i32>,
+    player_b: Option<i32>,
+  }
+
+  let mut ids = Vec::with_capacity(seeds.len());
+  for seed in &seeds {
+    let id: i32 = diesel::insert_into(tournament_match::table)
+      .values(&Insert {
+        tournament_id,
+        round: seed.round,
+        position: seed.position,
+        bracket: seed.bracket,
+        player_a: seed.player_ids[0],
+        player_b: seed.player_ids[1],
+      })
+      .returning(tournament_match::id)
+      .get_result(conn)?;
+    ids.push(id);
+  }
+
+  let mut matches = bracket::seeds_to_matches(tournament_id, 0, seeds);
+  let to_id = |slot_ref: Option<MatchSlotRef>| {
+    slot_ref.map(|r| MatchSlotRef {
+      match_id: ids[r.match_id as usize],
+      slot: r.slot,
+    })
+  };
+  for (m, id) in matches.iter_mut().zip(ids.iter()) {
+    m.id = *id;
+    m.winner_to = to_id(m.winner_to);
+    m.loser_to = to_id(m.loser_to);
+    if m.winner_to.is_some() || m.loser_to.is_some() {
+      diesel::update(tournament_match::table.find(m.id))
+        .set((
+          tournament_match::winner_to.eq(m.winner_to.map(serde_json::to_value).transpose()?),
+          tournament_match::loser_to.eq(m.loser_to.map(serde_json::to_value).transpose()?),
+        ))
+        .execute(conn)?;
+    }
+  }
+
+  Ok(matches)
+}
+
+pub fn get(conn: &DbConn, id: i32) -> Result<TournamentDetail> {
+  let row: Row = tournament::table
+    .find(id)
+    .select(Row::COLUMNS)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::TournamentNotFound)?;
+  let tournament = row.into_tournament()?;
+  let matches = get_matches(conn, id)?;
+  Ok(TournamentDetail {
+    tournament,
+    matches,
+  })
+}
+
+pub fn get_running(conn: &DbConn) -> Result<Vec<TournamentDetail>> {
+  let rows: Vec<Row> = tournament::table
+    .filter(tournament::status.eq(TournamentStatus::Running))
+    .select(Row::COLUMNS)
+    .order(tournament::id)
+    .load(conn)?;
+  rows
+    .into_iter()
+    .map(|row| {
+      let tournament = row.into_tournament()?;
+      let matches = get_matches(conn, tournament.id)?;
+      Ok(TournamentDetail {
+        tournament,
+        matches,
+      })
+    })
+    .collect()
+}
+
+fn get_matches(conn: &DbConn, tournament_id: i32) -> Result<Vec<TournamentMatch>> {
+  let rows: Vec<MatchRow> = tournament_match::table
+    .filter(tournament_match::tournament_id.eq(tournament_id))
+    .select(MatchRow::COLUMNS)
+    .order(tournament_match::id)
+    .load(conn)?;
+  rows.into_iter().map(MatchRow::into_match).collect()
+}
+
+pub fn update_match(conn: &DbConn, m: &TournamentMatch) -> Result<()> {
+  diesel::update(tournament_match::table.find(m.id))
+    .set((
+      tournament_match::player_a.eq(m.player_ids[0]),
+      tournament_match::player_b.eq(m.player_ids[1]),
+      tournament_match::status.eq(m.status),
+      tournament_match::winner_id.eq(m.winner_id),
+      tournament_match::games.eq(serde_json::to_value(&m.games)?),
+      tournament_match::map_pick.eq(m.map_pick.map(|v| v as i32)),
+      tournament_match::next_game_at.eq(m.next_game_at),
+      tournament_match::updated_at.eq(diesel::dsl::now),
+    ))
+    .execute(conn)?;
+  Ok(())
+}
+
+pub fn set_finished(conn: &DbConn, id: i32, winner_id: Option<i32>) -> Result<()> {
+  diesel::update(tournament::table.find(id))
+    .set((
+      tournament::status.eq(TournamentStatus::Finished),
+      tournament::winner_id.eq(winner_id),
+      tournament::updated_at.eq(diesel::dsl::now),
+    ))
+    .execute(conn)?;
+  Ok(())
+}
+
+pub fn cancel(conn: &DbConn, id: i32, api_client_id: i32) -> Result<()> {
+  let updated = diesel::update(
+    tournament::table.find(id).filter(
+      tournament::api_client_id
+        .eq(api_client_id)
+        .and(tournament::status.eq(TournamentStatus::Running)),
+    ),
+  )
+  .set((
+    tournament::status.eq(TournamentStatus::Cancelled),
+    tournament::updated_at.eq(diesel::dsl::now),
+  ))
+  .execute(conn)?;
+  if updated == 0 {
+    return Err(Error::TournamentNotFound);
+  }
+  Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SeriesGameOutcome {
+  Pending,
+  Won {
+    slot: usize,
+  },
+  /// No winner, the game has to be played again
+  Replay,
+}
+
+/// Resolves a forfeited series game, nobody advances if both players did not show up
+fn get_forfeit_outcome(no_show_player_ids: &[i32], player_ids: [i32; 2]) -> SeriesGameOutcome {
+  match (
+    no_show_player_ids.contains(&player_ids[0]),
+    no_show_player_ids.contains(&player_ids[1]),
+  ) {
+    (true, false) => SeriesGameOutcome::Won { slot: 1 },
+    (false, true) => SeriesGameOutcome::Won { slot: 0 },
+    _ => SeriesGameOutcome::Replay,
+  }
+}
+
+/// Resolves a series game from its scheduled game and the game result detection
+pub fn get_series_game_outcome(
+  conn: &DbConn,
+  scheduled_game_id: i32,
+  player_ids: [i32; 2],
+) -> Result<SeriesGameOutcome> {
+  let schedule = crate::schedule::db::get(conn, scheduled_game_id)?;
+  let game_id = match schedule.status {
+    ScheduledGameStatus::Scheduled => return Ok(SeriesGameOutcome::Pending),
+    ScheduledGameStatus::Cancelled | ScheduledGameStatus::Failed => {
+      return Ok(SeriesGameOutcome::Replay)
+    }
+    ScheduledGameStatus::Forfeited => {
+      return Ok(get_forfeit_outcome(
+        &schedule.no_show_player_ids,
+        player_ids,
+      ))
+    }
+    ScheduledGameStatus::Started => match schedule.game_id {
+      Some(id) => id,
+      None => return Ok(SeriesGameOutcome::Replay),
+    },
+  };
+
+  let game = crate::game::db::get_full(conn, game_id)?;
+  match game.status {
+    GameStatus::Ended => {}
+    GameStatus::Terminated => return Ok(SeriesGameOutcome::Replay),
+    _ => return Ok(SeriesGameOutcome::Pending),
+  }
+
+  let results = crate::game::db::get_player_results(conn, game_id)?;
+  let outcome = match GameOutcome::from_results(&game.slots, &results) {
+    Some(outcome) => outcome,
+    None => {
+      // remade games are played again
+      if results.values().any(|r| *r == PlayerGameResult::Aborted) {
+        return Ok(SeriesGameOutcome::Replay);
+      }
+      // the node reports the player results after the game ended
+      let waiting_results = game
+        .ended_at
+        .map(|t| Utc::now() - t < chrono::Duration::seconds(RESULT_WAIT_SECS))
+        .unwrap_or(true);
+      if waiting_results {
+        return Ok(SeriesGameOutcome::Pending);
+      }
+      return Ok(SeriesGameOutcome::Replay);
+    }
+  };
+  let rank_of = |player_id: i32| {
+    outcome
+      .teams
+      .iter()
+      .position(|team| team.contains(&player_id))
+      .map(|idx| outcome.ranks[idx])
+  };
+  Ok(match (rank_of(player_ids[0]), rank_of(player_ids[1])) {
+    (Some(a), Some(b)) if a < b => SeriesGameOutcome::Won { slot: 0 },
+    (Some(a), Some(b)) if b < a => SeriesGameOutcome::Won { slot: 1 },
+    _ => SeriesGameOutcome::Replay,
+  })
+}
+
+#[derive(Debug, Queryable)]
+struct Row {
+  id: i32,
+  api_client_id: i32,
+  api_player_id: i32,
+  name: String,
+  format: TournamentFormat,
+  best_of: i32,
+  map_pool: Value,
+  node_id: i32,
+  check_in_minutes: i32,
+  player_ids: Value,
+  status: TournamentStatus,
+  winner_id: Option<i32>,
+}
+
+type RowColumns = (
+  tournament::dsl::id,
+  tournament::dsl::api_client_id,
+  tournament::dsl::api_player_id,
+  tournament::dsl::name,
+  tournament::dsl::format,
+  tournament::dsl::best_of,
+  tournament::dsl::map_pool,
+  tournament::dsl::node_id,
+  tournament::dsl::check_in_minutes,
+  tournament::dsl::player_ids,
+  tournament::dsl::status,
+  tournament::dsl::winner_id,
+);
+
+impl Row {
+  const COLUMNS: RowColumns = (
+    tournament::dsl::id,
+    tournament::dsl::api_client_id,
+    tournament::dsl::api_player_id,
+    tournament::dsl::name,
+    tournament::dsl::format,
+    tournament::dsl::best_of,
+    tournament::dsl::map_pool,
+    tournament::dsl::node_id,
+    tournament::dsl::check_in_minutes,
+    tournament::dsl::player_ids,
+    tournament::dsl::status,
+    tournament::dsl::winner_id,
+  );
+
+  fn into_tournament(self) -> Result<Tournament> {
+    Ok(Tournament {
+      id: self.id,
+      api_client_id: self.api_client_id,
+      api_player_id: self.api_player_id,
+      name: self.name,
+      format: self.format,
+      best_of: self.best_of,
+      map_pool: serde_json::from_value(self.map_pool)?,
+      node_id: self.node_id,
+      check_in_minutes: self.check_in_minutes,
+      player_ids: serde_json::from_value(self.player_ids)?,
+      status: self.status,
+      winner_id: self.winner_id,
+    })
+  }
+}
+
+#[derive(Debug, Queryable)]
+struct MatchRow {
+  id: i32,
+  tournament_id: i32,
+  round: i32,
+  position: i32,
+  bracket: BracketSide,
+  player_a: Option<i32>,
+  player_b: Option<i32>,
+  winner_to: Option<Value>,
+  loser_to: Option<Value>,
+  status: TournamentMatchStatus,
+  winner_id: Option<i32>,
+  games: Value,
+  map_pick: Option<i32>,
+  next_game_at: Option<DateTime<Utc>>,
+}
+
+type MatchRowColumns = (
+  tournament_match::dsl::id,
+  tournament_match::dsl::tournament_id,
+  tournament_match::dsl::round,
+  tournament_match::dsl::position,
+  tournament_match::dsl::bracket,
+  tournament_match::dsl::player_a,
+  tournament_match::dsl::player_b,
+  tournament_match::dsl::winner_to,
+  tournament_match::dsl::loser_to,
+  tournament_match::dsl::status,
+  tournament_match::dsl::winner_id,
+  tournament_match::dsl::games,
+  tournament_match::dsl::map_pick,
+  tournament_match::dsl::next_game_at,
+);
+
+impl MatchRow {
+  const COLUMNS: MatchRowColumns = (
+    tournament_match::dsl::id,
+    tournament_match::dsl::tournament_id,
+    tournament_match::dsl::round,
+    tournament_match::dsl::position,
+    tournament_match::dsl::bracket,
+    tournament_match::dsl::player_a,
+    tournament_match::dsl::player_b,
+    tournament_match::dsl::winner_to,
+    tournament_match::dsl::loser_to,
+    tournament_match::dsl::status,
+    tournament_match::dsl::winner_id,
+    tournament_match::dsl::games,
+    tournament_match::dsl::map_pick,
+    tournament_match::dsl::next_game_at,
+  );
+
+  fn into_match(self) -> Result<TournamentMatch> {
+    Ok(TournamentMatch {
+      id: self.id,
+      tournament_id: self.tournament_id,
+      round: self.round,
+      position: self.position,
+      bracket: self.bracket,
+      player_ids: [self.player_a, self.player_b],
+      winner_to: self.winner_to.map(serde_json::from_value).transpose()?,
+      loser_to: self.loser_to.map(serde_json::from_value).transpose()?,
+      status: self.status,
+      winner_id: self.winner_id,
+      games: serde_json::from_value(self.games)?,
+      map_pick: self.map_pick.map(|v| v as usize),
+      next_game_at: self.next_game_at,
+    })
+  }
+}
+
+#[test]
+fn test_get_forfeit_outcome() {
+  assert_eq!(
+    get_forfeit_outcome(&[1], [1, 2]),
+    SeriesGameOutcome::Won { slot: 1 }
+  );
+  assert_eq!(
+    get_forfeit_outcome(&[2], [1, 2]),
+    SeriesGameOutcome::Won { slot: 0 }
+  );
+  assert_eq!(
+    get_forfeit_outcome(&[1, 2], [1, 2]),
+    SeriesGameOutcome::Replay
+  );
+}
@@ -0,0 +1,10 @@
+pub mod bracket;
+pub mod db;
+pub(crate) mod state;
+mod types;
+
+pub mod messages {
+  pub use super::state::{CancelTournament, CreateTournament, GetTournament, PickMap};
+}
+
+pub use types::*;
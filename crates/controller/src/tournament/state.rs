@@ -0,0 +1,480 @@
+use crate::error::*;
+use crate::game::{CreateGameSlot, Race, SlotSettings, SlotStatus};
+use crate::player::state::sender::PlayerRegistryHandle;
+use crate::player::state::PlayerRegistry;
+use crate::schedule::messages::{CancelScheduledGame, CreateScheduledGame};
+use crate::schedule::state::ScheduleRegistry;
+use crate::schedule::CreateScheduledGameParams;
+use crate::state::Data;
+use crate::tournament::bracket::Bracket;
+use crate::tournament::db::SeriesGameOutcome;
+use crate::tournament::types::*;
+use bs_diesel_utils::ExecutorRef;
+use chrono::Utc;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::PacketTournamentMatchUpdate;
+use flo_state::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use tokio::time::sleep;
+
+const TOURNAMENT_INTERVAL: Duration = Duration::from_secs(10);
+const MAP_PICK_TIMEOUT_SECS: i64 = 60;
+
+/// Running tournaments, plays their series through scheduled games and advances the brackets.
+pub struct TournamentRegistry {
+  db: ExecutorRef,
+  schedules: Addr<ScheduleRegistry>,
+  player_packet_sender: PlayerRegistryHandle,
+  tournaments: BTreeMap<i32, TournamentDetail>,
+}
+
+impl TournamentRegistry {
+  async fn load_tournaments(&mut self) -> Result<()> {
+    let tournaments = self
+      .db
+      .exec(|conn| crate::tournament::db::get_running(conn))
+      .await?;
+    self.tournaments = tournaments
+      .into_iter()
+      .map(|t| (t.tournament.id, t))
+      .collect();
+    Ok(())
+  }
+
+  async fn advance(&mut self, id: i32) -> Result<()> {
+    let mut detail = match self.tournaments.remove(&id) {
+      Some(v) => v,
+      None => return Ok(()),
+    };
+    let res = self.advance_detail(&mut detail).await;
+    if detail.tournament.status == TournamentStatus::Running {
+      self.tournaments.insert(id, detail);
+    }
+    res
+  }
+
+  async fn advance_detail(&self, detail: &mut TournamentDetail) -> Result<()> {
+    let tournament = &detail.tournament;
+    let now = Utc::now();
+    let mut updated = BTreeSet::new();
+
+    // collect the results of the running games
+    for idx in 0..detail.matches.len() {
+      let m = &detail.matches[idx];
+      let (scheduled_game_id, player_ids) = match (m.current_game(), m.player_ids) {
+        (Some(game), [Some(a), Some(b)]) => (game.scheduled_game_id, [a, b]),
+        _ => continue,
+      };
+      let outcome = self
+        .db
+        .exec(move |conn| {
+          crate::tournament::db::get_series_game_outcome(conn, scheduled_game_id, player_ids)
+        })
+        .await?;
+      let m = &mut detail.matches[idx];
+      match outcome {
+        SeriesGameOutcome::Pending => continue,
+        SeriesGameOutcome::Replay => {
+          m.games.retain(|g| g.winner_slot.is_some());
+        }
+        SeriesGameOutcome::Won { slot } => {
+          if let Some(game) = m.current_game_mut() {
+            game.winner_slot = Some(slot);
+          }
+          m.map_pick = None;
+          m.next_game_at = Some(now + chrono::Duration::seconds(MAP_PICK_TIMEOUT_SECS));
+        }
+      }
+      updated.insert(m.id);
+
+      if let Some(slot) = m.series_winner_slot(tournament.best_of) {
+        let (match_id, winner_id) = (m.id, m.player_ids[slot]);
+        let mut bracket = Bracket {
+          tournament,
+          matches: &mut detail.matches,
+        };
+        updated.extend(bracket.set_winner(match_id, winner_id));
+      }
+    }
+
+    let mut bracket = Bracket {
+      tournament,
+      matches: &mut detail.matches,
+    };
+    updated.extend(bracket.resolve_byes());
+    if let Some(seeds) = bracket.next_swiss_round() {
+      let tournament_id = tournament.id;
+      let added = self
+        .db
+        .exec(move |conn| crate::tournament::db::insert_matches(conn, tournament_id, seeds))
+        .await?;
+      detail.matches.extend(added);
+      let mut bracket = Bracket {
+        tournament,
+        matches: &mut detail.matches,
+      };
+      updated.extend(bracket.resolve_byes());
+    }
+
+    // schedule the next game of every playable series
+    let playable = Bracket {
+      tournament,
+      matches: &mut detail.matches,
+    }
+    .get_playable_match_ids();
+    for match_id in playable {
+      let m = match detail.matches.iter_mut().find(|m| m.id == match_id) {
+        Some(m) => m,
+        None => continue,
+      };
+      if m.current_game().is_some() {
+        continue;
+      }
+      if m.map_pick.is_none() && m.next_game_at.map(|t| t > now).unwrap_or(false) {
+        continue;
+      }
+      let player_ids = match m.player_ids {
+        [Some(a), Some(b)] => [a, b],
+        _ => continue,
+      };
+      let map_index = m.next_map_index(tournament.map_pool.len());
+      let slots = player_ids
+        .iter()
+        .enumerate()
+        .map(|(idx, player_id)| CreateGameSlot {
+          player_id: Some(*player_id),
+          settings: SlotSettings {
+            team: idx as i32,
+            color: idx as i32,
+            status: SlotStatus::Occupied,
+            race: Race::Random,
+            ..Default::default()
+          },
+        })
+        .collect();
+      let schedule = self
+        .schedules
+        .send(CreateScheduledGame {
+          api_client_id: tournament.api_client_id,
+          api_player_id: tournament.api_player_id,
+          params: CreateScheduledGameParams {
+            name: format!(
+              "{} R{} M{} G{}",
+              tournament.name,
+              m.round,
+              m.position + 1,
+              m.games.len() + 1
+            ),
+            map: tournament.map_pool[map_index].clone(),
+            node_id: tournament.node_id,
+            slots,
+            check_in_at: now,
+            start_at: now,
+            end_at: now + chrono::Duration::minutes(tournament.check_in_minutes as i64),
+          },
+        })
+        .await??;
+      m.games.push(SeriesGame {
+        scheduled_game_id: schedule.id,
+        map_index,
+        winner_slot: None,
+      });
+      m.status = TournamentMatchStatus::Running;
+      m.map_pick = None;
+      m.next_game_at = None;
+      updated.insert(m.id);
+    }
+
+    if !updated.is_empty() {
+      let updated_matches: Vec<TournamentMatch> = detail
+        .matches
+        .iter()
+        .filter(|m| updated.contains(&m.id))
+        .cloned()
+        .collect();
+      self
+        .db
+        .exec({
+          let updated_matches = updated_matches.clone();
+          move |conn| {
+            for m in &updated_matches {
+              crate::tournament::db::update_match(conn, m)?;
+            }
+            Ok::<_, Error>(())
+          }
+        })
+        .await?;
+      for m in &updated_matches {
+        self.broadcast_match(tournament, m).await;
+      }
+    }
+
+    let result = Bracket {
+      tournament,
+      matches: &mut detail.matches,
+    }
+    .get_result();
+    if let Some(winner_id) = result {
+      let tournament_id = tournament.id;
+      self
+        .db
+        .exec(move |conn| crate::tournament::db::set_finished(conn, tournament_id, winner_id))
+        .await?;
+      detail.tournament.status = TournamentStatus::Finished;
+      detail.tournament.winner_id = winner_id;
+      tracing::info!(
+        tournament_id,
+        "tournament finished, winner: {:?}",
+        winner_id
+      );
+    }
+
+    Ok(())
+  }
+
+  async fn broadcast_match(&self, tournament: &Tournament, m: &TournamentMatch) {
+    let picker_player_id =
+      if m.status == TournamentMatchStatus::Running && m.current_game().is_none() {
+        m.picker_slot().and_then(|slot| m.player_ids[slot])
+      } else {
+        None
+      };
+    let pkt = PacketTournamentMatchUpdate {
+      tournament_id: tournament.id,
+      tournament_name: tournament.name.clone(),
+      match_id: m.id,
+      round: m.round,
+      player_a: m.player_ids[0],
+      player_b: m.player_ids[1],
+      wins_a: m.wins(0),
+      wins_b: m.wins(1),
+      best_of: tournament.best_of,
+      winner_id: m.winner_id,
+      picker_player_id,
+      available_map_indexes: m
+        .available_map_indexes(tournament.map_pool.len())
+        .into_iter()
+        .map(|idx| idx as i32)
+        .collect(),
+      map_pool: tournament
+        .map_pool
+        .iter()
+        .map(|map| map.name.clone())
+        .collect(),
+    };
+    let frame = match pkt.encode_as_frame() {
+      Ok(frame) => frame,
+      Err(err) => {
+        tracing::error!(match_id = m.id, "encode match update: {}", err);
+        return;
+      }
+    };
+    let player_ids: Vec<i32> = m.player_ids.iter().flatten().cloned().collect();
+    if let Err(err) = self.player_packet_sender.broadcast(player_ids, frame).await {
+      tracing::error!(match_id = m.id, "broadcast match update: {}", err);
+    }
+  }
+}
+
+#[async_trait]
+impl Actor for TournamentRegistry {
+  async fn started(&mut self, ctx: &mut Context<Self>) {
+    if let Err(err) = self.load_tournaments().await {
+      tracing::error!("load tournaments: {}", err);
+    }
+    self.handle(ctx, RunTournaments).await;
+  }
+}
+
+#[async_trait]
+impl Service<Data> for TournamentRegistry {
+  type Error = Error;
+
+  async fn create(registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
+    let schedules = registry.resolve::<ScheduleRegistry>().await?;
+    let players = registry.resolve::<PlayerRegistry>().await?;
+    Ok(Self {
+      db: registry.data().db.clone(),
+      schedules,
+      player_packet_sender: players.into(),
+      tournaments: BTreeMap::new(),
+    })
+  }
+}
+
+pub struct CreateTournament {
+  pub api_client_id: i32,
+  pub api_player_id: i32,
+  pub params: CreateTournamentParams,
+}
+
+impl Message for CreateTournament {
+  type Result = Result<TournamentDetail>;
+}
+
+#[async_trait]
+impl Handler<CreateTournament> for TournamentRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    CreateTournament {
+      api_client_id,
+      api_player_id,
+      params,
+    }: CreateTournament,
+  ) -> <CreateTournament as Message>::Result {
+    let detail = self
+      .db
+      .exec(move |conn| crate::tournament::db::create(conn, api_client_id, api_player_id, params))
+      .await?;
+    let id = detail.tournament.id;
+    self.tournaments.insert(id, detail.clone());
+    if let Err(err) = self.advance(id).await {
+      tracing::error!(tournament_id = id, "advance: {}", err);
+    }
+    Ok(self.tournaments.get(&id).cloned().unwrap_or(detail))
+  }
+}
+
+pub struct GetTournament {
+  pub id: i32,
+}
+
+impl Message for GetTournament {
+  type Result = Result<TournamentDetail>;
+}
+
+#[async_trait]
+impl Handler<GetTournament> for TournamentRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    GetTournament { id }: GetTournament,
+  ) -> <GetTournament as Message>::Result {
+    if let Some(detail) = self.tournaments.get(&id) {
+      return Ok(detail.clone());
+    }
+    self
+      .db
+      .exec(move |conn| crate::tournament::db::get(conn, id))
+      .await
+      .map_err(Into::into)
+  }
+}
+
+pub struct CancelTournament {
+  pub api_client_id: i32,
+  pub id: i32,
+}
+
+impl Message for CancelTournament {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<CancelTournament> for TournamentRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    CancelTournament { api_client_id, id }: CancelTournament,
+  ) -> <CancelTournament as Message>::Result {
+    self
+      .db
+      .exec(move |conn| crate::tournament::db::cancel(conn, id, api_client_id))
+      .await?;
+    if let Some(detail) = self.tournaments.remove(&id) {
+      for m in &detail.matches {
+        if let Some(game) = m.current_game() {
+          self
+            .schedules
+            .send(CancelScheduledGame {
+              api_client_id,
+              id: game.scheduled_game_id,
+            })
+            .await?
+            .ok();
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// The loser of the last game picks the map of the next one
+pub struct PickMap {
+  pub player_id: i32,
+  pub match_id: i32,
+  pub map_index: usize,
+}
+
+impl Message for PickMap {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<PickMap> for TournamentRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    PickMap {
+      player_id,
+      match_id,
+      map_index,
+    }: PickMap,
+  ) -> <PickMap as Message>::Result {
+    let (tournament_id, m) = self
+      .tournaments
+      .values_mut()
+      .find_map(|detail| {
+        let tournament_id = detail.tournament.id;
+        let pool_len = detail.tournament.map_pool.len();
+        detail
+          .matches
+          .iter_mut()
+          .find(|m| m.id == match_id)
+          .map(|m| (tournament_id, pool_len, m))
+      })
+      .map(|(tournament_id, pool_len, m)| {
+        let allowed = m.status == TournamentMatchStatus::Running
+          && m.current_game().is_none()
+          && m.picker_slot().and_then(|slot| m.player_ids[slot]) == Some(player_id)
+          && m.available_map_indexes(pool_len).contains(&map_index);
+        if allowed {
+          m.map_pick = Some(map_index);
+        }
+        (tournament_id, if allowed { Some(m.clone()) } else { None })
+      })
+      .ok_or_else(|| Error::TournamentMatchNotFound)?;
+    let m = m.ok_or_else(|| Error::TournamentMapPickDenied)?;
+
+    self
+      .db
+      .exec(move |conn| crate::tournament::db::update_match(conn, &m))
+      .await?;
+    self.advance(tournament_id).await
+  }
+}
+
+struct RunTournaments;
+
+impl Message for RunTournaments {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<RunTournaments> for TournamentRegistry {
+  async fn handle(&mut self, ctx: &mut Context<Self>, _: RunTournaments) {
+    let ids: Vec<i32> = self.tournaments.keys().cloned().collect();
+    for id in ids {
+      if let Err(err) = self.advance(id).await {
+        tracing::error!(tournament_id = id, "advance: {}", err);
+      }
+    }
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      sleep(TOURNAMENT_INTERVAL).await;
+      addr.notify(RunTournaments).await.ok();
+    });
+  }
+}
@@ -0,0 +1,241 @@
+use bs_diesel_utils::BSDieselEnum;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::map::Map;
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum)]
+#[repr(i32)]
+pub enum TournamentFormat {
+  SingleElimination = 0,
+  /// Single grand final, no bracket reset
+  DoubleElimination = 1,
+  RoundRobin = 2,
+  Swiss = 3,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum)]
+#[repr(i32)]
+pub enum TournamentStatus {
+  Running = 0,
+  Finished = 1,
+  Cancelled = 2,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum)]
+#[repr(i32)]
+pub enum BracketSide {
+  /// Also used by round robin and Swiss matches
+  Winners = 0,
+  Losers = 1,
+  GrandFinal = 2,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum)]
+#[repr(i32)]
+pub enum TournamentMatchStatus {
+  Pending = 0,
+  Running = 1,
+  Finished = 2,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Tournament {
+  pub id: i32,
+  pub api_client_id: i32,
+  pub api_player_id: i32,
+  pub name: String,
+  pub format: TournamentFormat,
+  pub best_of: i32,
+  pub map_pool: Vec<Map>,
+  pub node_id: i32,
+  /// How long players have to check in for each game of a series
+  pub check_in_minutes: i32,
+  /// Ordered by seed
+  pub player_ids: Vec<i32>,
+  pub status: TournamentStatus,
+  pub winner_id: Option<i32>,
+}
+
+impl Tournament {
+  pub fn swiss_rounds(&self) -> i32 {
+    let mut rounds = 1;
+    while (1 << rounds) < self.player_ids.len() {
+      rounds += 1;
+    }
+    rounds
+  }
+}
+
+/// Where the winner or the loser of a match goes
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+pub struct MatchSlotRef {
+  pub match_id: i32,
+  pub slot: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SeriesGame {
+  pub scheduled_game_id: i32,
+  pub map_index: usize,
+  /// Slot of the winning player, `None` while the game is not decided
+  pub winner_slot: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TournamentMatch {
+  pub id: i32,
+  pub tournament_id: i32,
+  pub round: i32,
+  pub position: i32,
+  pub bracket: BracketSide,
+  pub player_ids: [Option<i32>; 2],
+  pub winner_to: Option<MatchSlotRef>,
+  pub loser_to: Option<MatchSlotRef>,
+  pub status: TournamentMatchStatus,
+  /// `None` for a finished match means both slots were empty
+  pub winner_id: Option<i32>,
+  pub games: Vec<SeriesGame>,
+  /// Map index picked by the loser of the previous game
+  pub map_pick: Option<usize>,
+  /// The next game of the series is scheduled at this time if the loser did not pick
+  pub next_game_at: Option<DateTime<Utc>>,
+}
+
+impl TournamentMatch {
+  pub fn wins(&self, slot: usize) -> i32 {
+    self
+      .games
+      .iter()
+      .filter(|g| g.winner_slot == Some(slot))
+      .count() as i32
+  }
+
+  /// Slot of the series winner
+  pub fn series_winner_slot(&self, best_of: i32) -> Option<usize> {
+    let needed = best_of / 2 + 1;
+    (0..2).find(|slot| self.wins(*slot) >= needed)
+  }
+
+  pub fn current_game(&self) -> Option<&SeriesGame> {
+    self.games.iter().find(|g| g.winner_slot.is_none())
+  }
+
+  pub fn current_game_mut(&mut self) -> Option<&mut SeriesGame> {
+    self.games.iter_mut().find(|g| g.winner_slot.is_none())
+  }
+
+  /// The loser of the last game picks the next map
+  pub fn picker_slot(&self) -> Option<usize> {
+    self
+      .games
+      .last()
+      .and_then(|g| g.winner_slot)
+      .map(|slot| 1 - slot)
+  }
+
+  pub fn slot_of(&self, player_id: i32) -> Option<usize> {
+    self.player_ids.iter().position(|id| *id == Some(player_id))
+  }
+
+  /// Maps not played yet in this series
+  pub fn available_map_indexes(&self, pool_len: usize) -> Vec<usize> {
+    let available: Vec<usize> = (0..pool_len)
+      .filter(|idx| !self.games.iter().any(|g| g.map_index == *idx))
+      .collect();
+    if available.is_empty() {
+      (0..pool_len).collect()
+    } else {
+      available
+    }
+  }
+
+  /// The loser's pick, or the next map of the pool not played yet.
+  /// Different matches start at different offsets so the pool alternates.
+  pub fn next_map_index(&self, pool_len: usize) -> usize {
+    let available = self.available_map_indexes(pool_len);
+    if let Some(pick) = self.map_pick {
+      if available.contains(&pick) {
+        return pick;
+      }
+    }
+    let offset = (self.round + self.position) as usize + self.games.len();
+    (0..pool_len)
+      .map(|i| (offset + i) % pool_len)
+      .find(|idx| available.contains(idx))
+      .unwrap_or(0)
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTournamentParams {
+  pub name: String,
+  pub format: TournamentFormat,
+  pub best_of: i32,
+  pub map_pool: Vec<Map>,
+  pub node_id: i32,
+  pub check_in_minutes: i32,
+  /// Ordered by seed
+  pub player_ids: Vec<i32>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TournamentDetail {
+  pub tournament: Tournament,
+  pub matches: Vec<TournamentMatch>,
+}
+
+#[test]
+fn test_tournament_match_series() {
+  let mut m = TournamentMatch {
+    id: 1,
+    tournament_id: 1,
+    round: 1,
+    position: 0,
+    bracket: BracketSide::Winners,
+    player_ids: [Some(1), Some(2)],
+    winner_to: None,
+    loser_to: None,
+    status: TournamentMatchStatus::Running,
+    winner_id: None,
+    games: vec![],
+    map_pick: None,
+    next_game_at: None,
+  };
+
+  assert_eq!(m.next_map_index(3), 1);
+  m.games.push(SeriesGame {
+    scheduled_game_id: 1,
+    map_index: 1,
+    winner_slot: None,
+  });
+  assert!(m.current_game().is_some());
+  assert_eq!(m.picker_slot(), None);
+
+  m.current_game_mut().unwrap().winner_slot = Some(0);
+  assert_eq!(m.picker_slot(), Some(1));
+  assert_eq!(m.series_winner_slot(3), None);
+  assert_eq!(m.series_winner_slot(1), Some(0));
+  assert_eq!(m.available_map_indexes(3), vec![0, 2]);
+  assert_eq!(m.next_map_index(3), 2);
+
+  m.map_pick = Some(1);
+  assert_eq!(m.next_map_index(3), 2);
+  m.map_pick = Some(0);
+  assert_eq!(m.next_map_index(3), 0);
+
+  m.games.push(SeriesGame {
+    scheduled_game_id: 2,
+    map_index: 0,
+    winner_slot: Some(1),
+  });
+  m.games.push(SeriesGame {
+    scheduled_game_id: 3,
+    map_index: 2,
+    winner_slot: Some(1),
+  });
+  assert_eq!(m.wins(0), 1);
+  assert_eq!(m.wins(1), 2);
+  assert_eq!(m.series_winner_slot(3), Some(1));
+  assert_eq!(m.available_map_indexes(3), vec![0, 1, 2]);
+}
@@ -0,0 +1,91 @@
+use crate::error::*;
+use crate::game_info::GameInfo;
+use crate::mdns::publisher::MdnsPublisher;
+use crate::mdns::search::{search_lan_games, LanGame};
+use crate::udp::publisher::UdpPublisher;
+use crate::udp::search::search_udp_lan_games;
+use std::time::Duration;
+
+/// Reforged (1.32+) uses Bonjour, older clients use UDP broadcasts on port 6112
+const FIRST_MDNS_MINOR_VERSION: u32 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LanDiscovery {
+  Mdns,
+  Udp {
+    /// Minor game version, e.g. 26 for 1.26
+    version: u32,
+  },
+}
+
+impl LanDiscovery {
+  /// Selects the protocol from a game version string like `1.26.0.6401`,
+  /// unparsable versions fall back to mDNS
+  pub fn from_game_version(version: &str) -> Self {
+    let mut parts = version.split('.').map(|v| v.parse::<u32>());
+    match (parts.next(), parts.next()) {
+      (Some(Ok(1)), Some(Ok(minor))) if minor < FIRST_MDNS_MINOR_VERSION => {
+        LanDiscovery::Udp { version: minor }
+      }
+      _ => LanDiscovery::Mdns,
+    }
+  }
+
+  pub async fn search(&self, timeout: Duration) -> Vec<LanGame> {
+    match *self {
+      LanDiscovery::Mdns => search_lan_games(timeout).await,
+      LanDiscovery::Udp { version } => search_udp_lan_games(version, timeout).await,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum LanPublisher {
+  Mdns(MdnsPublisher),
+  Udp(UdpPublisher),
+}
+
+impl LanPublisher {
+  pub async fn start(discovery: LanDiscovery, game_info: GameInfo) -> Result<Self> {
+    Ok(match discovery {
+      LanDiscovery::Mdns => LanPublisher::Mdns(MdnsPublisher::start(game_info).await?),
+      LanDiscovery::Udp { version } => {
+        LanPublisher::Udp(UdpPublisher::start(game_info, version).await?)
+      }
+    })
+  }
+
+  pub async fn update<F>(&mut self, f: F) -> Result<()>
+  where
+    F: FnOnce(&mut GameInfo),
+  {
+    match self {
+      LanPublisher::Mdns(publisher) => publisher.update(f).await,
+      LanPublisher::Udp(publisher) => publisher.update(f).await,
+    }
+  }
+
+  pub async fn refresh(&mut self) -> Result<()> {
+    match self {
+      LanPublisher::Mdns(publisher) => publisher.refresh().await,
+      LanPublisher::Udp(publisher) => publisher.refresh().await,
+    }
+  }
+}
+
+#[test]
+fn test_from_game_version() {
+  assert_eq!(
+    LanDiscovery::from_game_version("1.26.0.6401"),
+    LanDiscovery::Udp { version: 26 }
+  );
+  assert_eq!(
+    LanDiscovery::from_game_version("1.28.5.7680"),
+    LanDiscovery::Udp { version: 28 }
+  );
+  assert_eq!(
+    LanDiscovery::from_game_version("1.32.10.18067"),
+    LanDiscovery::Mdns
+  );
+  assert_eq!(LanDiscovery::from_game_version(""), LanDiscovery::Mdns);
+}
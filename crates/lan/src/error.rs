@@ -8,6 +8,8 @@ pub enum Error {
   BonjourRegister(std::io::Error),
   #[error("bonjour update: {0}")]
   BonjourUpdate(String),
+  #[error("udp update: {0}")]
+  UdpUpdate(String),
   #[error("get hostname: {0}")]
   GetHostName(std::io::Error),
   #[error("couldn't find game info record in the replay file")]
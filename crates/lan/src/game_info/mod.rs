@@ -6,6 +6,7 @@ use flo_util::binary::*;
 use flo_util::{BinDecode, BinEncode};
 use flo_w3gs::constants::GameFlags;
 use flo_w3gs::protocol::game::GameSettings;
+use flo_w3gs::protocol::lan;
 use flo_w3replay::W3Replay;
 
 use crate::error::*;
@@ -229,6 +230,61 @@ impl GameInfo {
   pub fn set_port(&mut self, port: u16) {
     self.data.port = port;
   }
+
+  /// Speed, visibility, observer and team options of the game
+  pub fn set_game_setting_flags(&mut self, flags: GameSettingFlags) {
+    self.data.settings.game_setting_flags = flags;
+  }
+
+  /// Converts to the payload of the classic UDP `GameInfo` packet
+  pub(crate) fn to_udp_game_info(&self, version: u32) -> Result<lan::GameInfo> {
+    let game_id = self
+      .game_id
+      .parse()
+      .map_err(|_| Error::InvalidGameInfo("game id is not a number"))?;
+    let mut info = lan::GameInfo::new(
+      version,
+      game_id,
+      self.secret,
+      self.data.name.clone(),
+      self.data.settings.clone(),
+      self.data.flags,
+      self.data.port,
+    );
+    info.slots_total = self.players_max as u32;
+    info.slots_open = self.players_max.saturating_sub(self.players_num) as u32;
+    info.uptime_secs = self
+      .create_time
+      .elapsed()
+      .map(|d| d.as_secs() as u32)
+      .unwrap_or_default();
+    Ok(info)
+  }
+
+  pub(crate) fn from_udp_game_info(info: lan::GameInfo) -> Self {
+    let players_max = info.slots_total.min(24) as u8;
+    let players_num = players_max.saturating_sub(info.slots_open.min(24) as u8);
+    let create_time = SystemTime::now()
+      .checked_sub(std::time::Duration::from_secs(info.uptime_secs as u64))
+      .unwrap_or_else(SystemTime::now);
+    Self {
+      message_id: 0,
+      game_id: info.game_id.to_string(),
+      create_time,
+      secret: info.entry_key,
+      name: info.name.clone(),
+      players_num,
+      players_max,
+      data: GameData {
+        name: info.name,
+        _unknown_byte: 0,
+        settings: info.settings,
+        slots_total: info.slots_total,
+        flags: info.flags,
+        port: info.port,
+      },
+    }
+  }
 }
 
 #[derive(Debug, BinEncode, BinDecode, PartialEq, Clone)]
@@ -289,3 +345,25 @@ fn test_decode_gamedata_2() {
   let data = GameData::decode(&mut bytes.as_slice()).unwrap();
   println!("{:#?}", data);
 }
+
+#[test]
+fn test_game_setting_flags() {
+  let flags = GameSettingFlags::SPEED_NORMAL
+    | GameSettingFlags::TERRAIN_EXPLORED
+    | GameSettingFlags::OBS_REFEREES
+    | GameSettingFlags::TEAMS_FIXED
+    | GameSettingFlags::RANDOM_HERO;
+  let mut game_info = GameInfo::new(1, "flo", "Maps/(2)EchoIsles.w3x", [1; 20], 0x1234).unwrap();
+  game_info.set_game_setting_flags(flags);
+
+  // mDNS
+  let bytes = game_info.encode_to_bytes().unwrap();
+  let decoded = GameInfo::decode_bytes(&bytes).unwrap();
+  assert_eq!(decoded.data.settings.game_setting_flags, flags);
+
+  // UDP
+  let udp = game_info.to_udp_game_info(26).unwrap();
+  let mut buf = udp.encode_to_bytes();
+  let udp = lan::GameInfo::decode(&mut buf).unwrap();
+  assert_eq!(udp.settings.game_setting_flags, flags);
+}
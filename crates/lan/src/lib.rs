@@ -1,11 +1,16 @@
+mod discovery;
 mod game_info;
 mod mdns;
 mod proto {
   include!(concat!(env!("OUT_DIR"), "/wc3.rs"));
 }
+mod udp;
 
 pub mod error;
 
+pub use self::discovery::{LanDiscovery, LanPublisher};
 pub use self::game_info::GameInfo;
 pub use self::mdns::publisher::MdnsPublisher;
 pub use self::mdns::search::{search_lan_games, LanGame};
+pub use self::udp::publisher::UdpPublisher;
+pub use self::udp::search::search_udp_lan_games;
@@ -0,0 +1,38 @@
+use flo_util::binary::{BinEncode, BytesMut};
+use flo_w3gs::lan::LAN_PORT;
+use flo_w3gs::packet::{Packet, PacketPayload};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use tokio::net::UdpSocket;
+
+pub mod publisher;
+pub mod search;
+
+/// Broadcasts are not always looped back, so the local game is also reached directly
+const TARGETS: &[Ipv4Addr] = &[Ipv4Addr::LOCALHOST, Ipv4Addr::BROADCAST];
+
+async fn bind() -> std::io::Result<UdpSocket> {
+  let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)).await?;
+  socket.set_broadcast(true)?;
+  Ok(socket)
+}
+
+fn encode<T>(payload: T) -> Result<BytesMut, flo_w3gs::error::Error>
+where
+  T: PacketPayload + BinEncode + std::fmt::Debug,
+{
+  let mut buf = BytesMut::new();
+  Packet::simple(payload)?.encode(&mut buf);
+  Ok(buf)
+}
+
+/// Send errors are only logged: hosts without a LAN route can still reach the local game
+async fn broadcast(socket: &UdpSocket, bytes: &[u8]) {
+  for ip in TARGETS {
+    if let Err(err) = socket
+      .send_to(bytes, SocketAddrV4::new(*ip, LAN_PORT))
+      .await
+    {
+      tracing::debug!("send to {}: {}", ip, err);
+    }
+  }
+}
@@ -0,0 +1,134 @@
+use crate::error::*;
+use crate::game_info::GameInfo;
+use flo_w3gs::lan::{CreateGame, DecreateGame, RefreshGame, PRODUCT_TFT};
+use futures::future::TryFutureExt;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing_futures::Instrument;
+
+type GameInfoRef = Arc<RwLock<GameInfo>>;
+type UpdateTx = mpsc::Sender<oneshot::Sender<()>>;
+
+/// The game client binds UDP 6112 itself, so instead of answering `SearchGame`
+/// the game info is broadcast periodically.
+const BROADCAST_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Publishes a game using the classic (pre-Reforged) UDP LAN protocol
+#[derive(Debug)]
+pub struct UdpPublisher {
+  update_tx: UpdateTx,
+  game_info: GameInfoRef,
+}
+
+impl UdpPublisher {
+  /// `version` is the minor game version, e.g. 26 for 1.26
+  pub async fn start(game_info: GameInfo, version: u32) -> Result<Self> {
+    // validate before spawning the worker
+    game_info.to_udp_game_info(version)?;
+
+    let socket = super::bind().await?;
+    let game_info = Arc::new(RwLock::new(game_info));
+    let (update_tx, update_rx) = mpsc::channel::<oneshot::Sender<()>>(1);
+
+    tokio::spawn(
+      Self::worker(socket, game_info.clone(), version, update_rx)
+        .map_err(|err| {
+          tracing::error!("worker exited with error: {}", err);
+        })
+        .instrument(tracing::debug_span!("worker")),
+    );
+
+    Ok(Self {
+      update_tx,
+      game_info,
+    })
+  }
+
+  async fn worker(
+    socket: tokio::net::UdpSocket,
+    game_info: GameInfoRef,
+    version: u32,
+    mut update_rx: mpsc::Receiver<oneshot::Sender<()>>,
+  ) -> Result<()> {
+    let encode_game_info = || -> Result<_> {
+      let info = game_info.read().to_udp_game_info(version)?;
+      Ok((info.game_id, info.slots_total, super::encode(info)?))
+    };
+
+    let (game_id, _, data) = encode_game_info()?;
+    let create = super::encode(CreateGame {
+      product: PRODUCT_TFT,
+      version,
+      game_id,
+    })?;
+    super::broadcast(&socket, &create).await;
+    super::broadcast(&socket, &data).await;
+
+    let mut interval = tokio::time::interval(BROADCAST_INTERVAL);
+    // the first tick completes immediately
+    interval.tick().await;
+
+    loop {
+      tokio::select! {
+        update = update_rx.recv() => {
+          tracing::debug!("update");
+          if let Some(ack) = update {
+            let (game_id, slots_total, data) = encode_game_info()?;
+            let players = {
+              let game_info = game_info.read();
+              game_info.players_num as u32
+            };
+            let refresh = super::encode(RefreshGame {
+              game_id,
+              players,
+              slots_total,
+            })?;
+            super::broadcast(&socket, &refresh).await;
+            super::broadcast(&socket, &data).await;
+            ack.send(()).ok();
+          } else {
+            tracing::debug!("update handle dropped");
+            break;
+          }
+        },
+        _ = interval.tick() => {
+          let (_, _, data) = encode_game_info()?;
+          super::broadcast(&socket, &data).await;
+        }
+      }
+    }
+
+    super::broadcast(&socket, &super::encode(DecreateGame { game_id })?).await;
+
+    tracing::debug!("exiting");
+    Ok(())
+  }
+
+  pub async fn update<F>(&mut self, f: F) -> Result<()>
+  where
+    F: FnOnce(&mut GameInfo),
+  {
+    {
+      let mut lock = self.game_info.write();
+      f(&mut lock)
+    }
+    self.refresh().await?;
+    Ok(())
+  }
+
+  pub async fn refresh(&mut self) -> Result<()> {
+    let (ack_tx, ack_rx) = oneshot::channel();
+    self
+      .update_tx
+      .send(ack_tx)
+      .await
+      .map_err(|_| Error::UdpUpdate("worker dead: send".to_string()))?;
+
+    tokio::time::timeout(Duration::from_secs(1), ack_rx)
+      .await
+      .map_err(|_| Error::UdpUpdate("timeout".to_string()))?
+      .map_err(|_| Error::UdpUpdate("worker dead: recv".to_string()))
+  }
+}
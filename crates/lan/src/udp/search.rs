@@ -0,0 +1,135 @@
+use crate::game_info::GameInfo;
+use crate::LanGame;
+use flo_util::binary::BytesMut;
+use flo_w3gs::lan::{self, SearchGame};
+use flo_w3gs::packet::Packet;
+use flo_w3gs::protocol::constants::PacketTypeId;
+use std::collections::BTreeSet;
+use std::net::{SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Searches games using the classic (pre-Reforged) UDP LAN protocol
+///
+/// `version` is the minor game version, e.g. 26 for 1.26
+pub async fn search_udp_lan_games(version: u32, timeout: Duration) -> Vec<LanGame> {
+  let mut records = vec![];
+
+  let socket = match super::bind().await {
+    Ok(socket) => socket,
+    Err(err) => {
+      tracing::error!("bind: {}", err);
+      return records;
+    }
+  };
+
+  match super::encode(SearchGame::new(version)) {
+    Ok(bytes) => super::broadcast(&socket, &bytes).await,
+    Err(err) => {
+      tracing::error!("encode: {}", err);
+      return records;
+    }
+  }
+
+  let mut found = BTreeSet::new();
+  let mut buf = [0_u8; 2048];
+  let timeout = sleep(timeout);
+  tokio::pin!(timeout);
+  loop {
+    tokio::select! {
+      _ = &mut timeout => break,
+      res = socket.recv_from(&mut buf) => {
+        let (len, from) = match res {
+          Ok(v) => v,
+          Err(err) => {
+            tracing::error!("recv: {}", err);
+            break;
+          }
+        };
+        let ip = match from {
+          SocketAddr::V4(addr) => *addr.ip(),
+          SocketAddr::V6(_) => continue,
+        };
+        let info = match decode_game_info(&buf[..len]) {
+          Ok(Some(info)) => info,
+          Ok(None) => continue,
+          Err(err) => {
+            tracing::error!("parse game info from `{}`: {}", from, err);
+            continue;
+          }
+        };
+        let addr = SocketAddrV4::new(ip, info.port);
+        if !found.insert((addr, info.game_id)) {
+          continue;
+        }
+        records.push(LanGame {
+          id: info.game_id,
+          addr,
+          game_info: GameInfo::from_udp_game_info(info),
+        });
+      }
+    }
+  }
+
+  records
+}
+
+fn decode_game_info(bytes: &[u8]) -> Result<Option<lan::GameInfo>, flo_w3gs::error::Error> {
+  let mut buf = BytesMut::from(bytes);
+  let header = Packet::decode_header(&mut buf)?;
+  if header.type_id != PacketTypeId::GameInfo {
+    return Ok(None);
+  }
+  Packet::decode(header, &mut buf)?.decode_simple().map(Some)
+}
+
+#[tokio::test]
+async fn test_decode_game_info() {
+  use flo_util::binary::CString;
+  use flo_w3gs::constants::GameFlags;
+  use flo_w3gs::game::{GameSettings, GameSettingsMap};
+  use std::net::Ipv4Addr;
+  use tokio::net::UdpSocket;
+
+  let info = lan::GameInfo::new(
+    26,
+    1,
+    0xDDDDDDDD,
+    CString::new("flo").unwrap(),
+    GameSettings::new(
+      Default::default(),
+      GameSettingsMap {
+        path: "Maps\\(2)EchoIsles.w3x".to_string(),
+        width: 116,
+        height: 116,
+        sha1: [1; 20],
+        checksum: 0x1234,
+      },
+    ),
+    GameFlags::CUSTOM_GAME,
+    16000,
+  );
+
+  let host = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+  let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+  let client_addr = client.local_addr().unwrap();
+  for bytes in [
+    super::encode(SearchGame::new(26)).unwrap(),
+    super::encode(info.clone()).unwrap(),
+  ] {
+    host.send_to(&bytes, client_addr).await.unwrap();
+  }
+
+  let mut buf = [0_u8; 2048];
+  let (len, _) = client.recv_from(&mut buf).await.unwrap();
+  assert_eq!(decode_game_info(&buf[..len]).unwrap(), None);
+  let (len, _) = client.recv_from(&mut buf).await.unwrap();
+  assert_eq!(decode_game_info(&buf[..len]).unwrap(), Some(info));
+}
+
+/// Searches the real network, run with `--ignored` next to a LAN game
+#[tokio::test]
+#[ignore]
+async fn test_search() {
+  dbg!(search_udp_lan_games(26, Duration::from_secs(5)).await);
+}
@@ -35,3 +35,37 @@ packet_type!(RemoveNode, PacketRemoveNode);
 packet_type!(PlayerMuteListUpdate, PacketPlayerMuteListUpdate);
 packet_type!(PlayerMuteAddRequest, PacketPlayerMuteAddRequest);
 packet_type!(PlayerMuteRemoveRequest, PacketPlayerMuteRemoveRequest);
+packet_type!(MatchmakingJoinRequest, PacketMatchmakingJoinRequest);
+packet_type!(MatchmakingLeaveRequest, PacketMatchmakingLeaveRequest);
+packet_type!(MatchmakingStatus, PacketMatchmakingStatus);
+packet_type!(GameSlotShuffleRequest, PacketGameSlotShuffleRequest);
+packet_type!(GameSlotBalanceRequest, PacketGameSlotBalanceRequest);
+packet_type!(GameSlotSwapRequest, PacketGameSlotSwapRequest);
+packet_type!(GameSlotSwapResponse, PacketGameSlotSwapResponse);
+packet_type!(GameSlotSwapStatus, PacketGameSlotSwapStatus);
+packet_type!(GameSlotLockRequest, PacketGameSlotLockRequest);
+packet_type!(GameSlotLockUpdate, PacketGameSlotLockUpdate);
+packet_type!(GameReadyCheckRequest, PacketGameReadyCheckRequest);
+packet_type!(GameReadyCheck, PacketGameReadyCheck);
+packet_type!(GameReadyCheckResponse, PacketGameReadyCheckResponse);
+packet_type!(GameReadyStatusUpdate, PacketGameReadyStatusUpdate);
+packet_type!(GameHostTransferRequest, PacketGameHostTransferRequest);
+packet_type!(GameHostUpdate, PacketGameHostUpdate);
+packet_type!(GameJoinRequest, PacketGameJoinRequest);
+packet_type!(GameJoinReject, PacketGameJoinReject);
+packet_type!(GameAccessUpdateRequest, PacketGameAccessUpdateRequest);
+packet_type!(GameInviteRequest, PacketGameInviteRequest);
+packet_type!(GameInvite, PacketGameInvite);
+packet_type!(ScheduledGameCheckInRequest, PacketScheduledGameCheckInRequest);
+packet_type!(ScheduledGameStatus, PacketScheduledGameStatus);
+packet_type!(TournamentMatchUpdate, PacketTournamentMatchUpdate);
+packet_type!(TournamentMapPickRequest, PacketTournamentMapPickRequest);
+packet_type!(
+  GameLobbySettingsUpdateRequest,
+  PacketGameLobbySettingsUpdateRequest
+);
+packet_type!(GameLobbySettingsUpdate, PacketGameLobbySettingsUpdate);
+packet_type!(
+  GameNodeSettingsUpdateRequest,
+  PacketGameNodeSettingsUpdateRequest
+);
@@ -33,3 +33,5 @@ packet_type!(
 );
 packet_type!(NodeGameStatusUpdate, PacketNodeGameStatusUpdate);
 packet_type!(NodeGameStatusUpdateBulk, PacketNodeGameStatusUpdateBulk);
+packet_type!(NodeGameDesync, PacketNodeGameDesync);
+packet_type!(NodeGamePlayerResult, PacketNodeGamePlayerResult);
@@ -236,6 +236,38 @@ pub enum PacketTypeId {
   PlayerMuteAddRequest,
   #[bin(value = 0x1F)]
   PlayerMuteRemoveRequest,
+  #[bin(value = 0x20)]
+  MatchmakingJoinRequest,
+  #[bin(value = 0x21)]
+  MatchmakingLeaveRequest,
+  #[bin(value = 0x22)]
+  MatchmakingStatus,
+  #[bin(value = 0x23)]
+  GameSlotShuffleRequest,
+  #[bin(value = 0x24)]
+  GameSlotBalanceRequest,
+  #[bin(value = 0x25)]
+  GameSlotSwapRequest,
+  #[bin(value = 0x26)]
+  GameSlotSwapResponse,
+  #[bin(value = 0x27)]
+  GameSlotSwapStatus,
+  #[bin(value = 0x28)]
+  GameSlotLockRequest,
+  #[bin(value = 0x29)]
+  GameSlotLockUpdate,
+  #[bin(value = 0x2A)]
+  GameReadyCheckRequest,
+  #[bin(value = 0x2B)]
+  GameReadyCheck,
+  #[bin(value = 0x2C)]
+  GameReadyCheckResponse,
+  #[bin(value = 0x2D)]
+  GameReadyStatusUpdate,
+  #[bin(value = 0x2E)]
+  GameHostTransferRequest,
+  #[bin(value = 0x2F)]
+  GameHostUpdate,
 
   // Lobby <-> Node
   #[bin(value = 0x30)]
@@ -282,6 +314,10 @@ pub enum PacketTypeId {
   NodeGameStatusUpdate,
   #[bin(value = 0x51)]
   NodeGameStatusUpdateBulk,
+  #[bin(value = 0x52)]
+  NodeGameDesync,
+  #[bin(value = 0x53)]
+  NodeGamePlayerResult,
 
   // Client <-> Observer
   #[bin(value = 0x60)]
@@ -295,6 +331,32 @@ pub enum PacketTypeId {
   #[bin(value = 0x64)]
   ObserverDataEnd,
 
+  // Client <-> Lobby
+  #[bin(value = 0x70)]
+  GameJoinRequest,
+  #[bin(value = 0x71)]
+  GameJoinReject,
+  #[bin(value = 0x72)]
+  GameAccessUpdateRequest,
+  #[bin(value = 0x73)]
+  GameInviteRequest,
+  #[bin(value = 0x74)]
+  GameInvite,
+  #[bin(value = 0x75)]
+  ScheduledGameCheckInRequest,
+  #[bin(value = 0x76)]
+  ScheduledGameStatus,
+  #[bin(value = 0x77)]
+  TournamentMatchUpdate,
+  #[bin(value = 0x78)]
+  TournamentMapPickRequest,
+  #[bin(value = 0x79)]
+  GameLobbySettingsUpdateRequest,
+  #[bin(value = 0x7A)]
+  GameLobbySettingsUpdate,
+  #[bin(value = 0x7B)]
+  GameNodeSettingsUpdateRequest,
+
   #[bin(value = 0xF7)]
   W3GS,
   UnknownValue(u8),
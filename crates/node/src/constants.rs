@@ -13,9 +13,23 @@ pub static GAME_DEFAULT_STEP_MS: Lazy<u16> = Lazy::new(|| {
     .and_then(|v| v.parse().ok())
     .unwrap_or(30)
 });
+pub static GAME_ADAPTIVE_STEP: Lazy<bool> = Lazy::new(|| {
+  std::env::var("FLO_GAME_ADAPTIVE_STEP")
+    .ok()
+    .map(|v| v == "1" || v == "true")
+    .unwrap_or(false)
+});
+pub const GAME_ADAPTIVE_STEP_INTERVAL: Duration = Duration::from_secs(3);
+pub const GAME_ADAPTIVE_STEP_HYSTERESIS_MS: u16 = 10;
+pub const GAME_ADAPTIVE_STEP_CONFIRM_ROUNDS: u8 = 3;
+pub const GAME_PLAYER_PAUSE_LIMIT: u8 = 3;
+pub const GAME_PLAYER_PAUSE_TIME_BUDGET: Duration = Duration::from_secs(120);
 pub const GAME_PING_INTERVAL: Duration = Duration::from_secs(1);
 pub const GAME_PING_TIMEOUT: Duration = Duration::from_secs(5);
 pub const GAME_CLOCK_MAX_PAUSE: Duration = Duration::from_secs(60 - 3);
+pub const GAME_DESYNC_CHECKSUM_HISTORY: usize = 16;
+pub const GAME_DESYNC_ACTION_HISTORY: usize = 32;
+pub const GAME_DESYNC_VOTE_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[cfg(not(debug_assertions))]
 pub const GAME_DELAY_RANGE: [Duration; 2] = [Duration::from_millis(25), Duration::from_millis(100)];
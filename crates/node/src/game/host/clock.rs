@@ -39,7 +39,7 @@ impl ActionTickStream {
 
   pub fn set_step(&mut self, value: u16) {
     self.step = std::cmp::min(Self::MAX_STEP, std::cmp::max(Self::MIN_STEP, value));
-    self.step_duration = Duration::from_millis(value as u64);
+    self.step_duration = Duration::from_millis(self.step as u64);
     self
       .delay
       .as_mut()
@@ -0,0 +1,234 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Instant;
+use thiserror::Error;
+
+use s2_grpc_utils::S2ProtoEnum;
+
+use super::sync::PlayerDesync;
+
+#[derive(Debug, Copy, Clone, PartialEq, S2ProtoEnum)]
+#[s2_grpc(proto_enum_type(flo_net::proto::flo_node::NodeGameDesyncResolution))]
+#[repr(i32)]
+pub enum DesyncResolution {
+  MinorityDropped = 0,
+  Continued = 1,
+  Aborted = 2,
+}
+
+/// Reported to the controller once a desync has been resolved
+#[derive(Debug, Clone)]
+pub struct DesyncReport {
+  pub tick: u32,
+  pub player_ids: Vec<i32>,
+  pub dropped_player_ids: Vec<i32>,
+  pub resolution: DesyncResolution,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DesyncVoteError {
+  #[error("There is no desync vote in progress.")]
+  NoVote,
+  #[error("Only the desynced players can vote.")]
+  NotParticipant,
+}
+
+/// A desync without a majority checksum group.
+/// The game is paused until every participant agrees to continue,
+/// any of them chooses to abort, or the vote times out.
+#[derive(Debug)]
+pub struct DesyncVote {
+  tick: u32,
+  player_ids: BTreeSet<i32>,
+  /// Players grouped by checksum
+  groups: Vec<BTreeSet<i32>>,
+  participants: BTreeSet<i32>,
+  continue_votes: BTreeSet<i32>,
+  abort: bool,
+  deadline: Instant,
+}
+
+impl DesyncVote {
+  pub fn new(
+    tick: u32,
+    groups: Vec<BTreeSet<i32>>,
+    participants: BTreeSet<i32>,
+    deadline: Instant,
+  ) -> Self {
+    Self {
+      tick,
+      player_ids: groups.iter().flatten().cloned().collect(),
+      groups,
+      participants,
+      continue_votes: BTreeSet::new(),
+      abort: false,
+      deadline,
+    }
+  }
+
+  pub fn vote(&mut self, player_id: i32, continue_game: bool) -> Result<(), DesyncVoteError> {
+    if !self.participants.contains(&player_id) {
+      return Err(DesyncVoteError::NotParticipant);
+    }
+    if continue_game {
+      self.continue_votes.insert(player_id);
+    } else {
+      self.abort = true;
+    }
+    Ok(())
+  }
+
+  pub fn remove_player(&mut self, player_id: i32) {
+    self.participants.remove(&player_id);
+    self.continue_votes.remove(&player_id);
+  }
+
+  /// Returns `(votes, required)` for continuing the game
+  pub fn progress(&self) -> (usize, usize) {
+    (self.continue_votes.len(), self.participants.len())
+  }
+
+  pub fn poll(&self, now: Instant) -> Option<DesyncResolution> {
+    if self.abort || self.participants.is_empty() {
+      return Some(DesyncResolution::Aborted);
+    }
+    if self.continue_votes.len() >= self.participants.len() {
+      return Some(DesyncResolution::Continued);
+    }
+    if now >= self.deadline {
+      return Some(DesyncResolution::Aborted);
+    }
+    None
+  }
+
+  /// The split players agreed to keep playing with
+  pub fn tolerate(&self) -> ToleratedDesync {
+    ToleratedDesync {
+      groups: self.groups.clone(),
+    }
+  }
+
+  pub fn into_report(self, resolution: DesyncResolution) -> DesyncReport {
+    DesyncReport {
+      tick: self.tick,
+      player_ids: self.player_ids.into_iter().collect(),
+      dropped_player_ids: vec![],
+      resolution,
+    }
+  }
+}
+
+/// Checksums keep diverging after players chose to continue a tied desync.
+/// Later desyncs are only ignored if they repeat the same split.
+#[derive(Debug)]
+pub struct ToleratedDesync {
+  groups: Vec<BTreeSet<i32>>,
+}
+
+impl ToleratedDesync {
+  /// Every checksum group of the desync stays within one tolerated group,
+  /// and no tolerated group split up
+  pub fn is_continuation(&self, desync: &[PlayerDesync]) -> bool {
+    let mut checksum_groups: BTreeMap<(u32, u32), BTreeSet<i32>> = BTreeMap::new();
+    for item in desync {
+      checksum_groups
+        .entry((item.tick, item.checksum))
+        .or_default()
+        .insert(item.player_id);
+    }
+    let mut matched = BTreeSet::new();
+    for ((tick, _), players) in checksum_groups {
+      let index = match self.groups.iter().position(|g| players.is_subset(g)) {
+        Some(index) => index,
+        None => return false,
+      };
+      if !matched.insert((tick, index)) {
+        return false;
+      }
+    }
+    true
+  }
+}
+
+#[test]
+fn test_desync_vote() {
+  use std::time::Duration;
+
+  let now = Instant::now();
+  let deadline = now + Duration::from_secs(60);
+  let new_vote = || {
+    DesyncVote::new(
+      1,
+      vec![
+        vec![1, 3].into_iter().collect(),
+        vec![2].into_iter().collect(),
+      ],
+      vec![1, 2].into_iter().collect(),
+      deadline,
+    )
+  };
+
+  let mut v = new_vote();
+  assert_eq!(v.vote(3, true), Err(DesyncVoteError::NotParticipant));
+  v.vote(1, true).unwrap();
+  assert_eq!(v.progress(), (1, 2));
+  assert_eq!(v.poll(now), None);
+  assert_eq!(v.poll(deadline), Some(DesyncResolution::Aborted));
+  v.vote(2, true).unwrap();
+  assert_eq!(v.poll(now), Some(DesyncResolution::Continued));
+
+  let mut v = new_vote();
+  v.vote(1, true).unwrap();
+  v.remove_player(2);
+  assert_eq!(v.poll(now), Some(DesyncResolution::Continued));
+
+  let mut v = new_vote();
+  v.vote(1, true).unwrap();
+  v.vote(2, false).unwrap();
+  assert_eq!(v.poll(now), Some(DesyncResolution::Aborted));
+  let report = v.into_report(DesyncResolution::Aborted);
+  assert_eq!(report.player_ids, vec![1, 2, 3]);
+}
+
+#[test]
+fn test_tolerated_desync() {
+  let desync = |tick: u32, groups: &[(u32, &[i32])]| -> Vec<PlayerDesync> {
+    groups
+      .iter()
+      .flat_map(|(checksum, player_ids)| {
+        player_ids.iter().map(move |player_id| PlayerDesync {
+          player_id: *player_id,
+          tick,
+          time: 0,
+          checksum: *checksum,
+          tied: false,
+        })
+      })
+      .collect()
+  };
+
+  let vote = DesyncVote::new(
+    10,
+    vec![
+      vec![1, 2].into_iter().collect(),
+      vec![3, 4].into_iter().collect(),
+    ],
+    vec![1, 2, 3, 4].into_iter().collect(),
+    Instant::now(),
+  );
+  let tolerated = vote.tolerate();
+
+  // same split, different checksums
+  assert!(tolerated.is_continuation(&desync(11, &[(5, &[1, 2]), (6, &[3, 4])])));
+  // a player left, the other side is reported as the minority
+  assert!(tolerated.is_continuation(&desync(12, &[(7, &[3])])));
+  // several ticks reported at once
+  let mut items = desync(13, &[(1, &[1, 2]), (2, &[3, 4])]);
+  items.extend(desync(14, &[(3, &[1, 2]), (4, &[3, 4])]));
+  assert!(tolerated.is_continuation(&items));
+
+  // a tolerated group split up
+  assert!(!tolerated.is_continuation(&desync(11, &[(1, &[1]), (2, &[2])])));
+  assert!(!tolerated.is_continuation(&desync(11, &[(1, &[1, 2]), (2, &[3]), (3, &[4])])));
+  // players of different groups agree with each other but not with the rest
+  assert!(!tolerated.is_continuation(&desync(11, &[(1, &[2, 3])])));
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use flo_net::proto::flo_observer::{
+  DesyncAction, DesyncActionBatch, DesyncChecksum, DesyncDiagnostics, DesyncPlayer, Map,
+};
+use flo_w3gs::protocol::action::PlayerAction;
+
+use super::settings::HostSettings;
+use super::sync::SyncMap;
+use crate::constants::GAME_DESYNC_ACTION_HISTORY;
+
+/// Keeps the recent game state needed to explain a desync
+/// and builds the diagnostic bundle pushed to the observer stream.
+#[derive(Debug)]
+pub struct DesyncRecorder {
+  map: Map,
+  game_version: String,
+  /// Versions reported by the players, keyed by player id
+  player_game_versions: BTreeMap<i32, String>,
+  batches: VecDeque<ActionBatch>,
+}
+
+#[derive(Debug)]
+struct ActionBatch {
+  tick: u32,
+  time_increment_ms: u16,
+  actions: Vec<PlayerAction>,
+}
+
+impl DesyncRecorder {
+  pub fn new(settings: &HostSettings, player_game_versions: BTreeMap<i32, String>) -> Self {
+    Self {
+      map: Map {
+        sha1: settings.map_sha1.clone(),
+        checksum: settings.map_checksum,
+        path: settings.map_path.clone(),
+      },
+      game_version: settings.game_version.clone(),
+      player_game_versions,
+      batches: VecDeque::with_capacity(GAME_DESYNC_ACTION_HISTORY),
+    }
+  }
+
+  pub fn push_actions(&mut self, tick: u32, time_increment_ms: u16, actions: &[PlayerAction]) {
+    if self.batches.len() == GAME_DESYNC_ACTION_HISTORY {
+      self.batches.pop_front();
+    }
+    self.batches.push_back(ActionBatch {
+      tick,
+      time_increment_ms,
+      actions: actions.to_vec(),
+    });
+  }
+
+  /// `players` yields `(player_id, slot_player_id)` of every player still in the game.
+  pub fn build(
+    &self,
+    tick: u32,
+    time: u32,
+    sync: &SyncMap,
+    players: impl Iterator<Item = (i32, u8)>,
+    desynced: &BTreeSet<i32>,
+  ) -> DesyncDiagnostics {
+    DesyncDiagnostics {
+      tick,
+      time,
+      map: Some(self.map.clone()),
+      game_version: self.game_version.clone(),
+      players: players
+        .map(|(player_id, slot_player_id)| DesyncPlayer {
+          player_id,
+          slot_player_id: slot_player_id as u32,
+          desynced: desynced.contains(&player_id),
+          checksums: sync
+            .checksum_history(player_id)
+            .map(|history| {
+              history
+                .map(|(tick, checksum)| DesyncChecksum { tick, checksum })
+                .collect()
+            })
+            .unwrap_or_default(),
+          game_version: self
+            .player_game_versions
+            .get(&player_id)
+            .filter(|v| !v.is_empty())
+            .unwrap_or(&self.game_version)
+            .clone(),
+        })
+        .collect(),
+      action_batches: self
+        .batches
+        .iter()
+        .map(|batch| DesyncActionBatch {
+          tick: batch.tick,
+          time_increment_ms: batch.time_increment_ms as u32,
+          actions: batch
+            .actions
+            .iter()
+            .map(|action| DesyncAction {
+              slot_player_id: action.player_id as u32,
+              data: action.data.to_vec(),
+            })
+            .collect(),
+        })
+        .collect(),
+    }
+  }
+}
+
+#[test]
+fn test_desync_recorder() {
+  use bytes::Bytes;
+
+  let mut r = DesyncRecorder::new(
+    &HostSettings {
+      map_sha1: vec![1, 2, 3],
+      game_version: "1.32.10".to_string(),
+      ..Default::default()
+    },
+    vec![(2, "1.32.9".to_string())].into_iter().collect(),
+  );
+  let mut sync = SyncMap::new(
+    vec![1, 2],
+    crate::constants::GAME_PLAYER_LAGGING_THRESHOLD_MS,
+  );
+
+  for tick in 1..=(GAME_DESYNC_ACTION_HISTORY as u32 + 10) {
+    let _ = sync.clock(30);
+    r.push_actions(
+      tick,
+      30,
+      &[PlayerAction {
+        player_id: 1,
+        data: Bytes::from(tick.to_le_bytes().to_vec()),
+      }],
+    );
+    sync.ack(1, tick).unwrap();
+    sync.ack(2, if tick > 40 { 0 } else { tick }).unwrap();
+  }
+
+  let desynced = vec![2].into_iter().collect();
+  let d = r.build(
+    42,
+    42 * 30,
+    &sync,
+    vec![(1, 1), (2, 2)].into_iter(),
+    &desynced,
+  );
+  assert_eq!(d.map.unwrap().sha1, vec![1, 2, 3]);
+  assert_eq!(d.game_version, "1.32.10");
+  assert_eq!(d.action_batches.len(), GAME_DESYNC_ACTION_HISTORY);
+  assert_eq!(d.action_batches[0].tick, 11);
+  assert_eq!(
+    d.action_batches.last().unwrap().actions[0].data,
+    42u32.to_le_bytes()
+  );
+  assert!(!d.players[0].desynced);
+  assert!(d.players[1].desynced);
+  assert_eq!(d.players[0].game_version, "1.32.10");
+  assert_eq!(d.players[1].game_version, "1.32.9");
+  let last = d.players[1].checksums.last().unwrap();
+  assert_eq!((last.tick, last.checksum), (42, 0));
+  assert_eq!(
+    d.players[0].checksums.len(),
+    crate::constants::GAME_DESYNC_CHECKSUM_HISTORY
+  );
+}
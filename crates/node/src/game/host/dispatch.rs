@@ -1,7 +1,12 @@
 use super::broadcast;
 use super::clock::ActionTickStream;
 use super::delay::{DelayedFrame, DelayedFrameStream};
+use super::desync::{DesyncReport, DesyncResolution, DesyncVote, DesyncVoteError, ToleratedDesync};
+use super::diagnostics::DesyncRecorder;
 use super::player::{PlayerDispatchInfo, PlayerSendError};
+use super::referee::{self, PauseRequest, RefereeState, Vote};
+use super::settings::{HostSettings, LagConfig};
+use super::step::StepController;
 use super::sync::SyncMap;
 use crate::error::*;
 use crate::game::host::clock::Tick;
@@ -100,6 +105,7 @@ impl Dispatcher {
   pub fn new(
     game_id: i32,
     slots: &[PlayerSlot],
+    settings: HostSettings,
     obs: ObserverPublisherHandle,
     out_tx: GameEventSender,
   ) -> Self {
@@ -112,6 +118,7 @@ impl Dispatcher {
     let state = State::new(
       game_id,
       slots,
+      &settings,
       obs.clone(),
       status_rx,
       action_tx.clone(),
@@ -122,7 +129,7 @@ impl Dispatcher {
     let mut chat_banned_player_names = vec![];
     if !state.chat_banned_player_ids.is_empty() {
       for p in &state.chat_banned_player_ids {
-        chat_banned_player_names.push(state._player_name_lookup.get(&p).cloned())
+        chat_banned_player_names.push(state.player_name_lookup.get(&p).cloned())
       }
       start_messages.push(format!("Some players in this game have been muted: {}", chat_banned_player_names.join(", ")));
     }
@@ -192,12 +199,26 @@ impl Dispatcher {
     ct: CancellationToken,
   ) {
     let (peer_tx, mut peer_rx) = channel::<PeerMsg>(crate::constants::GAME_DISPATCH_BUF_SIZE);
+    let mut desync_vote_interval = interval_at(
+      tokio::time::Instant::now() + Duration::from_secs(1),
+      Duration::from_secs(1),
+    );
+    desync_vote_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
     loop {
       tokio::select! {
         _ = ct.cancelled() => {
           break;
         }
+        _ = desync_vote_interval.tick() => {
+          match state.poll_desync_vote(&mut action_tx, &mut out_tx).await {
+            Ok(_) => {},
+            Err(Error::Cancelled) => {},
+            Err(err) => {
+              tracing::error!("poll desync vote: {}", err);
+            },
+          }
+        }
         Some(msg) = peer_rx.recv() => {
           let player_id = msg.player_id();
           match state.dispatch_peer(msg, &mut action_tx, &mut out_tx).await {
@@ -251,9 +272,27 @@ impl Dispatcher {
         }
       }
 
-      let mut tick_stream = ActionTickStream::new(*crate::constants::GAME_DEFAULT_STEP_MS);
+      let (mut tick_stream, auto_drop) = {
+        let shared = shared.lock();
+        (
+          ActionTickStream::new(shared.step.config().initial),
+          shared.lag.auto_drop,
+        )
+      };
       let pause_timeout = sleep(Duration::from_secs(0));
       tokio::pin!(pause_timeout);
+      let mut step_interval = interval_at(
+        tokio::time::Instant::now() + crate::constants::GAME_ADAPTIVE_STEP_INTERVAL,
+        crate::constants::GAME_ADAPTIVE_STEP_INTERVAL,
+      );
+      step_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+      let mut pause_check_interval = interval_at(
+        tokio::time::Instant::now() + Duration::from_secs(1),
+        Duration::from_secs(1),
+      );
+      pause_check_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+      // stops the game for a desync vote without using a player's pause
+      let mut clock_held = false;
 
       {
         let ct = ct.clone();
@@ -291,9 +330,9 @@ impl Dispatcher {
               }
               ActionMsg::SetStep(step) => {
                 tick_stream.set_step(step);
-                shared
-                  .lock()
-                  .broadcast_message(format!("Game step has been set to {}ms.", tick_stream.step()));
+                let mut shared = shared.lock();
+                shared.step.disable();
+                shared.broadcast_message(format!("Game step has been set to {}ms.", tick_stream.step()));
               },
               ActionMsg::CheckStopLag => {
                 if tick_stream.is_paused() {
@@ -321,14 +360,37 @@ impl Dispatcher {
                 tick_stream.resume();
                 status_tx.send(DispatchStatus::Running).ok();
               }
+              ActionMsg::HoldClock => {
+                tracing::info!(
+                  game_id,
+                  "hold clock"
+                );
+                clock_held = true;
+              }
+              ActionMsg::ReleaseClock => {
+                tracing::info!(
+                  game_id,
+                  "release clock"
+                );
+                clock_held = false;
+              }
+              ActionMsg::ExtendLag => {
+                if tick_stream.is_paused() {
+                  pause_timeout.as_mut().reset((Instant::now() + auto_drop).into());
+                  shared.lock().broadcast_message(format!(
+                    "Lag screen has been extended by {}s.",
+                    auto_drop.as_secs()
+                  ));
+                }
+              }
             }
           }
-          Some(tick) = tick_stream.next() => {
+          Some(tick) = tick_stream.next(), if !clock_held => {
             match shared.lock().dispatch_action_tick(tick) {
               Ok(DispatchResult::Continue) => {},
               Ok(DispatchResult::Lag(tick)) => {
                 tick_stream.replace_actions(tick.actions);
-                pause_timeout.as_mut().reset((Instant::now() + crate::constants::GAME_CLOCK_MAX_PAUSE).into());
+                pause_timeout.as_mut().reset((Instant::now() + auto_drop).into());
                 tick_stream.pause();
                 status_tx.send(DispatchStatus::Paused).ok();
               }
@@ -341,6 +403,38 @@ impl Dispatcher {
               }
             }
           }
+          _ = pause_check_interval.tick() => {
+            let mut shared = shared.lock();
+            if let Some(action) = shared.take_expired_pause() {
+              tick_stream.add_action(action);
+            }
+            if tick_stream.is_paused() {
+              match shared.drop_lag_budget_exceeded_players() {
+                Ok(true) => {
+                  tick_stream.resume();
+                  status_tx.send(DispatchStatus::Running).ok();
+                }
+                Ok(false) => {}
+                Err(err) => {
+                  tracing::error!(
+                    game_id,
+                    "drop lag budget exceeded players: {}", err
+                  );
+                  break;
+                }
+              }
+            }
+          }
+          _ = step_interval.tick(), if !tick_stream.is_paused() => {
+            let current = tick_stream.step();
+            if let Some(step) = shared.lock().step.evaluate(current) {
+              tick_stream.set_step(step);
+              tracing::debug!(
+                game_id,
+                "adaptive step: {}ms -> {}ms", current, tick_stream.step()
+              );
+            }
+          }
           _ = &mut pause_timeout, if tick_stream.is_paused() => {
             if let Err(err) = shared.lock().drop_all_lag_players() {
               tracing::error!(
@@ -363,6 +457,9 @@ enum ActionMsg {
   SetStep(u16),
   CheckStopLag,
   ResumeClock,
+  HoldClock,
+  ReleaseClock,
+  ExtendLag,
 }
 
 #[derive(Debug)]
@@ -372,7 +469,7 @@ struct State {
   shared: Arc<Mutex<Shared>>,
   status_rx: watch::Receiver<DispatchStatus>,
   game_player_id_lookup: BTreeMap<u8, i32>,
-  _player_name_lookup: BTreeMap<i32, String>,
+  player_name_lookup: BTreeMap<i32, String>,
   chat_banned_player_ids: Vec<i32>,
   left_players: BTreeSet<i32>,
 }
@@ -381,6 +478,7 @@ impl State {
   fn new(
     game_id: i32,
     slots: &[PlayerSlot],
+    settings: &HostSettings,
     obs: ObserverPublisherHandle,
     status_rx: watch::Receiver<DispatchStatus>,
     _action_tx: Sender<ActionMsg>,
@@ -389,13 +487,13 @@ impl State {
     State {
       game_id,
       ct,
-      shared: Arc::new(Mutex::new(Shared::new(game_id, slots, obs))),
+      shared: Arc::new(Mutex::new(Shared::new(game_id, slots, settings, obs))),
       status_rx,
       game_player_id_lookup: slots
         .into_iter()
         .map(|slot| ((slot.id + 1) as u8, slot.player.player_id))
         .collect(),
-      _player_name_lookup: slots
+      player_name_lookup: slots
         .into_iter()
         .map(|slot| (slot.player.player_id, slot.player.name.clone()))
         .collect(),
@@ -648,6 +746,7 @@ impl State {
   fn handle_pong(&mut self, player_id: i32, rtt: u32) {
     let mut shared = self.shared.lock();
     shared.get_player(player_id).map(|info| info.push_rtt(rtt));
+    shared.step.push_rtt(player_id, rtt);
   }
 
   async fn dispatch_incoming_w3gs(
@@ -656,7 +755,7 @@ impl State {
     meta: W3GSMetadata,
     packet: Packet,
     action_tx: &mut Sender<ActionMsg>,
-    _out_tx: &mut GameEventSender,
+    out_tx: &mut GameEventSender,
   ) -> Result<()> {
     use flo_w3gs::protocol::constants::PacketTypeId;
 
@@ -681,13 +780,38 @@ impl State {
     match packet.type_id() {
       PacketTypeId::OutgoingAction => {
         let payload: OutgoingAction = packet.decode_payload()?;
-        action_tx
-          .send(ActionMsg::PlayerAction(PlayerAction {
+        let split = match referee::split_pause_actions(&payload.data) {
+          Ok(split) => split,
+          Err(err) => {
+            tracing::warn!(game_id = self.game_id, player_id, "action dropped: {}", err);
+            return Ok(());
+          }
+        };
+        let mut actions = Vec::with_capacity(1);
+        if let Some((request, rest)) = split {
+          if !rest.is_empty() {
+            actions.push(PlayerAction {
+              player_id: slot_player_id,
+              data: rest,
+            });
+          }
+          let action = match request {
+            PauseRequest::Pause => self.shared.lock().pause_game(player_id),
+            PauseRequest::Resume => self.shared.lock().resume_game(player_id),
+          };
+          actions.extend(action);
+        } else {
+          actions.push(PlayerAction {
             player_id: slot_player_id,
             data: payload.data,
-          }))
-          .await
-          .map_err(|_| Error::Cancelled)?;
+          });
+        }
+        for action in actions {
+          action_tx
+            .send(ActionMsg::PlayerAction(action))
+            .await
+            .map_err(|_| Error::Cancelled)?;
+        }
       }
       PacketTypeId::DropReq => {
         tracing::info!(game_id = self.game_id, player_id, "drop request");
@@ -703,7 +827,9 @@ impl State {
         }
       }
       PacketTypeId::ChatToHost => {
-        self.dispatch_chat(player_id, packet, action_tx).await?;
+        self
+          .dispatch_chat(player_id, packet, action_tx, out_tx)
+          .await?;
       }
       PacketTypeId::OutgoingKeepAlive => {
         let payload: OutgoingKeepAlive = packet.decode_simple()?;
@@ -717,6 +843,11 @@ impl State {
               .await
               .map_err(|_| Error::Cancelled)?;
           }
+          Ok(AckAction::Desync { hold_clock, report }) => {
+            self
+              .handle_desync_action(hold_clock, report, action_tx, out_tx)
+              .await?;
+          }
           Err(err) => {
             tracing::error!(
               game_id = self.game_id,
@@ -769,6 +900,13 @@ impl State {
       ))
       .await
       .map_err(|_| Error::Cancelled)?;
+
+    if let Some(reason) = reason {
+      out_tx
+        .send(GameEvent::PlayerResult(player_id, reason))
+        .await
+        .map_err(|_| Error::Cancelled)?;
+    }
     Ok(())
   }
 
@@ -801,12 +939,16 @@ impl State {
     player_id: i32,
     mut packet: Packet,
     action_tx: &mut Sender<ActionMsg>,
+    out_tx: &mut GameEventSender,
   ) -> Result<()> {
     use flo_w3gs::protocol::constants::PacketTypeId;
 
     let chat: ChatToHost = packet.decode_simple()?;
     if let Some(cmd) = chat.chat_message().and_then(parse_chat_command) {
-      if self.handle_command(action_tx, player_id, cmd).await? {
+      if self
+        .handle_command(action_tx, out_tx, player_id, cmd)
+        .await?
+      {
         return Ok(());
       }
     }
@@ -843,14 +985,269 @@ impl State {
     Ok(())
   }
 
+  async fn handle_desync_action(
+    &mut self,
+    hold_clock: bool,
+    report: Option<DesyncReport>,
+    action_tx: &mut Sender<ActionMsg>,
+    out_tx: &mut GameEventSender,
+  ) -> Result<()> {
+    if hold_clock {
+      action_tx
+        .send(ActionMsg::HoldClock)
+        .await
+        .map_err(|_| Error::Cancelled)?;
+    }
+    action_tx
+      .send(ActionMsg::CheckStopLag)
+      .await
+      .map_err(|_| Error::Cancelled)?;
+    if let Some(report) = report {
+      out_tx
+        .send(GameEvent::Desync(report))
+        .await
+        .map_err(|_| Error::Cancelled)?;
+    }
+    Ok(())
+  }
+
+  async fn poll_desync_vote(
+    &mut self,
+    action_tx: &mut Sender<ActionMsg>,
+    out_tx: &mut GameEventSender,
+  ) -> Result<()> {
+    // desyncs detected while removing players
+    let action = self.shared.lock().take_pending_desync()?;
+    if let Some(AckAction::Desync { hold_clock, report }) = action {
+      self
+        .handle_desync_action(hold_clock, report, action_tx, out_tx)
+        .await?;
+    }
+
+    let resolved = self.shared.lock().take_resolved_desync_vote(Instant::now());
+    let resolved = if let Some(v) = resolved {
+      v
+    } else {
+      return Ok(());
+    };
+
+    if resolved.release_clock {
+      action_tx
+        .send(ActionMsg::ReleaseClock)
+        .await
+        .map_err(|_| Error::Cancelled)?;
+    }
+
+    for player_id in resolved.remove_player_ids {
+      if !self.left_players.contains(&player_id) {
+        self
+          .handle_player_leave(
+            player_id,
+            Some(LeaveReason::LeaveDisconnect),
+            action_tx,
+            out_tx,
+          )
+          .await?;
+      }
+    }
+
+    out_tx
+      .send(GameEvent::Desync(resolved.report))
+      .await
+      .map_err(|_| Error::Cancelled)?;
+    Ok(())
+  }
+
+  fn find_player_id(&self, name_or_slot: &str) -> Option<i32> {
+    if let Ok(slot) = name_or_slot.parse::<u8>() {
+      return self.game_player_id_lookup.get(&slot).cloned();
+    }
+    self
+      .player_name_lookup
+      .iter()
+      .find(|(_, name)| name.eq_ignore_ascii_case(name_or_slot))
+      .map(|(id, _)| *id)
+  }
+
   async fn handle_command(
-    &self,
+    &mut self,
     action_tx: &mut Sender<ActionMsg>,
+    out_tx: &mut GameEventSender,
     player_id: i32,
     cmd: ChatCommand<'_>,
   ) -> Result<bool> {
     let debug = cfg!(debug_assertions);
+    let privileged = self.shared.lock().referee.is_privileged(player_id);
     match cmd.name() {
+      "kick" | "dropall" | "extendlag" if !privileged => {
+        self.shared.lock().private_message(
+          player_id,
+          "Only the host or a referee can use this command.",
+        );
+      }
+      "continue" | "abort" => {
+        {
+          let mut guard = self.shared.lock();
+          guard.record_command(player_id, cmd.raw());
+          guard.vote_desync(player_id, cmd.name() == "continue");
+        }
+        self.poll_desync_vote(action_tx, out_tx).await?;
+      }
+      "pause" => {
+        let action = {
+          let mut guard = self.shared.lock();
+          let action = guard.pause_game(player_id);
+          if action.is_some() {
+            guard.record_command(player_id, cmd.raw());
+          }
+          action
+        };
+        if let Some(action) = action {
+          action_tx.send(ActionMsg::PlayerAction(action)).await.ok();
+        }
+      }
+      "unpause" | "resume" => {
+        let action = {
+          let mut guard = self.shared.lock();
+          let action = guard.resume_game(player_id);
+          if action.is_some() {
+            guard.record_command(player_id, cmd.raw());
+          }
+          action
+        };
+        if let Some(action) = action {
+          action_tx.send(ActionMsg::PlayerAction(action)).await.ok();
+        }
+      }
+      "kick" => {
+        let target = cmd
+          .parse_arguments::<(String,)>()
+          .ok()
+          .and_then(|(name,)| self.find_player_id(&name))
+          .filter(|id| *id != player_id && !self.left_players.contains(id));
+        if let Some(target) = target {
+          {
+            let mut guard = self.shared.lock();
+            guard.record_command(player_id, cmd.raw());
+            if let Some(name) = self.player_name_lookup.get(&target) {
+              guard.broadcast_message(format!("{} has been kicked.", name));
+            }
+          }
+          // reported as a disconnect, the kicked player did not lose the game
+          self
+            .handle_player_leave(
+              target,
+              Some(LeaveReason::LeaveDisconnect),
+              action_tx,
+              out_tx,
+            )
+            .await?;
+        } else {
+          self.shared.lock().private_message(
+            player_id,
+            "Invalid syntax, usage: !kick <player name or slot number>",
+          );
+        }
+      }
+      "dropall" => {
+        let dropped = {
+          let mut guard = self.shared.lock();
+          if guard.lagging_player_ids.is_empty() {
+            guard.private_message(player_id, "No lagging player.");
+            false
+          } else {
+            guard.record_command(player_id, cmd.raw());
+            guard.drop_all_lag_players()?;
+            true
+          }
+        };
+        if dropped {
+          action_tx.send(ActionMsg::ResumeClock).await.ok();
+        }
+      }
+      "extendlag" => {
+        self.shared.lock().record_command(player_id, cmd.raw());
+        action_tx.send(ActionMsg::ExtendLag).await.ok();
+      }
+      "remake" => {
+        let res = self.shared.lock().referee.vote_remake(player_id);
+        match res {
+          Ok(Vote::Voting { votes, required }) => {
+            let mut guard = self.shared.lock();
+            guard.record_command(player_id, cmd.raw());
+            guard.broadcast_message(format!(
+              "Remake vote: {}/{}, type !remake to vote.",
+              votes, required
+            ));
+          }
+          Ok(Vote::Passed(player_ids)) => {
+            {
+              let mut guard = self.shared.lock();
+              guard.record_command(player_id, cmd.raw());
+              guard.broadcast_message("Remake vote passed, ending the game.");
+            }
+            for id in player_ids {
+              if !self.left_players.contains(&id) {
+                // reported ahead of the leave and the game end status
+                out_tx
+                  .send(GameEvent::PlayerAborted(id))
+                  .await
+                  .map_err(|_| Error::Cancelled)?;
+                self
+                  .handle_player_leave(id, None, action_tx, out_tx)
+                  .await?;
+              }
+            }
+          }
+          Err(err) => {
+            self
+              .shared
+              .lock()
+              .private_message(player_id, err.to_string());
+          }
+        }
+      }
+      "ff" => {
+        let res = self.shared.lock().referee.vote_ff(player_id);
+        match res {
+          Ok(Vote::Voting { votes, required }) => {
+            let mut guard = self.shared.lock();
+            guard.record_command(player_id, cmd.raw());
+            let name = guard
+              .get_player(player_id)
+              .map(|p| p.player_name().to_string())
+              .unwrap_or_default();
+            guard.broadcast_message(format!(
+              "{} wants to surrender: {}/{}, teammates type !ff to agree.",
+              name, votes, required
+            ));
+          }
+          Ok(Vote::Passed(player_ids)) => {
+            {
+              let mut guard = self.shared.lock();
+              guard.record_command(player_id, cmd.raw());
+              let names: Vec<_> = player_ids
+                .iter()
+                .filter_map(|id| self.player_name_lookup.get(id).cloned())
+                .collect();
+              guard.broadcast_message(format!("{} surrendered.", names.join(", ")));
+            }
+            for id in player_ids {
+              if !self.left_players.contains(&id) {
+                self
+                  .handle_player_leave(id, Some(LeaveReason::LeaveLost), action_tx, out_tx)
+                  .await?;
+              }
+            }
+          }
+          Err(err) => {
+            self
+              .shared
+              .lock()
+              .private_message(player_id, err.to_string());
+          }
+        }
+      }
       "drop" if debug => {
         let shared = self.shared.clone();
         tokio::spawn(async move {
@@ -1034,12 +1431,30 @@ struct Shared {
   sync: SyncMap,
   lagging_player_ids: BTreeSet<i32>,
   drop_votes: BTreeSet<i32>,
+  step: StepController,
+  referee: RefereeState,
+  lag: LagConfig,
+  lag_started_at: Option<Instant>,
+  desync: DesyncRecorder,
+  desync_vote: Option<DesyncVote>,
+  /// Desyncs detected while removing players, resolved by the next vote poll
+  pending_desync: Vec<PlayerDesync>,
+  /// The split players chose to keep playing with after a tied desync
+  desync_tolerated: Option<ToleratedDesync>,
   obs: ObserverPublisherHandle,
 }
 
 impl Shared {
-  fn new(game_id: i32, slots: &[PlayerSlot], obs: ObserverPublisherHandle) -> Self {
-    let sync = SyncMap::new(slots.iter().map(|s| s.player.player_id).collect());
+  fn new(
+    game_id: i32,
+    slots: &[PlayerSlot],
+    settings: &HostSettings,
+    obs: ObserverPublisherHandle,
+  ) -> Self {
+    let sync = SyncMap::new(
+      slots.iter().map(|s| s.player.player_id).collect(),
+      settings.lag.lagging_threshold_ms,
+    );
     let mut slot_id_lookup = BTreeMap::new();
     Self {
       game_id,
@@ -1056,6 +1471,20 @@ impl Shared {
       sync,
       lagging_player_ids: BTreeSet::new(),
       drop_votes: BTreeSet::new(),
+      step: StepController::new(settings.step),
+      referee: RefereeState::from_slots(slots),
+      lag: settings.lag.clone(),
+      lag_started_at: None,
+      desync: DesyncRecorder::new(
+        settings,
+        slots
+          .iter()
+          .map(|slot| (slot.player.player_id, slot.player.game_version.clone()))
+          .collect(),
+      ),
+      desync_vote: None,
+      pending_desync: vec![],
+      desync_tolerated: None,
       obs,
     }
   }
@@ -1078,6 +1507,10 @@ impl Shared {
       }
     }
 
+    self
+      .desync
+      .push_actions(self.sync.tick(), time_increment_ms, &tick.actions);
+
     if tick.actions_bytes_len > DISPATCH_ACTIONS_MTU {
       tracing::debug!(
         "over-sized actions: tick = {}, size = {}, len = {}",
@@ -1155,6 +1588,7 @@ impl Shared {
   }
 
   fn handle_lag(&mut self, add_player_ids: Vec<i32>) -> Result<bool> {
+    self.lag_started_at.get_or_insert_with(Instant::now);
     self.lagging_player_ids.extend(add_player_ids);
     self.obs.push_start_lag(
       self.game_id,
@@ -1257,7 +1691,12 @@ impl Shared {
     }
 
     // tracing::debug!("remaining lag players: {:?}", self.lagging_player_ids);
-    Ok(self.lagging_player_ids.is_empty())
+    if self.lagging_player_ids.is_empty() {
+      self.lag_started_at.take();
+      Ok(true)
+    } else {
+      Ok(false)
+    }
   }
 
   fn refresh_lag_packet(&mut self) -> Result<Option<Vec<(i32, u8, u32)>>> {
@@ -1324,6 +1763,12 @@ impl Shared {
 
     tracing::info!(game_id = self.game_id, player_id, "remove player");
 
+    self.step.remove_player(player_id);
+    self.referee.remove_player(player_id);
+    if let Some(vote) = self.desync_vote.as_mut() {
+      vote.remove_player(player_id);
+    }
+
     for p in self.map.values_mut() {
       p.remove_lag_slot(player.slot_player_id());
     }
@@ -1340,7 +1785,8 @@ impl Shared {
         "desync detected after disconnecting player: {:?}",
         desync
       );
-      tracing::warn!("{}", self.sync.debug_pending())
+      tracing::warn!("{}", self.sync.debug_pending());
+      self.pending_desync.extend(desync);
     }
     player.close_stream();
     Ok(())
@@ -1418,13 +1864,35 @@ impl Shared {
   }
 
   pub fn request_drop(&mut self, player_id: i32) -> Result<RequestDropResult> {
-    let lagging = self.lagging_player_ids.len();
-
-    if lagging == 0 {
+    if self.lagging_player_ids.is_empty() {
       return Ok(RequestDropResult::NoLaggingPlayer);
     }
 
-    let vote_required = (self.map.len().saturating_sub(lagging) as f32 / 2.0).ceil() as usize;
+    if let Some(elapsed) = self.lag_started_at.map(|v| v.elapsed()) {
+      if elapsed < self.lag.drop_min_wait {
+        let wait = self.lag.drop_min_wait - elapsed;
+        self.private_message(
+          player_id,
+          format!("You can drop lagging players in {}s.", wait.as_secs() + 1),
+        );
+        return Ok(RequestDropResult::Voting);
+      }
+    }
+
+    let voters: Vec<i32> = self
+      .map
+      .keys()
+      .filter(|id| self.is_drop_voter(**id))
+      .cloned()
+      .collect();
+    if !voters.contains(&player_id) {
+      if self.lag.exclude_teammate_votes {
+        self.private_message(player_id, "Teammates of lagging players can not vote.");
+      }
+      return Ok(RequestDropResult::Voting);
+    }
+
+    let vote_required = (voters.len() as f32 / 2.0).ceil() as usize;
     if self.drop_votes.insert(player_id) {
       self.broadcast_message(format!(
         "Drop player vote: {}/{}",
@@ -1440,6 +1908,116 @@ impl Shared {
     }
   }
 
+  pub fn pause_game(&mut self, player_id: i32) -> Option<PlayerAction> {
+    if self.desync_vote.is_some() {
+      self.private_message(player_id, "The game is paused for a desync vote.");
+      return None;
+    }
+    match self.referee.pause(player_id, Instant::now()) {
+      Ok(action) => {
+        if let Some((pauses, time)) = self.referee.pause_budget(player_id) {
+          self.private_message(
+            player_id,
+            format!(
+              "Pauses left: {}, pause time left: {}s",
+              pauses,
+              time.as_secs()
+            ),
+          );
+        }
+        Some(action)
+      }
+      Err(err) => {
+        self.private_message(player_id, err.to_string());
+        None
+      }
+    }
+  }
+
+  pub fn resume_game(&mut self, player_id: i32) -> Option<PlayerAction> {
+    if self.desync_vote.is_some() {
+      self.private_message(player_id, "The game is paused for a desync vote.");
+      return None;
+    }
+    match self.referee.resume(player_id, Instant::now()) {
+      Ok(action) => Some(action),
+      Err(err) => {
+        self.private_message(player_id, err.to_string());
+        None
+      }
+    }
+  }
+
+  pub fn take_expired_pause(&mut self) -> Option<PlayerAction> {
+    let action = self.referee.take_expired_pause(Instant::now())?;
+    self.broadcast_message("Pause time is up, resuming the game.");
+    Some(action)
+  }
+
+  pub fn record_command(&mut self, player_id: i32, command: &str) {
+    tracing::info!(game_id = self.game_id, player_id, "command: {}", command);
+    self.obs.push_game_command(self.game_id, player_id, command);
+  }
+
+  fn is_drop_voter(&self, player_id: i32) -> bool {
+    if self.lagging_player_ids.contains(&player_id) {
+      return false;
+    }
+    if self.lag.exclude_teammate_votes {
+      if let Some(team) = self.referee.team_of(player_id) {
+        return !self
+          .lagging_player_ids
+          .iter()
+          .any(|id| self.referee.team_of(*id) == Some(team));
+      }
+    }
+    true
+  }
+
+  /// Drops lagging players who used up their lag budget.
+  /// Returns `true` if there is no lagging player left.
+  pub fn drop_lag_budget_exceeded_players(&mut self) -> Result<bool> {
+    let budget = if let Some(v) = self.lag.player_lag_budget {
+      v
+    } else {
+      return Ok(false);
+    };
+    let player_ids: Vec<_> = self
+      .lagging_player_ids
+      .iter()
+      .filter(|id| {
+        self
+          .map
+          .get(id)
+          .map(|p| p.total_lag() >= budget)
+          .unwrap_or_default()
+      })
+      .cloned()
+      .collect();
+    if player_ids.is_empty() {
+      return Ok(false);
+    }
+    for player_id in player_ids {
+      if let Some(name) = self
+        .map
+        .get(&player_id)
+        .map(|p| p.player_name().to_string())
+      {
+        self.broadcast_message(format!(
+          "{} has used up the lag time and was dropped.",
+          name
+        ));
+      }
+      tracing::info!(
+        game_id = self.game_id,
+        player_id,
+        "lag budget exceeded, player dropped."
+      );
+      self.remove_player_and_broadcast(player_id, None)?;
+    }
+    self.check_stop_lag()
+  }
+
   pub fn drop_all_lag_players(&mut self) -> Result<()> {
     let drop_player_ids: Vec<_> = self.lagging_player_ids.iter().cloned().collect();
     for drop_player_id in &drop_player_ids {
@@ -1451,6 +2029,7 @@ impl Shared {
       self.remove_player_and_broadcast(*drop_player_id, None)?;
     }
     self.lagging_player_ids.clear();
+    self.lag_started_at.take();
     Ok(())
   }
 
@@ -1476,7 +2055,7 @@ impl Shared {
         match err {
           AckError::PlayerNotFound(_) => {}
           AckError::TickNotFound(desync) => {
-            self.handle_desync(vec![desync])?;
+            return self.handle_desync(vec![desync]);
           }
         }
 
@@ -1487,18 +2066,51 @@ impl Shared {
         };
       }
     };
-    let has_desync = res.desync.is_some();
     if let Some(desync) = res.desync {
-      self.handle_desync(desync)?;
+      return self.handle_desync(desync);
     }
-    if !self.lagging_player_ids.contains(&player_id) && !has_desync {
+    if !self.lagging_player_ids.contains(&player_id) {
       Ok(AckAction::Continue)
     } else {
       Ok(AckAction::CheckStopLag)
     }
   }
 
-  fn handle_desync(&mut self, desync: Vec<PlayerDesync>) -> Result<()> {
+  fn take_pending_desync(&mut self) -> Result<Option<AckAction>> {
+    if self.pending_desync.is_empty() {
+      return Ok(None);
+    }
+    let desync = std::mem::take(&mut self.pending_desync);
+    self.handle_desync(desync).map(Some)
+  }
+
+  fn handle_desync(&mut self, desync: Vec<PlayerDesync>) -> Result<AckAction> {
+    // checksums keep diverging after the first desync,
+    // ignore them while players are deciding or if they repeat the split players accepted
+    if self.desync_vote.is_some() {
+      return Ok(AckAction::CheckStopLag);
+    }
+    let tolerated = self
+      .desync_tolerated
+      .as_ref()
+      .map(|tolerated| tolerated.is_continuation(&desync))
+      .unwrap_or(false);
+    if tolerated {
+      return Ok(AckAction::CheckStopLag);
+    }
+
+    let tick = if let Some(item) = desync.first() {
+      item.tick
+    } else {
+      return Ok(AckAction::CheckStopLag);
+    };
+
+    self.push_desync_diagnostics(&desync);
+
+    if desync.iter().any(|item| item.tied) {
+      return Ok(self.start_desync_vote(tick, desync));
+    }
+
     let mut handled = BTreeSet::new();
     let mut targets = vec![];
     for item in desync {
@@ -1526,17 +2138,159 @@ impl Shared {
       }
     }
 
+    let mut dropped_player_ids = Vec::with_capacity(targets.len());
     for (player_id, message) in targets {
       self.broadcast_message(message);
       self.remove_player_and_broadcast(player_id, None)?;
+      dropped_player_ids.push(player_id);
     }
-    Ok(())
+    Ok(AckAction::Desync {
+      hold_clock: false,
+      report: Some(DesyncReport {
+        tick,
+        player_ids: handled.into_iter().collect(),
+        dropped_player_ids,
+        resolution: DesyncResolution::MinorityDropped,
+      }),
+    })
+  }
+
+  /// No majority: stop the game clock and let the players decide.
+  fn start_desync_vote(&mut self, tick: u32, desync: Vec<PlayerDesync>) -> AckAction {
+    let player_ids: BTreeSet<i32> = desync.iter().map(|item| item.player_id).collect();
+    let mut participants: BTreeSet<i32> = player_ids
+      .iter()
+      .cloned()
+      .filter(|player_id| self.referee.team_of(*player_id).is_some())
+      .collect();
+    if participants.is_empty() {
+      participants = player_ids.clone();
+    }
+
+    tracing::warn!(
+      game_id = self.game_id,
+      tick,
+      "desync detected without majority: {:?}",
+      player_ids
+    );
+
+    let names: Vec<_> = player_ids
+      .iter()
+      .filter_map(|player_id| self.map.get(player_id).map(|v| v.player_name().to_string()))
+      .collect();
+    self.broadcast_message(format!(
+      "Desync detected between {} (tick = {}), no majority found.",
+      names.join(", "),
+      tick
+    ));
+    self.broadcast_message(format!(
+      "Type !continue to keep playing or !abort to end the game. The game ends in {}s if not all players agree to continue.",
+      crate::constants::GAME_DESYNC_VOTE_TIMEOUT.as_secs()
+    ));
+
+    let mut groups: BTreeMap<u32, BTreeSet<i32>> = BTreeMap::new();
+    for item in &desync {
+      groups
+        .entry(item.checksum)
+        .or_default()
+        .insert(item.player_id);
+    }
+
+    self.desync_vote = Some(DesyncVote::new(
+      tick,
+      groups.into_values().collect(),
+      participants,
+      Instant::now() + crate::constants::GAME_DESYNC_VOTE_TIMEOUT,
+    ));
+
+    AckAction::Desync {
+      hold_clock: true,
+      report: None,
+    }
+  }
+
+  pub fn vote_desync(&mut self, player_id: i32, continue_game: bool) {
+    let res = self
+      .desync_vote
+      .as_mut()
+      .ok_or(DesyncVoteError::NoVote)
+      .and_then(|vote| {
+        vote.vote(player_id, continue_game)?;
+        Ok(vote.progress())
+      });
+    match res {
+      Ok((votes, required)) => {
+        if continue_game {
+          self.broadcast_message(format!("Continue vote: {}/{}", votes, required));
+        }
+      }
+      Err(err) => self.private_message(player_id, err.to_string()),
+    }
+  }
+
+  fn take_resolved_desync_vote(&mut self, now: Instant) -> Option<ResolvedDesyncVote> {
+    let resolution = self.desync_vote.as_ref()?.poll(now)?;
+    let vote = self.desync_vote.take()?;
+    let tolerated = vote.tolerate();
+    let report = vote.into_report(resolution);
+    let mut resolved = ResolvedDesyncVote {
+      release_clock: false,
+      remove_player_ids: vec![],
+      report,
+    };
+    match resolution {
+      DesyncResolution::Continued => {
+        self.desync_tolerated = Some(tolerated);
+        self.broadcast_message("All players agreed to continue, resuming the game.");
+        resolved.release_clock = true;
+      }
+      DesyncResolution::Aborted | DesyncResolution::MinorityDropped => {
+        self.broadcast_message("Desync could not be resolved, ending the game.");
+        resolved.remove_player_ids = self.map.keys().cloned().collect();
+      }
+    }
+    tracing::info!(
+      game_id = self.game_id,
+      tick = resolved.report.tick,
+      "desync resolved: {:?}",
+      resolution
+    );
+    Some(resolved)
+  }
+
+  fn push_desync_diagnostics(&self, desync: &[PlayerDesync]) {
+    let (tick, time) = match desync.first() {
+      Some(item) => (item.tick, item.time),
+      None => return,
+    };
+    let desynced = desync.iter().map(|item| item.player_id).collect();
+    let diagnostics = self.desync.build(
+      tick,
+      time,
+      &self.sync,
+      self
+        .map
+        .iter()
+        .map(|(player_id, info)| (*player_id, info.slot_player_id())),
+      &desynced,
+    );
+    self.obs.push_desync(self.game_id, diagnostics);
   }
 }
 
 enum AckAction {
   Continue,
   CheckStopLag,
+  Desync {
+    hold_clock: bool,
+    report: Option<DesyncReport>,
+  },
+}
+
+struct ResolvedDesyncVote {
+  report: DesyncReport,
+  release_clock: bool,
+  remove_player_ids: Vec<i32>,
 }
 
 enum RequestDropResult {
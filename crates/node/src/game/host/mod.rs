@@ -1,7 +1,9 @@
 use s2_grpc_utils::S2ProtoEnum;
 
+pub use desync::DesyncReport;
 use dispatch::Dispatcher;
 use flo_net::packet::*;
+pub use settings::HostSettings;
 pub use sync::AckError;
 
 use crate::error::*;
@@ -13,8 +15,13 @@ use flo_w3gs::constants::LeaveReason;
 mod broadcast;
 mod clock;
 mod delay;
+mod desync;
+mod diagnostics;
 mod dispatch;
 mod player;
+mod referee;
+mod settings;
+mod step;
 pub mod stream;
 mod sync;
 
@@ -28,10 +35,11 @@ impl GameHost {
   pub fn new(
     game_id: i32,
     slots: &[PlayerSlot],
+    settings: HostSettings,
     obs: ObserverPublisherHandle,
     event_sender: GameEventSender,
   ) -> Self {
-    let dispatcher = Dispatcher::new(game_id, slots, obs, event_sender);
+    let dispatcher = Dispatcher::new(game_id, slots, settings, obs, event_sender);
     Self {
       game_id,
       dispatcher,
@@ -19,6 +19,8 @@ pub struct PlayerDispatchInfo {
   lag_duration_ms: u32,
   lag_start: Option<Instant>,
   lag_slot_ids: BTreeSet<u8>,
+  lag_total: Duration,
+  lag_total_start: Option<Instant>,
   delay: Option<Duration>,
   last_disconnect: Option<Instant>,
   rtt_stats: PlayerRTTStats,
@@ -37,6 +39,8 @@ impl PlayerDispatchInfo {
       lag_duration_ms: 0,
       lag_start: None,
       lag_slot_ids: BTreeSet::new(),
+      lag_total: Duration::default(),
+      lag_total_start: None,
       delay: None,
       last_disconnect: None,
       rtt_stats: PlayerRTTStats::default(),
@@ -164,10 +168,14 @@ impl PlayerDispatchInfo {
         .saturating_add((Instant::now() - start).as_millis() as u32);
     }
     self.lag_start.replace(Instant::now());
+    self.lag_total_start.get_or_insert_with(Instant::now);
     self.lag_duration_ms
   }
 
   pub fn end_lag(&mut self) -> u32 {
+    if let Some(start) = self.lag_total_start.take() {
+      self.lag_total += start.elapsed();
+    }
     if let Some(start) = self.lag_start.take() {
       self.lag_duration_ms = self.lag_duration_ms.saturating_add(std::cmp::min(
         500,
@@ -177,6 +185,15 @@ impl PlayerDispatchInfo {
     self.lag_duration_ms
   }
 
+  /// Total time this player has spent lagging in the game
+  pub fn total_lag(&self) -> Duration {
+    self.lag_total
+      + self
+        .lag_total_start
+        .map(|start| start.elapsed())
+        .unwrap_or_default()
+  }
+
   pub fn set_lag_slots<I: Iterator<Item = u8>>(&mut self, ids: I) {
     self.lag_slot_ids.clear();
     self.lag_slot_ids.extend(ids);
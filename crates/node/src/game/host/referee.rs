@@ -0,0 +1,450 @@
+use bytes::{Bytes, BytesMut};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use flo_util::binary::BinDecode;
+use flo_w3gs::actions::Action;
+use flo_w3gs::protocol::action::PlayerAction;
+
+use crate::constants::{GAME_PLAYER_PAUSE_LIMIT, GAME_PLAYER_PAUSE_TIME_BUDGET};
+use crate::game::{GamePlayerRole, PlayerSlot};
+
+// flo_w3gs::actions::ActionTypeId::{PauseGame, ResumeGame}
+const ACTION_PAUSE_GAME: u8 = 0x01;
+const ACTION_RESUME_GAME: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseRequest {
+  Pause,
+  Resume,
+}
+
+/// An action block could not be fully decoded and the undecoded bytes contain
+/// a pause or resume opcode, which would bypass the pause budget if forwarded.
+#[derive(Debug, Error, PartialEq)]
+#[error("undecodable action block with a pause or resume opcode")]
+pub struct UndecodablePauseAction;
+
+/// Walks an action block and removes the pause and resume actions, which are
+/// replaced by the node's own actions after the pause budget check.
+/// Returns `None` if the block contains neither.
+pub fn split_pause_actions(
+  data: &[u8],
+) -> Result<Option<(PauseRequest, Bytes)>, UndecodablePauseAction> {
+  let mut request = None;
+  let mut rest = BytesMut::new();
+  let mut buf = data;
+  while !buf.is_empty() {
+    let start = data.len() - buf.len();
+    let action = match Action::decode(&mut buf) {
+      Ok(action) => action,
+      // unknown action, the remaining bytes can not be split
+      Err(_) => {
+        let tail = &data[start..];
+        if tail
+          .iter()
+          .any(|b| *b == ACTION_PAUSE_GAME || *b == ACTION_RESUME_GAME)
+        {
+          return Err(UndecodablePauseAction);
+        }
+        rest.extend_from_slice(tail);
+        break;
+      }
+    };
+    match action {
+      Action::PauseGame => request = Some(PauseRequest::Pause),
+      Action::ResumeGame => request = Some(PauseRequest::Resume),
+      _ => rest.extend_from_slice(&data[start..(data.len() - buf.len())]),
+    }
+  }
+  Ok(request.map(|request| (request, rest.freeze())))
+}
+
+pub fn pause_action(slot_player_id: u8) -> PlayerAction {
+  PlayerAction {
+    player_id: slot_player_id,
+    data: Bytes::from_static(&[ACTION_PAUSE_GAME]),
+  }
+}
+
+pub fn resume_action(slot_player_id: u8) -> PlayerAction {
+  PlayerAction {
+    player_id: slot_player_id,
+    data: Bytes::from_static(&[ACTION_RESUME_GAME]),
+  }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PauseError {
+  #[error("Observers cannot pause the game.")]
+  Observer,
+  #[error("The game is already paused.")]
+  AlreadyPaused,
+  #[error("The game is not paused.")]
+  NotPaused,
+  #[error("You have no pauses left.")]
+  NoPausesLeft,
+  #[error("You have used up your pause time.")]
+  NoPauseTimeLeft,
+  #[error("Only the host or a referee can unpause the game.")]
+  Locked,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum VoteError {
+  #[error("Observers cannot vote.")]
+  Observer,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Vote {
+  Voting {
+    votes: usize,
+    required: usize,
+  },
+  /// Vote passed, contains the affected player ids
+  Passed(Vec<i32>),
+}
+
+/// Server side state of the host/referee in-game commands:
+/// pause budgets, remake votes and surrender votes.
+#[derive(Debug)]
+pub struct RefereeState {
+  players: BTreeMap<i32, RefereePlayer>,
+  pause: Option<ActivePause>,
+  remake_votes: BTreeSet<i32>,
+  ff_votes: BTreeMap<i32, BTreeSet<i32>>,
+}
+
+#[derive(Debug)]
+struct RefereePlayer {
+  slot_player_id: u8,
+  role: GamePlayerRole,
+  /// `None` for observers
+  team: Option<i32>,
+  pauses: u8,
+  pause_time: Duration,
+  left: bool,
+}
+
+impl RefereePlayer {
+  fn new(slot_player_id: u8, role: GamePlayerRole, team: Option<i32>) -> Self {
+    Self {
+      slot_player_id,
+      role,
+      team,
+      pauses: 0,
+      pause_time: Duration::default(),
+      left: false,
+    }
+  }
+}
+
+#[derive(Debug)]
+struct ActivePause {
+  player_id: i32,
+  started: Instant,
+  deadline: Option<Instant>,
+}
+
+impl RefereeState {
+  pub fn from_slots(slots: &[PlayerSlot]) -> Self {
+    Self::new(slots.iter().map(|slot| {
+      (
+        slot.player.player_id,
+        RefereePlayer::new(
+          (slot.id + 1) as u8,
+          slot.player.role,
+          if slot.settings.team == 24 {
+            None
+          } else {
+            Some(slot.settings.team)
+          },
+        ),
+      )
+    }))
+  }
+
+  fn new(players: impl IntoIterator<Item = (i32, RefereePlayer)>) -> Self {
+    Self {
+      players: players.into_iter().collect(),
+      pause: None,
+      remake_votes: BTreeSet::new(),
+      ff_votes: BTreeMap::new(),
+    }
+  }
+
+  pub fn is_privileged(&self, player_id: i32) -> bool {
+    self
+      .players
+      .get(&player_id)
+      .map(|p| p.role.is_privileged())
+      .unwrap_or_default()
+  }
+
+  pub fn remove_player(&mut self, player_id: i32) {
+    if let Some(player) = self.players.get_mut(&player_id) {
+      player.left = true;
+    }
+    self.remake_votes.remove(&player_id);
+    for votes in self.ff_votes.values_mut() {
+      votes.remove(&player_id);
+    }
+  }
+
+  pub fn pause(&mut self, player_id: i32, now: Instant) -> Result<PlayerAction, PauseError> {
+    if self.pause.is_some() {
+      return Err(PauseError::AlreadyPaused);
+    }
+    let player = self
+      .players
+      .get_mut(&player_id)
+      .ok_or_else(|| PauseError::Observer)?;
+
+    let deadline = if player.role.is_privileged() {
+      None
+    } else {
+      if player.team.is_none() {
+        return Err(PauseError::Observer);
+      }
+      if player.pauses >= GAME_PLAYER_PAUSE_LIMIT {
+        return Err(PauseError::NoPausesLeft);
+      }
+      if player.pause_time >= GAME_PLAYER_PAUSE_TIME_BUDGET {
+        return Err(PauseError::NoPauseTimeLeft);
+      }
+      player.pauses += 1;
+      Some(now + (GAME_PLAYER_PAUSE_TIME_BUDGET - player.pause_time))
+    };
+
+    self.pause = Some(ActivePause {
+      player_id,
+      started: now,
+      deadline,
+    });
+
+    Ok(pause_action(player.slot_player_id))
+  }
+
+  pub fn resume(&mut self, player_id: i32, now: Instant) -> Result<PlayerAction, PauseError> {
+    let paused_by = self
+      .pause
+      .as_ref()
+      .map(|p| p.player_id)
+      .ok_or_else(|| PauseError::NotPaused)?;
+    let player = self
+      .players
+      .get(&player_id)
+      .ok_or_else(|| PauseError::Observer)?;
+    if !player.role.is_privileged() {
+      if player.team.is_none() {
+        return Err(PauseError::Observer);
+      }
+      if self.is_privileged(paused_by) {
+        return Err(PauseError::Locked);
+      }
+    }
+    let slot_player_id = player.slot_player_id;
+    self.end_pause(now);
+    Ok(resume_action(slot_player_id))
+  }
+
+  /// Resumes the game if the pausing player ran out of pause time.
+  pub fn take_expired_pause(&mut self, now: Instant) -> Option<PlayerAction> {
+    let player_id = match self.pause.as_ref() {
+      Some(ActivePause {
+        player_id,
+        deadline: Some(deadline),
+        ..
+      }) if *deadline <= now => *player_id,
+      _ => return None,
+    };
+    self.end_pause(now);
+    let slot_player_id = self.players.get(&player_id)?.slot_player_id;
+    Some(resume_action(slot_player_id))
+  }
+
+  /// Returns the number of pauses and the pause time the player has left.
+  pub fn pause_budget(&self, player_id: i32) -> Option<(u8, Duration)> {
+    let player = self.players.get(&player_id)?;
+    if player.role.is_privileged() {
+      return None;
+    }
+    Some((
+      GAME_PLAYER_PAUSE_LIMIT.saturating_sub(player.pauses),
+      GAME_PLAYER_PAUSE_TIME_BUDGET
+        .checked_sub(player.pause_time)
+        .unwrap_or_default(),
+    ))
+  }
+
+  fn end_pause(&mut self, now: Instant) {
+    if let Some(pause) = self.pause.take() {
+      if let Some(player) = self.players.get_mut(&pause.player_id) {
+        player.pause_time += now.saturating_duration_since(pause.started);
+      }
+    }
+  }
+
+  /// Remake the game if the majority of the remaining players agree.
+  pub fn vote_remake(&mut self, player_id: i32) -> Result<Vote, VoteError> {
+    if self.team_of(player_id).is_none() {
+      return Err(VoteError::Observer);
+    }
+    self.remake_votes.insert(player_id);
+    let active: Vec<i32> = self
+      .players
+      .iter()
+      .filter(|(_, p)| !p.left && p.team.is_some())
+      .map(|(id, _)| *id)
+      .collect();
+    let votes = self.remake_votes.len();
+    let required = active.len() / 2 + 1;
+    if votes >= required {
+      self.remake_votes.clear();
+      Ok(Vote::Passed(
+        self
+          .players
+          .iter()
+          .filter(|(_, p)| !p.left)
+          .map(|(id, _)| *id)
+          .collect(),
+      ))
+    } else {
+      Ok(Vote::Voting { votes, required })
+    }
+  }
+
+  /// Surrender if all remaining players of the team agree.
+  pub fn vote_ff(&mut self, player_id: i32) -> Result<Vote, VoteError> {
+    let team = self.team_of(player_id).ok_or_else(|| VoteError::Observer)?;
+    let members: Vec<i32> = self
+      .players
+      .iter()
+      .filter(|(_, p)| !p.left && p.team == Some(team))
+      .map(|(id, _)| *id)
+      .collect();
+    let votes = self.ff_votes.entry(team).or_default();
+    votes.insert(player_id);
+    if votes.len() >= members.len() {
+      self.ff_votes.remove(&team);
+      Ok(Vote::Passed(members))
+    } else {
+      Ok(Vote::Voting {
+        votes: votes.len(),
+        required: members.len(),
+      })
+    }
+  }
+
+  pub fn team_of(&self, player_id: i32) -> Option<i32> {
+    self
+      .players
+      .get(&player_id)
+      .filter(|p| !p.left)
+      .and_then(|p| p.team)
+  }
+}
+
+#[test]
+fn test_referee_state() {
+  let mut s = RefereeState::new(vec![
+    (1, RefereePlayer::new(1, GamePlayerRole::Host, Some(0))),
+    (2, RefereePlayer::new(2, GamePlayerRole::Player, Some(0))),
+    (3, RefereePlayer::new(3, GamePlayerRole::Player, Some(1))),
+    (4, RefereePlayer::new(4, GamePlayerRole::Player, Some(1))),
+    (5, RefereePlayer::new(5, GamePlayerRole::Referee, None)),
+    (6, RefereePlayer::new(6, GamePlayerRole::Player, None)),
+  ]);
+  let now = Instant::now();
+
+  // pause budget
+  assert_eq!(s.pause(6, now).unwrap_err(), PauseError::Observer);
+  let action = s.pause(2, now).unwrap();
+  assert_eq!(action.player_id, 2);
+  assert_eq!(
+    split_pause_actions(&action.data),
+    Ok(Some((PauseRequest::Pause, Bytes::new())))
+  );
+  assert_eq!(s.pause(3, now).unwrap_err(), PauseError::AlreadyPaused);
+  assert!(s.take_expired_pause(now).is_none());
+  let action = s
+    .take_expired_pause(now + GAME_PLAYER_PAUSE_TIME_BUDGET)
+    .unwrap();
+  assert_eq!(
+    split_pause_actions(&action.data),
+    Ok(Some((PauseRequest::Resume, Bytes::new())))
+  );
+  assert_eq!(s.pause(2, now).unwrap_err(), PauseError::NoPauseTimeLeft);
+  assert_eq!(s.resume(2, now).unwrap_err(), PauseError::NotPaused);
+
+  // referee pause can only be resumed by host or referee
+  s.pause(5, now).unwrap();
+  assert!(s
+    .take_expired_pause(now + Duration::from_secs(3600))
+    .is_none());
+  assert_eq!(s.resume(3, now).unwrap_err(), PauseError::Locked);
+  assert_eq!(s.resume(1, now).unwrap().player_id, 1);
+
+  for _ in 0..GAME_PLAYER_PAUSE_LIMIT {
+    s.pause(3, now).unwrap();
+    s.resume(3, now).unwrap();
+  }
+  assert_eq!(s.pause(3, now).unwrap_err(), PauseError::NoPausesLeft);
+
+  // remake
+  assert_eq!(s.vote_remake(5).unwrap_err(), VoteError::Observer);
+  assert_eq!(
+    s.vote_remake(1).unwrap(),
+    Vote::Voting {
+      votes: 1,
+      required: 3
+    }
+  );
+  s.remove_player(4);
+  assert_eq!(s.vote_remake(2).unwrap(), Vote::Passed(vec![1, 2, 3, 5, 6]));
+
+  // surrender
+  assert_eq!(s.vote_ff(3).unwrap(), Vote::Passed(vec![3]));
+  assert_eq!(
+    s.vote_ff(1).unwrap(),
+    Vote::Voting {
+      votes: 1,
+      required: 2
+    }
+  );
+  assert_eq!(s.vote_ff(2).unwrap(), Vote::Passed(vec![1, 2]));
+}
+
+#[test]
+fn test_split_pause_actions() {
+  // esc pressed, pause, game speed
+  let data = [0x61, ACTION_PAUSE_GAME, 0x03, 0x02];
+  assert_eq!(
+    split_pause_actions(&data),
+    Ok(Some((
+      PauseRequest::Pause,
+      Bytes::from_static(&[0x61, 0x03, 0x02])
+    )))
+  );
+  assert_eq!(split_pause_actions(&[0x61, 0x03, 0x02]), Ok(None));
+  assert_eq!(
+    split_pause_actions(&[ACTION_RESUME_GAME, 0xFF, 0x03]),
+    Ok(Some((
+      PauseRequest::Resume,
+      Bytes::from_static(&[0xFF, 0x03])
+    )))
+  );
+  // unknown action hiding a pause behind it
+  assert_eq!(
+    split_pause_actions(&[0xFF, ACTION_PAUSE_GAME]),
+    Err(UndecodablePauseAction)
+  );
+  assert_eq!(
+    split_pause_actions(&[0x61, 0xFF, 0x00, ACTION_RESUME_GAME]),
+    Err(UndecodablePauseAction)
+  );
+  // unknown action without a pause is forwarded unchanged
+  assert_eq!(split_pause_actions(&[0xFF, 0x03]), Ok(None));
+}
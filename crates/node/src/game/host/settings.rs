@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use flo_net::proto::flo_node::{GameLagSettings, GameSettings};
+
+use super::step::StepConfig;
+use crate::constants::{GAME_CLOCK_MAX_PAUSE, GAME_PLAYER_LAGGING_THRESHOLD_MS};
+
+#[derive(Debug, Clone, Default)]
+pub struct HostSettings {
+  pub step: StepConfig,
+  pub lag: LagConfig,
+  pub map_path: String,
+  pub map_sha1: Vec<u8>,
+  pub map_checksum: u32,
+  pub game_version: String,
+}
+
+impl HostSettings {
+  pub fn from_proto(settings: Option<&GameSettings>) -> Self {
+    Self {
+      step: StepConfig::from_proto(settings.and_then(|v| v.step.as_ref())),
+      lag: LagConfig::from_proto(settings.and_then(|v| v.lag.as_ref())),
+      map_path: settings.map(|v| v.map_path.clone()).unwrap_or_default(),
+      map_sha1: settings.map(|v| v.map_sha1.clone()).unwrap_or_default(),
+      map_checksum: settings.map(|v| v.map_checksum).unwrap_or_default(),
+      game_version: settings.map(|v| v.game_version.clone()).unwrap_or_default(),
+    }
+  }
+}
+
+/// Lag screen and drop vote policy
+#[derive(Debug, Clone)]
+pub struct LagConfig {
+  /// Player is considered lagging after this much game time without ack
+  pub lagging_threshold_ms: u32,
+  /// Minimum lag screen time before drop votes are accepted
+  pub drop_min_wait: Duration,
+  /// Total lag time allowed per player over the whole game
+  pub player_lag_budget: Option<Duration>,
+  /// Lagging players are dropped automatically after this duration
+  pub auto_drop: Duration,
+  /// Teammates of lagging players can not vote to drop them
+  pub exclude_teammate_votes: bool,
+}
+
+impl LagConfig {
+  pub fn from_proto(settings: Option<&GameLagSettings>) -> Self {
+    let settings = if let Some(v) = settings {
+      v
+    } else {
+      return Self::default();
+    };
+    Self {
+      lagging_threshold_ms: if settings.lagging_threshold_ms == 0 {
+        GAME_PLAYER_LAGGING_THRESHOLD_MS
+      } else {
+        settings.lagging_threshold_ms
+      },
+      drop_min_wait: Duration::from_millis(settings.drop_min_wait_ms as u64),
+      player_lag_budget: if settings.player_lag_budget_ms == 0 {
+        None
+      } else {
+        Some(Duration::from_millis(settings.player_lag_budget_ms as u64))
+      },
+      auto_drop: if settings.auto_drop_ms == 0 {
+        GAME_CLOCK_MAX_PAUSE
+      } else {
+        Duration::from_millis(settings.auto_drop_ms as u64)
+      },
+      exclude_teammate_votes: settings.exclude_teammate_votes,
+    }
+  }
+}
+
+impl Default for LagConfig {
+  fn default() -> Self {
+    Self {
+      lagging_threshold_ms: GAME_PLAYER_LAGGING_THRESHOLD_MS,
+      drop_min_wait: Duration::default(),
+      player_lag_budget: None,
+      auto_drop: GAME_CLOCK_MAX_PAUSE,
+      exclude_teammate_votes: false,
+    }
+  }
+}
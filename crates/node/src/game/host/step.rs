@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+
+use flo_net::proto::flo_node::GameStepSettings;
+
+use crate::constants::{
+  GAME_ADAPTIVE_STEP, GAME_ADAPTIVE_STEP_CONFIRM_ROUNDS, GAME_ADAPTIVE_STEP_HYSTERESIS_MS,
+  GAME_DEFAULT_STEP_MS,
+};
+use crate::game::host::clock::ActionTickStream;
+
+const STEP_ROUNDING_MS: u16 = 5;
+const MIN_SAMPLES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepConfig {
+  pub initial: u16,
+  pub adaptive: bool,
+  pub min: u16,
+  pub max: u16,
+}
+
+impl StepConfig {
+  pub fn from_proto(settings: Option<&GameStepSettings>) -> Self {
+    let clamp = |v: u32| {
+      std::cmp::min(
+        ActionTickStream::MAX_STEP as u32,
+        std::cmp::max(ActionTickStream::MIN_STEP as u32, v),
+      ) as u16
+    };
+    let settings = if let Some(v) = settings {
+      v
+    } else {
+      return Self::default();
+    };
+    let min = if settings.min_ms == 0 {
+      ActionTickStream::MIN_STEP
+    } else {
+      clamp(settings.min_ms)
+    };
+    let max = if settings.max_ms == 0 {
+      ActionTickStream::MAX_STEP
+    } else {
+      std::cmp::max(min, clamp(settings.max_ms))
+    };
+    let initial = if settings.initial_ms == 0 {
+      *GAME_DEFAULT_STEP_MS
+    } else {
+      clamp(settings.initial_ms)
+    };
+    Self {
+      initial: std::cmp::min(max, std::cmp::max(min, initial)),
+      adaptive: settings.adaptive,
+      min,
+      max,
+    }
+  }
+}
+
+impl Default for StepConfig {
+  fn default() -> Self {
+    Self {
+      initial: *GAME_DEFAULT_STEP_MS,
+      adaptive: *GAME_ADAPTIVE_STEP,
+      min: ActionTickStream::MIN_STEP,
+      max: ActionTickStream::MAX_STEP,
+    }
+  }
+}
+
+/// Picks the game step from smoothed player RTTs.
+///
+/// RTT and jitter are tracked per player the same way as TCP's RTO estimator (RFC 6298).
+/// The step follows the worst player's one-way latency estimate, but only moves after
+/// the new value stays outside the hysteresis band for a few evaluations in a row.
+#[derive(Debug)]
+pub struct StepController {
+  config: StepConfig,
+  players: BTreeMap<i32, RttEstimator>,
+  pending: Option<PendingChange>,
+}
+
+#[derive(Debug)]
+struct PendingChange {
+  increase: bool,
+  rounds: u8,
+}
+
+impl StepController {
+  pub fn new(config: StepConfig) -> Self {
+    Self {
+      config,
+      players: BTreeMap::new(),
+      pending: None,
+    }
+  }
+
+  pub fn config(&self) -> &StepConfig {
+    &self.config
+  }
+
+  /// Stops adjusting the step, e.g. after it has been set manually.
+  pub fn disable(&mut self) {
+    self.config.adaptive = false;
+    self.pending.take();
+  }
+
+  pub fn push_rtt(&mut self, player_id: i32, rtt: u32) {
+    if !self.config.adaptive {
+      return;
+    }
+    self
+      .players
+      .entry(player_id)
+      .or_insert_with(RttEstimator::default)
+      .push(rtt);
+  }
+
+  pub fn remove_player(&mut self, player_id: i32) {
+    self.players.remove(&player_id);
+  }
+
+  /// Returns the new step if it should be changed.
+  pub fn evaluate(&mut self, current: u16) -> Option<u16> {
+    if !self.config.adaptive {
+      return None;
+    }
+
+    let worst = self
+      .players
+      .values()
+      .filter(|v| v.samples >= MIN_SAMPLES)
+      .map(|v| v.srtt + 2.0 * v.rttvar)
+      .fold(None, |acc: Option<f64>, v| {
+        Some(acc.map(|acc| acc.max(v)).unwrap_or(v))
+      })?;
+
+    let target = self.clamp(round_step(worst / 2.0));
+    let diff = if target > current {
+      target - current
+    } else {
+      current - target
+    };
+
+    if diff < GAME_ADAPTIVE_STEP_HYSTERESIS_MS {
+      self.pending.take();
+      return None;
+    }
+
+    let increase = target > current;
+    let rounds = match self.pending.take() {
+      Some(pending) if pending.increase == increase => pending.rounds + 1,
+      _ => 1,
+    };
+
+    if rounds >= GAME_ADAPTIVE_STEP_CONFIRM_ROUNDS {
+      Some(target)
+    } else {
+      self.pending = Some(PendingChange { increase, rounds });
+      None
+    }
+  }
+
+  fn clamp(&self, value: u16) -> u16 {
+    std::cmp::min(self.config.max, std::cmp::max(self.config.min, value))
+  }
+}
+
+fn round_step(value: f64) -> u16 {
+  let rounded = (value / STEP_ROUNDING_MS as f64).round() * STEP_ROUNDING_MS as f64;
+  if rounded > u16::MAX as f64 {
+    u16::MAX
+  } else {
+    rounded as u16
+  }
+}
+
+#[derive(Debug, Default)]
+struct RttEstimator {
+  samples: u32,
+  srtt: f64,
+  rttvar: f64,
+}
+
+impl RttEstimator {
+  const ALPHA: f64 = 1.0 / 8.0;
+  const BETA: f64 = 1.0 / 4.0;
+
+  fn push(&mut self, rtt: u32) {
+    let rtt = rtt as f64;
+    if self.samples == 0 {
+      self.srtt = rtt;
+      self.rttvar = rtt / 2.0;
+    } else {
+      self.rttvar = (1.0 - Self::BETA) * self.rttvar + Self::BETA * (self.srtt - rtt).abs();
+      self.srtt = (1.0 - Self::ALPHA) * self.srtt + Self::ALPHA * rtt;
+    }
+    self.samples = self.samples.saturating_add(1);
+  }
+}
+
+#[test]
+fn test_step_controller() {
+  let mut c = StepController::new(StepConfig {
+    initial: 30,
+    adaptive: true,
+    min: 15,
+    max: 100,
+  });
+
+  assert_eq!(c.evaluate(30), None);
+
+  for _ in 0..50 {
+    c.push_rtt(1, 40);
+    c.push_rtt(2, 200);
+  }
+
+  // needs to hold for a few rounds before changing
+  for _ in 1..GAME_ADAPTIVE_STEP_CONFIRM_ROUNDS {
+    assert_eq!(c.evaluate(30), None);
+  }
+  assert_eq!(c.evaluate(30), Some(100));
+
+  c.remove_player(2);
+  for _ in 1..GAME_ADAPTIVE_STEP_CONFIRM_ROUNDS {
+    assert_eq!(c.evaluate(100), None);
+  }
+  assert_eq!(c.evaluate(100), Some(20));
+
+  // small changes are ignored
+  c.push_rtt(1, 55);
+  assert_eq!(c.evaluate(20), None);
+
+  c.disable();
+  c.push_rtt(1, 500);
+  assert_eq!(c.evaluate(20), None);
+}
+
+#[test]
+fn test_step_config_from_proto() {
+  let config = |initial_ms: u32, min_ms: u32, max_ms: u32| {
+    StepConfig::from_proto(Some(&GameStepSettings {
+      initial_ms,
+      adaptive: false,
+      min_ms,
+      max_ms,
+    }))
+  };
+
+  assert_eq!(config(50, 30, 100).initial, 50);
+  assert_eq!(config(10, 30, 100).initial, 30);
+  assert_eq!(config(200, 30, 100).initial, 100);
+  // would wrap to 15 as u16
+  assert_eq!(config(65_551, 30, 100).initial, 100);
+  assert_eq!(config(u32::MAX, 0, 0).initial, ActionTickStream::MAX_STEP);
+  assert_eq!(config(0, 0, 0).initial, *GAME_DEFAULT_STEP_MS);
+}
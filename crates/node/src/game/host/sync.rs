@@ -1,8 +1,10 @@
 use slab::Slab;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::time::{Duration, Instant};
 use thiserror::Error;
 
+use crate::constants::GAME_DESYNC_CHECKSUM_HISTORY;
+
 #[derive(Debug)]
 pub struct SyncMap {
   tick: u32,
@@ -11,10 +13,11 @@ pub struct SyncMap {
   pending_tick: BTreeMap<u32, usize>,
   pending_slab: Slab<Pending>,
   desync_buf: Vec<PlayerDesync>,
+  lagging_threshold_ms: u32,
 }
 
 impl SyncMap {
-  pub fn new(players: Vec<i32>) -> Self {
+  pub fn new(players: Vec<i32>, lagging_threshold_ms: u32) -> Self {
     Self {
       tick: 0,
       time: 0,
@@ -25,6 +28,7 @@ impl SyncMap {
       pending_tick: BTreeMap::new(),
       pending_slab: Slab::new(),
       desync_buf: vec![],
+      lagging_threshold_ms,
     }
   }
 
@@ -52,9 +56,7 @@ impl SyncMap {
   fn check_timeout(&mut self, time_increment: u16) -> Option<Vec<PlayerTimeout>> {
     for id in self.pending_tick.values() {
       let item = &mut self.pending_slab[*id];
-      if (self.time + time_increment as u32) - item.time
-        > crate::constants::GAME_PLAYER_LAGGING_THRESHOLD_MS
-      {
+      if (self.time + time_increment as u32) - item.time > self.lagging_threshold_ms {
         return Some(
           self
             .players
@@ -114,12 +116,17 @@ impl SyncMap {
     let tick = state.tick + 1;
     let time = self.time;
     state.tick = tick;
+    if state.checksums.len() == GAME_DESYNC_CHECKSUM_HISTORY {
+      state.checksums.pop_front();
+    }
+    state.checksums.push_back((tick, checksum));
     let id = self.pending_tick.get(&tick).cloned().ok_or_else(|| {
       AckError::TickNotFound(PlayerDesync {
         player_id,
         tick,
         time,
         checksum,
+        tied: false,
       })
     })?;
     let pending = &mut self.pending_slab[id];
@@ -164,6 +171,14 @@ impl SyncMap {
     }
   }
 
+  /// Recent `(tick, checksum)` pairs reported by the player, oldest first
+  pub fn checksum_history(&self, player_id: i32) -> Option<impl Iterator<Item = (u32, u32)> + '_> {
+    self
+      .players
+      .get(&player_id)
+      .map(|state| state.checksums.iter().cloned())
+  }
+
   pub fn debug_pending(&self) -> String {
     let mut values = Vec::with_capacity(self.pending_tick.len());
     for (tick, id) in &self.pending_tick {
@@ -278,6 +293,7 @@ impl Pending {
                 time,
                 player_id,
                 checksum,
+                tied: true,
               })
             }));
           } else {
@@ -287,6 +303,7 @@ impl Pending {
               time,
               player_id,
               checksum,
+              tied: false,
             }));
           }
         }
@@ -300,6 +317,7 @@ impl Pending {
                 time,
                 player_id,
                 checksum,
+                tied: true,
               })
             }));
           } else {
@@ -313,6 +331,7 @@ impl Pending {
                     time,
                     player_id,
                     checksum,
+                    tied: false,
                   })
                 }),
             )
@@ -330,11 +349,16 @@ struct CheckDesyncToken;
 pub struct PlayerState {
   tick: u32,
   time: u32,
+  checksums: VecDeque<(u32, u32)>,
 }
 
 impl PlayerState {
   fn new() -> Self {
-    Self { tick: 0, time: 0 }
+    Self {
+      tick: 0,
+      time: 0,
+      checksums: VecDeque::with_capacity(GAME_DESYNC_CHECKSUM_HISTORY),
+    }
   }
 }
 
@@ -349,6 +373,9 @@ pub struct PlayerDesync {
   pub tick: u32,
   pub time: u32,
   pub checksum: u32,
+  /// No checksum group has more players than the others,
+  /// so every player is reported
+  pub tied: bool,
 }
 
 #[test]
@@ -361,7 +388,10 @@ fn test_sync_map() {
 
   let mut rng = thread_rng();
   let mut players = vec![0, 1, 2, 3];
-  let mut map = SyncMap::new(players.clone());
+  let mut map = SyncMap::new(
+    players.clone(),
+    crate::constants::GAME_PLAYER_LAGGING_THRESHOLD_MS,
+  );
 
   let mut acks = VecDeque::new();
   let mut buckets = vec![SIZE, SIZE, SIZE, SIZE];
@@ -457,3 +487,34 @@ fn test_sync_map() {
   assert!(map.pending_tick.is_empty());
   dbg!(&map.pending_slab.capacity());
 }
+
+#[test]
+fn test_sync_map_desync() {
+  let threshold = crate::constants::GAME_PLAYER_LAGGING_THRESHOLD_MS;
+
+  // minority
+  let mut map = SyncMap::new(vec![1, 2, 3], threshold);
+  let _ = map.clock(30);
+  assert!(map.ack(1, 1).unwrap().desync.is_none());
+  assert!(map.ack(2, 1).unwrap().desync.is_none());
+  let res = map.ack(3, 2).unwrap();
+  assert_eq!(res.agreed_checksum, Some(1));
+  assert_eq!(
+    res.desync.unwrap(),
+    vec![PlayerDesync {
+      player_id: 3,
+      tick: 1,
+      time: 30,
+      checksum: 2,
+      tied: false,
+    }]
+  );
+
+  // tie
+  let mut map = SyncMap::new(vec![1, 2], threshold);
+  let _ = map.clock(30);
+  assert!(map.ack(1, 1).unwrap().desync.is_none());
+  let desync = map.ack(2, 2).unwrap().desync.unwrap();
+  assert_eq!(desync.len(), 2);
+  assert!(desync.iter().all(|v| v.tied));
+}
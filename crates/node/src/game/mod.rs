@@ -15,7 +15,7 @@ use flo_task::SpawnScope;
 pub use flo_types::node::*;
 use host::stream::PlayerStreamHandle;
 pub use host::AckError;
-use host::GameHost;
+use host::{DesyncReport, GameHost, HostSettings};
 
 use crate::controller::ControllerServerHandle;
 use crate::error::*;
@@ -30,6 +30,10 @@ mod host;
 pub enum GameEvent {
   GameStatusChange(NodeGameStatus),
   PlayerStatusChange(i32, SlotClientStatus, SlotClientStatusUpdateSource),
+  Desync(DesyncReport),
+  PlayerResult(i32, LeaveReason),
+  /// The game was ended by a remake vote, the player has no result
+  PlayerAborted(i32),
 }
 
 pub type GameEventSender = Sender<GameEvent>;
@@ -55,6 +59,7 @@ impl GameSession {
     let scope = SpawnScope::new();
     let game_id = game.id;
     let (tx, mut rx) = GameEvent::channel(32);
+    let settings = HostSettings::from_proto(game.settings.as_ref());
     let slots: Vec<_> = Vec::<GameSlot>::unpack(game.slots)?
       .into_iter()
       .filter_map(PlayerSlot::from_game_slot)
@@ -64,7 +69,7 @@ impl GameSession {
     let state = Arc::new(Mutex::new(State {
       game_id,
       g_event_sender,
-      host: GameHost::new(game_id, &slots, obs.clone(), tx.clone()),
+      host: GameHost::new(game_id, &slots, settings, obs.clone(), tx.clone()),
       status: NodeGameStatus::Created,
       player_slots: slots
         .into_iter()
@@ -138,6 +143,37 @@ impl GameSession {
           _ => {}
         }
       }
+      GameEvent::Desync(report) => {
+        let guard = handle.0.lock().await;
+        let mut pkt = proto::PacketNodeGameDesync {
+          game_id: guard.game_id,
+          tick: report.tick,
+          player_ids: report.player_ids,
+          dropped_player_ids: report.dropped_player_ids,
+          ..Default::default()
+        };
+        pkt.set_resolution(report.resolution.into_proto_enum());
+        if guard.ctrl.send(pkt.encode_as_frame()?).await.is_err() {
+          tracing::warn!("report desync: controller disconnected");
+        }
+      }
+      GameEvent::PlayerResult(player_id, reason) => {
+        let result = match reason {
+          LeaveReason::LeaveWon => proto::NodeGamePlayerResult::Won,
+          LeaveReason::LeaveLost | LeaveReason::LeaveLostBuildings => {
+            proto::NodeGamePlayerResult::Lost
+          }
+          LeaveReason::LeaveDraw => proto::NodeGamePlayerResult::Draw,
+          LeaveReason::LeaveDisconnect => proto::NodeGamePlayerResult::Disconnected,
+          _ => return Ok(()),
+        };
+        handle.report_player_result(player_id, result).await?;
+      }
+      GameEvent::PlayerAborted(player_id) => {
+        handle
+          .report_player_result(player_id, proto::NodeGamePlayerResult::Aborted)
+          .await?;
+      }
     }
     Ok(())
   }
@@ -208,6 +244,24 @@ impl GameSessionHandle {
     Ok(())
   }
 
+  async fn report_player_result(
+    &self,
+    player_id: i32,
+    result: proto::NodeGamePlayerResult,
+  ) -> Result<()> {
+    let guard = self.0.lock().await;
+    let mut pkt = proto::PacketNodeGamePlayerResult {
+      game_id: guard.game_id,
+      player_id,
+      ..Default::default()
+    };
+    pkt.set_result(result);
+    if guard.ctrl.send(pkt.encode_as_frame()?).await.is_err() {
+      tracing::warn!(player_id, "report player result: controller disconnected");
+    }
+    Ok(())
+  }
+
   pub async fn update_player_client_status(
     &self,
     source: SlotClientStatusUpdateSource,
@@ -587,7 +641,7 @@ impl S2ProtoUnpack<proto::GameSlot> for GameSlot {
 #[s2_grpc(message_type(flo_net::proto::flo_node::SlotSettings))]
 #[allow(unused)]
 pub struct GameSlotSettings {
-  team: i32,
+  pub team: i32,
   color: i32,
   computer: Computer,
   handicap: i32,
@@ -620,6 +674,10 @@ pub struct GamePlayer {
   pub player_id: i32,
   pub name: String,
   pub ban_list: Vec<PlayerBanType>,
+  #[s2_grpc(proto_enum)]
+  pub role: GamePlayerRole,
+  /// Game version reported by the player's client
+  pub game_version: String,
 }
 
 impl<'a> From<&'a State> for NodeGameStatusSnapshot {
@@ -642,3 +700,22 @@ impl<'a> From<&'a State> for NodeGameStatusSnapshot {
 pub enum PlayerBanType {
   Chat = 0,
 }
+
+#[derive(Debug, Copy, Clone, S2ProtoEnum, PartialEq)]
+#[s2_grpc(proto_enum_type(flo_net::proto::flo_node::GamePlayerRole))]
+#[repr(i32)]
+pub enum GamePlayerRole {
+  Player = 0,
+  Host = 1,
+  Referee = 2,
+}
+
+impl GamePlayerRole {
+  /// Host and referee can use the privileged in-game commands
+  pub fn is_privileged(&self) -> bool {
+    match *self {
+      GamePlayerRole::Player => false,
+      GamePlayerRole::Host | GamePlayerRole::Referee => true,
+    }
+  }
+}
@@ -1,6 +1,7 @@
 use crate::error::Result;
 use backoff::backoff::Backoff;
 use bytes::{BufMut, Bytes, BytesMut};
+use flo_net::proto::flo_observer::DesyncDiagnostics;
 use flo_observer::{record::GameRecord, record::RTTStats, KINESIS_CLIENT};
 use flo_w3gs::packet::Packet;
 use parking_lot::Mutex;
@@ -78,6 +79,14 @@ impl ObserverPublisherHandle {
     self.push_record(GameRecord::new_rtt_stats(game_id, stats))
   }
 
+  pub fn push_game_command(&self, game_id: i32, player_id: i32, command: &str) {
+    self.push_record(GameRecord::new_game_command(game_id, player_id, command))
+  }
+
+  pub fn push_desync(&self, game_id: i32, diagnostics: DesyncDiagnostics) {
+    self.push_record(GameRecord::new_desync(game_id, diagnostics))
+  }
+
   fn push_record(&self, record: GameRecord) {
     if self.broken.get() {
       return;
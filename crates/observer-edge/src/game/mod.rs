@@ -332,6 +332,7 @@ impl GameHandler {
           // }
         }
         GameRecordData::TickChecksum { .. } => {}
+        GameRecordData::GameCommand(_) | GameRecordData::Desync(_) => {}
         GameRecordData::RTTStats(stats) => {
           self.game.put_rtt(self.meta.id, stats, snapshot_map)?;
           continue;
@@ -1,7 +1,9 @@
 use bytes::{Buf, BufMut};
-use flo_util::binary::{BinDecode, BinEncode};
+use flo_net::proto::flo_observer::DesyncDiagnostics;
+use flo_util::binary::{BinDecode, BinEncode, CString};
 use flo_util::{BinDecode, BinEncode};
 use flo_w3gs::protocol::packet::{Header as W3GSHeader, Packet};
+use prost::Message;
 use std::convert::TryFrom;
 use std::str::FromStr;
 use thiserror::Error;
@@ -22,6 +24,10 @@ pub enum RecordError {
   DecodeW3GSHeader(flo_util::error::BinDecodeError),
   #[error("decode rtt stats record: {0}")]
   DecodeRTTStatsRecord(flo_util::error::BinDecodeError),
+  #[error("decode game command record: {0}")]
+  DecodeGameCommandRecord(flo_util::error::BinDecodeError),
+  #[error("decode desync record: {0}")]
+  DecodeDesyncRecord(prost::DecodeError),
   #[error("decode w3gs: {0}")]
   DecodeW3GS(flo_w3gs::error::Error),
 }
@@ -107,6 +113,8 @@ pub enum GameRecordData {
   GameEnd,
   TickChecksum { tick: u32, checksum: u32 },
   RTTStats(RTTStats),
+  GameCommand(GameCommandRecord),
+  Desync(DesyncDiagnostics),
 }
 
 #[derive(Debug, Clone, BinEncode, BinDecode)]
@@ -137,6 +145,22 @@ pub struct RTTStatsItem {
   pub avg: f32,
 }
 
+/// A host/referee command accepted by the node
+#[derive(Debug, Clone, BinEncode, BinDecode)]
+pub struct GameCommandRecord {
+  pub player_id: i32,
+  pub command: CString,
+}
+
+impl GameCommandRecord {
+  pub fn new(player_id: i32, command: &str) -> Self {
+    Self {
+      player_id,
+      command: CString::new(command.replace('\0', "")).unwrap_or_default(),
+    }
+  }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u8)]
 pub enum DataTypeId {
@@ -146,6 +170,8 @@ pub enum DataTypeId {
   GameEnd = 4,
   TickChecksum = 5,
   RTTStat = 6,
+  GameCommand = 7,
+  Desync = 8,
 }
 
 impl GameRecordData {
@@ -157,6 +183,8 @@ impl GameRecordData {
       GameRecordData::GameEnd => DataTypeId::GameEnd,
       GameRecordData::TickChecksum { .. } => DataTypeId::TickChecksum,
       GameRecordData::RTTStats { .. } => DataTypeId::RTTStat,
+      GameRecordData::GameCommand(_) => DataTypeId::GameCommand,
+      GameRecordData::Desync(_) => DataTypeId::Desync,
     }
   }
 
@@ -168,6 +196,8 @@ impl GameRecordData {
       GameRecordData::GameEnd => 0,
       GameRecordData::TickChecksum { .. } => 4 + 4,
       GameRecordData::RTTStats(ref data) => 4 + 1 + (data.items.len() * RTTStatsItem::MIN_SIZE),
+      GameRecordData::GameCommand(ref data) => 4 + data.command.as_bytes_with_nul().len(),
+      GameRecordData::Desync(ref data) => 4 + data.encoded_len(),
     }
   }
 
@@ -200,6 +230,15 @@ impl GameRecordData {
       GameRecordData::RTTStats(ref data) => {
         data.encode(&mut buf);
       }
+      GameRecordData::GameCommand(ref data) => {
+        data.encode(&mut buf);
+      }
+      GameRecordData::Desync(ref data) => {
+        buf.put_u32(data.encoded_len() as u32);
+        data
+          .encode(&mut buf)
+          .expect("buffer capacity checked by encode_len");
+      }
     }
   }
 
@@ -214,6 +253,8 @@ impl GameRecordData {
       4 => DataTypeId::GameEnd,
       5 => DataTypeId::TickChecksum,
       6 => DataTypeId::RTTStat,
+      7 => DataTypeId::GameCommand,
+      8 => DataTypeId::Desync,
       other => return Err(RecordError::UnknownDataTypeId(other)),
     };
     Ok(match data_type {
@@ -262,6 +303,20 @@ impl GameRecordData {
       DataTypeId::RTTStat => {
         Self::RTTStats(RTTStats::decode(&mut buf).map_err(RecordError::DecodeRTTStatsRecord)?)
       }
+      DataTypeId::GameCommand => Self::GameCommand(
+        GameCommandRecord::decode(&mut buf).map_err(RecordError::DecodeGameCommandRecord)?,
+      ),
+      DataTypeId::Desync => {
+        if buf.remaining() < 4 {
+          return Err(RecordError::UnexpectedEndOfBuffer);
+        }
+        let len = buf.get_u32() as usize;
+        if buf.remaining() < len {
+          return Err(RecordError::UnexpectedEndOfBuffer);
+        }
+        let data = buf.copy_to_bytes(len);
+        Self::Desync(DesyncDiagnostics::decode(data).map_err(RecordError::DecodeDesyncRecord)?)
+      }
     })
   }
 }
@@ -309,6 +364,20 @@ impl GameRecord {
     }
   }
 
+  pub fn new_game_command(game_id: i32, player_id: i32, command: &str) -> Self {
+    Self {
+      game_id,
+      data: GameRecordData::GameCommand(GameCommandRecord::new(player_id, command)),
+    }
+  }
+
+  pub fn new_desync(game_id: i32, diagnostics: DesyncDiagnostics) -> Self {
+    Self {
+      game_id,
+      data: GameRecordData::Desync(diagnostics),
+    }
+  }
+
   pub fn encode_len(&self) -> usize {
     4 + self.data.encode_len()
   }
@@ -440,4 +509,43 @@ fn test_encode_decode() {
     assert_eq!(max, i as u16);
     assert_eq!(avg, i as f32);
   }
+
+  let record = encode_then_decode(&GameRecord::new_game_command(1234, 5678, "kick flux"));
+  assert_eq!(record.game_id, 1234);
+  assert_eq!(record.data.type_id(), DataTypeId::GameCommand);
+  let inner = match record.data {
+    GameRecordData::GameCommand(inner) => inner,
+    _ => unreachable!(),
+  };
+  assert_eq!(inner.player_id, 5678);
+  assert_eq!(inner.command.to_str().unwrap(), "kick flux");
+
+  use flo_net::proto::flo_observer::{DesyncChecksum, DesyncPlayer};
+  let record = encode_then_decode(&GameRecord::new_desync(
+    1234,
+    DesyncDiagnostics {
+      tick: 42,
+      time: 1000,
+      game_version: "1.32.10.18067".to_string(),
+      players: vec![DesyncPlayer {
+        player_id: 5678,
+        slot_player_id: 1,
+        desynced: true,
+        checksums: vec![DesyncChecksum {
+          tick: 42,
+          checksum: 0xDEAD,
+        }],
+        game_version: "1.32.10.18067".to_string(),
+      }],
+      ..Default::default()
+    },
+  ));
+  assert_eq!(record.game_id, 1234);
+  assert_eq!(record.data.type_id(), DataTypeId::Desync);
+  let inner = match record.data {
+    GameRecordData::Desync(inner) => inner,
+    _ => unreachable!(),
+  };
+  assert_eq!(inner.tick, 42);
+  assert_eq!(inner.players[0].checksums[0].checksum, 0xDEAD);
 }
@@ -0,0 +1,171 @@
+use flo_util::binary::*;
+use flo_util::{BinDecode, BinEncode};
+
+use crate::protocol::constants::{GameFlags, PacketTypeId};
+use crate::protocol::game::GameSettings;
+use crate::protocol::packet::PacketPayload;
+
+/// Classic (pre-Reforged) LAN games are broadcast to this UDP port
+pub const LAN_PORT: u16 = 6112;
+
+/// `W3XP` as a little endian dword
+pub const PRODUCT_TFT: [u8; 4] = *b"PX3W";
+/// `WAR3` as a little endian dword
+pub const PRODUCT_ROC: [u8; 4] = *b"3RAW";
+
+/// Broadcast by clients opening the LAN game list
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct SearchGame {
+  pub product: [u8; 4],
+  /// Minor version, e.g. 26 for 1.26
+  pub version: u32,
+  #[bin(eq = 0)]
+  _unknown: u32,
+}
+
+impl SearchGame {
+  pub fn new(version: u32) -> Self {
+    Self {
+      product: PRODUCT_TFT,
+      version,
+      _unknown: 0,
+    }
+  }
+}
+
+impl PacketPayload for SearchGame {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::SearchGame;
+}
+
+/// Sent to a searching client or broadcast by the host
+#[derive(Debug, BinDecode, BinEncode, PartialEq, Clone)]
+pub struct GameInfo {
+  pub product: [u8; 4],
+  pub version: u32,
+  /// Host counter
+  pub game_id: u32,
+  pub entry_key: u32,
+  pub name: CString,
+  #[bin(eq = 0)]
+  _password: u8,
+  pub settings: GameSettings,
+  pub slots_total: u32,
+  #[bin(bitflags(u32))]
+  pub flags: GameFlags,
+  _unknown: u32,
+  pub slots_open: u32,
+  pub uptime_secs: u32,
+  pub port: u16,
+}
+
+impl GameInfo {
+  pub fn new(
+    version: u32,
+    game_id: u32,
+    entry_key: u32,
+    name: CString,
+    settings: GameSettings,
+    flags: GameFlags,
+    port: u16,
+  ) -> Self {
+    Self {
+      product: PRODUCT_TFT,
+      version,
+      game_id,
+      entry_key,
+      name,
+      _password: 0,
+      settings,
+      slots_total: 24,
+      flags,
+      _unknown: 1,
+      slots_open: 24,
+      uptime_secs: 0,
+      port,
+    }
+  }
+}
+
+impl PacketPayload for GameInfo {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::GameInfo;
+}
+
+/// Broadcast by the host when the game is created
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct CreateGame {
+  pub product: [u8; 4],
+  pub version: u32,
+  pub game_id: u32,
+}
+
+impl PacketPayload for CreateGame {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::CreateGame;
+}
+
+/// Broadcast by the host when the player count changes
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct RefreshGame {
+  pub game_id: u32,
+  pub players: u32,
+  pub slots_total: u32,
+}
+
+impl PacketPayload for RefreshGame {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::RefreshGame;
+}
+
+/// Broadcast by the host when the game starts or is closed
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct DecreateGame {
+  pub game_id: u32,
+}
+
+impl PacketPayload for DecreateGame {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::DecreateGame;
+}
+
+#[test]
+fn test_lan_packets() {
+  use crate::packet::Packet;
+  use crate::protocol::game::GameSettingsMap;
+
+  fn roundtrip<T>(payload: T)
+  where
+    T: PacketPayload + BinEncode + BinDecode + PartialEq + std::fmt::Debug + Clone,
+  {
+    let mut buf = BytesMut::new();
+    Packet::simple(payload.clone()).unwrap().encode(&mut buf);
+    let header = Packet::decode_header(&mut buf).unwrap();
+    let packet = Packet::decode(header, &mut buf).unwrap();
+    assert_eq!(packet.type_id(), T::PACKET_TYPE_ID);
+    assert_eq!(packet.decode_simple::<T>().unwrap(), payload);
+  }
+
+  let mut buf = BytesMut::new();
+  Packet::simple(SearchGame::new(26))
+    .unwrap()
+    .encode(&mut buf);
+  assert_eq!(
+    &buf[..],
+    &[0xF7, 0x2F, 0x10, 0x00, b'P', b'X', b'3', b'W', 26, 0, 0, 0, 0, 0, 0, 0]
+  );
+
+  roundtrip(GameInfo::new(
+    26,
+    1,
+    0xDDDDDDDD,
+    CString::new("flo").unwrap(),
+    GameSettings::new(
+      Default::default(),
+      GameSettingsMap {
+        path: "Maps\\(2)EchoIsles.w3x".to_string(),
+        width: 116,
+        height: 116,
+        sha1: [1; 20],
+        checksum: 0x1234,
+      },
+    ),
+    GameFlags::CUSTOM_GAME | GameFlags::OBS_FULL,
+    16000,
+  ));
+}
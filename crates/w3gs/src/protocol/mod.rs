@@ -5,6 +5,7 @@ pub mod desync;
 pub mod game;
 pub mod join;
 pub mod lag;
+pub mod lan;
 pub mod leave;
 pub mod map;
 pub mod packet;
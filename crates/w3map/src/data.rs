@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+
+use flo_util::binary::BinDecode;
+use image::RgbaImage;
+
+use crate::error::{Error, Result};
+use crate::script::SCRIPT_PATHS;
+use crate::{
+  Archive, Doodads, MapScript, ObjectData, ObjectDataKind, PathingMap, Terrain, UnitPlacement,
+  UnitPlacements,
+};
+
+/// Everything placed in the map and the script, loaded by `W3Map::open_with_data`
+///
+/// Files missing from the archive are `None`, protected maps often remove them.
+#[derive(Debug, Default)]
+pub struct MapData {
+  pub terrain: Option<Terrain>,
+  pub pathing: Option<PathingMap>,
+  pub doodads: Option<Doodads>,
+  pub units: Option<UnitPlacements>,
+  pub object_data: BTreeMap<ObjectDataKind, ObjectData>,
+  pub script: Option<MapScript>,
+}
+
+impl MapData {
+  pub(crate) fn load(archive: &mut Archive) -> Result<Self> {
+    let mut object_data = BTreeMap::new();
+    for kind in ObjectDataKind::ALL.iter().cloned() {
+      let path = kind.file_name();
+      if let Some(bytes) = archive.read_file_all_opt(path)? {
+        let data = ObjectData::decode(kind, &mut bytes.as_slice())
+          .map_err(|err| Error::ReadObjectData(path, err))?;
+        object_data.insert(kind, data);
+      }
+    }
+
+    let mut script = None;
+    for (path, kind) in SCRIPT_PATHS.iter().cloned() {
+      if let Some(bytes) = archive.read_file_all_opt(path)? {
+        script = Some(MapScript::new(kind, path, &bytes));
+        break;
+      }
+    }
+
+    Ok(MapData {
+      terrain: archive
+        .read_file_all_opt("war3map.w3e")?
+        .map(|bytes| BinDecode::decode(&mut bytes.as_slice()).map_err(Error::ReadTerrain))
+        .transpose()?,
+      pathing: archive
+        .read_file_all_opt("war3map.wpm")?
+        .map(|bytes| BinDecode::decode(&mut bytes.as_slice()).map_err(Error::ReadPathing))
+        .transpose()?,
+      doodads: archive
+        .read_file_all_opt("war3map.doo")?
+        .map(|bytes| BinDecode::decode(&mut bytes.as_slice()).map_err(Error::ReadDoodads))
+        .transpose()?,
+      units: archive
+        .read_file_all_opt("war3mapUnits.doo")?
+        .map(|bytes| BinDecode::decode(&mut bytes.as_slice()).map_err(Error::ReadUnits))
+        .transpose()?,
+      object_data,
+      script,
+    })
+  }
+
+  pub fn start_locations(&self) -> Vec<&UnitPlacement> {
+    self
+      .units
+      .as_ref()
+      .map(|units| units.start_locations().collect())
+      .unwrap_or_default()
+  }
+
+  pub fn gold_mines(&self) -> Vec<&UnitPlacement> {
+    self
+      .units
+      .as_ref()
+      .map(|units| units.gold_mines().collect())
+      .unwrap_or_default()
+  }
+
+  /// Renders terrain, water and pathing with start locations, gold mines and creep camps on top,
+  /// the whole terrain is stretched to `width` x `height`
+  ///
+  /// Returns `None` if the map has no terrain file.
+  pub fn render_minimap(&self, width: u32, height: u32) -> Option<RgbaImage> {
+    crate::minimap::render(self, width, height)
+  }
+
+  pub fn render_minimap_png(&self, width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = vec![];
+    if let Some(image) = self.render_minimap(width, height) {
+      image::DynamicImage::ImageRgba8(image)
+        .write_to(&mut bytes, image::ImageFormat::Png)
+        .ok();
+    }
+    bytes
+  }
+}
@@ -0,0 +1,230 @@
+// The war3map.doo file: The Doodad (trees, destructables) Placement File
+
+use crate::object_data::ObjectId;
+use flo_util::binary::*;
+use flo_util::BinDecode;
+
+const TAG: &[u8] = b"W3do";
+
+/// `W3do` header shared by war3map.doo and war3mapUnits.doo
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PlacementHeader {
+  pub version: u32,
+  pub subversion: u32,
+  pub count: u32,
+}
+
+impl PlacementHeader {
+  pub fn is_expansion(&self) -> bool {
+    self.version >= 8
+  }
+
+  /// Reforged adds skins to placements
+  pub fn has_skin(&self) -> bool {
+    self.version >= 8 && self.subversion >= 11
+  }
+}
+
+impl BinDecode for PlacementHeader {
+  const MIN_SIZE: usize = 16;
+  const FIXED_SIZE: bool = true;
+
+  fn decode<T: Buf>(buf: &mut T) -> Result<Self, BinDecodeError> {
+    buf.check_size(Self::MIN_SIZE)?;
+    buf.get_tag(TAG)?;
+    Ok(Self {
+      version: buf.get_u32_le(),
+      subversion: buf.get_u32_le(),
+      count: buf.get_u32_le(),
+    })
+  }
+}
+
+#[derive(Debug, Clone, BinDecode)]
+pub struct DroppedItem {
+  pub id: ObjectId,
+  /// Percentage
+  pub chance: u32,
+}
+
+/// One item of each set is dropped on death
+#[derive(Debug, Clone, BinDecode)]
+pub struct DroppedItemSet {
+  _num_items: u32,
+  #[bin(repeat = "_num_items")]
+  pub items: Vec<DroppedItem>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Doodads {
+  pub version: u32,
+  pub subversion: u32,
+  pub doodads: Vec<Doodad>,
+  /// Doodads painted as part of the terrain, e.g. cliff decorations
+  pub special_doodads: Vec<SpecialDoodad>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Doodad {
+  pub type_id: ObjectId,
+  pub variation: u32,
+  pub position: [f32; 3],
+  /// Radians
+  pub angle: f32,
+  pub scale: [f32; 3],
+  pub skin_id: Option<ObjectId>,
+  pub flags: u8,
+  /// Percentage
+  pub life: u8,
+  /// -1 if the map item table isn't used
+  pub item_table: i32,
+  pub item_sets: Vec<DroppedItemSet>,
+  pub editor_id: u32,
+}
+
+#[derive(Debug, Clone, BinDecode)]
+pub struct SpecialDoodad {
+  pub type_id: ObjectId,
+  pub variation: u32,
+  /// Tile coordinates
+  pub x: u32,
+  pub y: u32,
+}
+
+impl BinDecode for Doodads {
+  const MIN_SIZE: usize = PlacementHeader::MIN_SIZE;
+  const FIXED_SIZE: bool = false;
+
+  fn decode<T: Buf>(buf: &mut T) -> Result<Self, BinDecodeError> {
+    let header = PlacementHeader::decode(buf)?;
+    let mut doodads = vec![];
+    for i in 0..header.count {
+      doodads.push(Doodad::decode_with_header(buf, &header).map_err(|e| e.context(i))?);
+    }
+
+    // the special doodads table is missing from some older files
+    let special_doodads = if buf.remaining() >= 8 {
+      let _version = buf.get_u32_le();
+      let count = buf.get_u32_le();
+      buf.get_repeated(count as usize)?
+    } else {
+      vec![]
+    };
+
+    Ok(Self {
+      version: header.version,
+      subversion: header.subversion,
+      doodads,
+      special_doodads,
+    })
+  }
+}
+
+impl Doodad {
+  fn decode_with_header<T: Buf>(
+    buf: &mut T,
+    header: &PlacementHeader,
+  ) -> Result<Self, BinDecodeError> {
+    let type_id = ObjectId::decode(buf)?;
+    buf.check_size(4 + 12 + 4 + 12)?;
+    let variation = buf.get_u32_le();
+    let position = [buf.get_f32_le(), buf.get_f32_le(), buf.get_f32_le()];
+    let angle = buf.get_f32_le();
+    let scale = [buf.get_f32_le(), buf.get_f32_le(), buf.get_f32_le()];
+    let skin_id = if header.has_skin() {
+      Some(ObjectId::decode(buf)?)
+    } else {
+      None
+    };
+    buf.check_size(2)?;
+    let flags = buf.get_u8();
+    let life = buf.get_u8();
+    let (item_table, item_sets) = if header.is_expansion() {
+      buf.check_size(8)?;
+      let item_table = buf.get_i32_le();
+      let num_sets = buf.get_u32_le();
+      (item_table, buf.get_repeated(num_sets as usize)?)
+    } else {
+      (-1, vec![])
+    };
+    buf.check_size(4)?;
+    let editor_id = buf.get_u32_le();
+    Ok(Self {
+      type_id,
+      variation,
+      position,
+      angle,
+      scale,
+      skin_id,
+      flags,
+      life,
+      item_table,
+      item_sets,
+      editor_id,
+    })
+  }
+}
+
+#[cfg(test)]
+pub(crate) fn put_placement_common(buf: &mut Vec<u8>, id: &[u8; 4], x: f32, y: f32) {
+  buf.extend_from_slice(id);
+  buf.put_u32_le(0);
+  buf.put_f32_le(x);
+  buf.put_f32_le(y);
+  buf.put_f32_le(0.0);
+  buf.put_f32_le(4.71);
+  buf.put_f32_le(1.0);
+  buf.put_f32_le(1.0);
+  buf.put_f32_le(1.0);
+  buf.extend_from_slice(id);
+}
+
+#[test]
+fn test_decode_doodads() {
+  let mut bytes = vec![];
+  bytes.extend_from_slice(TAG);
+  bytes.put_u32_le(8);
+  bytes.put_u32_le(11);
+  bytes.put_u32_le(2);
+
+  put_placement_common(&mut bytes, b"LTlt", 64.0, -64.0);
+  bytes.put_u8(2);
+  bytes.put_u8(100);
+  bytes.put_i32_le(-1);
+  bytes.put_u32_le(0);
+  bytes.put_u32_le(0);
+
+  put_placement_common(&mut bytes, b"LTbr", 128.0, 0.0);
+  bytes.put_u8(2);
+  bytes.put_u8(50);
+  bytes.put_i32_le(-1);
+  bytes.put_u32_le(1);
+  bytes.put_u32_le(1);
+  bytes.extend_from_slice(b"ratf");
+  bytes.put_u32_le(100);
+  bytes.put_u32_le(1);
+
+  bytes.put_u32_le(0);
+  bytes.put_u32_le(1);
+  bytes.extend_from_slice(b"D000");
+  bytes.put_u32_le(0);
+  bytes.put_u32_le(10);
+  bytes.put_u32_le(20);
+
+  let doodads = Doodads::decode(&mut bytes.as_slice()).unwrap();
+  assert_eq!(doodads.doodads.len(), 2);
+  let tree = &doodads.doodads[0];
+  assert_eq!(tree.type_id, b"LTlt");
+  assert_eq!(tree.skin_id, Some(ObjectId::new(b"LTlt")));
+  assert_eq!(tree.position, [64.0, -64.0, 0.0]);
+  let barrel = &doodads.doodads[1];
+  assert_eq!(barrel.life, 50);
+  assert_eq!(barrel.item_sets.len(), 1);
+  assert_eq!(barrel.item_sets[0].items[0].id, b"ratf");
+  assert_eq!(barrel.editor_id, 1);
+  assert_eq!(doodads.special_doodads.len(), 1);
+  assert_eq!(
+    (doodads.special_doodads[0].x, doodads.special_doodads[0].y),
+    (10, 20)
+  );
+}
@@ -5,6 +5,8 @@ use thiserror::Error;
 pub enum Error {
   #[error("map script not found")]
   MapScriptNotFound,
+  #[error("map listfile not found")]
+  MapListFileNotFound,
   #[error("storage file not found: {0}")]
   StorageFileNotFound(String),
   #[cfg(feature = "w3storage")]
@@ -24,6 +26,16 @@ pub enum Error {
   ReadMinimapIcons(BinDecodeError),
   #[error("read map trigger strings: {0}")]
   ReadTriggerStrings(BinDecodeError),
+  #[error("read map terrain: {0}")]
+  ReadTerrain(BinDecodeError),
+  #[error("read map pathing: {0}")]
+  ReadPathing(BinDecodeError),
+  #[error("read map doodads: {0}")]
+  ReadDoodads(BinDecodeError),
+  #[error("read map units: {0}")]
+  ReadUnits(BinDecodeError),
+  #[error("read map object data `{0}`: {1}")]
+  ReadObjectData(&'static str, BinDecodeError),
   #[error("io: {0}")]
   Io(#[from] std::io::Error),
 }
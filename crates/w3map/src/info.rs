@@ -17,7 +17,7 @@ pub enum MapFormatVersion {
   UnknownValue(u32),
 }
 
-#[derive(Debug, BinDecode)]
+#[derive(Debug, Clone, BinEncode, BinDecode)]
 pub struct MapInfo {
   pub version: MapFormatVersion,
   pub save_count: u32,
@@ -65,7 +65,7 @@ pub struct MapInfo {
   pub forces: Vec<Force>,
 }
 
-#[derive(Debug, Clone, BinDecode)]
+#[derive(Debug, Clone, BinEncode, BinDecode)]
 pub struct GameVersion {
   pub major: u32,
   pub minor: u32,
@@ -73,13 +73,13 @@ pub struct GameVersion {
   pub commit: u32,
 }
 
-#[derive(Debug, Clone, BinDecode)]
+#[derive(Debug, Clone, BinEncode, BinDecode)]
 pub struct CameraBounds {
   pub bounds: [f32; 8],
   pub complements: [u32; 4],
 }
 
-#[derive(Debug, Clone, BinDecode)]
+#[derive(Debug, Clone, BinEncode, BinDecode)]
 pub struct GameEnv {
   pub fog: u32,
   pub fog_start: f32,
@@ -92,7 +92,7 @@ pub struct GameEnv {
   pub water_color: u32,
 }
 
-#[derive(Debug, Clone, BinDecode)]
+#[derive(Debug, Clone, BinEncode, BinDecode)]
 pub struct ClassicPlayer {
   pub id: u32,
   pub type_: u32,
@@ -105,7 +105,7 @@ pub struct ClassicPlayer {
   pub ally_prio_high: u32,
 }
 
-#[derive(Debug, Clone, BinDecode)]
+#[derive(Debug, Clone, BinEncode, BinDecode)]
 pub struct ReforgedPlayer {
   pub id: u32,
   pub type_: u32,
@@ -120,7 +120,7 @@ pub struct ReforgedPlayer {
   pub _unknown_2: u32,
 }
 
-#[derive(Debug, Clone, BinDecode)]
+#[derive(Debug, Clone, BinEncode, BinDecode)]
 pub struct Force {
   pub flags: u32,
   pub player_set: u32,
@@ -163,6 +163,16 @@ fn test_parse_w3i_tft() {
   dbg!("{:#?}", info);
 }
 
+#[test]
+fn test_encode_w3i() {
+  for name in &["(2)ConcealedHill.w3x", "test_roc.w3m", "test_tft.w3x"] {
+    let mut map = crate::open_archive(flo_util::sample_path!("map", name)).unwrap();
+    let bytes = map.open_file("war3map.w3i").unwrap().read_all().unwrap();
+    let info = MapInfo::decode(&mut bytes.as_slice()).unwrap();
+    assert_eq!(&info.encode_to_bytes()[..], &bytes[..]);
+  }
+}
+
 #[test]
 fn test_parse_custom() {
   let mut map = crate::open_archive(flo_util::sample_path!(
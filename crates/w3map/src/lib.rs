@@ -9,15 +9,33 @@ pub mod error;
 
 mod checksum;
 mod constants;
+mod data;
+mod doodads;
 mod info;
 mod minimap;
+mod object_data;
+mod pathing;
+mod scan;
+mod script;
+mod terrain;
 mod trigger_string;
+mod units;
+mod writer;
 
 pub use self::checksum::MapChecksum;
 pub use self::constants::*;
+pub use self::data::MapData;
+pub use self::doodads::{Doodad, Doodads, DroppedItem, DroppedItemSet, SpecialDoodad};
 pub use self::info::*;
 pub use self::minimap::*;
+pub use self::object_data::*;
+pub use self::pathing::*;
+pub use self::scan::{ScanFinding, ScanLimits, ScanReport, ScanVerdict};
+pub use self::script::{MapScript, ScriptKind};
+pub use self::terrain::*;
 pub use self::trigger_string::*;
+pub use self::units::*;
+pub use self::writer::MapWriter;
 
 pub use flo_blp::BLPImage;
 #[cfg(feature = "w3storage")]
@@ -47,10 +65,32 @@ impl W3Map {
     Ok((map, checksum))
   }
 
+  /// Also loads terrain, placements, object data and the script
+  pub fn open_with_data<P: AsRef<Path>>(path: P) -> Result<(Self, MapData)> {
+    let mut archive = Self::open_archive_file(path)?;
+    let data = MapData::load(&mut archive)?;
+    let map = Self::load_info(archive)?;
+    Ok((map, data))
+  }
+
   pub fn open_memory(bytes: &[u8]) -> Result<Self> {
     Self::load_info(Self::open_archive_memory(bytes)?)
   }
 
+  pub fn open_memory_with_checksum(bytes: &[u8]) -> Result<(Self, MapChecksum)> {
+    let mut archive = Self::open_archive_memory(bytes)?;
+    let checksum = MapChecksum::compute(&mut archive)?;
+    let map = Self::load_info(archive)?;
+    Ok((map, checksum))
+  }
+
+  pub fn open_memory_with_data(bytes: &[u8]) -> Result<(Self, MapData)> {
+    let mut archive = Self::open_archive_memory(bytes)?;
+    let data = MapData::load(&mut archive)?;
+    let map = Self::load_info(archive)?;
+    Ok((map, data))
+  }
+
   #[cfg(feature = "w3storage")]
   pub fn open_storage(storage: &W3Storage, path: &str) -> Result<Self> {
     use flo_w3storage::Data;
@@ -419,6 +459,18 @@ fn test_open_storage_with_checksum() {
   )
 }
 
+#[test]
+fn test_open_map_with_data() {
+  let (map, data) =
+    W3Map::open_with_data(flo_util::sample_path!("map", "(2)ConcealedHill.w3x")).unwrap();
+  let terrain = data.terrain.as_ref().unwrap();
+  let pathing = data.pathing.as_ref().unwrap();
+  assert_eq!(pathing.width, (terrain.width - 1) * 4);
+  assert_eq!(data.start_locations().len(), map.num_players());
+  assert!(!data.gold_mines().is_empty());
+  assert!(data.script.is_some());
+}
+
 #[test]
 fn test_open_map_special() {
   let map = W3Map::open(flo_util::sample_path!(
@@ -428,3 +480,13 @@ fn test_open_map_special() {
   .unwrap();
   dbg!(map.flags());
 }
+
+#[test]
+fn test_open_memory_with_checksum() {
+  let path = flo_util::sample_path!("map", "(2)ConcealedHill.w3x");
+  let (_, checksum) = W3Map::open_with_checksum(&path).unwrap();
+  let bytes = std::fs::read(&path).unwrap();
+  let (map, memory_checksum) = W3Map::open_memory_with_checksum(&bytes).unwrap();
+  assert_eq!(checksum, memory_checksum);
+  assert_eq!(map.file_size(), bytes.len());
+}
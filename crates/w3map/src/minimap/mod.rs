@@ -3,6 +3,10 @@ use image::{GenericImage, GenericImageView};
 use image::{ImageBuffer, Rgba};
 use lazy_static::lazy_static;
 
+mod render;
+
+pub(crate) use self::render::render;
+
 #[derive(Debug, BinDecode, Default)]
 pub struct MinimapIcons {
   #[bin(eq = 0)]
@@ -0,0 +1,444 @@
+//! Minimap rendering from terrain, pathing and placement data, for maps without a usable
+//! `war3mapMap.blp` preview
+
+use image::{Rgba, RgbaImage};
+
+use super::{MinimapIcon, MinimapIconType};
+use crate::{MapData, ObjectId, PathingFlags, Terrain, TilePoint, TilePointFlags, UnitPlacement};
+
+/// World units per terrain tile
+const TILE_SIZE: f32 = 128.0;
+/// Neutral hostile units closer than this to a camp's center belong to the camp
+const CREEP_CAMP_RADIUS: f32 = 600.0;
+
+const PLAYER_COLORS: [[u8; 3]; 24] = [
+  [255, 3, 3],
+  [0, 66, 255],
+  [28, 230, 185],
+  [84, 0, 129],
+  [255, 252, 0],
+  [254, 138, 14],
+  [32, 192, 0],
+  [229, 91, 176],
+  [149, 150, 151],
+  [126, 191, 241],
+  [16, 98, 70],
+  [78, 42, 4],
+  [155, 0, 0],
+  [0, 0, 195],
+  [0, 234, 255],
+  [190, 0, 254],
+  [235, 205, 135],
+  [248, 164, 139],
+  [191, 255, 128],
+  [220, 185, 235],
+  [40, 40, 40],
+  [235, 240, 255],
+  [0, 120, 30],
+  [164, 111, 51],
+];
+
+/// Returns `None` if the map has no terrain file
+pub(crate) fn render(data: &MapData, width: u32, height: u32) -> Option<RgbaImage> {
+  let terrain = data.terrain.as_ref()?;
+  if terrain.width < 2 || terrain.height < 2 || width == 0 || height == 0 {
+    return None;
+  }
+
+  let texture_colors: Vec<[u8; 3]> = terrain
+    .ground_tilesets
+    .iter()
+    .map(ground_texture_color)
+    .collect();
+  let projection = Projection::new(terrain, width, height);
+
+  let mut image = RgbaImage::from_fn(width, height, |px, py| {
+    let (tx, ty) = projection.to_tile(px, py);
+    let x = (tx.round() as u32).min(terrain.width - 1);
+    let y = (ty.round() as u32).min(terrain.height - 1);
+    let point = match terrain.get(x, y) {
+      Some(point) => point,
+      None => return Rgba([0, 0, 0, 255]),
+    };
+
+    let mut color = if point.is_water() {
+      water_color(point)
+    } else {
+      let base = texture_colors
+        .get(point.ground_texture as usize)
+        .cloned()
+        .unwrap_or(DEFAULT_GROUND);
+      shade(base, 1.0 + point.height() / 1024.0)
+    };
+
+    if is_cliff(terrain, x, y) {
+      color = shade(color, 0.6);
+    }
+
+    if let Some(pathing) = data.pathing.as_ref() {
+      let cx = ((tx * 4.0) as u32).min(pathing.width.saturating_sub(1));
+      let cy = ((ty * 4.0) as u32).min(pathing.height.saturating_sub(1));
+      if let Some(flags) = pathing.get(cx, cy) {
+        if flags.contains(PathingFlags::UNWALKABLE) && !point.is_water() {
+          color = shade(color, 0.7);
+        }
+      }
+    }
+
+    if point
+      .flags
+      .intersects(TilePointFlags::BOUNDARY | TilePointFlags::MAP_EDGE)
+    {
+      color = shade(color, 0.35);
+    }
+
+    Rgba([color[0], color[1], color[2], 255])
+  });
+
+  for camp in creep_camps(data) {
+    let (px, py) = projection.to_pixel(camp.position);
+    let radius = (2.0 + (camp.count.min(6) as f32) / 2.0) * projection.marker_scale();
+    fill_circle(&mut image, px, py, radius + 1.0, [40, 0, 0]);
+    fill_circle(&mut image, px, py, radius, [230, 40, 40]);
+  }
+
+  for mine in data.gold_mines() {
+    icon(
+      &projection,
+      MinimapIconType::Gold,
+      mine,
+      [255, 255, 255, 255],
+    )
+    .draw_into(&mut image);
+  }
+
+  for location in data.start_locations() {
+    let [r, g, b] = PLAYER_COLORS
+      .get(location.owner as usize)
+      .cloned()
+      .unwrap_or([255, 255, 255]);
+    icon(
+      &projection,
+      MinimapIconType::Cross,
+      location,
+      [b, g, r, 255],
+    )
+    .draw_into(&mut image);
+  }
+
+  Some(image)
+}
+
+/// Maps output pixels to terrain tile coordinates, the image is stretched to the whole terrain
+struct Projection {
+  offset: [f32; 2],
+  tiles_w: f32,
+  tiles_h: f32,
+  width: u32,
+  height: u32,
+}
+
+impl Projection {
+  fn new(terrain: &Terrain, width: u32, height: u32) -> Self {
+    Self {
+      offset: terrain.center_offset,
+      tiles_w: (terrain.width - 1) as f32,
+      tiles_h: (terrain.height - 1) as f32,
+      width,
+      height,
+    }
+  }
+
+  /// Terrain rows start from the bottom
+  fn to_tile(&self, px: u32, py: u32) -> (f32, f32) {
+    (
+      (px as f32 + 0.5) / self.width as f32 * self.tiles_w,
+      (self.height as f32 - py as f32 - 0.5) / self.height as f32 * self.tiles_h,
+    )
+  }
+
+  fn to_pixel(&self, position: [f32; 2]) -> (f32, f32) {
+    let tx = (position[0] - self.offset[0]) / TILE_SIZE;
+    let ty = (position[1] - self.offset[1]) / TILE_SIZE;
+    (
+      tx / self.tiles_w * self.width as f32,
+      self.height as f32 - ty / self.tiles_h * self.height as f32,
+    )
+  }
+
+  /// Markers are sized for a 256x256 minimap
+  fn marker_scale(&self) -> f32 {
+    (self.width.min(self.height) as f32 / 256.0).max(0.5)
+  }
+}
+
+fn icon(
+  projection: &Projection,
+  type_: MinimapIconType,
+  unit: &UnitPlacement,
+  bgra: [u8; 4],
+) -> MinimapIcon {
+  let (x, y) = projection.to_pixel([unit.position[0], unit.position[1]]);
+  MinimapIcon {
+    type_,
+    pos_x: x.max(0.0) as u32,
+    pos_y: y.max(0.0) as u32,
+    bgra,
+  }
+}
+
+const DEFAULT_GROUND: [u8; 3] = [150, 130, 95];
+
+/// Ground texture colors approximated from the texture name, e.g. `Lgrs` (Lordaeron grass)
+fn ground_texture_color(id: &ObjectId) -> [u8; 3] {
+  let name = id.to_string().to_lowercase();
+  let name = name.get(1..).unwrap_or("");
+  let contains = |keys: &[&str]| keys.iter().any(|key| name.contains(key));
+  if contains(&["sn"]) {
+    [225, 230, 235]
+  } else if contains(&["ic"]) {
+    [175, 205, 225]
+  } else if contains(&["lv", "lav"]) {
+    [150, 60, 30]
+  } else if contains(&["gr", "vin", "crp", "leaf"]) {
+    [80, 120, 50]
+  } else if contains(&["rk", "ro", "rc"]) {
+    [120, 115, 105]
+  } else if contains(&["sq", "bk", "br", "ti", "fl", "st"]) {
+    [140, 135, 120]
+  } else if contains(&["d"]) {
+    [130, 100, 65]
+  } else {
+    DEFAULT_GROUND
+  }
+}
+
+fn water_color(point: &TilePoint) -> [u8; 3] {
+  let depth = ((point.water_height() - point.height()) / 256.0).clamp(0.0, 1.0);
+  let shallow = [50_f32, 100.0, 170.0];
+  let deep = [15_f32, 40.0, 100.0];
+  [
+    (shallow[0] + (deep[0] - shallow[0]) * depth) as u8,
+    (shallow[1] + (deep[1] - shallow[1]) * depth) as u8,
+    (shallow[2] + (deep[2] - shallow[2]) * depth) as u8,
+  ]
+}
+
+fn shade(color: [u8; 3], factor: f32) -> [u8; 3] {
+  let factor = factor.clamp(0.0, 1.3);
+  [
+    (color[0] as f32 * factor).min(255.0) as u8,
+    (color[1] as f32 * factor).min(255.0) as u8,
+    (color[2] as f32 * factor).min(255.0) as u8,
+  ]
+}
+
+/// A tile point is on a cliff if its layer differs from a neighbor's
+fn is_cliff(terrain: &Terrain, x: u32, y: u32) -> bool {
+  let layer = match terrain.get(x, y) {
+    Some(point) => point.layer_height,
+    None => return false,
+  };
+  let neighbors = [(1_i64, 0_i64), (0, 1), (-1, 0), (0, -1)];
+  neighbors.iter().any(|(dx, dy)| {
+    let nx = x as i64 + dx;
+    let ny = y as i64 + dy;
+    if nx < 0 || ny < 0 {
+      return false;
+    }
+    terrain
+      .get(nx as u32, ny as u32)
+      .map(|point| point.layer_height != layer && !point.flags.contains(TilePointFlags::RAMP))
+      .unwrap_or(false)
+  })
+}
+
+fn fill_circle(image: &mut RgbaImage, cx: f32, cy: f32, radius: f32, color: [u8; 3]) {
+  let (width, height) = image.dimensions();
+  let x0 = (cx - radius).floor().max(0.0) as u32;
+  let y0 = (cy - radius).floor().max(0.0) as u32;
+  let x1 = ((cx + radius).ceil().max(0.0) as u32).min(width);
+  let y1 = ((cy + radius).ceil().max(0.0) as u32).min(height);
+  for y in y0..y1 {
+    for x in x0..x1 {
+      let dx = x as f32 + 0.5 - cx;
+      let dy = y as f32 + 0.5 - cy;
+      if dx * dx + dy * dy <= radius * radius {
+        image.put_pixel(x, y, Rgba([color[0], color[1], color[2], 255]));
+      }
+    }
+  }
+}
+
+#[derive(Debug)]
+struct CreepCamp {
+  position: [f32; 2],
+  count: usize,
+}
+
+/// Groups neutral hostile units by distance
+///
+/// Neutral hostile is player 24 in maps supporting 24 players and player 12 before,
+/// 24 is assumed if any unit is owned by a player above 15.
+fn creep_camps(data: &MapData) -> Vec<CreepCamp> {
+  let units = match data.units.as_ref() {
+    Some(units) => &units.units,
+    None => return vec![],
+  };
+  let neutral_hostile = if units.iter().any(|u| u.owner > 15) {
+    24
+  } else {
+    12
+  };
+
+  let mut camps: Vec<CreepCamp> = vec![];
+  for unit in units
+    .iter()
+    .filter(|u| u.owner == neutral_hostile && !u.is_gold_mine() && !u.is_start_location())
+  {
+    let [x, y, _] = unit.position;
+    let camp = camps.iter_mut().find(|camp| {
+      let dx = camp.position[0] - x;
+      let dy = camp.position[1] - y;
+      dx * dx + dy * dy <= CREEP_CAMP_RADIUS * CREEP_CAMP_RADIUS
+    });
+    match camp {
+      Some(camp) => {
+        let n = camp.count as f32;
+        camp.position = [
+          (camp.position[0] * n + x) / (n + 1.0),
+          (camp.position[1] * n + y) / (n + 1.0),
+        ];
+        camp.count += 1;
+      }
+      None => camps.push(CreepCamp {
+        position: [x, y],
+        count: 1,
+      }),
+    }
+  }
+  camps
+}
+
+#[cfg(test)]
+fn test_map_data() -> MapData {
+  use crate::{PathingMap, RandomUnit, UnitPlacements};
+
+  fn point(layer_height: u8, water: bool) -> TilePoint {
+    TilePoint {
+      ground_height: if water { 0x1C00 } else { 0x2000 },
+      water_level: 0x2000,
+      flags: if water {
+        TilePointFlags::WATER
+      } else {
+        TilePointFlags::empty()
+      },
+      ground_texture: 0,
+      texture_details: 0,
+      cliff_texture: 0,
+      layer_height,
+    }
+  }
+
+  fn unit(type_id: &[u8; 4], owner: i32, x: f32, y: f32) -> UnitPlacement {
+    UnitPlacement {
+      type_id: ObjectId::new(type_id),
+      variation: 0,
+      position: [x, y, 0.0],
+      angle: 0.0,
+      scale: [1.0; 3],
+      skin_id: None,
+      flags: 2,
+      owner,
+      hit_points: -1,
+      mana_points: -1,
+      item_table: -1,
+      item_sets: vec![],
+      gold: 12500,
+      target_acquisition: -1.0,
+      hero_level: 1,
+      hero_attributes: None,
+      inventory: vec![],
+      abilities: vec![],
+      random: RandomUnit::Any {
+        level: 0,
+        item_class: 0,
+      },
+      color: -1,
+      waygate: -1,
+      editor_id: 0,
+    }
+  }
+
+  // 16x16 tiles, world coordinates from -1024 to 1024, water in the top right quarter
+  let mut tile_points = vec![];
+  for y in 0..17 {
+    for x in 0..17 {
+      tile_points.push(point(2, x > 8 && y > 8));
+    }
+  }
+  MapData {
+    terrain: Some(Terrain {
+      version: 11,
+      tileset: b'L',
+      custom_tilesets: false,
+      ground_tilesets: vec![ObjectId::new(b"Lgrs")],
+      cliff_tilesets: vec![],
+      width: 17,
+      height: 17,
+      center_offset: [-1024.0, -1024.0],
+      tile_points,
+    }),
+    pathing: Some(PathingMap {
+      version: 0,
+      width: 64,
+      height: 64,
+      cells: vec![PathingFlags::empty(); 64 * 64],
+    }),
+    units: Some(UnitPlacements {
+      version: 8,
+      subversion: 11,
+      units: vec![
+        unit(b"sloc", 0, -768.0, -768.0),
+        unit(b"ngol", 15, -512.0, -768.0),
+        unit(b"nfsp", 12, 512.0, -512.0),
+        unit(b"nfsh", 12, 600.0, -512.0),
+        unit(b"nfsp", 12, -512.0, 512.0),
+      ],
+    }),
+    ..Default::default()
+  }
+}
+
+#[test]
+fn test_creep_camps() {
+  let camps = creep_camps(&test_map_data());
+  assert_eq!(camps.len(), 2);
+  assert_eq!(camps[0].count, 2);
+  assert_eq!(camps[0].position, [556.0, -512.0]);
+  assert_eq!(camps[1].count, 1);
+}
+
+#[test]
+fn test_render_minimap() {
+  let data = test_map_data();
+  for (width, height) in [(64, 64), (256, 256), (300, 200)].iter().cloned() {
+    let image = render(&data, width, height).unwrap();
+    assert_eq!(image.dimensions(), (width, height));
+
+    // top right is water
+    let Rgba([r, g, b, _]) = *image.get_pixel(width * 7 / 8, height / 8);
+    assert!(b > r && b > g);
+    // top left is grass
+    let Rgba([r, g, b, _]) = *image.get_pixel(width / 8, height / 8);
+    assert!(g > r && g > b);
+    // creep camp in the bottom right
+    let (x, y) = Projection::new(data.terrain.as_ref().unwrap(), width, height)
+      .to_pixel(creep_camps(&data)[0].position);
+    let Rgba([r, g, b, _]) = *image.get_pixel(x as u32, y as u32);
+    assert!(r > 200 && g < 100 && b < 100);
+  }
+
+  assert!(render(&MapData::default(), 64, 64).is_none());
+  assert!(render(&data, 0, 64).is_none());
+}
@@ -0,0 +1,309 @@
+// The object data files: war3map.w3u, .w3t, .w3b, .w3d, .w3a, .w3h and .w3q
+
+use flo_util::binary::*;
+use std::fmt;
+
+/// Four character id of a unit, item, ability etc., e.g. `hfoo`
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId([u8; 4]);
+
+impl ObjectId {
+  pub const fn new(bytes: &[u8; 4]) -> Self {
+    ObjectId(*bytes)
+  }
+
+  pub fn as_bytes(&self) -> &[u8; 4] {
+    &self.0
+  }
+
+  pub fn is_null(&self) -> bool {
+    self.0 == [0; 4]
+  }
+}
+
+impl BinDecode for ObjectId {
+  const MIN_SIZE: usize = 4;
+  const FIXED_SIZE: bool = true;
+  fn decode<T: Buf>(buf: &mut T) -> Result<Self, BinDecodeError> {
+    buf.check_size(4)?;
+    let mut bytes = [0; 4];
+    buf.copy_to_slice(&mut bytes);
+    Ok(ObjectId(bytes))
+  }
+}
+
+impl<'a> PartialEq<&'a [u8; 4]> for ObjectId {
+  fn eq(&self, other: &&'a [u8; 4]) -> bool {
+    &self.0 == *other
+  }
+}
+
+impl fmt::Display for ObjectId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for b in self.0.iter().cloned().filter(|b| *b != 0) {
+      write!(f, "{}", char::from(b))?;
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Debug for ObjectId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "'{}'", self)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ObjectDataKind {
+  Units,
+  Items,
+  Destructables,
+  Doodads,
+  Abilities,
+  Buffs,
+  Upgrades,
+}
+
+impl ObjectDataKind {
+  pub const ALL: [ObjectDataKind; 7] = [
+    ObjectDataKind::Units,
+    ObjectDataKind::Items,
+    ObjectDataKind::Destructables,
+    ObjectDataKind::Doodads,
+    ObjectDataKind::Abilities,
+    ObjectDataKind::Buffs,
+    ObjectDataKind::Upgrades,
+  ];
+
+  pub fn file_name(&self) -> &'static str {
+    match *self {
+      ObjectDataKind::Units => "war3map.w3u",
+      ObjectDataKind::Items => "war3map.w3t",
+      ObjectDataKind::Destructables => "war3map.w3b",
+      ObjectDataKind::Doodads => "war3map.w3d",
+      ObjectDataKind::Abilities => "war3map.w3a",
+      ObjectDataKind::Buffs => "war3map.w3h",
+      ObjectDataKind::Upgrades => "war3map.w3q",
+    }
+  }
+
+  /// Modifications in these files carry a level (or variation) and a data pointer
+  fn has_levels(&self) -> bool {
+    matches!(
+      *self,
+      ObjectDataKind::Doodads | ObjectDataKind::Abilities | ObjectDataKind::Upgrades
+    )
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectData {
+  pub version: u32,
+  /// Changes to built-in objects
+  pub original: Vec<ObjectMod>,
+  /// Objects created in the editor
+  pub custom: Vec<ObjectMod>,
+}
+
+impl ObjectData {
+  pub fn decode<T: Buf>(kind: ObjectDataKind, buf: &mut T) -> Result<Self, BinDecodeError> {
+    buf.check_size(4)?;
+    let version = buf.get_u32_le();
+    if version > 3 {
+      return Err(BinDecodeError::failure(format!(
+        "unsupported object data version: {}",
+        version
+      )));
+    }
+    let original = decode_table(kind, version, buf).map_err(|e| e.context("original"))?;
+    // the custom table is missing from some very old files
+    let custom = if buf.has_remaining() {
+      decode_table(kind, version, buf).map_err(|e| e.context("custom"))?
+    } else {
+      vec![]
+    };
+    Ok(Self {
+      version,
+      original,
+      custom,
+    })
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &ObjectMod> {
+    self.original.iter().chain(self.custom.iter())
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectMod {
+  pub original_id: ObjectId,
+  /// Set for custom objects
+  pub new_id: Option<ObjectId>,
+  /// Modifications of all sets, version 3 splits them by set (SD/HD)
+  pub modifications: Vec<Modification>,
+}
+
+impl ObjectMod {
+  /// Id the object is referred to in the map, e.g. in unit placements
+  pub fn id(&self) -> ObjectId {
+    self.new_id.unwrap_or(self.original_id)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modification {
+  pub id: ObjectId,
+  /// Level or variation, only used by doodads, abilities and upgrades
+  pub level: u32,
+  pub data_pointer: u32,
+  pub value: ModificationValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModificationValue {
+  Int(i32),
+  Real(f32),
+  Unreal(f32),
+  String(String),
+}
+
+fn decode_table<T: Buf>(
+  kind: ObjectDataKind,
+  version: u32,
+  buf: &mut T,
+) -> Result<Vec<ObjectMod>, BinDecodeError> {
+  buf.check_size(4)?;
+  let count = buf.get_u32_le();
+  let mut items = vec![];
+  for _ in 0..count {
+    buf.check_size(8)?;
+    let original_id = ObjectId::decode(buf)?;
+    let new_id = ObjectId::decode(buf)?;
+    let num_sets = if version >= 3 {
+      buf.check_size(4)?;
+      buf.get_u32_le()
+    } else {
+      1
+    };
+    let mut modifications = vec![];
+    for _ in 0..num_sets {
+      if version >= 3 {
+        buf.check_size(4)?;
+        let _set_flag = buf.get_u32_le();
+      }
+      buf.check_size(4)?;
+      let num_mods = buf.get_u32_le();
+      for _ in 0..num_mods {
+        modifications
+          .push(decode_modification(kind, buf).map_err(|e| e.context(original_id.to_string()))?);
+      }
+    }
+    items.push(ObjectMod {
+      original_id,
+      new_id: if new_id.is_null() { None } else { Some(new_id) },
+      modifications,
+    });
+  }
+  Ok(items)
+}
+
+fn decode_modification<T: Buf>(
+  kind: ObjectDataKind,
+  buf: &mut T,
+) -> Result<Modification, BinDecodeError> {
+  let id = ObjectId::decode(buf)?;
+  let (level, data_pointer) = if kind.has_levels() {
+    buf.check_size(8)?;
+    (buf.get_u32_le(), buf.get_u32_le())
+  } else {
+    (0, 0)
+  };
+  buf.check_size(4)?;
+  let value = match buf.get_u32_le() {
+    0 => {
+      buf.check_size(4)?;
+      ModificationValue::Int(buf.get_i32_le())
+    }
+    1 => {
+      buf.check_size(4)?;
+      ModificationValue::Real(buf.get_f32_le())
+    }
+    2 => {
+      buf.check_size(4)?;
+      ModificationValue::Unreal(buf.get_f32_le())
+    }
+    3 => ModificationValue::String(CString::decode(buf)?.to_string_lossy().to_string()),
+    other => {
+      return Err(BinDecodeError::failure(format!(
+        "unknown modification value type: {}",
+        other
+      )))
+    }
+  };
+  // end of modification marker: 0 or the object id
+  buf.check_size(4)?;
+  buf.advance(4);
+  Ok(Modification {
+    id,
+    level,
+    data_pointer,
+    value,
+  })
+}
+
+#[test]
+fn test_decode_object_data() {
+  fn put_table(buf: &mut Vec<u8>, version: u32, objects: &[(&[u8; 4], &[u8; 4])]) {
+    buf.put_u32_le(objects.len() as u32);
+    for (original_id, new_id) in objects {
+      buf.extend_from_slice(*original_id);
+      buf.extend_from_slice(*new_id);
+      if version >= 3 {
+        buf.put_u32_le(1);
+        buf.put_u32_le(0);
+      }
+      buf.put_u32_le(2);
+      buf.extend_from_slice(b"Cad1");
+      buf.put_u32_le(1);
+      buf.put_u32_le(0);
+      buf.put_u32_le(0);
+      buf.put_i32_le(3);
+      buf.put_u32_le(0);
+      buf.extend_from_slice(b"anam");
+      buf.put_u32_le(0);
+      buf.put_u32_le(0);
+      buf.put_u32_le(3);
+      buf.extend_from_slice(b"TRIGSTR_001\0");
+      buf.extend_from_slice(*original_id);
+    }
+  }
+
+  for version in [2, 3].iter().cloned() {
+    let mut bytes = vec![];
+    bytes.put_u32_le(version);
+    put_table(&mut bytes, version, &[(b"AHbz", &[0; 4])]);
+    put_table(&mut bytes, version, &[(b"ANcl", b"A000")]);
+
+    let data = ObjectData::decode(ObjectDataKind::Abilities, &mut bytes.as_slice()).unwrap();
+    assert_eq!(data.original.len(), 1);
+    assert_eq!(data.original[0].id(), ObjectId::new(b"AHbz"));
+    assert_eq!(data.custom[0].id(), ObjectId::new(b"A000"));
+    assert_eq!(
+      data.custom[0].modifications,
+      vec![
+        Modification {
+          id: ObjectId::new(b"Cad1"),
+          level: 1,
+          data_pointer: 0,
+          value: ModificationValue::Int(3),
+        },
+        Modification {
+          id: ObjectId::new(b"anam"),
+          level: 0,
+          data_pointer: 0,
+          value: ModificationValue::String("TRIGSTR_001".to_string()),
+        }
+      ]
+    );
+  }
+}
@@ -0,0 +1,85 @@
+// The war3map.wpm file: The Path Map File
+
+use bitflags::bitflags;
+use flo_util::binary::*;
+
+const TAG: &[u8] = b"MP3W";
+
+bitflags! {
+  pub struct PathingFlags: u8 {
+    const UNWALKABLE = 0x02;
+    const UNFLYABLE = 0x04;
+    const UNBUILDABLE = 0x08;
+    const BLIGHT = 0x20;
+    const UNFLOATABLE = 0x40;
+    const UNKNOWN = 0x80;
+  }
+}
+
+/// Pathing cells are 32x32 world units, 4 per terrain tile in each direction
+#[derive(Debug, Clone)]
+pub struct PathingMap {
+  pub version: u32,
+  pub width: u32,
+  pub height: u32,
+  /// Row by row, starting from the bottom left corner
+  pub cells: Vec<PathingFlags>,
+}
+
+impl PathingMap {
+  pub fn get(&self, x: u32, y: u32) -> Option<PathingFlags> {
+    if x >= self.width || y >= self.height {
+      return None;
+    }
+    self.cells.get((y * self.width + x) as usize).cloned()
+  }
+}
+
+impl BinDecode for PathingMap {
+  const MIN_SIZE: usize = 4 + 4 + 4 + 4;
+  const FIXED_SIZE: bool = false;
+
+  fn decode<T: Buf>(buf: &mut T) -> Result<Self, BinDecodeError> {
+    buf.check_size(Self::MIN_SIZE)?;
+    buf.get_tag(TAG)?;
+    let version = buf.get_u32_le();
+    let width = buf.get_u32_le();
+    let height = buf.get_u32_le();
+    let len = (width as usize)
+      .checked_mul(height as usize)
+      .ok_or_else(|| BinDecodeError::failure("pathing map size overflow"))?;
+    buf.check_size(len)?;
+    let mut cells = Vec::with_capacity(len);
+    for _ in 0..len {
+      cells.push(PathingFlags::from_bits_truncate(buf.get_u8()));
+    }
+    Ok(Self {
+      version,
+      width,
+      height,
+      cells,
+    })
+  }
+}
+
+#[test]
+fn test_decode_pathing() {
+  let mut bytes = vec![];
+  bytes.extend_from_slice(TAG);
+  bytes.put_u32_le(0);
+  bytes.put_u32_le(2);
+  bytes.put_u32_le(2);
+  bytes.extend_from_slice(&[0x00, 0x0A, 0x4E, 0xCE]);
+
+  let map = PathingMap::decode(&mut bytes.as_slice()).unwrap();
+  assert_eq!(map.get(0, 0), Some(PathingFlags::empty()));
+  assert_eq!(
+    map.get(1, 0),
+    Some(PathingFlags::UNWALKABLE | PathingFlags::UNBUILDABLE)
+  );
+  assert!(map.get(0, 1).unwrap().contains(PathingFlags::UNFLOATABLE));
+  assert!(map.get(1, 1).unwrap().contains(PathingFlags::UNKNOWN));
+  assert_eq!(map.get(2, 0), None);
+
+  assert!(PathingMap::decode(&mut &bytes[..bytes.len() - 1]).is_err());
+}
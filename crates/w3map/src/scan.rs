@@ -0,0 +1,679 @@
+//! Checks uploaded maps before they are hosted: MPQ structure, size limits and script natives
+
+use std::io::Cursor;
+
+use flo_util::binary::*;
+use flo_util::BinDecode;
+use lazy_static::lazy_static;
+
+use crate::script::SCRIPT_PATHS;
+use crate::{MapInfo, MapScript, ScriptKind};
+
+const MPQ_MAGIC: &[u8] = b"MPQ\x1A";
+/// The game only looks for the MPQ header at 512 bytes boundaries
+const MPQ_HEADER_ALIGN: usize = 512;
+const MPQ_FILE_EXISTS: u32 = 0x8000_0000;
+const MPQ_HASH_FILE_KEY: u32 = 3;
+const BLOCK_TABLE_KEY_NAME: &str = "(block table)";
+const MAX_SECTOR_SIZE_SHIFT: u16 = 15;
+
+/// Neither the game nor the archive can run these, but nothing in a map needs them
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+  "asi", "bat", "cmd", "com", "dll", "exe", "flt", "hta", "js", "lnk", "m3d", "mix", "msi", "ps1",
+  "reg", "scr", "url", "vbs",
+];
+
+#[derive(Debug, Clone)]
+pub struct ScanLimits {
+  pub max_file_size: usize,
+  pub max_files: usize,
+  pub max_unpacked_file_size: u64,
+  pub max_unpacked_size: u64,
+  pub max_script_size: usize,
+}
+
+impl Default for ScanLimits {
+  fn default() -> Self {
+    ScanLimits {
+      max_file_size: 128 * 1024 * 1024,
+      max_files: 16384,
+      max_unpacked_file_size: 256 * 1024 * 1024,
+      max_unpacked_size: 512 * 1024 * 1024,
+      max_script_size: 32 * 1024 * 1024,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScanVerdict {
+  Clean,
+  /// Unusual but used by legit maps, e.g. save/load systems writing preload files
+  Suspicious,
+  /// Malformed archive, limit exceeded or a dangerous native, the map should not be hosted
+  Flagged,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanFinding {
+  pub verdict: ScanVerdict,
+  pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanReport {
+  pub verdict: ScanVerdict,
+  pub findings: Vec<ScanFinding>,
+  pub num_files: usize,
+  pub unpacked_size: u64,
+}
+
+impl ScanReport {
+  /// Sizes are checked against the block table before any file is decompressed
+  pub fn scan(bytes: &[u8], limits: &ScanLimits) -> Self {
+    let mut report = ScanReport {
+      verdict: ScanVerdict::Clean,
+      findings: vec![],
+      num_files: 0,
+      unpacked_size: 0,
+    };
+    report.scan_archive(bytes, limits);
+    report
+  }
+
+  pub fn is_flagged(&self) -> bool {
+    self.verdict == ScanVerdict::Flagged
+  }
+
+  fn add<T: Into<String>>(&mut self, verdict: ScanVerdict, message: T) {
+    self.verdict = self.verdict.max(verdict);
+    self.findings.push(ScanFinding {
+      verdict,
+      message: message.into(),
+    })
+  }
+
+  fn scan_archive(&mut self, bytes: &[u8], limits: &ScanLimits) {
+    if bytes.len() > limits.max_file_size {
+      self.add(
+        ScanVerdict::Flagged,
+        format!("file size {} exceeds {}", bytes.len(), limits.max_file_size),
+      );
+      return;
+    }
+
+    let blocks = match self.scan_structure(bytes) {
+      Some(blocks) => blocks,
+      None => return,
+    };
+
+    self.num_files = blocks.len();
+    self.unpacked_size = blocks.iter().map(|b| b.file_size as u64).sum();
+    if self.num_files > limits.max_files {
+      self.add(
+        ScanVerdict::Flagged,
+        format!("{} files exceed {}", self.num_files, limits.max_files),
+      );
+    }
+    if let Some(block) = blocks
+      .iter()
+      .find(|b| b.file_size as u64 > limits.max_unpacked_file_size)
+    {
+      self.add(
+        ScanVerdict::Flagged,
+        format!(
+          "unpacked file size {} exceeds {}",
+          block.file_size, limits.max_unpacked_file_size
+        ),
+      );
+    }
+    if self.unpacked_size > limits.max_unpacked_size {
+      self.add(
+        ScanVerdict::Flagged,
+        format!(
+          "unpacked size {} exceeds {}",
+          self.unpacked_size, limits.max_unpacked_size
+        ),
+      );
+    }
+
+    // decompressing is only safe within the limits
+    if !self.is_flagged() {
+      self.scan_files(bytes, limits);
+    }
+  }
+
+  /// Returns the existing blocks
+  fn scan_structure(&mut self, bytes: &[u8]) -> Option<Vec<BlockEntry>> {
+    let offset = match (0..bytes.len())
+      .step_by(MPQ_HEADER_ALIGN)
+      .find(|offset| bytes[*offset..].starts_with(MPQ_MAGIC))
+    {
+      Some(offset) => offset,
+      None => {
+        self.add(ScanVerdict::Flagged, "MPQ header not found");
+        return None;
+      }
+    };
+
+    let header = match MpqHeader::decode(&mut &bytes[(offset + MPQ_MAGIC.len())..]) {
+      Ok(header) => header,
+      Err(_) => {
+        self.add(ScanVerdict::Flagged, "MPQ header truncated");
+        return None;
+      }
+    };
+    if header.sector_size_shift > MAX_SECTOR_SIZE_SHIFT {
+      self.add(
+        ScanVerdict::Flagged,
+        format!(
+          "MPQ sector size shift {} is too large",
+          header.sector_size_shift
+        ),
+      );
+      return None;
+    }
+    if header.format_version > 3 {
+      self.add(
+        ScanVerdict::Suspicious,
+        format!("unknown MPQ format version {}", header.format_version),
+      );
+    }
+    if !header.hash_table_entries.is_power_of_two() {
+      self.add(
+        ScanVerdict::Suspicious,
+        format!(
+          "MPQ hash table size {} is not a power of two",
+          header.hash_table_entries
+        ),
+      );
+    }
+
+    let archive = &bytes[offset..];
+    let hash_table_end = header.hash_table_offset as u64 + header.hash_table_entries as u64 * 16;
+    if hash_table_end > archive.len() as u64 {
+      self.add(ScanVerdict::Suspicious, "MPQ hash table is out of bounds");
+    }
+
+    let start = (header.block_table_offset as usize).min(archive.len());
+    let available = (archive.len() - start) / BlockEntry::SIZE;
+    let entries = header.block_table_entries as usize;
+    if entries > available {
+      self.add(ScanVerdict::Suspicious, "MPQ block table is out of bounds");
+    }
+    let mut table: Vec<u32> = archive[start..]
+      .chunks_exact(4)
+      .take(entries.min(available) * 4)
+      .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+      .collect();
+    decrypt(
+      &mut table,
+      hash_string(BLOCK_TABLE_KEY_NAME, MPQ_HASH_FILE_KEY),
+    );
+
+    let blocks: Vec<BlockEntry> = table
+      .chunks_exact(4)
+      .map(|v| BlockEntry {
+        offset: v[0],
+        packed_size: v[1],
+        file_size: v[2],
+        flags: v[3],
+      })
+      .filter(|b| b.flags & MPQ_FILE_EXISTS != 0)
+      .collect();
+    let out_of_bounds = blocks
+      .iter()
+      .filter(|b| b.offset as u64 + b.packed_size as u64 > archive.len() as u64)
+      .count();
+    if out_of_bounds > 0 {
+      self.add(
+        ScanVerdict::Suspicious,
+        format!("{} files point outside the archive", out_of_bounds),
+      );
+    }
+    Some(blocks)
+  }
+
+  fn scan_files(&mut self, bytes: &[u8], limits: &ScanLimits) {
+    let mut archive = match ceres_mpq::Archive::open(Cursor::new(bytes)) {
+      Ok(archive) => archive,
+      Err(err) => {
+        self.add(ScanVerdict::Flagged, format!("open archive: {}", err));
+        return;
+      }
+    };
+
+    // protected maps remove the listfile, the files the game needs are still checked below
+    for path in archive.files().unwrap_or_default() {
+      if let Some(ext) = path.rsplit('.').next() {
+        if path.contains('.') && EXECUTABLE_EXTENSIONS.contains(&ext.to_lowercase().as_str()) {
+          self.add(
+            ScanVerdict::Suspicious,
+            format!("executable file `{}`", path),
+          );
+        }
+      }
+      match archive.read_file(&path) {
+        Ok(_) | Err(ceres_mpq::Error::FileNotFound) => {}
+        Err(err) => self.add(
+          ScanVerdict::Flagged,
+          format!("read file `{}`: {}", path, err),
+        ),
+      }
+    }
+
+    match archive.read_file("war3map.w3i") {
+      Ok(bytes) => {
+        if let Err(err) = MapInfo::decode(&mut bytes.as_slice()) {
+          self.add(
+            ScanVerdict::Flagged,
+            format!("invalid war3map.w3i: {}", err),
+          );
+        }
+      }
+      Err(err) => self.add(ScanVerdict::Flagged, format!("read war3map.w3i: {}", err)),
+    }
+
+    let script = SCRIPT_PATHS
+      .iter()
+      .find_map(|(path, kind)| Some((*path, *kind, archive.read_file(path).ok()?)));
+    match script {
+      Some((path, kind, bytes)) => {
+        if bytes.len() > limits.max_script_size {
+          self.add(
+            ScanVerdict::Flagged,
+            format!(
+              "script size {} exceeds {}",
+              bytes.len(),
+              limits.max_script_size
+            ),
+          );
+        } else {
+          self.scan_script(&MapScript::new(kind, path, &bytes));
+        }
+      }
+      None => self.add(ScanVerdict::Flagged, "map script not found"),
+    }
+  }
+
+  fn scan_script(&mut self, script: &MapScript) {
+    let tokens = tokenize(script.kind, &script.source);
+    let mut matched = vec![false; SCRIPT_RULES.len()];
+    for (i, token) in tokens.iter().enumerate() {
+      let name = match *token {
+        Token::Ident(name) => name.strip_prefix("_G.").unwrap_or(name),
+        _ => continue,
+      };
+
+      // each rule is reported once, at the first match
+      let rule = SCRIPT_RULES.iter().position(|rule| {
+        rule.kind.map(|kind| kind == script.kind).unwrap_or(true) && rule.matches(name)
+      });
+      if let Some(index) = rule {
+        if !matched[index] {
+          matched[index] = true;
+          let rule = &SCRIPT_RULES[index];
+          self.add(rule.verdict, format!("`{}` {}", name, rule.reason));
+        }
+      }
+
+      if name == "PreloadGenEnd" || name == "Preloader" {
+        let path = match (tokens.get(i + 1), tokens.get(i + 2)) {
+          (Some(Token::Punct('(')), Some(Token::Str(path))) => Some(path),
+          _ => None,
+        };
+        match path {
+          Some(path) if is_dangerous_preload_path(path) => self.add(
+            ScanVerdict::Flagged,
+            format!(
+              "`{}` accesses `{}` outside the CustomMapData folder",
+              name, path
+            ),
+          ),
+          Some(_) if name == "Preloader" => {}
+          Some(path) => self.add(
+            ScanVerdict::Suspicious,
+            format!("`{}` writes `{}`", name, path),
+          ),
+          None => self.add(
+            ScanVerdict::Suspicious,
+            format!("`{}` uses a computed path", name),
+          ),
+        }
+      }
+    }
+  }
+}
+
+#[derive(Debug, BinDecode)]
+struct MpqHeader {
+  _header_size: u32,
+  _archive_size: u32,
+  format_version: u16,
+  sector_size_shift: u16,
+  hash_table_offset: u32,
+  block_table_offset: u32,
+  hash_table_entries: u32,
+  block_table_entries: u32,
+}
+
+#[derive(Debug)]
+struct BlockEntry {
+  offset: u32,
+  packed_size: u32,
+  file_size: u32,
+  flags: u32,
+}
+
+impl BlockEntry {
+  const SIZE: usize = 16;
+}
+
+lazy_static! {
+  static ref CRYPT_TABLE: [u32; 0x500] = {
+    let mut table = [0_u32; 0x500];
+    let mut seed: u32 = 0x0010_0001;
+    for i in 0..0x100 {
+      for j in 0..5 {
+        seed = (seed * 125 + 3) % 0x2A_AAAB;
+        let high = (seed & 0xFFFF) << 16;
+        seed = (seed * 125 + 3) % 0x2A_AAAB;
+        table[i + j * 0x100] = high | (seed & 0xFFFF);
+      }
+    }
+    table
+  };
+}
+
+fn hash_string(value: &str, hash_type: u32) -> u32 {
+  let mut seed1: u32 = 0x7FED_7FED;
+  let mut seed2: u32 = 0xEEEE_EEEE;
+  for b in value.bytes() {
+    let b = b.to_ascii_uppercase() as u32;
+    seed1 = CRYPT_TABLE[(hash_type * 0x100 + b) as usize] ^ seed1.wrapping_add(seed2);
+    seed2 = b
+      .wrapping_add(seed1)
+      .wrapping_add(seed2)
+      .wrapping_add(seed2 << 5)
+      .wrapping_add(3);
+  }
+  seed1
+}
+
+fn decrypt(data: &mut [u32], mut key: u32) {
+  let mut seed: u32 = 0xEEEE_EEEE;
+  for v in data {
+    seed = seed.wrapping_add(CRYPT_TABLE[0x400 + (key & 0xFF) as usize]);
+    let plain = *v ^ key.wrapping_add(seed);
+    key = ((!key << 0x15).wrapping_add(0x1111_1111)) | (key >> 0x0B);
+    seed = plain
+      .wrapping_add(seed)
+      .wrapping_add(seed << 5)
+      .wrapping_add(3);
+    *v = plain;
+  }
+}
+
+struct ScriptRule {
+  kind: Option<ScriptKind>,
+  /// Names ending with `.` match the whole table
+  name: &'static str,
+  verdict: ScanVerdict,
+  reason: &'static str,
+}
+
+impl ScriptRule {
+  fn matches(&self, name: &str) -> bool {
+    if self.name.ends_with('.') {
+      name.starts_with(self.name)
+    } else {
+      name == self.name
+    }
+  }
+}
+
+macro_rules! rule {
+  ($kind:ident, $name:expr, $verdict:ident, $reason:expr) => {
+    ScriptRule {
+      kind: Some(ScriptKind::$kind),
+      name: $name,
+      verdict: ScanVerdict::$verdict,
+      reason: $reason,
+    }
+  };
+}
+
+/// The game doesn't expose these to map scripts, seeing them means the runtime is being probed
+const SCRIPT_RULES: &[ScriptRule] = &[
+  rule!(Lua, "os.execute", Flagged, "runs shell commands"),
+  rule!(Lua, "io.popen", Flagged, "runs shell commands"),
+  rule!(Lua, "os.remove", Flagged, "deletes files"),
+  rule!(Lua, "os.rename", Flagged, "moves files"),
+  rule!(Lua, "io.", Flagged, "accesses files"),
+  rule!(Lua, "package.loadlib", Flagged, "loads native libraries"),
+  rule!(Lua, "dofile", Flagged, "runs files from disk"),
+  rule!(Lua, "loadfile", Flagged, "runs files from disk"),
+  rule!(Lua, "debug.", Suspicious, "inspects the runtime"),
+  rule!(Lua, "string.dump", Suspicious, "dumps bytecode"),
+  rule!(Lua, "load", Suspicious, "runs generated code"),
+  rule!(Lua, "loadstring", Suspicious, "runs generated code"),
+  rule!(Lua, "require", Suspicious, "loads modules"),
+  rule!(
+    Jass,
+    "I2C",
+    Suspicious,
+    "is a return bug typecast used by memory exploits"
+  ),
+  rule!(
+    Jass,
+    "C2I",
+    Suspicious,
+    "is a return bug typecast used by memory exploits"
+  ),
+  rule!(Jass, "ReadRealMemory", Flagged, "reads game memory"),
+  rule!(Jass, "WriteRealMemory", Flagged, "writes game memory"),
+];
+
+/// Preload files belong in `CustomMapData`, the classic exploit writes a batch file to the
+/// Windows startup folder
+fn is_dangerous_preload_path(path: &str) -> bool {
+  let path = path.replace("\\\\", "\\").replace('/', "\\");
+  let ext = path.rsplit('.').next().unwrap_or("").to_lowercase();
+  path.contains("..")
+    || path.contains(':')
+    || path.starts_with('\\')
+    || (path.contains('.') && EXECUTABLE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+  /// Lua table accesses are joined, e.g. `os.execute`
+  Ident(&'a str),
+  Str(&'a str),
+  Punct(char),
+}
+
+/// Skips comments, string contents are kept as written
+fn tokenize(kind: ScriptKind, source: &str) -> Vec<Token<'_>> {
+  let bytes = source.as_bytes();
+  let mut tokens = vec![];
+  let mut i = 0;
+  while i < bytes.len() {
+    let b = bytes[i];
+    let rest = &source[i..];
+    if b.is_ascii_whitespace() {
+      i += 1;
+    } else if kind == ScriptKind::Jass && rest.starts_with("//") {
+      i += rest.find('\n').unwrap_or(rest.len());
+    } else if kind == ScriptKind::Lua && rest.starts_with("--") {
+      i += 2
+        + match lua_long_bracket(&rest[2..]) {
+          Some((_, len)) => len,
+          None => rest.find('\n').unwrap_or(rest.len()) - 2,
+        };
+    } else if b == b'"' || b == b'\'' {
+      let mut end = i + 1;
+      while end < bytes.len() && bytes[end] != b {
+        end += if bytes[end] == b'\\' { 2 } else { 1 };
+      }
+      let end = end.min(bytes.len());
+      tokens.push(Token::Str(&source[(i + 1)..end]));
+      i = end + 1;
+    } else if let Some((value, len)) = lua_long_bracket(rest).filter(|_| kind == ScriptKind::Lua) {
+      tokens.push(Token::Str(value));
+      i += len;
+    } else if b.is_ascii_alphanumeric() || b == b'_' {
+      let len = rest
+        .find(|c: char| {
+          !(c.is_ascii_alphanumeric() || c == '_' || (c == '.' && kind == ScriptKind::Lua))
+        })
+        .unwrap_or(rest.len());
+      tokens.push(Token::Ident(rest[..len].trim_end_matches('.')));
+      i += len;
+    } else {
+      let c = rest.chars().next().unwrap_or_default();
+      tokens.push(Token::Punct(c));
+      i += c.len_utf8();
+    }
+  }
+  tokens
+}
+
+/// `[[...]]` or `[==[...]==]`, returns the contents and the total length
+fn lua_long_bracket(value: &str) -> Option<(&str, usize)> {
+  let rest = value.strip_prefix('[')?;
+  let level = rest.find(|c: char| c != '=')?;
+  let rest = rest[level..].strip_prefix('[')?;
+  let close = format!("]{}]", "=".repeat(level));
+  let start = level + 2;
+  match rest.find(&close) {
+    Some(pos) => Some((&rest[..pos], start + pos + close.len())),
+    None => Some((rest, value.len())),
+  }
+}
+
+#[test]
+fn test_block_table_key() {
+  assert_eq!(
+    hash_string(BLOCK_TABLE_KEY_NAME, MPQ_HASH_FILE_KEY),
+    0xEC83_B3A3
+  );
+  assert_eq!(hash_string("(hash table)", MPQ_HASH_FILE_KEY), 0xC3AF_3770);
+}
+
+#[test]
+fn test_tokenize() {
+  let tokens = tokenize(
+    ScriptKind::Lua,
+    "--[[ os.execute ]] local f = _G.os.execute(\"a\") -- io.open\nprint([==[x]]y]==])",
+  );
+  assert_eq!(
+    tokens,
+    vec![
+      Token::Ident("local"),
+      Token::Ident("f"),
+      Token::Punct('='),
+      Token::Ident("_G.os.execute"),
+      Token::Punct('('),
+      Token::Str("a"),
+      Token::Punct(')'),
+      Token::Ident("print"),
+      Token::Punct('('),
+      Token::Str("x]]y"),
+      Token::Punct(')'),
+    ]
+  );
+
+  let tokens = tokenize(
+    ScriptKind::Jass,
+    "// PreloadGenEnd(\"x\")\ncall PreloadGenEnd(\"a\\\\b.pld\")",
+  );
+  assert_eq!(
+    tokens,
+    vec![
+      Token::Ident("call"),
+      Token::Ident("PreloadGenEnd"),
+      Token::Punct('('),
+      Token::Str("a\\\\b.pld"),
+      Token::Punct(')'),
+    ]
+  );
+}
+
+#[test]
+fn test_scan_script() {
+  fn scan(kind: ScriptKind, source: &str) -> ScanReport {
+    let mut report = ScanReport {
+      verdict: ScanVerdict::Clean,
+      findings: vec![],
+      num_files: 0,
+      unpacked_size: 0,
+    };
+    report.scan_script(&MapScript::new(kind, "war3map", source.as_bytes()));
+    report
+  }
+
+  let report = scan(
+    ScriptKind::Jass,
+    "function main takes nothing returns nothing\n  // os.execute\n  call Preloader(\"save\\\\slot1.pld\")\nendfunction\n",
+  );
+  assert_eq!(report.verdict, ScanVerdict::Clean);
+
+  let report = scan(
+    ScriptKind::Jass,
+    "call PreloadGenEnd(\"save\\\\slot1.pld\")",
+  );
+  assert_eq!(report.verdict, ScanVerdict::Suspicious);
+
+  let report = scan(
+    ScriptKind::Jass,
+    "call PreloadGenEnd(\"..\\\\..\\\\AppData\\\\Roaming\\\\Microsoft\\\\Windows\\\\Start Menu\\\\Programs\\\\Startup\\\\x.bat\")",
+  );
+  assert_eq!(report.verdict, ScanVerdict::Flagged);
+
+  let report = scan(
+    ScriptKind::Lua,
+    "function main()\n  local h = io.popen('dir')\n  h:close()\nend\n",
+  );
+  assert_eq!(report.verdict, ScanVerdict::Flagged);
+  assert_eq!(report.findings.len(), 1);
+
+  let report = scan(
+    ScriptKind::Lua,
+    "local t = load(code)\nlocal u = load(code)\n",
+  );
+  assert_eq!(report.verdict, ScanVerdict::Suspicious);
+  assert_eq!(report.findings.len(), 1);
+}
+
+#[test]
+fn test_scan_structure() {
+  let report = ScanReport::scan(b"not a map", &ScanLimits::default());
+  assert_eq!(report.verdict, ScanVerdict::Flagged);
+
+  let report = ScanReport::scan(
+    &[0_u8; 1024],
+    &ScanLimits {
+      max_file_size: 512,
+      ..Default::default()
+    },
+  );
+  assert_eq!(report.verdict, ScanVerdict::Flagged);
+  assert_eq!(report.findings.len(), 1);
+}
+
+#[test]
+fn test_scan_map() {
+  let bytes = std::fs::read(flo_util::sample_path!("map", "(2)ConcealedHill.w3x")).unwrap();
+  let report = ScanReport::scan(&bytes, &ScanLimits::default());
+  assert_eq!(report.verdict, ScanVerdict::Clean, "{:?}", report.findings);
+  assert!(report.num_files > 0);
+
+  let report = ScanReport::scan(
+    &bytes,
+    &ScanLimits {
+      max_files: 1,
+      ..Default::default()
+    },
+  );
+  assert!(report.is_flagged());
+}
@@ -0,0 +1,86 @@
+// The war3map.j / war3map.lua file: The Map Script
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptKind {
+  Jass,
+  Lua,
+}
+
+/// Paths in the order the game looks them up
+pub(crate) const SCRIPT_PATHS: &[(&str, ScriptKind)] = &[
+  ("war3map.j", ScriptKind::Jass),
+  ("scripts\\war3map.j", ScriptKind::Jass),
+  ("war3map.lua", ScriptKind::Lua),
+];
+
+#[derive(Debug, Clone)]
+pub struct MapScript {
+  pub kind: ScriptKind,
+  pub path: &'static str,
+  pub source: String,
+}
+
+impl MapScript {
+  /// Scripts are not always valid UTF-8, invalid sequences are replaced
+  pub(crate) fn new(kind: ScriptKind, path: &'static str, bytes: &[u8]) -> Self {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    Self {
+      kind,
+      path,
+      source: String::from_utf8_lossy(bytes).into_owned(),
+    }
+  }
+
+  /// Names of the functions defined in the script
+  pub fn functions(&self) -> impl Iterator<Item = &str> {
+    let kind = self.kind;
+    self.source.lines().filter_map(move |line| {
+      let line = line.trim_start();
+      let rest = match kind {
+        ScriptKind::Jass => line
+          .strip_prefix("constant ")
+          .unwrap_or(line)
+          .trim_start()
+          .strip_prefix("function ")?,
+        ScriptKind::Lua => line
+          .strip_prefix("local ")
+          .unwrap_or(line)
+          .trim_start()
+          .strip_prefix("function ")?,
+      };
+      let name = rest
+        .trim_start()
+        .split(|c: char| c.is_whitespace() || c == '(')
+        .next()?;
+      if name.is_empty() {
+        None
+      } else {
+        Some(name)
+      }
+    })
+  }
+}
+
+#[test]
+fn test_script_functions() {
+  let script = MapScript::new(
+    ScriptKind::Jass,
+    "war3map.j",
+    b"\xEF\xBB\xBFglobals\nendglobals\nfunction main takes nothing returns nothing\nendfunction\n  constant function config takes nothing returns nothing\nendfunction\n",
+  );
+  assert!(script.source.starts_with("globals"));
+  assert_eq!(
+    script.functions().collect::<Vec<_>>(),
+    vec!["main", "config"]
+  );
+
+  let script = MapScript::new(
+    ScriptKind::Lua,
+    "war3map.lua",
+    b"function main()\nend\nlocal function helper(a)\nend\n",
+  );
+  assert_eq!(
+    script.functions().collect::<Vec<_>>(),
+    vec!["main", "helper"]
+  );
+}
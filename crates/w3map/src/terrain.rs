@@ -0,0 +1,210 @@
+// The war3map.w3e file: The Environment File
+
+use crate::object_data::ObjectId;
+use bitflags::bitflags;
+use flo_util::binary::*;
+
+const TAG: &[u8] = b"W3E!";
+const TILE_POINT_SIZE_V11: usize = 7;
+const TILE_POINT_SIZE_V12: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct Terrain {
+  pub version: u32,
+  /// Main tileset, e.g. `A` for Ashenvale
+  pub tileset: u8,
+  pub custom_tilesets: bool,
+  pub ground_tilesets: Vec<ObjectId>,
+  pub cliff_tilesets: Vec<ObjectId>,
+  /// Number of tile points (corners) in a row, map width + 1
+  pub width: u32,
+  /// Number of tile points (corners) in a column, map height + 1
+  pub height: u32,
+  /// World coordinates of the bottom left corner
+  pub center_offset: [f32; 2],
+  /// Row by row, starting from the bottom left corner
+  pub tile_points: Vec<TilePoint>,
+}
+
+impl Terrain {
+  pub fn get(&self, x: u32, y: u32) -> Option<&TilePoint> {
+    if x >= self.width || y >= self.height {
+      return None;
+    }
+    self.tile_points.get((y * self.width + x) as usize)
+  }
+}
+
+impl BinDecode for Terrain {
+  const MIN_SIZE: usize = 4 + 4 + 1 + 4 + 4 + 4 + 8 + 8;
+  const FIXED_SIZE: bool = false;
+
+  fn decode<T: Buf>(buf: &mut T) -> Result<Self, BinDecodeError> {
+    buf.check_size(Self::MIN_SIZE)?;
+    buf.get_tag(TAG)?;
+    let version = buf.get_u32_le();
+    let tile_point_size = match version {
+      11 => TILE_POINT_SIZE_V11,
+      12 => TILE_POINT_SIZE_V12,
+      other => {
+        return Err(BinDecodeError::failure(format!(
+          "unsupported terrain version: {}",
+          other
+        )))
+      }
+    };
+    let tileset = buf.get_u8();
+    let custom_tilesets = buf.get_u32_le() != 0;
+    let num_ground_tilesets = buf.get_u32_le();
+    let ground_tilesets = buf.get_repeated(num_ground_tilesets as usize)?;
+    buf.check_size(4)?;
+    let num_cliff_tilesets = buf.get_u32_le();
+    let cliff_tilesets = buf.get_repeated(num_cliff_tilesets as usize)?;
+    buf.check_size(16)?;
+    let width = buf.get_u32_le();
+    let height = buf.get_u32_le();
+    let center_offset = [buf.get_f32_le(), buf.get_f32_le()];
+
+    let len = (width as usize)
+      .checked_mul(height as usize)
+      .ok_or_else(|| BinDecodeError::failure("terrain size overflow"))?;
+    buf.check_size(len.saturating_mul(tile_point_size))?;
+    let mut tile_points = Vec::with_capacity(len);
+    for _ in 0..len {
+      tile_points.push(TilePoint::decode_versioned(buf, version));
+    }
+
+    Ok(Self {
+      version,
+      tileset,
+      custom_tilesets,
+      ground_tilesets,
+      cliff_tilesets,
+      width,
+      height,
+      center_offset,
+      tile_points,
+    })
+  }
+}
+
+bitflags! {
+  pub struct TilePointFlags: u8 {
+    const RAMP = 0x01;
+    const BLIGHT = 0x02;
+    const WATER = 0x04;
+    const BOUNDARY = 0x08;
+    /// Set on the water level field, marks the unplayable map edge
+    const MAP_EDGE = 0x10;
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TilePoint {
+  pub ground_height: i16,
+  pub water_level: i16,
+  pub flags: TilePointFlags,
+  /// Index into `Terrain::ground_tilesets`
+  pub ground_texture: u8,
+  pub texture_details: u8,
+  /// Index into `Terrain::cliff_tilesets`
+  pub cliff_texture: u8,
+  pub layer_height: u8,
+}
+
+impl TilePoint {
+  /// Caller checks the remaining size
+  fn decode_versioned<T: Buf>(buf: &mut T, version: u32) -> Self {
+    let ground_height = buf.get_i16_le();
+    let water_level = buf.get_u16_le();
+    let mut flags = TilePointFlags::empty();
+    if water_level & 0x4000 != 0 {
+      flags |= TilePointFlags::MAP_EDGE;
+    }
+    let (ground_texture, flag_bits) = if version >= 12 {
+      let v = buf.get_u16_le();
+      ((v & 0x3F) as u8, (v >> 6) as u8)
+    } else {
+      let v = buf.get_u8();
+      (v & 0x0F, v >> 4)
+    };
+    flags |= TilePointFlags::from_bits_truncate(flag_bits & 0x0F);
+    let texture_details = buf.get_u8();
+    let cliff = buf.get_u8();
+    Self {
+      ground_height,
+      water_level: (water_level & 0x3FFF) as i16,
+      flags,
+      ground_texture,
+      texture_details,
+      cliff_texture: cliff >> 4,
+      layer_height: cliff & 0x0F,
+    }
+  }
+
+  /// Ground height in world units
+  pub fn height(&self) -> f32 {
+    (self.ground_height as f32 - 8192.0 + (self.layer_height as f32 - 2.0) * 512.0) / 4.0
+  }
+
+  /// Water surface height in world units
+  pub fn water_height(&self) -> f32 {
+    (self.water_level as f32 - 8192.0) / 4.0 - 89.6
+  }
+
+  pub fn is_water(&self) -> bool {
+    self.flags.contains(TilePointFlags::WATER) && self.water_height() > self.height()
+  }
+}
+
+#[test]
+fn test_decode_terrain() {
+  let mut bytes = vec![];
+  bytes.extend_from_slice(TAG);
+  bytes.put_u32_le(11);
+  bytes.put_u8(b'L');
+  bytes.put_u32_le(0);
+  bytes.put_u32_le(2);
+  bytes.extend_from_slice(b"Ldrt");
+  bytes.extend_from_slice(b"Lgrs");
+  bytes.put_u32_le(1);
+  bytes.extend_from_slice(b"CLdi");
+  bytes.put_u32_le(2);
+  bytes.put_u32_le(1);
+  bytes.put_f32_le(-128.0);
+  bytes.put_f32_le(-128.0);
+  // plain ground
+  bytes.put_i16_le(0x2000);
+  bytes.put_u16_le(0x2000);
+  bytes.put_u8(0x01);
+  bytes.put_u8(0);
+  bytes.put_u8(0x02);
+  // water on the map edge
+  bytes.put_i16_le(0x1E00);
+  bytes.put_u16_le(0x4000 | 0x2000);
+  bytes.put_u8(0x40);
+  bytes.put_u8(0);
+  bytes.put_u8(0x01);
+
+  let terrain = Terrain::decode(&mut bytes.as_slice()).unwrap();
+  assert_eq!(terrain.tileset, b'L');
+  assert_eq!(
+    terrain.ground_tilesets,
+    vec![ObjectId::new(b"Ldrt"), ObjectId::new(b"Lgrs")]
+  );
+  assert_eq!(terrain.cliff_tilesets, vec![ObjectId::new(b"CLdi")]);
+  assert_eq!((terrain.width, terrain.height), (2, 1));
+
+  let ground = terrain.get(0, 0).unwrap();
+  assert_eq!(ground.ground_texture, 1);
+  assert_eq!(ground.height(), 0.0);
+  assert!(!ground.is_water());
+
+  let water = terrain.get(1, 0).unwrap();
+  assert_eq!(water.water_level, 0x2000);
+  assert!(water.flags.contains(TilePointFlags::MAP_EDGE));
+  assert!(water.flags.contains(TilePointFlags::WATER));
+  assert_eq!(water.layer_height, 1);
+  assert!(water.is_water());
+  assert!(terrain.get(2, 0).is_none());
+}
@@ -189,6 +189,17 @@ impl BinDecode for TriggerStringRef {
   }
 }
 
+impl BinEncode for TriggerStringRef {
+  fn encode<T: BufMut>(&self, buf: &mut T) {
+    match *self {
+      TriggerStringRef::Null => {}
+      TriggerStringRef::Ref(id) => buf.put_slice(format!("TRIGSTR_{:03}", id).as_bytes()),
+      TriggerStringRef::Inline(ref value) => buf.put_slice(value.as_bytes()),
+    }
+    buf.put_u8(0);
+  }
+}
+
 #[test]
 fn test_parse_trigger_string_file() {
   let mut bytes = &include_bytes!("../../../deps/wc3-samples/map/test_tft.wts")[..];
@@ -0,0 +1,306 @@
+// The war3mapUnits.doo file: The Unit and Item Placement File
+
+use crate::doodads::{DroppedItemSet, PlacementHeader};
+use crate::object_data::ObjectId;
+use flo_util::binary::*;
+use flo_util::BinDecode;
+
+pub const START_LOCATION_ID: ObjectId = ObjectId::new(b"sloc");
+pub const GOLD_MINE_ID: ObjectId = ObjectId::new(b"ngol");
+
+#[derive(Debug, Clone)]
+pub struct UnitPlacements {
+  pub version: u32,
+  pub subversion: u32,
+  pub units: Vec<UnitPlacement>,
+}
+
+impl UnitPlacements {
+  pub fn start_locations(&self) -> impl Iterator<Item = &UnitPlacement> {
+    self.units.iter().filter(|u| u.is_start_location())
+  }
+
+  pub fn gold_mines(&self) -> impl Iterator<Item = &UnitPlacement> {
+    self.units.iter().filter(|u| u.is_gold_mine())
+  }
+}
+
+impl BinDecode for UnitPlacements {
+  const MIN_SIZE: usize = PlacementHeader::MIN_SIZE;
+  const FIXED_SIZE: bool = false;
+
+  fn decode<T: Buf>(buf: &mut T) -> Result<Self, BinDecodeError> {
+    let header = PlacementHeader::decode(buf)?;
+    let mut units = vec![];
+    for i in 0..header.count {
+      units.push(UnitPlacement::decode_with_header(buf, &header).map_err(|e| e.context(i))?);
+    }
+    Ok(Self {
+      version: header.version,
+      subversion: header.subversion,
+      units,
+    })
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct UnitPlacement {
+  pub type_id: ObjectId,
+  pub variation: u32,
+  pub position: [f32; 3],
+  /// Radians
+  pub angle: f32,
+  pub scale: [f32; 3],
+  pub skin_id: Option<ObjectId>,
+  pub flags: u8,
+  pub owner: i32,
+  /// -1 for the default value
+  pub hit_points: i32,
+  /// -1 for the default value
+  pub mana_points: i32,
+  /// -1 if the map item table isn't used
+  pub item_table: i32,
+  pub item_sets: Vec<DroppedItemSet>,
+  pub gold: i32,
+  /// -1 for normal, -2 for camp
+  pub target_acquisition: f32,
+  pub hero_level: i32,
+  pub hero_attributes: Option<HeroAttributes>,
+  pub inventory: Vec<InventoryItem>,
+  pub abilities: Vec<ModifiedAbility>,
+  pub random: RandomUnit,
+  /// -1 for the owner's color
+  pub color: i32,
+  /// Editor id of the destination region, -1 if not a waygate
+  pub waygate: i32,
+  pub editor_id: u32,
+}
+
+impl UnitPlacement {
+  pub fn is_start_location(&self) -> bool {
+    self.type_id == START_LOCATION_ID
+  }
+
+  pub fn is_gold_mine(&self) -> bool {
+    self.type_id == GOLD_MINE_ID
+  }
+
+  fn decode_with_header<T: Buf>(
+    buf: &mut T,
+    header: &PlacementHeader,
+  ) -> Result<Self, BinDecodeError> {
+    let type_id = ObjectId::decode(buf)?;
+    buf.check_size(4 + 12 + 4 + 12)?;
+    let variation = buf.get_u32_le();
+    let position = [buf.get_f32_le(), buf.get_f32_le(), buf.get_f32_le()];
+    let angle = buf.get_f32_le();
+    let scale = [buf.get_f32_le(), buf.get_f32_le(), buf.get_f32_le()];
+    let skin_id = if header.has_skin() {
+      Some(ObjectId::decode(buf)?)
+    } else {
+      None
+    };
+    buf.check_size(1 + 4 + 2 + 4 + 4)?;
+    let flags = buf.get_u8();
+    let owner = buf.get_i32_le();
+    buf.advance(2);
+    let hit_points = buf.get_i32_le();
+    let mana_points = buf.get_i32_le();
+    let item_table = if header.is_expansion() {
+      buf.check_size(4)?;
+      buf.get_i32_le()
+    } else {
+      -1
+    };
+    buf.check_size(4)?;
+    let num_sets = buf.get_u32_le();
+    let item_sets = buf.get_repeated(num_sets as usize)?;
+    buf.check_size(4 + 4 + 4)?;
+    let gold = buf.get_i32_le();
+    let target_acquisition = buf.get_f32_le();
+    let hero_level = buf.get_i32_le();
+    let hero_attributes = if header.is_expansion() {
+      Some(HeroAttributes::decode(buf)?)
+    } else {
+      None
+    };
+    buf.check_size(4)?;
+    let num_items = buf.get_u32_le();
+    let inventory = buf.get_repeated(num_items as usize)?;
+    buf.check_size(4)?;
+    let num_abilities = buf.get_u32_le();
+    let abilities = buf.get_repeated(num_abilities as usize)?;
+    let random = RandomUnit::decode(buf)?;
+    buf.check_size(4 + 4 + 4)?;
+    let color = buf.get_i32_le();
+    let waygate = buf.get_i32_le();
+    let editor_id = buf.get_u32_le();
+    Ok(Self {
+      type_id,
+      variation,
+      position,
+      angle,
+      scale,
+      skin_id,
+      flags,
+      owner,
+      hit_points,
+      mana_points,
+      item_table,
+      item_sets,
+      gold,
+      target_acquisition,
+      hero_level,
+      hero_attributes,
+      inventory,
+      abilities,
+      random,
+      color,
+      waygate,
+      editor_id,
+    })
+  }
+}
+
+#[derive(Debug, Clone, BinDecode)]
+pub struct HeroAttributes {
+  pub strength: i32,
+  pub agility: i32,
+  pub intelligence: i32,
+}
+
+#[derive(Debug, Clone, BinDecode)]
+pub struct InventoryItem {
+  pub slot: u32,
+  pub id: ObjectId,
+}
+
+#[derive(Debug, Clone, BinDecode)]
+pub struct ModifiedAbility {
+  pub id: ObjectId,
+  pub autocast: u32,
+  pub level: u32,
+}
+
+/// Random units and items placed with `YYU#`, `iDNR` etc.
+#[derive(Debug, Clone)]
+pub enum RandomUnit {
+  /// Any unit or item of a level, `level` is 24 bits
+  Any { level: u32, item_class: u8 },
+  /// Picked from a random group of the map
+  Group { group: u32, position: u32 },
+  /// Picked from a custom list
+  Custom(Vec<RandomUnitChoice>),
+}
+
+#[derive(Debug, Clone, BinDecode)]
+pub struct RandomUnitChoice {
+  pub id: ObjectId,
+  /// Percentage
+  pub chance: u32,
+}
+
+impl BinDecode for RandomUnit {
+  const MIN_SIZE: usize = 8;
+  const FIXED_SIZE: bool = false;
+
+  fn decode<T: Buf>(buf: &mut T) -> Result<Self, BinDecodeError> {
+    buf.check_size(Self::MIN_SIZE)?;
+    match buf.get_u32_le() {
+      0 => {
+        let v = buf.get_u32_le();
+        Ok(RandomUnit::Any {
+          level: v & 0x00FF_FFFF,
+          item_class: (v >> 24) as u8,
+        })
+      }
+      1 => {
+        buf.check_size(4)?;
+        Ok(RandomUnit::Group {
+          group: buf.get_u32_le(),
+          position: buf.get_u32_le(),
+        })
+      }
+      2 => {
+        let count = buf.get_u32_le();
+        Ok(RandomUnit::Custom(buf.get_repeated(count as usize)?))
+      }
+      other => Err(BinDecodeError::failure(format!(
+        "unknown random unit flag: {}",
+        other
+      ))),
+    }
+  }
+}
+
+#[test]
+fn test_decode_units() {
+  use crate::doodads::put_placement_common;
+
+  fn put_unit(buf: &mut Vec<u8>, id: &[u8; 4], owner: i32, x: f32, y: f32, random: &[u32]) {
+    put_placement_common(buf, id, x, y);
+    buf.put_u8(2);
+    buf.put_i32_le(owner);
+    buf.put_u8(0);
+    buf.put_u8(0);
+    buf.put_i32_le(-1);
+    buf.put_i32_le(-1);
+    buf.put_i32_le(-1);
+    buf.put_u32_le(0);
+    buf.put_i32_le(12500);
+    buf.put_f32_le(-1.0);
+    buf.put_i32_le(1);
+    buf.put_i32_le(0);
+    buf.put_i32_le(0);
+    buf.put_i32_le(0);
+    buf.put_u32_le(0);
+    buf.put_u32_le(1);
+    buf.extend_from_slice(b"Ahrl");
+    buf.put_u32_le(0);
+    buf.put_u32_le(1);
+    for v in random {
+      buf.put_u32_le(*v);
+    }
+    buf.put_i32_le(-1);
+    buf.put_i32_le(-1);
+    buf.put_u32_le(0);
+  }
+
+  let mut bytes = vec![];
+  bytes.extend_from_slice(b"W3do");
+  bytes.put_u32_le(8);
+  bytes.put_u32_le(11);
+  bytes.put_u32_le(4);
+  put_unit(&mut bytes, b"sloc", 0, -3000.0, 2000.0, &[0, 0xFFFFFF]);
+  put_unit(&mut bytes, b"sloc", 1, 3000.0, -2000.0, &[0, 0xFFFFFF]);
+  put_unit(&mut bytes, b"ngol", 15, -2500.0, 2200.0, &[1, 0, 2]);
+  put_unit(
+    &mut bytes,
+    b"hfoo",
+    0,
+    0.0,
+    0.0,
+    &[2, 1, u32::from_le_bytes(*b"hfoo"), 100],
+  );
+
+  let units = UnitPlacements::decode(&mut bytes.as_slice()).unwrap();
+  assert_eq!(units.units.len(), 4);
+  let locations: Vec<_> = units
+    .start_locations()
+    .map(|u| (u.owner, u.position[0], u.position[1]))
+    .collect();
+  assert_eq!(locations, vec![(0, -3000.0, 2000.0), (1, 3000.0, -2000.0)]);
+  let mines: Vec<_> = units.gold_mines().collect();
+  assert_eq!(mines.len(), 1);
+  assert_eq!(mines[0].gold, 12500);
+  assert!(matches!(
+    mines[0].random,
+    RandomUnit::Group { position: 2, .. }
+  ));
+  let footman = &units.units[3];
+  assert_eq!(footman.abilities[0].id, b"Ahrl");
+  match footman.random {
+    RandomUnit::Custom(ref choices) => assert_eq!(choices[0].id, b"hfoo"),
+    ref other => panic!("unexpected {:?}", other),
+  }
+}
@@ -0,0 +1,219 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use ceres_mpq::{Creator, FileOptions};
+use flo_blp::{BLPEncoder, BLPFormat};
+use flo_util::binary::{BinDecode, BinEncode};
+use image::RgbaImage;
+
+use crate::error::{Error, Result};
+use crate::{MapChecksum, MapInfo, W3Map};
+
+const HEADER_SIZE: usize = 512;
+const HEADER_MAGIC: &[u8] = b"HM3W";
+const INFO_PATH: &str = "war3map.w3i";
+const PREVIEW_PATH: &str = "war3mapMap.blp";
+/// Regenerated by the MPQ creator or stale after the rewrite
+const SKIPPED_PATHS: &[&str] = &["(listfile)", "(attributes)", "(signature)"];
+
+/// Writes a modified copy of a map archive
+///
+/// All files named in the source `(listfile)` are copied, protected maps without one can't be
+/// rewritten. The 512 bytes `HM3W` header is kept and its flags and player count follow
+/// `war3map.w3i`.
+#[derive(Debug)]
+pub struct MapWriter {
+  header: Option<Vec<u8>>,
+  files: Vec<(String, Vec<u8>)>,
+}
+
+impl MapWriter {
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+    Self::open_memory(&std::fs::read(path)?)
+  }
+
+  pub fn open_memory(bytes: &[u8]) -> Result<Self> {
+    let header = if bytes.starts_with(HEADER_MAGIC) && bytes.len() >= HEADER_SIZE {
+      Some(bytes[..HEADER_SIZE].to_vec())
+    } else {
+      None
+    };
+
+    let mut archive = ceres_mpq::Archive::open(Cursor::new(bytes))?;
+    let paths = archive.files().ok_or(Error::MapListFileNotFound)?;
+    let mut files = Vec::with_capacity(paths.len());
+    for path in paths {
+      if SKIPPED_PATHS.iter().any(|skipped| path_eq(skipped, &path)) {
+        continue;
+      }
+      match archive.read_file(&path) {
+        Ok(bytes) => files.push((path, bytes)),
+        // listfiles may name files which are not in the archive
+        Err(ceres_mpq::Error::FileNotFound) => {}
+        Err(err) => return Err(err.into()),
+      }
+    }
+
+    Ok(MapWriter { header, files })
+  }
+
+  pub fn file(&self, path: &str) -> Option<&[u8]> {
+    self
+      .files
+      .iter()
+      .find(|(p, _)| path_eq(p, path))
+      .map(|(_, bytes)| bytes.as_slice())
+  }
+
+  pub fn files(&self) -> impl Iterator<Item = &str> {
+    self.files.iter().map(|(path, _)| path.as_str())
+  }
+
+  /// Adds a file or replaces the existing one, paths are case-insensitive
+  pub fn put_file<T: Into<Vec<u8>>>(&mut self, path: &str, bytes: T) {
+    let bytes = bytes.into();
+    match self.files.iter_mut().find(|(p, _)| path_eq(p, path)) {
+      Some(file) => file.1 = bytes,
+      None => self.files.push((path.to_string(), bytes)),
+    }
+  }
+
+  pub fn remove_file(&mut self, path: &str) -> Option<Vec<u8>> {
+    let index = self.files.iter().position(|(p, _)| path_eq(p, path))?;
+    Some(self.files.remove(index).1)
+  }
+
+  pub fn info(&self) -> Result<MapInfo> {
+    let mut bytes = self
+      .file(INFO_PATH)
+      .ok_or_else(|| Error::StorageFileNotFound(INFO_PATH.to_string()))?;
+    MapInfo::decode(&mut bytes).map_err(Error::ReadInfo)
+  }
+
+  /// `num_players` and `num_forces` must match the lengths of the player and force lists
+  pub fn set_info(&mut self, info: &MapInfo) {
+    if let Some(ref mut header) = self.header {
+      patch_header(header, info);
+    }
+    self.put_file(INFO_PATH, info.encode_to_bytes().to_vec());
+  }
+
+  pub fn update_info<F>(&mut self, f: F) -> Result<()>
+  where
+    F: FnOnce(&mut MapInfo),
+  {
+    let mut info = self.info()?;
+    f(&mut info);
+    self.set_info(&info);
+    Ok(())
+  }
+
+  /// Replaces `war3mapMap.blp`, the image shown in the lobby and the minimap
+  pub fn set_preview(&mut self, image: &RgbaImage) {
+    let bytes = BLPEncoder::new(BLPFormat::Blp1Palettized).encode(image);
+    self.put_file(PREVIEW_PATH, bytes);
+  }
+
+  pub fn write(&self) -> Result<Vec<u8>> {
+    let mut creator = Creator::default();
+    for (path, bytes) in &self.files {
+      creator.add_file(
+        path,
+        bytes.as_slice(),
+        FileOptions {
+          encrypt: false,
+          compress: true,
+          adjust_key: false,
+        },
+      );
+    }
+    let mut mpq = Cursor::new(vec![]);
+    creator.write(&mut mpq)?;
+
+    let mpq = mpq.into_inner();
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + mpq.len());
+    if let Some(ref header) = self.header {
+      bytes.extend_from_slice(header);
+    }
+    bytes.extend(mpq);
+    Ok(bytes)
+  }
+
+  pub fn write_with_checksum(&self) -> Result<(Vec<u8>, MapChecksum)> {
+    let bytes = self.write()?;
+    let checksum = MapChecksum::compute(&mut W3Map::open_archive_memory(&bytes)?)?;
+    Ok((bytes, checksum))
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<MapChecksum> {
+    let (bytes, checksum) = self.write_with_checksum()?;
+    std::fs::write(path, bytes)?;
+    Ok(checksum)
+  }
+}
+
+fn path_eq(a: &str, b: &str) -> bool {
+  a.len() == b.len()
+    && a.bytes().zip(b.bytes()).all(|(a, b)| {
+      let normalize = |v: u8| {
+        if v == b'/' {
+          b'\\'
+        } else {
+          v.to_ascii_lowercase()
+        }
+      };
+      normalize(a) == normalize(b)
+    })
+}
+
+/// magic, unused u32, map name, flags, max players
+fn patch_header(header: &mut [u8], info: &MapInfo) {
+  let name_start = HEADER_MAGIC.len() + 4;
+  let name_end = match header[name_start..].iter().position(|b| *b == 0) {
+    Some(pos) => name_start + pos + 1,
+    None => return,
+  };
+  if name_end + 8 > header.len() {
+    return;
+  }
+  header[name_end..(name_end + 4)].copy_from_slice(&info.flags.to_le_bytes());
+  header[(name_end + 4)..(name_end + 8)].copy_from_slice(&info.num_players.to_le_bytes());
+}
+
+#[test]
+fn test_path_eq() {
+  assert!(path_eq("war3map.j", "War3Map.J"));
+  assert!(path_eq("scripts\\war3map.j", "scripts/war3map.j"));
+  assert!(!path_eq("war3map.j", "war3map.lua"));
+}
+
+#[test]
+fn test_write_map() {
+  use crate::MapFlags;
+
+  let path = flo_util::sample_path!("map", "(2)ConcealedHill.w3x");
+  let mut writer = MapWriter::open(&path).unwrap();
+  let flags = writer.info().unwrap().flags
+    | (MapFlags::FIXED_PLAYER_SETTINGS | MapFlags::CUSTOM_FORCES).bits();
+  writer.update_info(|info| info.flags = flags).unwrap();
+  writer.set_preview(&RgbaImage::from_pixel(
+    64,
+    64,
+    image::Rgba([10, 200, 10, 255]),
+  ));
+  writer.put_file(
+    "scripts\\flo.j",
+    &b"function FloInit takes nothing returns nothing\nendfunction\n"[..],
+  );
+
+  let (bytes, checksum) = writer.write_with_checksum().unwrap();
+  let (map, memory_checksum) = W3Map::open_memory_with_checksum(&bytes).unwrap();
+  assert_eq!(checksum, memory_checksum);
+  assert_eq!(map.flags().bits(), flags);
+  assert_eq!(map.num_players(), 2);
+  assert_eq!(bytes[..4], *HEADER_MAGIC);
+
+  let writer = MapWriter::open_memory(&bytes).unwrap();
+  assert!(writer.file("scripts/flo.j").is_some());
+  assert_eq!(writer.info().unwrap().flags, flags);
+}
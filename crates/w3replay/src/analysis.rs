@@ -0,0 +1,706 @@
+//! Replay analysis
+//!
+//! Builds a [`ReplaySummary`] from replay records: players, APM, hero picks and skill order,
+//! build order, chat log, leave events and a best-effort winner.
+//!
+//! Build orders and hero picks are derived from the orders players issued,
+//! not from the game simulation, so cancelled or queued orders are included.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+use flo_w3gs::actions::Action;
+use flo_w3gs::protocol::chat::{ChatMessage, MessageScope};
+use flo_w3gs::slot::{RacePref, SlotInfo, SlotStatus};
+
+use crate::error::*;
+use crate::{PlayerAction, Record, RecordIter, ReforgedMetadata, W3Replay};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplaySummary {
+  pub game_name: String,
+  pub host_name: String,
+  pub map_path: String,
+  pub duration_ms: u32,
+  pub players: Vec<PlayerSummary>,
+  pub observers: Vec<ObserverSummary>,
+  pub chat: Vec<ChatEntry>,
+  pub leaves: Vec<LeaveEvent>,
+  /// `None` if the winner can't be determined
+  pub winner_team: Option<u8>,
+}
+
+impl ReplaySummary {
+  pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+    Self::from_records(W3Replay::open(path)?.into_records())
+  }
+
+  pub fn from_records<R: Read>(records: RecordIter<R>) -> Result<Self> {
+    let mut analyzer = ReplayAnalyzer::new();
+    for record in records {
+      analyzer.push(&record?);
+    }
+    analyzer.finish()
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerSummary {
+  /// 0 for computers
+  pub player_id: u8,
+  pub name: String,
+  /// Reforged only
+  pub battle_tag: Option<String>,
+  pub computer: bool,
+  pub slot_index: usize,
+  pub team: u8,
+  pub color: u8,
+  /// Race selected in the lobby
+  pub race: Race,
+  /// Race guessed from the first hero, unit or building, resolves random picks
+  pub detected_race: Option<Race>,
+  /// Average APM while the player was in the game
+  pub apm: u32,
+  /// Number of actions in each minute of the game
+  pub apm_timeline: Vec<u32>,
+  pub heroes: Vec<HeroPick>,
+  pub build_order: Vec<BuildOrderItem>,
+  pub left_at_ms: Option<u32>,
+  pub result: Option<PlayerResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Race {
+  Human,
+  Orc,
+  NightElf,
+  Undead,
+  Random,
+}
+
+impl Race {
+  fn from_race_pref(race: RacePref) -> Self {
+    if race.contains(RacePref::HUMAN) {
+      Race::Human
+    } else if race.contains(RacePref::ORC) {
+      Race::Orc
+    } else if race.contains(RacePref::NIGHTELF) {
+      Race::NightElf
+    } else if race.contains(RacePref::UNDEAD) {
+      Race::Undead
+    } else {
+      Race::Random
+    }
+  }
+
+  /// Object ids of melee units and buildings start with a race prefix
+  fn from_object_id(id: &str) -> Option<Self> {
+    match id.as_bytes().first()?.to_ascii_lowercase() {
+      b'h' => Some(Race::Human),
+      b'o' => Some(Race::Orc),
+      b'e' => Some(Race::NightElf),
+      b'u' => Some(Race::Undead),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ObserverSummary {
+  pub player_id: u8,
+  pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HeroPick {
+  pub hero_id: String,
+  pub time_ms: u32,
+  pub skills: Vec<SkillPick>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkillPick {
+  pub ability_id: String,
+  pub time_ms: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum BuildOrderKind {
+  Unit,
+  Building,
+  Upgrade,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildOrderItem {
+  pub time_ms: u32,
+  pub object_id: String,
+  pub kind: BuildOrderKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ChatScope {
+  All,
+  Allies,
+  Observers,
+  Player(u8),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatEntry {
+  pub time_ms: u32,
+  pub player_id: u8,
+  pub scope: ChatScope,
+  pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum PlayerResult {
+  Left,
+  Lost,
+  Won,
+  Draw,
+  ObserverLeft,
+  Unknown(u32),
+}
+
+impl From<u32> for PlayerResult {
+  fn from(value: u32) -> Self {
+    match value {
+      0x01 | 0x07 => PlayerResult::Left,
+      0x08 => PlayerResult::Lost,
+      0x09 => PlayerResult::Won,
+      0x0A => PlayerResult::Draw,
+      0x0B => PlayerResult::ObserverLeft,
+      v => PlayerResult::Unknown(v),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LeaveEvent {
+  pub time_ms: u32,
+  pub player_id: u8,
+  pub result: PlayerResult,
+}
+
+const MINUTE_MS: u32 = 60 * 1000;
+
+/// Incremental analyzer, records can be pushed while they are decoded
+#[derive(Debug, Default)]
+pub struct ReplayAnalyzer {
+  game_name: String,
+  host_name: String,
+  map_path: String,
+  names: BTreeMap<u8, String>,
+  battle_tags: BTreeMap<u8, String>,
+  slots: Option<SlotInfo>,
+  time_ms: u32,
+  states: BTreeMap<u8, PlayerState>,
+  chat: Vec<ChatEntry>,
+  leaves: Vec<LeaveEvent>,
+}
+
+#[derive(Debug, Default)]
+struct PlayerState {
+  apm_timeline: Vec<u32>,
+  heroes: Vec<HeroPick>,
+  build_order: Vec<BuildOrderItem>,
+}
+
+impl ReplayAnalyzer {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn push(&mut self, record: &Record) {
+    match *record {
+      Record::GameInfo(ref info) => {
+        self.game_name = info.game_name.to_string_lossy().to_string();
+        self.host_name = info.game_settings.host_name.to_string_lossy().to_string();
+        self.map_path = info.game_settings.map_path.to_string_lossy().to_string();
+        self.names.insert(
+          info.host_player_info.id,
+          info.host_player_info.name.to_string_lossy().to_string(),
+        );
+      }
+      Record::PlayerInfo(ref info) => {
+        self.names.insert(
+          info.player_info.id,
+          info.player_info.name.to_string_lossy().to_string(),
+        );
+      }
+      Record::SlotInfo(ref info) => {
+        self.slots = Some(info.clone());
+      }
+      Record::ProtoBuf(ref payload) => {
+        if let Ok(ReforgedMetadata::PlayerProfile(profile)) = ReforgedMetadata::decode(payload) {
+          if !profile.battle_tag.is_empty() {
+            self
+              .battle_tags
+              .insert(profile.player_id as u8, profile.battle_tag);
+          }
+        }
+      }
+      Record::TimeSlotFragment(ref slot) => self.push_time_slot(&slot.0),
+      Record::TimeSlot(ref slot) => self.push_time_slot(slot),
+      Record::ChatMessage(ref chat) => {
+        if let ChatMessage::Scoped { scope, ref message } = chat.message {
+          self.chat.push(ChatEntry {
+            time_ms: self.time_ms,
+            player_id: chat.player_id,
+            scope: match scope {
+              MessageScope::All => ChatScope::All,
+              MessageScope::Allies => ChatScope::Allies,
+              MessageScope::Observers => ChatScope::Observers,
+              MessageScope::Player(id) => ChatScope::Player(id),
+            },
+            message: message.to_string_lossy().to_string(),
+          })
+        }
+      }
+      Record::PlayerLeft(ref left) => self.leaves.push(LeaveEvent {
+        time_ms: self.time_ms,
+        player_id: left.player_id,
+        result: PlayerResult::from(left.result),
+      }),
+      _ => {}
+    }
+  }
+
+  /// Actions in a time slot are executed after the time increment
+  fn push_time_slot(&mut self, slot: &crate::TimeSlot) {
+    self.time_ms = self.time_ms.saturating_add(slot.time_increment_ms as u32);
+    for action in &slot.actions {
+      self.push_player_action(action);
+    }
+  }
+
+  fn push_player_action(&mut self, action: &PlayerAction) {
+    let time_ms = self.time_ms;
+    let state = self.states.entry(action.player_id).or_default();
+    for action in action.actions() {
+      // the remaining bytes can't be decoded once an unknown action is reached
+      let action = match action {
+        Ok(action) => action,
+        Err(_) => break,
+      };
+
+      if is_apm_action(&action) {
+        let minute = (time_ms / MINUTE_MS) as usize;
+        if state.apm_timeline.len() <= minute {
+          state.apm_timeline.resize(minute + 1, 0);
+        }
+        state.apm_timeline[minute] += 1;
+      }
+
+      match action {
+        Action::UnitBuildingAbility(ref a) => {
+          if let Some(id) = object_id(a.item_id) {
+            state.push_order(time_ms, id);
+          }
+        }
+        Action::UnitBuildingAbilityTargeted(ref a) => {
+          if let Some(id) = object_id(a.item_id) {
+            if !is_ability_id(&id) {
+              state.build_order.push(BuildOrderItem {
+                time_ms,
+                object_id: id,
+                kind: BuildOrderKind::Building,
+              });
+            }
+          }
+        }
+        _ => {}
+      }
+    }
+  }
+
+  pub fn finish(self) -> Result<ReplaySummary> {
+    let ReplayAnalyzer {
+      game_name,
+      host_name,
+      map_path,
+      names,
+      battle_tags,
+      slots,
+      time_ms: duration_ms,
+      mut states,
+      chat,
+      leaves,
+    } = self;
+
+    let slots = slots.ok_or(Error::NoSlotInfoRecord)?;
+    let observer_team = if slots.slots().len() > 12 { 24 } else { 12 };
+
+    let mut players = vec![];
+    let mut observers = vec![];
+    for (slot_index, slot) in slots.slots().iter().enumerate() {
+      if slot.slot_status != SlotStatus::Occupied {
+        continue;
+      }
+
+      if !slot.computer && slot.team == observer_team {
+        observers.push(ObserverSummary {
+          player_id: slot.player_id,
+          name: names.get(&slot.player_id).cloned().unwrap_or_default(),
+        });
+        continue;
+      }
+
+      let state = if slot.computer {
+        PlayerState::default()
+      } else {
+        states.remove(&slot.player_id).unwrap_or_default()
+      };
+      let leave = if slot.computer {
+        None
+      } else {
+        leaves.iter().find(|l| l.player_id == slot.player_id)
+      };
+      let active_ms = leave.map(|l| l.time_ms).unwrap_or(duration_ms);
+      let num_actions: u32 = state.apm_timeline.iter().sum();
+      let detected_race = state
+        .heroes
+        .iter()
+        .map(|h| h.hero_id.as_str())
+        .chain(state.build_order.iter().map(|b| b.object_id.as_str()))
+        .find_map(Race::from_object_id);
+
+      players.push(PlayerSummary {
+        player_id: if slot.computer { 0 } else { slot.player_id },
+        name: if slot.computer {
+          "Computer".to_string()
+        } else {
+          names.get(&slot.player_id).cloned().unwrap_or_default()
+        },
+        battle_tag: if slot.computer {
+          None
+        } else {
+          battle_tags.get(&slot.player_id).cloned()
+        },
+        computer: slot.computer,
+        slot_index,
+        team: slot.team,
+        color: slot.color,
+        race: Race::from_race_pref(slot.race),
+        detected_race,
+        apm: if active_ms > 0 {
+          (num_actions as u64 * MINUTE_MS as u64 / active_ms as u64) as u32
+        } else {
+          0
+        },
+        apm_timeline: state.apm_timeline,
+        heroes: state.heroes,
+        build_order: state.build_order,
+        left_at_ms: leave.map(|l| l.time_ms),
+        result: leave.map(|l| l.result),
+      });
+    }
+
+    let winner_team = guess_winner_team(&players);
+
+    Ok(ReplaySummary {
+      game_name,
+      host_name,
+      map_path,
+      duration_ms,
+      players,
+      observers,
+      chat,
+      leaves,
+      winner_team,
+    })
+  }
+}
+
+impl PlayerState {
+  fn push_order(&mut self, time_ms: u32, id: String) {
+    if let Some(hero) = find_hero_by_ability(&id) {
+      if let Some(pick) = self.heroes.iter_mut().find(|h| h.hero_id == hero) {
+        pick.skills.push(SkillPick {
+          ability_id: id,
+          time_ms,
+        });
+      }
+      return;
+    }
+
+    if HERO_ABILITIES.iter().any(|(hero, _)| *hero == id) {
+      if !self.heroes.iter().any(|h| h.hero_id == id) {
+        self.heroes.push(HeroPick {
+          hero_id: id,
+          time_ms,
+          skills: vec![],
+        });
+      }
+      return;
+    }
+
+    if is_ability_id(&id) {
+      return;
+    }
+
+    self.build_order.push(BuildOrderItem {
+      time_ms,
+      kind: if id.starts_with('R') {
+        BuildOrderKind::Upgrade
+      } else {
+        BuildOrderKind::Unit
+      },
+      object_id: id,
+    });
+  }
+}
+
+/// Winner heuristics, in order:
+/// 1. the team of a player who left with the `won` result
+/// 2. the only team without a player who left with the `lost` result
+/// 3. the only team that stayed in the game the longest
+fn guess_winner_team(players: &[PlayerSummary]) -> Option<u8> {
+  if let Some(p) = players.iter().find(|p| p.result == Some(PlayerResult::Won)) {
+    return Some(p.team);
+  }
+
+  let mut teams: BTreeMap<u8, (bool, u32)> = BTreeMap::new();
+  for p in players.iter().filter(|p| !p.computer) {
+    let entry = teams.entry(p.team).or_insert((false, 0));
+    entry.0 = entry.0 || p.result == Some(PlayerResult::Lost);
+    entry.1 = entry.1.max(p.left_at_ms.unwrap_or(u32::MAX));
+  }
+
+  if teams.len() < 2 {
+    return None;
+  }
+
+  let not_lost: Vec<u8> = teams
+    .iter()
+    .filter(|(_, (lost, _))| !lost)
+    .map(|(team, _)| *team)
+    .collect();
+  if not_lost.len() == 1 && not_lost.len() < teams.len() {
+    return Some(not_lost[0]);
+  }
+
+  let last = teams.values().map(|(_, left_at)| *left_at).max()?;
+  let mut last_teams = teams.iter().filter(|(_, (_, left_at))| *left_at == last);
+  match (last_teams.next(), last_teams.next()) {
+    (Some((team, _)), None) => Some(*team),
+    _ => None,
+  }
+}
+
+/// Actions a player issues on purpose, selection bookkeeping sent by the game is excluded
+fn is_apm_action(action: &Action) -> bool {
+  match *action {
+    Action::ChangeSelection(ref a) => a.select_mode != 0x02,
+    Action::UnitBuildingAbility(_)
+    | Action::UnitBuildingAbilityTargeted(_)
+    | Action::UnitBuildingAbilityTargetedId(_)
+    | Action::ItemGivenDropped(_)
+    | Action::UnitBuildingAbility2Targets2Items(_)
+    | Action::AssignGroupHotkey(_)
+    | Action::SelectGroupHotkey(_)
+    | Action::SelectGroundItem(_)
+    | Action::CancelHeroRevival(_)
+    | Action::RemoveUnitFromBuildingQueue(_)
+    | Action::TransferResources(_)
+    | Action::EscPressed
+    | Action::MinimapSignal(_) => true,
+    _ => false,
+  }
+}
+
+/// Object ids are stored as reversed four character codes, ability orders are plain numbers
+fn object_id(value: u32) -> Option<String> {
+  let mut bytes = value.to_le_bytes();
+  bytes.reverse();
+  if bytes.iter().all(|b| b.is_ascii_alphanumeric()) {
+    Some(String::from_utf8_lossy(&bytes).to_string())
+  } else {
+    None
+  }
+}
+
+fn is_ability_id(id: &str) -> bool {
+  id.starts_with('A')
+}
+
+fn find_hero_by_ability(id: &str) -> Option<&'static str> {
+  HERO_ABILITIES
+    .iter()
+    .find(|(_, abilities)| abilities.contains(&id))
+    .map(|(hero, _)| *hero)
+}
+
+const HERO_ABILITIES: &[(&str, [&str; 4])] = &[
+  // Human
+  ("Hamg", ["AHbz", "AHwe", "AHab", "AHmt"]),
+  ("Hmkg", ["AHtc", "AHtb", "AHbh", "AHav"]),
+  ("Hpal", ["AHhb", "AHds", "AHad", "AHre"]),
+  ("Hblm", ["AHfs", "AHbn", "AHdr", "AHpx"]),
+  // Orc
+  ("Obla", ["AOwk", "AOmi", "AOcr", "AOww"]),
+  ("Ofar", ["AOfs", "AOsf", "AOcl", "AOeq"]),
+  ("Otch", ["AOsh", "AOae", "AOws", "AOre"]),
+  ("Oshd", ["AOhw", "AOhx", "AOsw", "AOvd"]),
+  // Night Elf
+  ("Edem", ["AEmb", "AEim", "AEev", "AEme"]),
+  ("Ekee", ["AEer", "AEfn", "AEah", "AEtq"]),
+  ("Emoo", ["AHfa", "AEst", "AEar", "AEsf"]),
+  ("Ewar", ["AEbl", "AEfk", "AEsh", "AEsv"]),
+  // Undead
+  ("Udea", ["AUdc", "AUdp", "AUau", "AUan"]),
+  ("Ulic", ["AUfn", "AUfu", "AUdr", "AUdd"]),
+  ("Udre", ["AUav", "AUsl", "AUcs", "AUin"]),
+  ("Ucrl", ["AUim", "AUts", "AUcb", "AUls"]),
+  // Neutral
+  ("Nbrn", ["ANsi", "ANba", "ANdr", "ANch"]),
+  ("Nngs", ["ANfl", "ANfa", "ANms", "ANto"]),
+  ("Npbm", ["ANbf", "ANdh", "ANdb", "ANef"]),
+  ("Nbst", ["ANsg", "ANsq", "ANsw", "ANst"]),
+  ("Nplh", ["ANrf", "ANht", "ANca", "ANdo"]),
+  ("Ntin", ["ANsy", "ANcs", "ANeg", "ANrg"]),
+  ("Nfir", ["ANso", "ANlm", "ANic", "ANvc"]),
+  ("Nalc", ["ANhs", "ANab", "ANcr", "ANtm"]),
+];
+
+#[test]
+fn test_analyzer() {
+  use crate::{PlayerChatMessage, PlayerInfo, PlayerInfoRecord, PlayerLeft, TimeSlot};
+  use bytes::{BufMut, Bytes, BytesMut};
+  use flo_w3gs::constants::LeaveReason;
+  use std::ffi::CString;
+
+  fn order(player_id: u8, id: &[u8; 4]) -> PlayerAction {
+    let mut data = BytesMut::new();
+    data.put_u8(0x10);
+    data.put_u16_le(0x40);
+    let mut id = *id;
+    id.reverse();
+    data.put_slice(&id);
+    data.put_u32_le(0xFFFFFFFF);
+    data.put_u32_le(0xFFFFFFFF);
+    PlayerAction {
+      player_id,
+      data: data.freeze(),
+    }
+  }
+
+  fn time_slot(ms: u16, actions: Vec<PlayerAction>) -> Record {
+    Record::TimeSlot(TimeSlot {
+      time_increment_ms: ms,
+      actions,
+    })
+  }
+
+  let mut slots = SlotInfo::build().num_slots(24).num_players(3).build();
+  for (i, (player_id, team, race)) in [
+    (1, 0, RacePref::RANDOM),
+    (2, 1, RacePref::ORC),
+    (3, 24, RacePref::RANDOM),
+  ]
+  .iter()
+  .enumerate()
+  {
+    let slot = slots.slot_mut(i).unwrap();
+    slot.player_id = *player_id;
+    slot.slot_status = SlotStatus::Occupied;
+    slot.team = *team;
+    slot.color = i as u8;
+    slot.race = *race;
+  }
+
+  let mut analyzer = ReplayAnalyzer::new();
+  for id in 1..=3 {
+    analyzer.push(&Record::PlayerInfo(PlayerInfoRecord {
+      player_info: PlayerInfo::new(id, format!("player{}", id)),
+      unknown: 0,
+    }));
+  }
+  analyzer.push(&Record::ProtoBuf(crate::ProtoBufPayload::new(
+    crate::PlayerProfileMessage::new(1, "player1#1234"),
+  )));
+  analyzer.push(&Record::SlotInfo(slots));
+  analyzer.push(&time_slot(
+    1000,
+    vec![
+      order(1, b"hpea"),
+      order(1, b"Hpal"),
+      order(2, b"Obla"),
+      PlayerAction {
+        player_id: 2,
+        data: Bytes::from_static(&[0x61]),
+      },
+    ],
+  ));
+  analyzer.push(&time_slot(
+    MINUTE_MS as u16,
+    vec![order(1, b"AHhb"), order(1, b"Rhde"), order(2, b"AOwk")],
+  ));
+  analyzer.push(&Record::ChatMessage(PlayerChatMessage {
+    player_id: 2,
+    message: ChatMessage::Scoped {
+      scope: MessageScope::All,
+      message: CString::new("gg").unwrap(),
+    },
+  }));
+  analyzer.push(&Record::PlayerLeft(PlayerLeft {
+    reason: LeaveReason::LeaveDisconnect,
+    player_id: 2,
+    result: 0x07,
+    unknown: 0,
+  }));
+  analyzer.push(&time_slot(1000, vec![]));
+  analyzer.push(&Record::PlayerLeft(PlayerLeft {
+    reason: LeaveReason::LeaveDisconnect,
+    player_id: 1,
+    result: 0x07,
+    unknown: 0,
+  }));
+
+  let summary = analyzer.finish().unwrap();
+  assert_eq!(summary.duration_ms, 62000);
+  assert_eq!(summary.players.len(), 2);
+  assert_eq!(summary.observers.len(), 1);
+  assert_eq!(summary.observers[0].name, "player3");
+  assert_eq!(summary.winner_team, Some(0));
+
+  let p1 = &summary.players[0];
+  assert_eq!(p1.name, "player1");
+  assert_eq!(p1.battle_tag.as_deref(), Some("player1#1234"));
+  assert_eq!(p1.race, Race::Random);
+  assert_eq!(p1.detected_race, Some(Race::Human));
+  assert_eq!(p1.apm_timeline, vec![2, 2]);
+  assert_eq!(p1.apm, 3);
+  assert_eq!(p1.heroes.len(), 1);
+  assert_eq!(p1.heroes[0].hero_id, "Hpal");
+  assert_eq!(p1.heroes[0].skills[0].ability_id, "AHhb");
+  assert_eq!(p1.heroes[0].skills[0].time_ms, 61000);
+  let build_order: Vec<_> = p1
+    .build_order
+    .iter()
+    .map(|b| (b.object_id.as_str(), b.kind))
+    .collect();
+  assert_eq!(
+    build_order,
+    vec![
+      ("hpea", BuildOrderKind::Unit),
+      ("Rhde", BuildOrderKind::Upgrade)
+    ]
+  );
+
+  let p2 = &summary.players[1];
+  assert_eq!(p2.race, Race::Orc);
+  assert_eq!(p2.apm_timeline, vec![2, 1]);
+  assert_eq!(p2.left_at_ms, Some(61000));
+  assert_eq!(p2.result, Some(PlayerResult::Left));
+  assert_eq!(p2.heroes[0].skills[0].ability_id, "AOwk");
+
+  assert_eq!(summary.chat.len(), 1);
+  assert_eq!(summary.chat[0].time_ms, 61000);
+  assert_eq!(summary.chat[0].message, "gg");
+  assert_eq!(summary.leaves.len(), 2);
+}
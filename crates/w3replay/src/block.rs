@@ -20,11 +20,13 @@
 //!
 //! The last block is padded with 0 bytes up to the 8K border. These bytes can
 //! be disregarded.
+//!
+//! Since Reforged (build 10032) both size fields are dwords, the header is 0x0C bytes.
 
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
-use flate2::{Compression, Crc, CrcReader, CrcWriter};
-use std::io::{Cursor, Read, Write};
+use flate2::{Compression, Crc, CrcWriter};
+use std::io::{Read, Write};
 
 use flo_util::binary::*;
 use flo_util::{BinDecode, BinEncode};
@@ -40,9 +42,115 @@ pub struct BlockHeader {
   pub crc16_compressed_data: u16,
 }
 
+impl BlockHeader {
+  const LEGACY_SIZE: usize = 8;
+
+  pub(crate) fn size(format: BlockFormat) -> usize {
+    match format {
+      BlockFormat::Legacy => Self::LEGACY_SIZE,
+      BlockFormat::Reforged => Self::MIN_SIZE,
+    }
+  }
+
+  /// `buf` must be `BlockHeader::size(format)` bytes long
+  pub(crate) fn decode_with_format(buf: &[u8], format: BlockFormat) -> Result<Self> {
+    let header = match format {
+      BlockFormat::Legacy => BlockHeader {
+        compressed_data_size: u16::from_le_bytes([buf[0], buf[1]]) as u32,
+        decompressed_data_size: u16::from_le_bytes([buf[2], buf[3]]) as u32,
+        crc16_header: u16::from_le_bytes([buf[4], buf[5]]),
+        crc16_compressed_data: u16::from_le_bytes([buf[6], buf[7]]),
+      },
+      BlockFormat::Reforged => {
+        let header = BlockHeader::decode(&mut &buf[..])?;
+        let crc = crc16(&[
+          &header.compressed_data_size.to_le_bytes()[..],
+          &header.decompressed_data_size.to_le_bytes()[..],
+          &[0, 0, 0, 0],
+        ]);
+        if crc != header.crc16_header {
+          return Err(Error::InvalidChecksum {
+            subject: "header",
+            expected: header.crc16_header,
+            got: crc,
+          });
+        }
+        header
+      }
+    };
+
+    if header.decompressed_data_size != SUPPORTED_BLOCK_SIZE as u32 {
+      return Err(Error::UnsupportedBlockSize(
+        header.decompressed_data_size as usize,
+      ));
+    }
+
+    Ok(header)
+  }
+}
+
+/// Decompresses the data of a block
+///
+/// The checksum scheme of legacy blocks is undocumented, only Reforged blocks are verified.
+pub(crate) fn decompress_block(
+  header: BlockHeader,
+  compressed: &[u8],
+  format: BlockFormat,
+) -> Result<Block> {
+  let mut buf = vec![0_u8; SUPPORTED_BLOCK_SIZE];
+  let mut d = ZlibDecoder::new(compressed);
+  d.read_exact(&mut buf).map_err(Error::ReadBlockHeader)?;
+
+  if format == BlockFormat::Reforged {
+    let crc = crc16(&[compressed]);
+    if crc != header.crc16_compressed_data {
+      return Err(Error::InvalidChecksum {
+        subject: "data",
+        expected: header.crc16_compressed_data,
+        got: crc,
+      });
+    }
+  }
+
+  Ok(Block {
+    header,
+    data: Bytes::from(buf),
+  })
+}
+
+/// CRC32 folded into 16 bits
+fn crc16(parts: &[&[u8]]) -> u16 {
+  let mut crc = Crc::new();
+  for part in parts {
+    crc.update(part);
+  }
+  let crc = crc.sum();
+  (crc ^ (crc >> 16)) as u16
+}
+
+/// Layout of data block headers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockFormat {
+  /// Word size fields, used before Reforged
+  Legacy,
+  /// Dword size fields
+  Reforged,
+}
+
+impl BlockFormat {
+  pub fn from_header(header: &crate::Header) -> Self {
+    if header.has_legacy_block_header() {
+      BlockFormat::Legacy
+    } else {
+      BlockFormat::Reforged
+    }
+  }
+}
+
 #[derive(Debug)]
 pub struct Blocks<R> {
   r: R,
+  format: BlockFormat,
   num_blocks: usize,
   _total_size: usize,
   finished_block: usize,
@@ -53,6 +161,7 @@ impl<R> Blocks<R> {
     Self {
       _total_size: total_size,
       r,
+      format: BlockFormat::Reforged,
       num_blocks,
       finished_block: 0,
     }
@@ -67,12 +176,19 @@ where
     Self {
       _total_size: buf.remaining(),
       r: buf.reader(),
+      format: BlockFormat::Reforged,
       num_blocks,
       finished_block: 0,
     }
   }
 }
 
+impl<R> Blocks<R> {
+  pub fn with_format(self, format: BlockFormat) -> Self {
+    Self { format, ..self }
+  }
+}
+
 impl<R> Iterator for Blocks<R>
 where
   R: Read,
@@ -84,69 +200,24 @@ where
       return None;
     }
 
-    let mut buf: Vec<u8> = Vec::with_capacity(SUPPORTED_BLOCK_SIZE);
-    buf.resize(buf.capacity(), 0);
-    if let Err(err) = self.r.read_exact(&mut buf[0..BlockHeader::MIN_SIZE]) {
+    let mut header_buf = [0_u8; BlockHeader::MIN_SIZE];
+    let header_buf = &mut header_buf[..BlockHeader::size(self.format)];
+    if let Err(err) = self.r.read_exact(header_buf) {
       return Some(Err(Error::ReadBlockHeader(err)));
     }
-
-    let header = match BlockHeader::decode(&mut buf.as_slice()) {
+    let header = match BlockHeader::decode_with_format(header_buf, self.format) {
       Ok(header) => header,
-      Err(err) => return Some(Err(err.into())),
+      Err(err) => return Some(Err(err)),
     };
 
-    let header_for_crc = BlockHeader {
-      crc16_header: 0,
-      crc16_compressed_data: 0,
-      ..header.clone()
-    };
-    let chain = std::io::Read::chain(
-      Cursor::new(header_for_crc.encode_to_bytes()),
-      self.r.by_ref(),
-    );
-    let mut r = CrcReader::new(chain);
-    if let Err(e) = r.read_exact(&mut buf[0..BlockHeader::MIN_SIZE]) {
-      return Some(Err(Error::ReadBlockHeader(e)));
-    }
-
-    let crc = r.crc().sum();
-    let crc = (crc ^ (crc >> 16)) as u16;
-    if crc != header.crc16_header {
-      return Some(Err(Error::InvalidChecksum {
-        subject: "header",
-        expected: header.crc16_header,
-        got: crc,
-      }));
-    }
-
-    if header.decompressed_data_size != SUPPORTED_BLOCK_SIZE as u32 {
-      return Some(Err(Error::UnsupportedBlockSize(
-        header.decompressed_data_size as usize,
-      )));
-    }
-
-    let r = CrcReader::new(self.r.by_ref().take(header.compressed_data_size as u64));
-    let mut d = ZlibDecoder::new(r);
-    if let Err(err) = d.read_exact(&mut buf) {
+    let mut compressed = vec![0_u8; header.compressed_data_size as usize];
+    if let Err(err) = self.r.read_exact(&mut compressed) {
       return Some(Err(Error::ReadBlockHeader(err)));
     }
 
-    let crc = d.get_ref().crc().sum();
-    let crc = (crc ^ (crc >> 16)) as u16;
-    if crc != header.crc16_compressed_data {
-      return Some(Err(Error::InvalidChecksum {
-        subject: "data",
-        expected: header.crc16_compressed_data,
-        got: crc,
-      }));
-    }
-
     self.finished_block = self.finished_block + 1;
 
-    Some(Ok(Block {
-      header,
-      data: Bytes::from(buf),
-    }))
+    Some(decompress_block(header, &compressed, self.format))
   }
 }
 
@@ -157,10 +228,7 @@ pub struct Block {
 }
 
 pub struct BlocksEncoder<W> {
-  current_data_len: usize,
-  num_of_blocks: usize,
-  num_of_uncompressed_bytes: usize,
-  block_w: ZlibEncoder<CrcWriter<Vec<u8>>>,
+  builder: BlockBuilder,
   w: W,
 }
 
@@ -170,72 +238,100 @@ where
 {
   pub fn new(w: W) -> Self {
     Self {
+      builder: BlockBuilder::new(),
+      w,
+    }
+  }
+
+  pub fn with_format(self, format: BlockFormat) -> Self {
+    Self {
+      builder: self.builder.with_format(format),
+      ..self
+    }
+  }
+
+  pub fn encode(&mut self, record: &Record) -> Result<usize> {
+    let len = self.builder.push(record)?;
+    self.w.write_all(&self.builder.take_output())?;
+    Ok(len)
+  }
+
+  pub fn finish(mut self) -> Result<Finished<W>> {
+    self.builder.finish()?;
+    self.w.write_all(&self.builder.take_output())?;
+    Ok(Finished {
+      num_of_blocks: self.builder.num_of_blocks,
+      num_of_uncompressed_bytes: self.builder.num_of_uncompressed_bytes,
+      inner: self.w,
+    })
+  }
+}
+
+/// Packs records into compressed blocks, completed blocks are buffered until taken
+pub(crate) struct BlockBuilder {
+  format: BlockFormat,
+  current_data_len: usize,
+  pub(crate) num_of_blocks: usize,
+  pub(crate) num_of_uncompressed_bytes: usize,
+  block_w: ZlibEncoder<CrcWriter<Vec<u8>>>,
+  output: Vec<u8>,
+}
+
+impl BlockBuilder {
+  pub(crate) fn new() -> Self {
+    Self {
+      format: BlockFormat::Reforged,
       current_data_len: 0,
       num_of_blocks: 0,
       num_of_uncompressed_bytes: 0,
       block_w: Self::make_writer(),
-      w,
+      output: vec![],
     }
   }
 
-  pub fn encode(&mut self, record: &Record) -> Result<usize> {
+  pub(crate) fn with_format(self, format: BlockFormat) -> Self {
+    Self { format, ..self }
+  }
+
+  pub(crate) fn push(&mut self, record: &Record) -> Result<usize> {
     let mut buf = record.encode_to_bytes().freeze();
     let len = buf.len();
 
     self.num_of_uncompressed_bytes += len;
 
-    if self.current_data_len + len <= SUPPORTED_BLOCK_SIZE {
-      self.block_w.write_all(&buf)?;
-      self.current_data_len += len;
+    while buf.has_remaining() {
+      let slice_len = std::cmp::min(
+        SUPPORTED_BLOCK_SIZE - self.current_data_len,
+        buf.remaining(),
+      );
+      self.block_w.write_all(&buf[0..slice_len])?;
+      self.current_data_len += slice_len;
+      buf.advance(slice_len);
 
       if self.current_data_len == SUPPORTED_BLOCK_SIZE {
         self.finish_block()?;
       }
-    } else {
-      let current_remaining_len = SUPPORTED_BLOCK_SIZE - self.current_data_len;
-      let slice = &buf[0..current_remaining_len];
-
-      self.block_w.write_all(slice)?;
-      self.current_data_len += slice.len();
-
-      buf.advance(current_remaining_len);
-      self.finish_block()?;
-
-      while buf.has_remaining() {
-        let slice_len = std::cmp::min(SUPPORTED_BLOCK_SIZE, buf.remaining());
-        let slice = &buf[0..slice_len];
-
-        self.block_w.write_all(slice)?;
-        self.current_data_len += slice.len();
-
-        buf.advance(slice_len);
-        if self.current_data_len == SUPPORTED_BLOCK_SIZE {
-          self.finish_block()?;
-        }
-      }
     }
     Ok(len)
   }
 
-  pub fn finish(mut self) -> Result<Finished<W>> {
+  /// Pads and compresses the last block
+  pub(crate) fn finish(&mut self) -> Result<()> {
     if self.current_data_len < SUPPORTED_BLOCK_SIZE {
       let pad_len = SUPPORTED_BLOCK_SIZE - self.current_data_len;
-      for _ in 0..pad_len {
-        self.block_w.write_all(&[0])?;
-      }
+      self.block_w.write_all(&vec![0; pad_len])?;
       self.current_data_len = SUPPORTED_BLOCK_SIZE;
     }
-    self.finish_block()?;
-    Ok(Finished {
-      num_of_blocks: self.num_of_blocks,
-      num_of_uncompressed_bytes: self.num_of_uncompressed_bytes,
-      inner: self.w,
-    })
+    self.finish_block()
+  }
+
+  /// Takes the encoded bytes of completed blocks
+  pub(crate) fn take_output(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.output)
   }
 
   fn finish_block(&mut self) -> Result<()> {
-    let w = std::mem::replace(&mut self.block_w, Self::make_writer())
-      .finish()?;
+    let w = std::mem::replace(&mut self.block_w, Self::make_writer()).finish()?;
     let data_crc = w.crc().sum();
     let buf = w.into_inner();
 
@@ -246,19 +342,33 @@ where
       decompressed_data_size: self.current_data_len as u32,
     };
 
-    let mut header_crc = Crc::new();
-    header_crc.update(&header.compressed_data_size.to_le_bytes());
-    header_crc.update(&header.decompressed_data_size.to_le_bytes());
-    header_crc.update(&[0, 0, 0, 0]); // crc16_header, crc16_compressed_data
-    let header_crc = header_crc.sum();
-
-    header.crc16_header = (header_crc ^ (header_crc >> 16)) as u16;
     header.crc16_compressed_data = (data_crc ^ (data_crc >> 16)) as u16;
-
-    let mut header_buf = [0_u8; BlockHeader::MIN_SIZE];
-    header.encode(&mut header_buf.as_mut_slice());
-    self.w.write_all(&header_buf)?;
-    self.w.write_all(&buf)?;
+    match self.format {
+      BlockFormat::Legacy => {
+        let sizes = [
+          header.compressed_data_size as u16,
+          header.decompressed_data_size as u16,
+        ];
+        let sizes = [sizes[0].to_le_bytes(), sizes[1].to_le_bytes()].concat();
+        header.crc16_header = crc16(&[&sizes, &[0, 0, 0, 0]]);
+        self.output.extend_from_slice(&sizes);
+        self
+          .output
+          .extend_from_slice(&header.crc16_header.to_le_bytes());
+        self
+          .output
+          .extend_from_slice(&header.crc16_compressed_data.to_le_bytes());
+      }
+      BlockFormat::Reforged => {
+        header.crc16_header = crc16(&[
+          &header.compressed_data_size.to_le_bytes()[..],
+          &header.decompressed_data_size.to_le_bytes()[..],
+          &[0, 0, 0, 0], // crc16_header, crc16_compressed_data
+        ]);
+        header.encode(&mut self.output);
+      }
+    }
+    self.output.extend_from_slice(&buf);
 
     self.current_data_len = 0;
     self.num_of_blocks += 1;
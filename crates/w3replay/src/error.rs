@@ -20,6 +20,8 @@ pub enum Error {
   NoSlotInfoRecord,
   #[error("decompress: {0}")]
   Decompress(#[from] flate2::DecompressError),
+  #[error("w3gs: {0}")]
+  W3GS(#[from] flo_w3gs::error::Error),
   #[error("bin decode: {0}")]
   BinDecode(#[from] flo_util::binary::BinDecodeError),
   #[error("io: {0}")]
@@ -41,19 +41,46 @@
 //!        |           |  including this field which is set to zero)
 //!
 //! Overall header size for version 1 is 0x44 bytes.
+//!
+//! - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+//! 2.1 SubHeader for header version 0
+//! - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
+//!
+//! This header was used for all replays saved with WarCraft III patch version
+//! v1.06 and below.
+//!
+//! offset | size/type | Description
+//! -------+-----------+-----------------------------------------------------------
+//! 0x0000 |  1  word  | unknown (always zero so far)
+//! 0x0002 |  1  word  | version number (corresponds to patch 1.xx)
+//! 0x0004 |  1  word  | build number (see section 2.3)
+//! 0x0006 |  1  word  | flags
+//!        |           |   0x0000 for single player games
+//!        |           |   0x8000 for multiplayer games (LAN or Battle.net)
+//! 0x0008 |  1 dword  | replay length in msec
+//! 0x000C |  1 dword  | CRC32 checksum for the header
+//!        |           | (the checksum is calculated for the complete header
+//!        |           |  including this field which is set to zero)
+//!
+//! Overall header size for version 0 is 0x40 bytes.
+
+use flate2::Crc;
+use std::io::Read;
 
 use flo_util::binary::*;
 use flo_util::dword_string::DwordString;
 use flo_util::{BinDecode, BinEncode};
 
-#[derive(Debug, BinEncode, BinDecode)]
+use crate::constants::SIGNATURE;
+use crate::error::*;
+
+pub const PRODUCT_ROC: &[u8; 4] = b"WAR3";
+pub const PRODUCT_TFT: &[u8; 4] = b"W3XP";
+
+#[derive(Debug, Clone)]
 pub struct Header {
-  #[bin(eq = SIGNATURE)]
-  _sig: [u8; 28],
-  #[bin(eq = 68)]
   pub size_header: u32,
   pub size_file: u32,
-  #[bin(eq = 1)] // only version 1 is supported
   pub header_version: u32,
   pub size_blocks: u32,
   pub num_blocks: u32,
@@ -64,10 +91,16 @@ pub struct Header {
 }
 
 impl Header {
+  /// Size of header version 0
+  pub const SIZE_V0: usize = 0x40;
+  /// Size of header version 1
+  pub const SIZE_V1: usize = 0x44;
+  /// Size of the fields shared by all header versions
+  pub(crate) const SIZE_COMMON: usize = 0x30;
+
   pub fn new(game_version: GameVersion, flags: u16) -> Self {
     Self {
-      _sig: crate::constants::SIGNATURE,
-      size_header: 68,
+      size_header: Self::SIZE_V1 as u32,
       size_file: 0,
       header_version: 0x01,
       size_blocks: 0,
@@ -78,20 +111,151 @@ impl Header {
       crc: 0,
     }
   }
+
+  /// Reads the header and leaves the reader at the first data block
+  pub fn read<R: Read>(r: &mut R) -> Result<Self> {
+    let mut buf = [0_u8; Self::SIZE_V1];
+    r.read_exact(&mut buf[..Self::SIZE_COMMON])
+      .map_err(Error::ReadHeader)?;
+    let size = Self::size_by_common_fields(&buf);
+    r.read_exact(&mut buf[Self::SIZE_COMMON..size])
+      .map_err(Error::ReadHeader)?;
+    let header = Self::decode(&mut &buf[..size]).map_err(|e| e.context("header"))?;
+    Ok(header)
+  }
+
+  /// Header size according to the version field, `buf` holds at least the common fields
+  pub(crate) fn size_by_common_fields(buf: &[u8]) -> usize {
+    match u32::from_le_bytes([buf[0x24], buf[0x25], buf[0x26], buf[0x27]]) {
+      0 => Self::SIZE_V0,
+      _ => Self::SIZE_V1,
+    }
+  }
+
+  /// Encodes the header with the CRC32 field updated
+  pub fn encode_with_crc(&mut self) -> Vec<u8> {
+    self.crc = 0;
+    let mut buf = Vec::with_capacity(self.size());
+    self.encode(&mut buf);
+    let mut crc = Crc::new();
+    crc.update(&buf);
+    self.crc = crc.sum();
+    let len = buf.len();
+    buf[(len - 4)..].copy_from_slice(&self.crc.to_le_bytes());
+    buf
+  }
+
+  pub fn size(&self) -> usize {
+    self.size_header as usize
+  }
+
+  /// Reforged (build 10032) widened the size fields of data block headers
+  pub fn has_legacy_block_header(&self) -> bool {
+    self.header_version == 0 || self.game_version.version < 10032
+  }
+}
+
+impl BinDecode for Header {
+  const MIN_SIZE: usize = Self::SIZE_V0;
+  const FIXED_SIZE: bool = false;
+
+  fn decode<T: Buf>(buf: &mut T) -> Result<Self, BinDecodeError> {
+    buf.check_size(Self::SIZE_COMMON)?;
+    let mut sig = [0_u8; 28];
+    buf.copy_to_slice(&mut sig);
+    if sig != SIGNATURE {
+      return Err(BinDecodeError::failure("invalid signature"));
+    }
+    let size_header = buf.get_u32_le();
+    let size_file = buf.get_u32_le();
+    let header_version = buf.get_u32_le();
+    let size_blocks = buf.get_u32_le();
+    let num_blocks = buf.get_u32_le();
+
+    let expected_size = match header_version {
+      0 => Self::SIZE_V0,
+      1 => Self::SIZE_V1,
+      v => {
+        return Err(BinDecodeError::failure(format!(
+          "unsupported header version: {}",
+          v
+        )))
+      }
+    };
+    if size_header as usize != expected_size {
+      return Err(BinDecodeError::failure(format!(
+        "unexpected header size for version {}: {}",
+        header_version, size_header
+      )));
+    }
+    buf.check_size(expected_size - Self::SIZE_COMMON)?;
+
+    let game_version = if header_version == 0 {
+      buf.advance(2);
+      let version = buf.get_u16_le();
+      let build_number = buf.get_u16_le();
+      GameVersion {
+        product: DwordString::new(PRODUCT_ROC),
+        version: version as u32,
+        build_number,
+      }
+    } else {
+      GameVersion::decode(buf)?
+    };
+
+    Ok(Self {
+      size_header,
+      size_file,
+      header_version,
+      size_blocks,
+      num_blocks,
+      game_version,
+      flags: buf.get_u16_le(),
+      duration_ms: buf.get_u32_le(),
+      crc: buf.get_u32_le(),
+    })
+  }
+}
+
+impl BinEncode for Header {
+  fn encode<T: BufMut>(&self, buf: &mut T) {
+    buf.put_slice(&SIGNATURE);
+    buf.put_u32_le(self.size_header);
+    buf.put_u32_le(self.size_file);
+    buf.put_u32_le(self.header_version);
+    buf.put_u32_le(self.size_blocks);
+    buf.put_u32_le(self.num_blocks);
+    if self.header_version == 0 {
+      buf.put_u16_le(0);
+      buf.put_u16_le(self.game_version.version as u16);
+      buf.put_u16_le(self.game_version.build_number);
+    } else {
+      self.game_version.encode(buf);
+    }
+    buf.put_u16_le(self.flags);
+    buf.put_u32_le(self.duration_ms);
+    buf.put_u32_le(self.crc);
+  }
 }
 
-#[derive(Debug, BinEncode, BinDecode)]
+#[derive(Debug, Clone, BinEncode, BinDecode)]
 pub struct GameVersion {
-  #[bin(eq = b"W3XP")]
   pub product: DwordString,
   pub version: u32,
   pub build_number: u16,
 }
 
+impl GameVersion {
+  /// `true` for The Frozen Throne, `false` for Reign of Chaos
+  pub fn is_expansion(&self) -> bool {
+    self.product == PRODUCT_TFT
+  }
+}
+
 impl Default for GameVersion {
   fn default() -> Self {
     Self {
-      product: DwordString::new(b"W3XP"),
+      product: DwordString::new(PRODUCT_TFT),
       version: Default::default(),
       build_number: Default::default(),
     }
@@ -105,3 +269,54 @@ fn test_header() {
   let header = Header::decode(&mut buf).unwrap();
   dbg!(&header);
 }
+
+#[test]
+fn test_header_v0() {
+  let mut bytes = SIGNATURE.to_vec();
+  for v in &[0x40_u32, 0x1234, 0, 0x5678, 3] {
+    bytes.extend_from_slice(&v.to_le_bytes());
+  }
+  for v in &[0_u16, 6, 4656, 0x8000] {
+    bytes.extend_from_slice(&v.to_le_bytes());
+  }
+  bytes.extend_from_slice(&120000_u32.to_le_bytes());
+  bytes.extend_from_slice(&0xAABBCCDD_u32.to_le_bytes());
+  bytes.extend_from_slice(&[0xFF; 8]);
+
+  let mut r = bytes.as_slice();
+  let header = Header::read(&mut r).unwrap();
+  assert_eq!(r.len(), 8);
+  assert_eq!(header.size(), Header::SIZE_V0);
+  assert_eq!(header.game_version.product, PRODUCT_ROC);
+  assert!(!header.game_version.is_expansion());
+  assert_eq!(header.game_version.version, 6);
+  assert_eq!(header.game_version.build_number, 4656);
+  assert_eq!(header.flags, 0x8000);
+  assert_eq!(header.duration_ms, 120000);
+  assert_eq!(header.crc, 0xAABBCCDD);
+  assert!(header.has_legacy_block_header());
+  assert_eq!(&header.encode_to_bytes()[..], &bytes[..Header::SIZE_V0]);
+}
+
+#[test]
+fn test_header_v1_roc() {
+  let mut header = Header::new(
+    GameVersion {
+      product: DwordString::new(PRODUCT_ROC),
+      version: 26,
+      build_number: 6059,
+    },
+    0x8000,
+  );
+  header.num_blocks = 1;
+  let bytes = header.encode_to_bytes();
+  assert_eq!(bytes.len(), Header::SIZE_V1);
+  let decoded = Header::read(&mut &bytes[..]).unwrap();
+  assert!(!decoded.game_version.is_expansion());
+  assert_eq!(decoded.game_version.version, 26);
+  assert!(decoded.has_legacy_block_header());
+
+  let mut bytes = bytes.to_vec();
+  bytes[0x24] = 2;
+  assert!(Header::read(&mut bytes.as_slice()).is_err());
+}
@@ -1,8 +1,7 @@
 use bytes::buf::Reader;
 use bytes::Buf;
 use std::fs::File;
-use std::io::prelude::*;
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 use std::path::Path;
 
 mod block;
@@ -10,58 +9,82 @@ mod constants;
 mod header;
 mod records;
 
+pub mod analysis;
 pub mod error;
-use block::Blocks;
+use block::{BlockFormat, Blocks};
 pub use constants::*;
 use error::*;
-pub use header::Header;
+pub use header::{GameVersion, Header, PRODUCT_ROC, PRODUCT_TFT};
 pub use records::*;
 pub mod replay;
 pub use replay::*;
+pub mod stream;
+pub use stream::*;
+pub mod transform;
 
 #[derive(Debug)]
 pub struct W3Replay<R> {
-  _header: Header,
+  header: Header,
   blocks: Blocks<R>,
 }
 
 impl W3Replay<BufReader<File>> {
   pub fn open<P: AsRef<Path>>(path: P) -> Result<W3Replay<BufReader<File>>> {
-    use flo_util::binary::BinDecode;
-
     let f = File::open(path)?;
     let len = f.metadata()?.len() as usize;
     let mut r = BufReader::new(f);
-    let mut buf: [u8; Header::MIN_SIZE] = [0; Header::MIN_SIZE];
-    r.read_exact(&mut buf).map_err(Error::ReadHeader)?;
-    let mut buf_slice = &buf[..];
-    let header = Header::decode(&mut buf_slice).map_err(|e| e.context("header"))?;
+    let header = Header::read(&mut r)?;
     Ok(W3Replay {
-      blocks: Blocks::new(r, header.num_blocks as usize, len - Header::MIN_SIZE),
-      _header: header,
+      blocks: Blocks::new(
+        r,
+        header.num_blocks as usize,
+        len.saturating_sub(header.size()),
+      )
+      .with_format(BlockFormat::from_header(&header)),
+      header,
     })
   }
 
   pub fn inspect<P: AsRef<Path>>(path: P) -> Result<(ReplayInfo, RecordIter<BufReader<File>>)> {
-    let replay = Self::open(path)?;
+    Self::open(path)?.into_info()
+  }
+}
+
+impl<R> W3Replay<R>
+where
+  R: Read,
+{
+  /// Reads records until the game starts
+  pub fn into_info(self) -> Result<(ReplayInfo, RecordIter<R>)> {
     let mut game = None;
     let mut players = vec![];
     let mut slots = None;
-    let mut iter = replay.into_records();
+    let mut profiles = vec![];
+    let mut skins = vec![];
+    let header = self.header.clone();
+    let mut iter = self.into_records();
     while let Some(record) = iter.next() {
       match record? {
         Record::GameInfo(info) => game = Some(info),
         Record::SlotInfo(info) => slots = Some(info),
         Record::PlayerInfo(info) => players.push(info.player_info),
+        Record::ProtoBuf(payload) => match ReforgedMetadata::decode(&payload)? {
+          ReforgedMetadata::PlayerProfile(profile) => profiles.push(profile),
+          ReforgedMetadata::PlayerSkins(msg) => skins.push(msg),
+          _ => {}
+        },
         Record::GameStart(_) => break,
         _ => {}
       }
     }
     Ok((
       ReplayInfo {
+        header,
         game: game.ok_or_else(|| Error::NoGameInfoRecord)?,
         players,
         slots: slots.ok_or_else(|| Error::NoSlotInfoRecord)?,
+        profiles,
+        skins,
       },
       iter,
     ))
@@ -76,13 +99,18 @@ where
     use flo_util::binary::BinDecode;
     let header = Header::decode(&mut buf).map_err(|e| e.context("header"))?;
     Ok(W3Replay {
-      blocks: Blocks::from_buf(buf, header.num_blocks as usize),
-      _header: header,
+      blocks: Blocks::from_buf(buf, header.num_blocks as usize)
+        .with_format(BlockFormat::from_header(&header)),
+      header,
     })
   }
 }
 
 impl<R> W3Replay<R> {
+  pub fn header(&self) -> &Header {
+    &self.header
+  }
+
   pub fn into_records(self) -> RecordIter<R> {
     RecordIter::new(self.blocks)
   }
@@ -90,9 +118,14 @@ impl<R> W3Replay<R> {
 
 #[derive(Debug)]
 pub struct ReplayInfo {
+  pub header: Header,
   pub game: GameInfo,
   pub players: Vec<PlayerInfo>,
   pub slots: SlotInfo,
+  /// Reforged only
+  pub profiles: Vec<PlayerProfileMessage>,
+  /// Reforged only
+  pub skins: Vec<PlayerSkinsMessage>,
 }
 
 #[test]
@@ -4,10 +4,13 @@ use std::io::prelude::*;
 use flo_util::binary::*;
 use flo_util::{BinDecode, BinEncode};
 pub use flo_w3gs::action::PlayerAction;
-pub use flo_w3gs::constants::{GameFlags, LeaveReason, RacePref};
+pub use flo_w3gs::constants::{GameFlags, LeaveReason, ProtoBufMessageTypeId, RacePref};
 pub use flo_w3gs::desync::Desync;
 pub use flo_w3gs::game::GameSettings;
 pub use flo_w3gs::packet::ProtoBufPayload;
+pub use flo_w3gs::player::{
+  PlayerProfileMessage, PlayerProfileRealm, PlayerSkin, PlayerSkinsMessage, PlayerUnknown5Message,
+};
 pub use flo_w3gs::protocol::chat::ChatMessage;
 pub use flo_w3gs::slot::SlotInfo;
 
@@ -222,6 +225,26 @@ pub struct GameInfo {
   pub language_id: u32,
 }
 
+impl GameInfo {
+  pub fn new(
+    host_player_info: PlayerInfo,
+    game_name: impl IntoCStringLossy,
+    game_settings: GameSettings,
+    player_count: u32,
+  ) -> Self {
+    GameInfo {
+      num_of_host_records: 1,
+      host_player_info,
+      game_name: game_name.into_c_string_lossy(),
+      _unk_1: 0,
+      game_settings,
+      player_count,
+      game_flags: GameFlags::CUSTOM_GAME,
+      language_id: 0,
+    }
+  }
+}
+
 #[derive(Debug, BinEncode, BinDecode, PartialEq, Clone)]
 pub struct PlayerInfo {
   pub id: u8,
@@ -231,6 +254,17 @@ pub struct PlayerInfo {
   pub additional_data: Vec<u8>,
 }
 
+impl PlayerInfo {
+  pub fn new(id: u8, name: impl IntoCStringLossy) -> Self {
+    PlayerInfo {
+      id,
+      name: name.into_c_string_lossy(),
+      _size_of_additional_data: 0,
+      additional_data: vec![],
+    }
+  }
+}
+
 #[derive(Debug, BinEncode, BinDecode, PartialEq)]
 pub struct PlayerInfoRecord {
   pub player_info: PlayerInfo,
@@ -400,6 +434,43 @@ pub struct EndTimer {
   pub countdown_sec: u32,
 }
 
+/// Reforged metadata stored in `Record::ProtoBuf`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReforgedMetadata {
+  /// Battle tag, clan and portrait
+  PlayerProfile(PlayerProfileMessage),
+  PlayerSkins(PlayerSkinsMessage),
+  PlayerUnknown5(PlayerUnknown5Message),
+  Unknown(ProtoBufMessageTypeId),
+}
+
+impl ReforgedMetadata {
+  pub fn decode(payload: &ProtoBufPayload) -> crate::error::Result<Self> {
+    Ok(match payload.message_type_id() {
+      ProtoBufMessageTypeId::PlayerProfile => {
+        ReforgedMetadata::PlayerProfile(payload.decode_message()?)
+      }
+      ProtoBufMessageTypeId::PlayerSkins => {
+        ReforgedMetadata::PlayerSkins(payload.decode_message()?)
+      }
+      ProtoBufMessageTypeId::PlayerUnknown5 => {
+        ReforgedMetadata::PlayerUnknown5(payload.decode_message()?)
+      }
+      other => ReforgedMetadata::Unknown(other),
+    })
+  }
+}
+
+impl Record {
+  /// Decodes the Reforged metadata if this is a `ProtoBuf` record
+  pub fn reforged_metadata(&self) -> Option<crate::error::Result<ReforgedMetadata>> {
+    match *self {
+      Record::ProtoBuf(ref payload) => Some(ReforgedMetadata::decode(payload)),
+      _ => None,
+    }
+  }
+}
+
 #[test]
 fn test_record() {
   let bytes = flo_util::sample_bytes!("replay", "16k.w3g");
@@ -486,3 +557,38 @@ fn test_record_iter() {
   dbg!(records);
   dbg!(actions);
 }
+
+#[test]
+fn test_reforged_metadata() {
+  let record = Record::ProtoBuf(ProtoBufPayload::new(PlayerProfileMessage::new(
+    2,
+    "PLAYER#1234",
+  )));
+  let bytes = record.encode_to_bytes();
+  let record = Record::decode(&mut bytes.freeze()).unwrap();
+  match record.reforged_metadata().unwrap().unwrap() {
+    ReforgedMetadata::PlayerProfile(profile) => {
+      assert_eq!(profile.player_id, 2);
+      assert_eq!(profile.battle_tag, "PLAYER#1234");
+    }
+    other => panic!("unexpected {:?}", other),
+  }
+
+  let mut skins = PlayerSkinsMessage::new(2);
+  skins.skins.push(PlayerSkin {
+    unit: 1,
+    skin: 2,
+    collection: "c".to_string(),
+  });
+  let record = Record::ProtoBuf(ProtoBufPayload::new(skins.clone()));
+  assert_eq!(
+    record.reforged_metadata().unwrap().unwrap(),
+    ReforgedMetadata::PlayerSkins(skins)
+  );
+  assert!(Record::EndTimer(EndTimer {
+    over: true,
+    countdown_sec: 0
+  })
+  .reforged_metadata()
+  .is_none());
+}
@@ -1,8 +1,5 @@
-use flate2::Crc;
-use flo_util::binary::{BinDecode, BinEncode};
-
 use crate::{
-  block::{Blocks, BlocksEncoder},
+  block::{BlockFormat, Blocks, BlocksEncoder},
   error::Result,
   header::GameVersion,
   Header, Record, RecordIter,
@@ -16,14 +13,13 @@ pub struct ReplayDecoder<R> {
 
 impl<R: Read> ReplayDecoder<R> {
   pub fn new(mut r: R) -> Result<Self> {
-    let mut header = [0_u8; Header::MIN_SIZE];
-    r.read_exact(header.as_mut_slice())?;
-    let header = Header::decode(&mut header.as_slice()).unwrap();
+    let header = Header::read(&mut r)?;
     let blocks = Blocks::new(
       r,
       header.num_blocks as _,
       (header.size_header + header.size_blocks) as _,
-    );
+    )
+    .with_format(BlockFormat::from_header(&header));
     Ok(Self { header, blocks })
   }
 
@@ -46,59 +42,64 @@ pub struct ReplayEncoder<W> {
 }
 
 impl<W: Write + Seek> ReplayEncoder<W> {
-  pub fn new(game_version: GameVersion, flags: u16, mut w: W) -> Result<Self> {
-    w.seek(SeekFrom::Start(Header::MIN_SIZE as u64))?;
+  pub fn new(game_version: GameVersion, flags: u16, w: W) -> Result<Self> {
+    Self::with_header(Header::new(game_version, flags), w)
+  }
+
+  /// Keeps the header version, game version and flags of `header`, e.g. when rewriting a replay
+  pub fn with_header(mut header: Header, mut w: W) -> Result<Self> {
+    header.size_file = 0;
+    header.size_blocks = 0;
+    header.num_blocks = 0;
+    header.duration_ms = 0;
+    header.crc = 0;
+    w.seek(SeekFrom::Start(header.size() as u64))?;
+    let format = BlockFormat::from_header(&header);
     Ok(Self {
-      header: Header::new(game_version, flags),
-      w: BlocksEncoder::new(w),
+      header,
+      w: BlocksEncoder::new(w).with_format(format),
     })
   }
 
+  pub fn encode(&mut self, record: &Record) -> Result<()> {
+    self.header.duration_ms += time_increment_ms(record);
+    self.w.encode(record)?;
+    Ok(())
+  }
+
   pub fn encode_records<'a, I>(&mut self, iter: I) -> Result<()>
   where
     I: IntoIterator<Item = &'a Record>,
   {
     for r in iter {
-      match *r {
-        Record::TimeSlotFragment(ref slot) => {
-          self.header.duration_ms += slot.0.time_increment_ms as u32;
-        }
-        Record::TimeSlot(ref slot) => {
-          self.header.duration_ms += slot.time_increment_ms as u32;
-        }
-        _ => {}
-      }
-      self.w.encode(r)?;
+      self.encode(r)?;
     }
     Ok(())
   }
 
-  pub fn finish(mut self) -> Result<()> {
+  pub fn finish(mut self) -> Result<W> {
     let blocks = self.w.finish()?;
 
     let mut w = blocks.inner;
 
     self.header.size_file = w.stream_position()? as u32;
-
-    w.seek(SeekFrom::Start(0))?;
-    let mut buf = [0_u8; Header::MIN_SIZE];
-
     self.header.num_blocks = blocks.num_of_blocks as u32;
     self.header.size_blocks = blocks.num_of_uncompressed_bytes as u32;
 
-    self.header.encode(&mut buf.as_mut_slice());
-
-    let mut crc = Crc::new();
-    crc.update(&buf);
-    self.header.crc = crc.sum();
-
-    (&mut buf[(Header::MIN_SIZE - 4)..]).copy_from_slice(&self.header.crc.to_le_bytes());
-
-    w.write_all(&buf)?;
+    w.seek(SeekFrom::Start(0))?;
+    w.write_all(&self.header.encode_with_crc())?;
 
     w.flush()?;
 
-    Ok(())
+    Ok(w)
+  }
+}
+
+pub(crate) fn time_increment_ms(record: &Record) -> u32 {
+  match *record {
+    Record::TimeSlotFragment(ref slot) => slot.0.time_increment_ms as u32,
+    Record::TimeSlot(ref slot) => slot.time_increment_ms as u32,
+    _ => 0,
   }
 }
 
@@ -114,6 +115,7 @@ fn test_decode() {
 
 #[test]
 fn test_encode() {
+  use flate2::Crc;
   let path = flo_util::sample_path!("replay", "grubby_happy.w3g");
   let out_path = "../../target/gen.w3g";
   let d = ReplayDecoder::new(std::fs::File::open(&path).unwrap()).unwrap();
@@ -177,3 +179,77 @@ fn test_encode() {
   //   assert_eq!(a, b);
   // }
 }
+
+#[test]
+fn test_encode_decode() {
+  use crate::{EndTimer, PlayerProfileMessage, ProtoBufPayload, ReforgedMetadata};
+  let records = vec![
+    Record::ProtoBuf(ProtoBufPayload::new(PlayerProfileMessage::new(1, "a#1"))),
+    Record::EndTimer(EndTimer {
+      over: true,
+      countdown_sec: 10,
+    }),
+  ];
+  let mut e = ReplayEncoder::new(
+    GameVersion {
+      version: 10032,
+      build_number: 6110,
+      ..Default::default()
+    },
+    0x8000,
+    std::io::Cursor::new(vec![]),
+  )
+  .unwrap();
+  e.encode_records(&records).unwrap();
+  let bytes = e.finish().unwrap().into_inner();
+
+  let d = ReplayDecoder::new(bytes.as_slice()).unwrap();
+  assert_eq!(d.header().size_file as usize, bytes.len());
+  assert_eq!(d.header().game_version.version, 10032);
+  assert!(d.header().game_version.is_expansion());
+  let decoded = d.into_records().collect::<Result<Vec<_>, _>>().unwrap();
+  assert_eq!(decoded, records);
+  assert!(matches!(
+    decoded[0].reforged_metadata(),
+    Some(Ok(ReforgedMetadata::PlayerProfile(_)))
+  ));
+}
+
+#[test]
+fn test_encode_decode_legacy() {
+  use crate::{EndTimer, PRODUCT_ROC};
+  use flo_util::binary::BinDecode;
+  use flo_util::dword_string::DwordString;
+
+  let mut header = Header::new(
+    GameVersion {
+      product: DwordString::new(PRODUCT_ROC),
+      version: 6,
+      build_number: 4656,
+    },
+    0x8000,
+  );
+  header.header_version = 0;
+  header.size_header = Header::SIZE_V0 as u32;
+  let records = vec![Record::EndTimer(EndTimer {
+    over: true,
+    countdown_sec: 10,
+  })];
+  let mut e = ReplayEncoder::with_header(header, std::io::Cursor::new(vec![])).unwrap();
+  e.encode_records(&records).unwrap();
+  let bytes = e.finish().unwrap().into_inner();
+
+  let header = Header::decode(&mut bytes.as_slice()).unwrap();
+  assert_eq!(header.header_version, 0);
+  assert_eq!(header.game_version.version, 6);
+  // legacy block header: u16 sizes
+  let block = &bytes[Header::SIZE_V0..];
+  assert_eq!(
+    u16::from_le_bytes([block[0], block[1]]) as usize,
+    block.len() - 8
+  );
+
+  let d = ReplayDecoder::new(bytes.as_slice()).unwrap();
+  let decoded = d.into_records().collect::<Result<Vec<_>, _>>().unwrap();
+  assert_eq!(decoded, records);
+}
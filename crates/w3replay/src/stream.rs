@@ -0,0 +1,263 @@
+//! Async replay decoder and encoder
+//!
+//! Both work block by block, memory usage doesn't grow with the replay size.
+
+use bytes::{Buf, BytesMut};
+use flo_util::binary::{BinDecode, BinDecodeError};
+use std::io::SeekFrom;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use crate::block::{decompress_block, Block, BlockBuilder, BlockFormat, BlockHeader};
+use crate::error::*;
+use crate::header::GameVersion;
+use crate::replay::time_increment_ms;
+use crate::{Header, Record};
+
+pub struct AsyncReplayDecoder<R> {
+  header: Header,
+  format: BlockFormat,
+  remaining_blocks: usize,
+  buf: BytesMut,
+  r: R,
+}
+
+impl<R> AsyncReplayDecoder<R>
+where
+  R: AsyncRead + Unpin,
+{
+  pub async fn new(mut r: R) -> Result<Self> {
+    let mut buf = [0_u8; Header::SIZE_V1];
+    r.read_exact(&mut buf[..Header::SIZE_COMMON])
+      .await
+      .map_err(Error::ReadHeader)?;
+    let size = Header::size_by_common_fields(&buf);
+    r.read_exact(&mut buf[Header::SIZE_COMMON..size])
+      .await
+      .map_err(Error::ReadHeader)?;
+    let header = Header::decode(&mut &buf[..size]).map_err(|e| e.context("header"))?;
+    Ok(Self {
+      format: BlockFormat::from_header(&header),
+      remaining_blocks: header.num_blocks as usize,
+      header,
+      buf: BytesMut::new(),
+      r,
+    })
+  }
+
+  pub fn header(&self) -> &Header {
+    &self.header
+  }
+
+  pub async fn next_block(&mut self) -> Result<Option<Block>> {
+    if self.remaining_blocks == 0 {
+      return Ok(None);
+    }
+
+    let mut header_buf = [0_u8; BlockHeader::MIN_SIZE];
+    let header_buf = &mut header_buf[..BlockHeader::size(self.format)];
+    self
+      .r
+      .read_exact(header_buf)
+      .await
+      .map_err(Error::ReadBlockHeader)?;
+    let header = BlockHeader::decode_with_format(header_buf, self.format)?;
+
+    let mut compressed = vec![0_u8; header.compressed_data_size as usize];
+    self
+      .r
+      .read_exact(&mut compressed)
+      .await
+      .map_err(Error::ReadBlockHeader)?;
+
+    self.remaining_blocks -= 1;
+
+    decompress_block(header, &compressed, self.format).map(Some)
+  }
+
+  /// Records can span blocks, at most one block and a partial record are buffered
+  pub async fn next_record(&mut self) -> Result<Option<Record>> {
+    loop {
+      match self.buf.first().cloned() {
+        // 0 padding reached, skip the rest of the block
+        Some(0) => self.buf.clear(),
+        Some(_) => {
+          let mut view = &self.buf[..];
+          match Record::decode(&mut view) {
+            Ok(record) => {
+              let consumed = self.buf.len() - view.len();
+              self.buf.advance(consumed);
+              return Ok(Some(record));
+            }
+            Err(err) if err.is_incomplete() => {}
+            Err(err) => return Err(err.into()),
+          }
+        }
+        None => {}
+      }
+
+      match self.next_block().await? {
+        Some(block) => self.buf.extend_from_slice(&block.data),
+        None => {
+          return if self.buf.is_empty() {
+            Ok(None)
+          } else {
+            Err(BinDecodeError::incomplete().context("record").into())
+          };
+        }
+      }
+    }
+  }
+
+  pub fn into_inner(self) -> R {
+    self.r
+  }
+}
+
+/// Encodes records while they are produced, e.g. when a game is in progress
+///
+/// Sizes and the CRC in the header are written by `finish`.
+pub struct AsyncReplayEncoder<W> {
+  header: Header,
+  builder: BlockBuilder,
+  w: W,
+}
+
+impl<W> AsyncReplayEncoder<W>
+where
+  W: AsyncWrite + AsyncSeek + Unpin,
+{
+  pub async fn new(game_version: GameVersion, flags: u16, mut w: W) -> Result<Self> {
+    let mut header = Header::new(game_version, flags);
+    w.seek(SeekFrom::Start(0)).await?;
+    w.write_all(&header.encode_with_crc()).await?;
+    Ok(Self {
+      header,
+      builder: BlockBuilder::new(),
+      w,
+    })
+  }
+
+  pub fn header(&self) -> &Header {
+    &self.header
+  }
+
+  pub async fn encode(&mut self, record: &Record) -> Result<usize> {
+    self.header.duration_ms += time_increment_ms(record);
+    let len = self.builder.push(record)?;
+    let output = self.builder.take_output();
+    if !output.is_empty() {
+      self.w.write_all(&output).await?;
+    }
+    Ok(len)
+  }
+
+  pub async fn encode_records<'a, I>(&mut self, iter: I) -> Result<()>
+  where
+    I: IntoIterator<Item = &'a Record>,
+  {
+    for r in iter {
+      self.encode(r).await?;
+    }
+    Ok(())
+  }
+
+  /// Flushes completed blocks, the incomplete block stays in memory until it is filled
+  pub async fn flush(&mut self) -> Result<()> {
+    self.w.flush().await?;
+    Ok(())
+  }
+
+  pub async fn finish(mut self) -> Result<W> {
+    self.builder.finish()?;
+    self.w.write_all(&self.builder.take_output()).await?;
+
+    self.header.size_file = self.w.stream_position().await? as u32;
+    self.header.num_blocks = self.builder.num_of_blocks as u32;
+    self.header.size_blocks = self.builder.num_of_uncompressed_bytes as u32;
+
+    self.w.seek(SeekFrom::Start(0)).await?;
+    self.w.write_all(&self.header.encode_with_crc()).await?;
+    self.w.flush().await?;
+
+    Ok(self.w)
+  }
+}
+
+#[cfg(test)]
+fn test_records() -> Vec<Record> {
+  use crate::{PlayerChatMessage, TimeSlot};
+  use flo_w3gs::protocol::chat::{ChatMessage, MessageScope};
+  use std::ffi::CString;
+
+  (0..1000_u32)
+    .map(|i| {
+      if i % 2 == 0 {
+        Record::TimeSlot(TimeSlot {
+          time_increment_ms: 100,
+          actions: vec![],
+        })
+      } else {
+        Record::ChatMessage(PlayerChatMessage {
+          player_id: 1,
+          message: ChatMessage::Scoped {
+            scope: MessageScope::All,
+            message: CString::new(format!("message {}", i)).unwrap(),
+          },
+        })
+      }
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn test_async_decode() {
+  let records = test_records();
+  let mut e = crate::ReplayEncoder::new(
+    GameVersion {
+      version: 10032,
+      build_number: 6110,
+      ..Default::default()
+    },
+    0,
+    std::io::Cursor::new(vec![]),
+  )
+  .unwrap();
+  e.encode_records(&records).unwrap();
+  let bytes = e.finish().unwrap().into_inner();
+
+  let mut d = AsyncReplayDecoder::new(bytes.as_slice()).await.unwrap();
+  assert_eq!(d.header().duration_ms, 50000);
+  assert!(d.header().num_blocks > 1);
+  let mut decoded = vec![];
+  while let Some(record) = d.next_record().await.unwrap() {
+    decoded.push(record);
+  }
+  assert_eq!(decoded, records);
+}
+
+#[tokio::test]
+async fn test_async_encode() {
+  let records = test_records();
+  let mut e = AsyncReplayEncoder::new(
+    GameVersion {
+      version: 10032,
+      build_number: 6110,
+      ..Default::default()
+    },
+    0,
+    std::io::Cursor::new(vec![]),
+  )
+  .await
+  .unwrap();
+  for record in &records {
+    e.encode(record).await.unwrap();
+  }
+  e.flush().await.unwrap();
+  let bytes = e.finish().await.unwrap().into_inner();
+
+  let d = crate::ReplayDecoder::new(bytes.as_slice()).unwrap();
+  assert_eq!(d.header().duration_ms, 50000);
+  assert_eq!(d.header().size_file as usize, bytes.len());
+  let decoded = d.into_records().collect::<Result<Vec<_>, _>>().unwrap();
+  assert_eq!(decoded, records);
+}
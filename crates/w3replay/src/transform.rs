@@ -0,0 +1,387 @@
+//! Replay rewriting
+//!
+//! Records are decoded, passed through a [`RecordTransform`] and encoded again,
+//! block checksums and the header are recomputed by the encoder.
+
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+use flo_util::binary::IntoCStringLossy;
+use flo_w3gs::protocol::chat::ChatMessage;
+use flo_w3gs::slot::SlotStatus;
+
+use crate::error::*;
+use crate::{
+  ProtoBufPayload, Record, ReforgedMetadata, ReplayDecoder, ReplayEncoder, ReplayInfo, W3Replay,
+};
+
+pub trait RecordTransform {
+  /// Returns `None` to drop the record
+  fn transform(&mut self, record: Record) -> Option<Record>;
+}
+
+impl<F> RecordTransform for F
+where
+  F: FnMut(Record) -> Option<Record>,
+{
+  fn transform(&mut self, record: Record) -> Option<Record> {
+    self(record)
+  }
+}
+
+/// Rewrites a replay record by record, the header version, game version and flags are kept
+pub fn transform_replay<R, W, T>(r: R, w: W, transform: &mut T) -> Result<W>
+where
+  R: Read,
+  W: Write + Seek,
+  T: RecordTransform,
+{
+  let decoder = ReplayDecoder::new(r)?;
+  let mut encoder = ReplayEncoder::with_header(decoder.header().clone(), w)?;
+  for record in decoder.into_records() {
+    if let Some(record) = transform.transform(record?) {
+      encoder.encode(&record)?;
+    }
+  }
+  encoder.finish()
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SanitizeOptions {
+  /// Replaces player names with `Player {slot number}`
+  pub mask_player_names: bool,
+  /// Original name to new name, takes precedence over masking
+  pub rename: BTreeMap<String, String>,
+  /// Removes all chat messages
+  pub strip_chat: bool,
+  pub game_name: Option<String>,
+}
+
+/// Masks or renames players consistently across player, game, chat and Reforged records,
+/// names mentioned in chat messages are replaced too
+#[derive(Debug)]
+pub struct Sanitizer {
+  names: BTreeMap<u8, CString>,
+  /// Original name to new name, for names mentioned in chat
+  replacements: Vec<(String, String)>,
+  strip_chat: bool,
+  game_name: Option<CString>,
+}
+
+impl Sanitizer {
+  pub fn new(options: &SanitizeOptions, info: &ReplayInfo) -> Self {
+    let slot_numbers: BTreeMap<u8, usize> = info
+      .slots
+      .slots()
+      .iter()
+      .enumerate()
+      .filter(|(_, slot)| slot.slot_status == SlotStatus::Occupied && !slot.computer)
+      .map(|(idx, slot)| (slot.player_id, idx + 1))
+      .collect();
+
+    let mut names = BTreeMap::new();
+    let mut replacements = vec![];
+    let players = std::iter::once(&info.game.host_player_info).chain(info.players.iter());
+    for player in players {
+      let name = player.name.to_string_lossy();
+      let new_name = if let Some(new_name) = options.rename.get(name.as_ref()) {
+        new_name.clone()
+      } else if options.mask_player_names {
+        format!(
+          "Player {}",
+          slot_numbers
+            .get(&player.id)
+            .cloned()
+            .unwrap_or(player.id as usize)
+        )
+      } else {
+        continue;
+      };
+      if !name.is_empty() {
+        replacements.push((name.to_string(), new_name.clone()));
+      }
+      names.insert(player.id, new_name.into_c_string_lossy());
+    }
+    // longer names first in case a name contains another
+    replacements.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+
+    Self {
+      names,
+      replacements,
+      strip_chat: options.strip_chat,
+      game_name: options
+        .game_name
+        .as_ref()
+        .map(|name| name.as_str().into_c_string_lossy()),
+    }
+  }
+
+  fn rename(&self, player_id: u8, name: &mut CString) {
+    if let Some(new_name) = self.names.get(&player_id) {
+      *name = new_name.clone();
+    }
+  }
+
+  fn replace_names(&self, message: &mut CString) {
+    if self.replacements.is_empty() {
+      return;
+    }
+    let text = replace_words(&message.to_string_lossy(), &self.replacements);
+    *message = text.into_c_string_lossy();
+  }
+}
+
+/// Replaces whole-word occurrences, so a short name like "a" doesn't match inside other words.
+/// `replacements` are tried in order at each position.
+fn replace_words(text: &str, replacements: &[(String, String)]) -> String {
+  fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+  }
+  fn is_boundary(edge: Option<char>, adjacent: Option<char>) -> bool {
+    match (edge, adjacent) {
+      (Some(edge), Some(adjacent)) => !(is_word_char(edge) && is_word_char(adjacent)),
+      _ => true,
+    }
+  }
+
+  let mut output = String::with_capacity(text.len());
+  let mut prev: Option<char> = None;
+  let mut rest = text;
+  'outer: while let Some(c) = rest.chars().next() {
+    for (name, new_name) in replacements {
+      if !rest.starts_with(name.as_str()) {
+        continue;
+      }
+      let next = rest[name.len()..].chars().next();
+      if is_boundary(name.chars().next(), prev) && is_boundary(name.chars().last(), next) {
+        output.push_str(new_name);
+        prev = name.chars().last();
+        rest = &rest[name.len()..];
+        continue 'outer;
+      }
+    }
+    output.push(c);
+    prev = Some(c);
+    rest = &rest[c.len_utf8()..];
+  }
+  output
+}
+
+impl RecordTransform for Sanitizer {
+  fn transform(&mut self, record: Record) -> Option<Record> {
+    let record = match record {
+      Record::GameInfo(mut info) => {
+        let host_id = info.host_player_info.id;
+        self.rename(host_id, &mut info.host_player_info.name);
+        self.rename(host_id, &mut info.game_settings.host_name);
+        if let Some(name) = self.game_name.as_ref() {
+          info.game_name = name.clone();
+        }
+        Record::GameInfo(info)
+      }
+      Record::PlayerInfo(mut info) => {
+        self.rename(info.player_info.id, &mut info.player_info.name);
+        Record::PlayerInfo(info)
+      }
+      Record::ChatMessage(_) if self.strip_chat => return None,
+      Record::ChatMessage(mut chat) => {
+        match chat.message {
+          ChatMessage::Chat(ref mut message)
+          | ChatMessage::Scoped {
+            ref mut message, ..
+          } => self.replace_names(message),
+          _ => {}
+        }
+        Record::ChatMessage(chat)
+      }
+      Record::ProtoBuf(payload) => match ReforgedMetadata::decode(&payload) {
+        Ok(ReforgedMetadata::PlayerProfile(mut profile)) => {
+          match self.names.get(&(profile.player_id as u8)) {
+            Some(name) => {
+              profile.battle_tag = name.to_string_lossy().to_string();
+              profile.clan = String::new();
+              Record::ProtoBuf(ProtoBufPayload::new(profile))
+            }
+            None => Record::ProtoBuf(payload),
+          }
+        }
+        _ => Record::ProtoBuf(payload),
+      },
+      record => record,
+    };
+    Some(record)
+  }
+}
+
+/// Writes a sanitized copy of the replay at `input` to `output`
+pub fn sanitize_replay<P1, P2>(input: P1, output: P2, options: &SanitizeOptions) -> Result<()>
+where
+  P1: AsRef<Path>,
+  P2: AsRef<Path>,
+{
+  let (info, _) = W3Replay::inspect(input.as_ref())?;
+  let mut sanitizer = Sanitizer::new(options, &info);
+  let r = std::io::BufReader::new(File::open(input)?);
+  let w = BufWriter::new(File::create(output)?);
+  transform_replay(r, w, &mut sanitizer)?.flush()?;
+  Ok(())
+}
+
+#[test]
+fn test_sanitize() {
+  use crate::{
+    GameInfo, GameSettings, PlayerChatMessage, PlayerInfo, PlayerInfoRecord, PlayerProfileMessage,
+    TimeSlot,
+  };
+  use flo_w3gs::constants::GameSettingFlags;
+  use flo_w3gs::game::GameSettingsMap;
+  use flo_w3gs::protocol::chat::MessageScope;
+  use flo_w3gs::slot::SlotInfo;
+
+  let mut game_settings = GameSettings::new(
+    GameSettingFlags::default(),
+    GameSettingsMap {
+      path: "Maps\\(2)EchoIsles.w3x".to_string(),
+      width: 0,
+      height: 0,
+      sha1: [0; 20],
+      checksum: 0,
+    },
+  );
+  game_settings.host_name = CString::new("alice").unwrap();
+  let mut slots = SlotInfo::build().num_slots(24).num_players(2).build();
+  for (idx, player_id) in [(0, 2_u8), (1, 1)].iter() {
+    let slot = slots.slot_mut(*idx).unwrap();
+    slot.player_id = *player_id;
+    slot.slot_status = SlotStatus::Occupied;
+  }
+
+  let records = vec![
+    Record::GameInfo(GameInfo::new(
+      PlayerInfo::new(1, "alice"),
+      "alice's game",
+      game_settings,
+      2,
+    )),
+    Record::PlayerInfo(PlayerInfoRecord {
+      player_info: PlayerInfo::new(2, "bob"),
+      unknown: 0,
+    }),
+    Record::ProtoBuf(ProtoBufPayload::new(PlayerProfileMessage::new(
+      1,
+      "alice#1234",
+    ))),
+    Record::SlotInfo(slots),
+    Record::TimeSlot(TimeSlot {
+      time_increment_ms: 100,
+      actions: vec![],
+    }),
+    Record::ChatMessage(PlayerChatMessage {
+      player_id: 1,
+      message: ChatMessage::Scoped {
+        scope: MessageScope::All,
+        message: CString::new("hi bob").unwrap(),
+      },
+    }),
+  ];
+  let mut e = ReplayEncoder::new(Default::default(), 0x8000, std::io::Cursor::new(vec![])).unwrap();
+  e.encode_records(&records).unwrap();
+  let bytes = e.finish().unwrap().into_inner();
+
+  let (info, _) = W3Replay::from_buf(bytes.as_slice())
+    .unwrap()
+    .into_info()
+    .unwrap();
+  let mut sanitizer = Sanitizer::new(
+    &SanitizeOptions {
+      mask_player_names: true,
+      rename: vec![("bob".to_string(), "Caster".to_string())]
+        .into_iter()
+        .collect(),
+      strip_chat: true,
+      game_name: Some("Qualifier".to_string()),
+    },
+    &info,
+  );
+  let output = transform_replay(
+    bytes.as_slice(),
+    std::io::Cursor::new(vec![]),
+    &mut sanitizer,
+  )
+  .unwrap()
+  .into_inner();
+
+  let d = ReplayDecoder::new(output.as_slice()).unwrap();
+  assert_eq!(d.header().duration_ms, 100);
+  let records = d.into_records().collect::<Result<Vec<_>, _>>().unwrap();
+  assert_eq!(records.len(), 5);
+  match records[0] {
+    Record::GameInfo(ref info) => {
+      assert_eq!(info.host_player_info.name.to_str().unwrap(), "Player 2");
+      assert_eq!(info.game_settings.host_name.to_str().unwrap(), "Player 2");
+      assert_eq!(info.game_name.to_str().unwrap(), "Qualifier");
+    }
+    ref other => panic!("unexpected {:?}", other),
+  }
+  match records[1] {
+    Record::PlayerInfo(ref info) => {
+      assert_eq!(info.player_info.name.to_str().unwrap(), "Caster");
+    }
+    ref other => panic!("unexpected {:?}", other),
+  }
+  match records[2].reforged_metadata() {
+    Some(Ok(ReforgedMetadata::PlayerProfile(ref profile))) => {
+      assert_eq!(profile.battle_tag, "Player 2");
+    }
+    ref other => panic!("unexpected {:?}", other),
+  }
+  assert!(records.iter().all(|r| !matches!(r, Record::ChatMessage(_))));
+
+  let mut sanitizer = Sanitizer::new(
+    &SanitizeOptions {
+      mask_player_names: true,
+      ..Default::default()
+    },
+    &info,
+  );
+  let output = transform_replay(
+    bytes.as_slice(),
+    std::io::Cursor::new(vec![]),
+    &mut sanitizer,
+  )
+  .unwrap()
+  .into_inner();
+  let records = ReplayDecoder::new(output.as_slice())
+    .unwrap()
+    .into_records()
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  match records[5] {
+    Record::ChatMessage(PlayerChatMessage {
+      message: ChatMessage::Scoped { ref message, .. },
+      ..
+    }) => assert_eq!(message.to_str().unwrap(), "hi Player 1"),
+    ref other => panic!("unexpected {:?}", other),
+  }
+}
+
+#[test]
+fn test_replace_words() {
+  let replacements = vec![
+    ("Moon".to_string(), "Player 2".to_string()),
+    ("a".to_string(), "Player 1".to_string()),
+  ];
+  assert_eq!(
+    replace_words("a Moon walk at Moonlight, gg a!", &replacements),
+    "Player 1 Player 2 walk at Moonlight, gg Player 1!"
+  );
+  assert_eq!(replace_words("(a)", &replacements), "(Player 1)");
+  assert_eq!(replace_words("aa Moons", &replacements), "aa Moons");
+  assert_eq!(
+    replace_words("[TH]a", &[("[TH]a".to_string(), "Player 3".to_string())]),
+    "Player 3"
+  );
+}
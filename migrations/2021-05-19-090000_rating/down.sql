@@ -0,0 +1,3 @@
+drop table player_rating_history;
+drop table player_rating;
+drop table rating_pool;
\ No newline at end of file
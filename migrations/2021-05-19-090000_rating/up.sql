@@ -0,0 +1,45 @@
+create table rating_pool (
+    id serial not null primary key,
+    api_client_id integer not null references api_client(id),
+    name text not null,
+    algorithm integer not null,
+    created_at timestamp with time zone default now() not null,
+    unique(api_client_id, name)
+);
+
+create table player_rating (
+    id serial not null primary key,
+    pool_id integer not null references rating_pool(id),
+    player_id integer not null references player(id),
+    rating double precision not null,
+    deviation double precision not null,
+    volatility double precision not null,
+    score double precision not null,
+    games integer default 0 not null,
+    wins integer default 0 not null,
+    losses integer default 0 not null,
+    draws integer default 0 not null,
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null,
+    unique(pool_id, player_id)
+);
+
+create index player_rating_pool_id_score on player_rating(pool_id, score desc);
+create index player_rating_player_id on player_rating(player_id);
+
+create table player_rating_history (
+    id serial not null primary key,
+    pool_id integer not null references rating_pool(id),
+    player_id integer not null references player(id),
+    game_id integer not null references game(id),
+    rating_before double precision not null,
+    rating_after double precision not null,
+    deviation_before double precision not null,
+    deviation_after double precision not null,
+    result integer not null,
+    created_at timestamp with time zone default now() not null,
+    unique(pool_id, player_id, game_id)
+);
+
+create index player_rating_history_pool_id_player_id on player_rating_history(pool_id, player_id);
+create index player_rating_history_game_id on player_rating_history(game_id);
\ No newline at end of file
@@ -0,0 +1 @@
+drop table matchmaking_queue;
\ No newline at end of file
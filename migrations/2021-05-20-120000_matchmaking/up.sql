@@ -0,0 +1,16 @@
+create table matchmaking_queue (
+    id serial not null primary key,
+    name text not null,
+    api_client_id integer not null references api_client(id),
+    api_player_id integer not null references player(id),
+    team_size integer not null,
+    region text,
+    map_pool jsonb not null,
+    rating_pool_id integer references rating_pool(id),
+    rating_spread integer not null,
+    rating_spread_per_minute integer not null,
+    max_ping integer not null,
+    enabled boolean default true not null,
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null
+);
\ No newline at end of file
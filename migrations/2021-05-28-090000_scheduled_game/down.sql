@@ -0,0 +1 @@
+drop table scheduled_game;
\ No newline at end of file
@@ -0,0 +1,20 @@
+create table scheduled_game (
+    id serial not null primary key,
+    api_client_id integer not null references api_client(id),
+    api_player_id integer not null references player(id),
+    name text not null,
+    map jsonb not null,
+    node_id integer not null references node(id),
+    slots jsonb not null,
+    check_in_at timestamp with time zone not null,
+    start_at timestamp with time zone not null,
+    end_at timestamp with time zone not null,
+    status integer default 0 not null,
+    checked_in_player_ids jsonb default '[]' not null,
+    no_show_player_ids jsonb default '[]' not null,
+    game_id integer references game(id),
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null
+);
+
+create index scheduled_game_status on scheduled_game(status);
\ No newline at end of file
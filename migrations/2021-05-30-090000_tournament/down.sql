@@ -0,0 +1,2 @@
+drop table tournament_match;
+drop table tournament;
\ No newline at end of file
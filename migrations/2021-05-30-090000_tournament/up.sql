@@ -0,0 +1,39 @@
+create table tournament (
+    id serial not null primary key,
+    api_client_id integer not null references api_client(id),
+    api_player_id integer not null references player(id),
+    name text not null,
+    format integer not null,
+    best_of integer not null,
+    map_pool jsonb not null,
+    node_id integer not null references node(id),
+    check_in_minutes integer not null,
+    player_ids jsonb not null,
+    status integer default 0 not null,
+    winner_id integer references player(id),
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null
+);
+
+create index tournament_status on tournament(status);
+
+create table tournament_match (
+    id serial not null primary key,
+    tournament_id integer not null references tournament(id),
+    round integer not null,
+    position integer not null,
+    bracket integer not null,
+    player_a integer references player(id),
+    player_b integer references player(id),
+    winner_to jsonb,
+    loser_to jsonb,
+    status integer default 0 not null,
+    winner_id integer references player(id),
+    games jsonb default '[]' not null,
+    map_pick integer,
+    next_game_at timestamp with time zone,
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null
+);
+
+create index tournament_match_tournament_id on tournament_match(tournament_id);
\ No newline at end of file
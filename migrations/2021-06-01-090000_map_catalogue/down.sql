@@ -0,0 +1,2 @@
+drop table map_pool;
+drop table map_version;
\ No newline at end of file
@@ -0,0 +1,25 @@
+create table map_version (
+    id serial not null primary key,
+    api_client_id integer not null references api_client(id),
+    sha1 text not null unique,
+    checksum bigint not null,
+    name text not null,
+    path text not null,
+    file_size bigint not null,
+    meta jsonb not null,
+    tags jsonb default '[]' not null,
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null
+);
+
+create index map_version_name on map_version(name);
+
+create table map_pool (
+    id serial not null primary key,
+    api_client_id integer not null references api_client(id),
+    name text not null,
+    map_version_ids jsonb default '[]' not null,
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null,
+    unique (api_client_id, name)
+);
\ No newline at end of file